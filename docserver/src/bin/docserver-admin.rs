@@ -0,0 +1,377 @@
+//! Maintenance commands run against the configured root without the HTTP
+//! server. Settings come from the same config file, environment variables
+//! and flags as the server's, given after the command and its arguments:
+//!
+//! ```text
+//! docserver-admin migrate [--config FILE] [--root-path DIR] ...
+//! docserver-admin create-user CLIENT_ID EMAIL < password
+//! docserver-admin export-clients [FILE]
+//! docserver-admin import-clients FILE
+//! docserver-admin verify [--repair]
+//! docserver-admin reindex
+//! docserver-admin rotate-field-key
+//! docserver-admin purge-trash [DAYS]
+//! docserver-admin backup
+//! docserver-admin list-backups
+//! docserver-admin verify-backup ARCHIVE
+//! docserver-admin restore ARCHIVE TARGET
+//! ```
+//!
+//! An ARCHIVE is a path, or the name of an archive in `backup_path`. A FILE
+//! of `-` is standard input or output.
+
+use chrono::{Duration, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use docserver::config::{
+    self, AppState, DbBackend, Settings, DEFAULT_TRASH_RETENTION_DAYS, PORTAL_PASSWORD_MIN_LEN,
+};
+use docserver::db::{migrations, FieldKey};
+use docserver::documents::{consistency, trash};
+use docserver::{links, Client};
+
+const USAGE: &str = "Usage: docserver-admin <command> [arguments] [settings flags]
+
+Commands:
+  init, migrate             Create the root, database and keys, or bring the database schema up to date
+  create-user CLIENT_ID EMAIL
+                            Add a portal sign-in; the password is read from standard input
+  export-clients [FILE]     Write every client as JSON, social security numbers in plain text
+  import-clients FILE       Add the clients in a JSON array, all or none
+  verify [--repair]         Compare the database with the files under the root; --repair moves
+                            orphan folders and files to the trash and records missing checksums
+  reindex                   Extract and classify every recorded document again
+  rotate-field-key          Re-encrypt sensitive columns under a new field key; stop the server first.
+                            The signing key for public links is not rotated
+  purge-trash [DAYS]        Delete trash batches older than DAYS (default 30)
+  backup                    Back up the database, keys and documents now
+  list-backups              List archives in the backup directory
+  verify-backup ARCHIVE     Check every entry of an archive against its manifest
+  restore ARCHIVE TARGET    Restore an archive into an empty directory";
+
+/// A failure to report, and whether it was the caller's mistake.
+enum Failure {
+    Usage(String),
+    Failed(String),
+}
+
+fn usage(message: impl Into<String>) -> Failure {
+    Failure::Usage(message.into())
+}
+
+fn failed(e: impl std::fmt::Display) -> Failure {
+    Failure::Failed(e.to_string())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(message)) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Failed(message)) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(usage("No command given"));
+    };
+    // `--repair` is verify's own flag rather than a setting
+    let repair = command == "verify" && rest.iter().any(|arg| arg == "--repair");
+    let rest: Vec<String> = rest.iter().filter(|arg| !repair || *arg != "--repair").cloned().collect();
+    // Arguments come first; everything from the first flag on is settings
+    let split = rest.iter().position(|arg| arg.starts_with("--")).unwrap_or(rest.len());
+    let (arguments, flags) = rest.split_at(split);
+    let settings = || Settings::resolve(flags, &|name| std::env::var(name).ok()).map_err(|e| usage(e.to_string()));
+    // Commands holding the field key keep it from being rotated under them
+    let open = || {
+        let state = AppState::new(settings()?).map_err(|e| usage(e.to_string()))?;
+        state.share_field_key().map_err(failed)?;
+        Ok(state)
+    };
+    let expect_arguments = |counts: RangeInclusive<usize>| {
+        if counts.contains(&arguments.len()) {
+            Ok(())
+        } else if counts.start() == counts.end() {
+            Err(usage(format!("{} takes {} argument(s), not {}", command, counts.start(), arguments.len())))
+        } else {
+            Err(usage(format!("{} takes {} to {} arguments, not {}", command, counts.start(), counts.end(), arguments.len())))
+        }
+    };
+
+    match command.as_str() {
+        "init" | "migrate" => {
+            expect_arguments(0..=0)?;
+            migrate(settings()?)?;
+        }
+        "create-user" => {
+            expect_arguments(2..=2)?;
+            let client_id: i64 = arguments[0].parse().map_err(|_| usage(format!("Not a client id: {}", arguments[0])))?;
+            let email = arguments[1].trim();
+            let mut password = String::new();
+            io::stdin().read_line(&mut password).map_err(failed)?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            if email.is_empty() || password.len() < PORTAL_PASSWORD_MIN_LEN {
+                return Err(failed(format!(
+                    "An email and a password of at least {} characters are required", PORTAL_PASSWORD_MIN_LEN
+                )));
+            }
+
+            let state = open()?;
+            let repo = state.repository();
+            if repo.get_client(client_id).map_err(failed)?.is_none() {
+                return Err(failed(format!("Client {} not found", client_id)));
+            }
+            if repo.find_portal_user(email).map_err(failed)?.is_some() {
+                return Err(failed("That email already has a portal sign-in"));
+            }
+            let user_id = repo.create_portal_user(client_id, email, &links::hash_password(password)).map_err(failed)?;
+            println!("Created portal user {} for client {}", user_id, client_id);
+        }
+        "export-clients" => {
+            expect_arguments(0..=1)?;
+            let state = open()?;
+            let clients = state.repository().list_clients().map_err(failed)?;
+            let json = serde_json::to_string_pretty(&clients).map_err(failed)?;
+            match arguments.first().map(String::as_str) {
+                None | Some("-") => println!("{}", json),
+                Some(file) => {
+                    let path = Path::new(file);
+                    write_private(path, &(json + "\n")).map_err(|e| failed(format!("Cannot write {}: {}", path.display(), e)))?;
+                    eprintln!("Exported {} clients to {}", clients.len(), path.display());
+                }
+            }
+        }
+        "import-clients" => {
+            expect_arguments(1..=1)?;
+            let json = read_input(&arguments[0])?;
+            let imported: Vec<Client> = serde_json::from_str(&json)
+                .map_err(|e| failed(format!("{} is not a JSON array of clients: {}", arguments[0], e)))?;
+
+            let state = open()?;
+            let repo = state.repository();
+            let ids = repo.import_clients(&imported).map_err(failed)?;
+            match (ids.first(), ids.last()) {
+                (Some(first), Some(last)) => println!("Imported {} clients as ids {} to {}", ids.len(), first, last),
+                _ => println!("Imported no clients"),
+            }
+        }
+        "verify" => {
+            expect_arguments(0..=0)?;
+            let state = open()?;
+            let settings = state.settings().clone();
+            let repo = state.repository();
+            let report = if repair {
+                let repaired = repo.repair_consistency(&settings).map_err(failed)?;
+                print_report(&repaired.report);
+                if let Some(batch) = &repaired.repair.trash_batch {
+                    println!("Moved {} orphans to {}", repaired.repair.trashed.len(), batch.display());
+                }
+                println!("Recorded {} checksums", repaired.repair.checksums_recorded);
+                repo.check_consistency(&settings).map_err(failed)?
+            } else {
+                let report = repo.check_consistency(&settings).map_err(failed)?;
+                print_report(&report);
+                report
+            };
+            if !report.is_consistent() {
+                return Err(failed(format!(
+                    "{} orphan folders, {} orphan files, {} missing files, {} changed files, {} returns without a client{}",
+                    report.orphan_directories.len(), report.orphan_files.len(), report.missing_files.len(),
+                    report.checksum_mismatches.len(), report.dangling_returns.len(),
+                    if repair { " remain" } else { "; --repair moves orphans to the trash" }
+                )));
+            }
+            println!("The database and the files under {} agree", settings.root_path.display());
+        }
+        "reindex" => {
+            expect_arguments(0..=0)?;
+            let state = open()?;
+            let root_path = state.settings().root_path.clone();
+            let repo = state.repository();
+            let (mut reindexed, mut missing) = (0, 0);
+            for document in repo.list_documents().map_err(failed)? {
+                match repo.reindex_document(&root_path, &document).map_err(failed)? {
+                    Some(_) => reindexed += 1,
+                    None => {
+                        eprintln!("Skipping {}/{}: the file is missing", document.client_id, document.file_name);
+                        missing += 1;
+                    }
+                }
+            }
+            println!("Reindexed {} documents; {} skipped", reindexed, missing);
+        }
+        "rotate-field-key" => {
+            expect_arguments(0..=0)?;
+            let state = AppState::new(settings()?).map_err(|e| usage(e.to_string()))?;
+            state.claim_field_key().map_err(|e| failed(format!("{}; stop the server before rotating", e)))?;
+            rotate_field_key(state)?;
+        }
+        "purge-trash" => {
+            expect_arguments(0..=1)?;
+            let days = match arguments.first() {
+                Some(days) => days.parse::<i64>().ok().filter(|days| *days >= 0)
+                    .ok_or_else(|| usage(format!("Not a number of days: {}", days)))?,
+                None => DEFAULT_TRASH_RETENTION_DAYS,
+            };
+            let settings = settings()?;
+            let summary = trash::purge_trash(&settings.root_path, Duration::days(days), Utc::now()).map_err(failed)?;
+            println!("Purged {} trash batches ({} files, {} bytes)", summary.batches, summary.files, summary.bytes);
+        }
+        "backup" => {
+            expect_arguments(0..=0)?;
+            let summary = open()?.backup_now().map_err(failed)?;
+            println!("Wrote {} ({} files, {} bytes archived in {} bytes)",
+                summary.path.display(), summary.files, summary.bytes, summary.archive_size);
+            for name in summary.removed {
+                println!("Removed {}", name);
+            }
+        }
+        "list-backups" => {
+            expect_arguments(0..=0)?;
+            let settings = settings()?;
+            for backup in config::list_backups(&settings.backup_path).map_err(failed)? {
+                println!("{}\t{}\t{}", backup.name, backup.created_at.to_rfc3339(), backup.size);
+            }
+        }
+        "verify-backup" => {
+            expect_arguments(1..=1)?;
+            let archive = find_archive(&settings()?, &arguments[0])?;
+            let manifest = config::verify_backup(&archive).map_err(failed)?;
+            println!("{} is intact: {} entries, schema version {}, taken {}",
+                archive.display(), manifest.entries.len(), manifest.schema_version, manifest.created_at.to_rfc3339());
+        }
+        "restore" => {
+            expect_arguments(2..=2)?;
+            let archive = find_archive(&settings()?, &arguments[0])?;
+            let report = config::restore_backup(&archive, Path::new(&arguments[1])).map_err(failed)?;
+            println!("Restored {} files ({} bytes) into {}; start the server with --root-path {}",
+                report.files, report.bytes, report.target.display(), report.target.display());
+        }
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        other => return Err(usage(format!("Unknown command {}", other))),
+    }
+    Ok(())
+}
+
+/// One line per problem, tab separated, its kind first.
+fn print_report(report: &consistency::ConsistencyReport) {
+    for orphan in &report.orphan_directories {
+        println!("orphan-folder\t{}\t{}", orphan.path.display(), orphan.reason);
+    }
+    for file in &report.orphan_files {
+        println!("orphan-file\t{}", file.display());
+    }
+    for missing in &report.missing_files {
+        println!("missing\t{}/{}\tdocument {}", missing.client_id, missing.file_name, missing.document_id);
+    }
+    for mismatch in &report.checksum_mismatches {
+        println!("changed\t{}/{}\tdocument {}\t{}", mismatch.client_id, mismatch.file_name, mismatch.document_id, mismatch.actual);
+    }
+    for dangling in &report.dangling_returns {
+        println!("dangling-return\treturn {}\tclient {}", dangling.tax_return_id, dangling.client_id);
+    }
+    if !report.unchecksummed.is_empty() {
+        println!("unchecksummed\t{} documents predate checksums; --repair records them", report.unchecksummed.len());
+    }
+}
+
+/// Creates or upgrades the configured database and reports its schema
+/// version before and after, creating the root and keys as well.
+fn migrate(settings: Settings) -> Result<(), Failure> {
+    let before = if settings.db_backend == DbBackend::Sqlite && settings.db_path.is_file() {
+        let conn = rusqlite::Connection::open(&settings.db_path).map_err(failed)?;
+        migrations::current_version(&conn).map_err(failed)?
+    } else {
+        0
+    };
+    let state = AppState::new(settings).map_err(|e| usage(e.to_string()))?;
+    let after = state.repository().schema_version().map_err(failed)?;
+    let settings = state.settings();
+    println!("Root {}", settings.root_path.display());
+    match settings.db_backend {
+        DbBackend::Sqlite => println!("Database {} at schema version {} (was {})", settings.db_path.display(), after, before),
+        DbBackend::Postgres => println!("PostgreSQL database at schema version {}", after),
+    }
+    println!("Keys {} and {}", settings.field_key_path.display(), settings.signing_key_path.display());
+    Ok(())
+}
+
+/// Re-encrypts under a new key, written beside the old one first so a
+/// failure part way leaves both. The old key goes to the trash rather than
+/// being deleted, so it can be put back until the trash is purged; backups
+/// carry their own copy of the key they were taken with. The signing key is
+/// not rotated: replacing it invalidates every outstanding public link.
+fn rotate_field_key(state: AppState) -> Result<(), Failure> {
+    let (root_path, key_path) = {
+        let settings = state.settings();
+        (settings.root_path.clone(), settings.field_key_path.clone())
+    };
+    let mut new_path = key_path.clone().into_os_string();
+    new_path.push(".new");
+    let new_path = PathBuf::from(new_path);
+
+    let new_key = FieldKey::generate();
+    new_key.save(&new_path).map_err(|e| failed(format!("Cannot write {}: {}", new_path.display(), e)))?;
+    let rotated = match state.repository().rotate_field_key(&new_key) {
+        Ok(rotated) => rotated,
+        Err(e) => {
+            let _ = fs::remove_file(&new_path);
+            return Err(failed(format!("Nothing was rotated: {}", e)));
+        }
+    };
+
+    let kept = trash::TrashBatch::create(&root_path, Utc::now())
+        .and_then(|batch| batch.put(&key_path, Path::new(key_path.file_name().unwrap_or_default())))
+        .and_then(|kept| fs::rename(&new_path, &key_path).map(|_| kept))
+        .map_err(|e| failed(format!(
+            "Rotated {} values, but the new key is still at {}; move it to {} before starting the server: {}",
+            rotated, new_path.display(), key_path.display(), e
+        )))?;
+    println!("Rotated {} values to a new key at {}; the old key is at {}", rotated, key_path.display(), kept.display());
+    Ok(())
+}
+
+/// Writes `contents` to `path`, readable by the owner only from the moment
+/// the file exists.
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to a new file; an existing one is narrowed first
+    links::restrict_permissions(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+/// The contents of `file`, or of standard input for `-`.
+fn read_input(file: &str) -> Result<String, Failure> {
+    let mut contents = String::new();
+    if file == "-" {
+        io::stdin().read_to_string(&mut contents).map_err(failed)?;
+    } else {
+        contents = fs::read_to_string(file).map_err(|e| failed(format!("Cannot read {}: {}", file, e)))?;
+    }
+    Ok(contents)
+}
+
+/// `archive` as given if it names a file, otherwise an archive of that name
+/// in the backup directory.
+fn find_archive(settings: &Settings, archive: &str) -> Result<PathBuf, Failure> {
+    let path = PathBuf::from(archive);
+    if path.is_file() {
+        return Ok(path);
+    }
+    config::find_backup(&settings.backup_path, archive).map_err(failed)
+}
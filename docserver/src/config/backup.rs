@@ -0,0 +1,712 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, TryLockError};
+use std::thread;
+use std::time::Duration;
+
+use super::relocate::take_inventory;
+use super::{AppState, DbBackend, RelocationError, Settings, DEFAULT_DB_FILENAME, FIELD_KEY_FILENAME, SIGNING_KEY_FILENAME, TRASH_DIRNAME};
+use crate::db::{migrations, DbConnection};
+use crate::links::{restrict_permissions, to_hex};
+
+// Archives are named `docserver-<UTC time>.tar.gz`, so names sort by age
+const ARCHIVE_PREFIX: &str = "docserver-";
+const ARCHIVE_SUFFIX: &str = ".tar.gz";
+const ARCHIVE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+const MANIFEST_NAME: &str = "manifest.json";
+const MANIFEST_FORMAT: u32 = 1;
+// Where each part of the root is kept inside an archive
+const DATABASE_DIR: &str = "database";
+const KEYS_DIR: &str = "keys";
+const FILES_DIR: &str = "files";
+// Wait before retrying a database copy that found the source locked
+const BACKUP_STEP_RETRY: Duration = Duration::from_millis(100);
+// A failed scheduled backup is retried this much later rather than at once
+const SCHEDULE_RETRY: Duration = Duration::from_secs(15 * 60);
+// The schedule thread wakes this often to notice a stopped server
+const SCHEDULE_TICK: Duration = Duration::from_secs(60);
+
+/// Lists every entry of an archive with its checksum. Stored in the archive
+/// as `manifest.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: u32,
+    pub created_at: DateTime<Utc>,
+    // Migration version of the database copy
+    pub schema_version: i64,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// An archive in the backup directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupSummary {
+    pub name: String,
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    pub schema_version: i64,
+    // Entries archived, counting the database and keys
+    pub files: usize,
+    pub bytes: u64,
+    pub archive_size: u64,
+    // Older archives removed to stay within `backup_keep`
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+    pub archive: PathBuf,
+    pub target: PathBuf,
+    pub created_at: DateTime<Utc>,
+    pub schema_version: i64,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    InProgress,
+    // PostgreSQL databases are backed up with the server's own tools
+    NotSqlite,
+    NotFound(String),
+    Io(String, io::Error),
+    Database(String),
+    Inventory(RelocationError),
+    // A document changed size while it was being archived
+    Changed(PathBuf),
+    // The archive is damaged or was not written by a backup
+    Invalid(String),
+    Missing(String),
+    ChecksumMismatch(String),
+    // Written by a newer server whose migrations this one lacks
+    NewerSchema(i64),
+    NotADirectory(PathBuf),
+    TargetNotEmpty(PathBuf),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::InProgress => write!(f, "A backup is already running"),
+            BackupError::NotSqlite => write!(f, "The database is on PostgreSQL; back it up with pg_dump"),
+            BackupError::NotFound(name) => write!(f, "Backup {} not found", name),
+            BackupError::Io(action, e) => write!(f, "Failed to {}: {}", action, e),
+            BackupError::Database(e) => write!(f, "Database backup failed: {}", e),
+            BackupError::Inventory(e) => write!(f, "Failed to list the storage root: {}", e),
+            BackupError::Changed(path) => write!(f, "{} changed while it was being backed up", path.display()),
+            BackupError::Invalid(message) => write!(f, "Invalid backup archive: {}", message),
+            BackupError::Missing(path) => write!(f, "Backup archive is missing {}", path),
+            BackupError::ChecksumMismatch(path) => write!(f, "{} in the backup does not match its checksum", path),
+            BackupError::NewerSchema(version) => {
+                write!(f, "Backup has schema version {}; this server only knows up to {}", version, migrations::latest_version())
+            }
+            BackupError::NotADirectory(path) => write!(f, "Invalid path: {} is not a directory", path.display()),
+            BackupError::TargetNotEmpty(path) => write!(f, "{} is not empty; backups are restored into an empty root", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<rusqlite::Error> for BackupError {
+    fn from(e: rusqlite::Error) -> Self {
+        BackupError::Database(e.to_string())
+    }
+}
+
+fn io_error(action: impl Into<String>) -> impl FnOnce(io::Error) -> BackupError {
+    let action = action.into();
+    move |e| BackupError::Io(action, e)
+}
+
+/// Checksums whatever is read through it.
+struct Hashing<R> {
+    inner: R,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<R: Read> Hashing<R> {
+    fn new(inner: R) -> Self {
+        Hashing { inner, hasher: Sha256::new(), bytes: 0 }
+    }
+
+    fn finish(self) -> (u64, String) {
+        (self.bytes, to_hex(&self.hasher.finalize()))
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+fn archive_name(created_at: DateTime<Utc>) -> String {
+    format!("{}{}{}", ARCHIVE_PREFIX, created_at.format(ARCHIVE_TIME_FORMAT), ARCHIVE_SUFFIX)
+}
+
+/// When the archive called `name` was taken, or `None` if it is not one.
+fn archive_time(name: &str) -> Option<DateTime<Utc>> {
+    let stamp = name.strip_prefix(ARCHIVE_PREFIX)?.strip_suffix(ARCHIVE_SUFFIX)?;
+    NaiveDateTime::parse_from_str(stamp, ARCHIVE_TIME_FORMAT).ok().map(|t| t.and_utc())
+}
+
+/// The archives in `dir`, newest first. A missing directory has none.
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>, BackupError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(BackupError::Io(format!("read {}", dir.display()), e)),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(io_error(format!("read {}", dir.display())))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(created_at) = archive_time(&name) else {
+            continue;
+        };
+        let size = entry.metadata().map_err(io_error(format!("inspect {}", name)))?.len();
+        backups.push(BackupInfo { name, created_at, size });
+    }
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// The path of the archive called `name` in `dir`.
+pub fn find_backup(dir: &Path, name: &str) -> Result<PathBuf, BackupError> {
+    let path = dir.join(name);
+    if archive_time(name).is_none() || !path.is_file() {
+        return Err(BackupError::NotFound(name.to_string()));
+    }
+    Ok(path)
+}
+
+/// Removes all but the newest `keep` archives in `dir`, returning the names
+/// of those removed.
+pub fn prune_backups(dir: &Path, keep: u32) -> Result<Vec<String>, BackupError> {
+    let mut removed = Vec::new();
+    for backup in list_backups(dir)?.into_iter().skip(keep as usize) {
+        fs::remove_file(dir.join(&backup.name)).map_err(io_error(format!("remove {}", backup.name)))?;
+        removed.push(backup.name);
+    }
+    Ok(removed)
+}
+
+/// Writes an archive of the database, keys and documents into
+/// `settings.backup_path`. The database is copied with SQLite's backup API
+/// from `conn`, so writers carry on meanwhile. Documents are read after the
+/// copy, so any that arrive during a backup are at worst kept without their
+/// row, never listed without their file. The archive only takes its final
+/// name once it is complete.
+pub fn create_backup(settings: &Settings, conn: &Connection) -> Result<BackupSummary, BackupError> {
+    let dir = &settings.backup_path;
+    fs::create_dir_all(dir).map_err(io_error(format!("create {}", dir.display())))?;
+    let created_at = Utc::now();
+    let name = archive_name(created_at);
+    let partial = dir.join(format!(".{}.partial", name));
+    let database_copy = dir.join(format!(".{}.db", name));
+
+    let written = write_archive(settings, conn, created_at, &partial, &database_copy);
+    if let Err(e) = fs::remove_file(&database_copy) {
+        if e.kind() != io::ErrorKind::NotFound {
+            eprintln!("Failed to remove {}: {}", database_copy.display(), e);
+        }
+    }
+    let manifest = match written {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+    };
+
+    let path = dir.join(&name);
+    fs::rename(&partial, &path).map_err(io_error(format!("rename {}", partial.display())))?;
+    let archive_size = fs::metadata(&path).map_err(io_error(format!("inspect {}", path.display())))?.len();
+    Ok(BackupSummary {
+        name,
+        path,
+        created_at,
+        schema_version: manifest.schema_version,
+        files: manifest.entries.len(),
+        bytes: manifest.entries.iter().map(|entry| entry.size).sum(),
+        archive_size,
+        removed: Vec::new(),
+    })
+}
+
+/// Creates or truncates `path` readable by its owner only.
+fn create_private(path: &Path) -> Result<File, BackupError> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let file = options.open(path).map_err(io_error(format!("create {}", path.display())))?;
+    // The mode only applies to a new file; a leftover one is narrowed first
+    restrict_permissions(path).map_err(io_error(format!("protect {}", path.display())))?;
+    Ok(file)
+}
+
+fn write_archive(
+    settings: &Settings,
+    conn: &Connection,
+    created_at: DateTime<Utc>,
+    partial: &Path,
+    database_copy: &Path,
+) -> Result<BackupManifest, BackupError> {
+    // One step copies every page under a single read transaction, which
+    // in WAL mode does not hold up writers; copying in several steps would
+    // start over each time another connection wrote. Both files hold the
+    // field key's data, so they are created owner-only before anything is
+    // written to them
+    create_private(database_copy)?;
+    let mut copy = Connection::open(database_copy)?;
+    {
+        let backup = Backup::new(conn, &mut copy)?;
+        while backup.step(-1)? != StepResult::Done {
+            thread::sleep(BACKUP_STEP_RETRY);
+        }
+    }
+    let schema_version = check_database(&copy)?;
+    drop(copy);
+
+    let file = create_private(partial)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let mut entries = Vec::new();
+    let mut append = |archive: &mut tar::Builder<_>, name: PathBuf, source: &Path| -> Result<(), BackupError> {
+        let file = File::open(source).map_err(io_error(format!("read {}", source.display())))?;
+        let size = file.metadata().map_err(io_error(format!("inspect {}", source.display())))?.len();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o600);
+        header.set_mtime(created_at.timestamp().max(0) as u64);
+
+        let mut reader = Hashing::new(file.take(size));
+        archive.append_data(&mut header, &name, &mut reader)
+            .map_err(io_error(format!("archive {}", source.display())))?;
+        let (bytes, sha256) = reader.finish();
+        if bytes != size {
+            return Err(BackupError::Changed(source.to_path_buf()));
+        }
+        entries.push(ManifestEntry { path: name.to_string_lossy().to_string(), size, sha256 });
+        Ok(())
+    };
+
+    append(&mut archive, Path::new(DATABASE_DIR).join(DEFAULT_DB_FILENAME), database_copy)?;
+    append(&mut archive, Path::new(KEYS_DIR).join(FIELD_KEY_FILENAME), &settings.field_key_path)?;
+    append(&mut archive, Path::new(KEYS_DIR).join(SIGNING_KEY_FILENAME), &settings.signing_key_path)?;
+
+    // The database and keys are archived above; earlier backups and the
+    // trash are not archived at all
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let root = canonical(&settings.root_path);
+    let skipped = [&settings.db_path, &settings.field_key_path, &settings.signing_key_path].map(|p| canonical(p));
+    let backup_dir = canonical(&settings.backup_path);
+    let trash_dir = root.join(TRASH_DIRNAME);
+    let inventory = take_inventory(&root, &settings.db_path).map_err(BackupError::Inventory)?;
+    for (file, _) in &inventory.files {
+        let path = root.join(file);
+        if skipped.contains(&path) || path.starts_with(&backup_dir) || path.starts_with(&trash_dir) {
+            continue;
+        }
+        append(&mut archive, Path::new(FILES_DIR).join(file), &path)?;
+    }
+
+    let manifest = BackupManifest { format: MANIFEST_FORMAT, created_at, schema_version, entries };
+    let contents = serde_json::to_vec_pretty(&manifest).expect("A manifest always serializes");
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(created_at.timestamp().max(0) as u64);
+    archive.append_data(&mut header, MANIFEST_NAME, contents.as_slice())
+        .map_err(io_error("archive the manifest"))?;
+
+    let file = archive.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(io_error(format!("write {}", partial.display())))?;
+    file.sync_all().map_err(io_error(format!("write {}", partial.display())))?;
+    Ok(manifest)
+}
+
+/// Runs SQLite's integrity check, returning the schema version if it passes.
+fn check_database(conn: &Connection) -> Result<i64, BackupError> {
+    let result: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if result != "ok" {
+        return Err(BackupError::Database(format!("integrity check failed: {}", result)));
+    }
+    Ok(migrations::current_version(conn)?)
+}
+
+/// Where an archive entry goes under a restored root: the database and keys
+/// at their default names, documents where they were.
+fn restored_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let mut components = path.components();
+    let section = components.next()?.as_os_str().to_str()?;
+    let rest = components.as_path();
+    match section {
+        DATABASE_DIR if rest == Path::new(DEFAULT_DB_FILENAME) => Some(rest.to_path_buf()),
+        KEYS_DIR if rest == Path::new(FIELD_KEY_FILENAME) || rest == Path::new(SIGNING_KEY_FILENAME) => {
+            Some(rest.to_path_buf())
+        }
+        FILES_DIR if rest.components().next().is_some() => Some(rest.to_path_buf()),
+        _ => None,
+    }
+}
+
+/// Reads every entry of `archive`, passing each one's contents to `visit`,
+/// and checks them all against the manifest.
+fn read_archive(
+    archive: &Path,
+    mut visit: impl FnMut(&str, &mut dyn Read) -> Result<(), BackupError>,
+) -> Result<BackupManifest, BackupError> {
+    let file = File::open(archive).map_err(io_error(format!("read {}", archive.display())))?;
+    let mut tar = tar::Archive::new(GzDecoder::new(file));
+    let read_error = || io_error(format!("read {}", archive.display()));
+
+    let mut manifest = None;
+    let mut seen: BTreeMap<String, (u64, String)> = BTreeMap::new();
+    for entry in tar.entries().map_err(read_error())? {
+        let mut entry = entry.map_err(read_error())?;
+        let name = entry.path().map_err(read_error())?.to_string_lossy().to_string();
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            return Err(BackupError::Invalid(format!("{} is not a regular file", name)));
+        }
+        if name == MANIFEST_NAME {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).map_err(read_error())?;
+            let parsed: BackupManifest = serde_json::from_str(&contents)
+                .map_err(|e| BackupError::Invalid(format!("unreadable manifest: {}", e)))?;
+            manifest = Some(parsed);
+            continue;
+        }
+        if restored_path(&name).is_none() {
+            return Err(BackupError::Invalid(format!("unexpected entry {}", name)));
+        }
+
+        let mut reader = Hashing::new(&mut entry);
+        visit(&name, &mut reader)?;
+        io::copy(&mut reader, &mut io::sink()).map_err(read_error())?;
+        if seen.insert(name.clone(), reader.finish()).is_some() {
+            return Err(BackupError::Invalid(format!("{} appears twice", name)));
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| BackupError::Invalid("no manifest".to_string()))?;
+    if manifest.format != MANIFEST_FORMAT {
+        return Err(BackupError::Invalid(format!("unknown manifest format {}", manifest.format)));
+    }
+    for entry in &manifest.entries {
+        match seen.remove(&entry.path) {
+            None => return Err(BackupError::Missing(entry.path.clone())),
+            Some((size, sha256)) if size != entry.size || sha256 != entry.sha256 => {
+                return Err(BackupError::ChecksumMismatch(entry.path.clone()));
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(extra) = seen.keys().next() {
+        return Err(BackupError::Invalid(format!("{} is not in the manifest", extra)));
+    }
+    Ok(manifest)
+}
+
+/// Reads the whole archive and checks every entry against its manifest.
+pub fn verify_backup(archive: &Path) -> Result<BackupManifest, BackupError> {
+    read_archive(archive, |_, _| Ok(()))
+}
+
+/// Unpacks `archive` into `target`, which must be empty or not yet exist.
+/// Every entry is checked against the manifest and the database has to pass
+/// SQLite's integrity check; if anything fails, what was unpacked is removed
+/// again. The restored root keeps the database and keys at their default
+/// names, so a server pointed at it with `--root-path` opens it as it was.
+pub fn restore_backup(archive: &Path, target: &Path) -> Result<RestoreReport, BackupError> {
+    let created = !target.exists();
+    if created {
+        fs::create_dir_all(target).map_err(io_error(format!("create {}", target.display())))?;
+    } else if !target.is_dir() {
+        return Err(BackupError::NotADirectory(target.to_path_buf()));
+    } else if fs::read_dir(target).map_err(io_error(format!("read {}", target.display())))?.next().is_some() {
+        return Err(BackupError::TargetNotEmpty(target.to_path_buf()));
+    }
+
+    let restored = unpack(archive, target);
+    if restored.is_err() {
+        let emptied = if created {
+            fs::remove_dir_all(target)
+        } else {
+            fs::read_dir(target).and_then(|entries| entries.into_iter().try_for_each(|entry| {
+                let path = entry?.path();
+                if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) }
+            }))
+        };
+        if let Err(e) = emptied {
+            eprintln!("Failed to remove the partial restore in {}: {}", target.display(), e);
+        }
+    }
+    restored
+}
+
+fn unpack(archive: &Path, target: &Path) -> Result<RestoreReport, BackupError> {
+    let manifest = read_archive(archive, |name, reader| {
+        let relative = restored_path(name).expect("Entry names are checked before they are visited");
+        let destination = target.join(&relative);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(io_error(format!("create {}", parent.display())))?;
+        }
+        // A name given twice would otherwise overwrite the first copy
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&destination)
+            .map_err(io_error(format!("create {}", destination.display())))?;
+        io::copy(reader, &mut file).map_err(io_error(format!("write {}", destination.display())))?;
+        file.sync_all().map_err(io_error(format!("write {}", destination.display())))?;
+        if name.starts_with(KEYS_DIR) {
+            restrict_permissions(&destination).map_err(io_error(format!("protect {}", destination.display())))?;
+        }
+        Ok(())
+    })?;
+
+    let schema_version = check_database(&Connection::open(target.join(DEFAULT_DB_FILENAME))?)?;
+    if schema_version > migrations::latest_version() {
+        return Err(BackupError::NewerSchema(schema_version));
+    }
+    Ok(RestoreReport {
+        archive: archive.to_path_buf(),
+        target: target.to_path_buf(),
+        created_at: manifest.created_at,
+        schema_version,
+        files: manifest.entries.len(),
+        bytes: manifest.entries.iter().map(|entry| entry.size).sum(),
+    })
+}
+
+/// Backs up through the shared database pool, then prunes old archives.
+/// Holding the database lock throughout keeps a relocation from moving the
+/// root mid-backup.
+fn run_backup(
+    settings: &RwLock<Settings>,
+    db: &RwLock<Option<DbConnection>>,
+    running: &Mutex<()>,
+) -> Result<BackupSummary, BackupError> {
+    let _running = match running.try_lock() {
+        Ok(guard) => guard,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return Err(BackupError::InProgress),
+    };
+    let db = db.read().expect("Database lock poisoned");
+    let settings = settings.read().expect("Settings lock poisoned").clone();
+    if settings.db_backend == DbBackend::Postgres {
+        return Err(BackupError::NotSqlite);
+    }
+    let conn = db.as_ref()
+        .ok_or_else(|| BackupError::Database("the database is not open".to_string()))?
+        .conn()
+        .map_err(|e| BackupError::Database(e.to_string()))?;
+
+    let mut summary = create_backup(&settings, &conn)?;
+    summary.removed = prune_backups(&settings.backup_path, settings.backup_keep)?;
+    Ok(summary)
+}
+
+/// How long until the next scheduled backup: the interval after the newest
+/// archive, so restarts do not put it off.
+fn next_backup_in(settings: &Settings) -> Duration {
+    let interval = chrono::Duration::hours(settings.backup_interval_hours as i64);
+    let newest = list_backups(&settings.backup_path).ok().and_then(|backups| backups.first().map(|b| b.created_at));
+    newest.map_or(Duration::ZERO, |newest| (newest + interval - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+impl AppState {
+    /// Takes a backup now, as the schedule would.
+    pub fn backup_now(&self) -> Result<BackupSummary, BackupError> {
+        run_backup(&self.settings, &self.db, &self.backup_running)
+    }
+
+    /// [`backup_now`](Self::backup_now) on the blocking thread pool, for handlers.
+    pub async fn backup_in_background(&self) -> Result<BackupSummary, BackupError> {
+        let settings = Arc::clone(&self.settings);
+        let db = Arc::clone(&self.db);
+        let running = Arc::clone(&self.backup_running);
+        super::unblock(move || run_backup(&settings, &db, &running)).await
+    }
+
+    /// Starts a thread taking a backup every `backup_interval_hours`. It
+    /// stops by itself once this state is dropped.
+    pub fn start_backup_schedule(&self) {
+        if self.settings().backup_interval_hours == 0 {
+            return;
+        }
+        if self.settings().db_backend == DbBackend::Postgres {
+            eprintln!("Not scheduling backups: a PostgreSQL database is backed up with pg_dump");
+            return;
+        }
+        let settings = Arc::downgrade(&self.settings);
+        let db = Arc::downgrade(&self.db);
+        let running = Arc::downgrade(&self.backup_running);
+        let spawned = thread::Builder::new().name("backup-schedule".to_string()).spawn(move || loop {
+            let (Some(settings), Some(db), Some(running)) = (settings.upgrade(), db.upgrade(), running.upgrade()) else {
+                return;
+            };
+            let due_in = next_backup_in(&settings.read().expect("Settings lock poisoned"));
+            let wait = if due_in.is_zero() {
+                match run_backup(&settings, &db, &running) {
+                    Ok(_) => Duration::ZERO,
+                    Err(e) => {
+                        eprintln!("Scheduled backup failed: {}", e);
+                        SCHEDULE_RETRY
+                    }
+                }
+            } else {
+                due_in.min(SCHEDULE_TICK)
+            };
+            drop((settings, db, running));
+            thread::sleep(wait);
+        });
+        if let Err(e) = spawned {
+            eprintln!("Failed to start the backup schedule: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn state_in(dir: &Path) -> AppState {
+        let config = dir.join("docserver.toml");
+        fs::write(&config, "root_path = \"root\"\n").unwrap();
+        let args = vec!["--config".to_string(), config.to_string_lossy().to_string()];
+        AppState::new(Settings::resolve(&args, &|_| None).unwrap()).unwrap()
+    }
+
+    fn client_count(db_path: &Path) -> i64 {
+        let conn = Connection::open(db_path).unwrap();
+        conn.query_row("SELECT COUNT(*) FROM clients", [], |row| row.get(0)).unwrap()
+    }
+
+    /// Rewrites `archive` with one entry's contents replaced.
+    fn tamper(archive: &Path, entry_name: &str, contents: &[u8]) {
+        let mut original = tar::Archive::new(GzDecoder::new(File::open(archive).unwrap()));
+        let mut rewritten = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for entry in original.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_path_buf();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            if name == Path::new(entry_name) {
+                data = contents.to_vec();
+            }
+            let mut header = entry.header().clone();
+            header.set_size(data.len() as u64);
+            rewritten.append_data(&mut header, &name, data.as_slice()).unwrap();
+        }
+        let bytes = rewritten.into_inner().unwrap().finish().unwrap();
+        File::create(archive).unwrap().write_all(&bytes).unwrap();
+    }
+
+    #[test]
+    fn test_backup_and_restore_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let state = state_in(dir.path());
+        let root = state.get_root_path().unwrap();
+        fs::create_dir_all(root.join("1")).unwrap();
+        fs::write(root.join("1").join("w2.pdf"), "%PDF-1.4 wages").unwrap();
+
+        let summary = state.backup_now().unwrap();
+        let backup_dir = state.settings().backup_path.clone();
+        assert_eq!(list_backups(&backup_dir).unwrap().len(), 1);
+        // Only the archive is left behind; staging files are removed
+        assert_eq!(fs::read_dir(&backup_dir).unwrap().count(), 1);
+        // The archive holds the field key, so only its owner may read it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&summary.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let manifest = verify_backup(&summary.path).unwrap();
+        let names: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert!(names.contains(&"database/docstore.db") && names.contains(&"keys/field.key"));
+        assert!(names.contains(&"files/1/w2.pdf"));
+        assert!(!names.iter().any(|name| name.contains("backups")));
+
+        let target = dir.path().join("restored");
+        let report = restore_backup(&summary.path, &target).unwrap();
+        assert_eq!(report.files, manifest.entries.len());
+        assert_eq!(fs::read_to_string(target.join("1").join("w2.pdf")).unwrap(), "%PDF-1.4 wages");
+        assert_eq!(fs::read(target.join(FIELD_KEY_FILENAME)).unwrap(), fs::read(&state.settings().field_key_path).unwrap());
+        assert_eq!(client_count(&target.join(DEFAULT_DB_FILENAME)), client_count(&state.settings().db_path));
+
+        assert!(matches!(restore_backup(&summary.path, &target), Err(BackupError::TargetNotEmpty(_))));
+    }
+
+    #[test]
+    fn test_damaged_backup_is_not_restored() {
+        let dir = TempDir::new().unwrap();
+        let state = state_in(dir.path());
+        fs::write(state.get_root_path().unwrap().join("notes.txt"), "original").unwrap();
+        let summary = state.backup_now().unwrap();
+
+        tamper(&summary.path, "files/notes.txt", b"altered!");
+        assert!(matches!(verify_backup(&summary.path), Err(BackupError::ChecksumMismatch(path)) if path == "files/notes.txt"));
+
+        // What was unpacked before the damage was found is removed again
+        let target = dir.path().join("restored");
+        fs::create_dir(&target).unwrap();
+        assert!(matches!(restore_backup(&summary.path, &target), Err(BackupError::ChecksumMismatch(_))));
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_prune_keeps_newest_archives() {
+        let dir = TempDir::new().unwrap();
+        let names = [
+            "docserver-20240101T000000.000Z.tar.gz",
+            "docserver-20240102T000000.000Z.tar.gz",
+            "docserver-20240103T000000.000Z.tar.gz",
+        ];
+        for name in names.iter().chain(&["notes.txt"]) {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        assert_eq!(prune_backups(dir.path(), 2).unwrap(), vec![names[0].to_string()]);
+        let left: Vec<String> = list_backups(dir.path()).unwrap().into_iter().map(|b| b.name).collect();
+        assert_eq!(left, vec![names[2].to_string(), names[1].to_string()]);
+        assert!(dir.path().join("notes.txt").exists());
+        assert!(matches!(find_backup(dir.path(), "../notes.txt"), Err(BackupError::NotFound(_))));
+    }
+}
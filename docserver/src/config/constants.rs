@@ -1,9 +1,48 @@
-use std::path::PathBuf;
-
 // Database settings
 pub const DEFAULT_DB_FILENAME: &str = "docstore.db";
+// Pooled connections; each waits up to the busy timeout for SQLite's write lock
+pub const DEFAULT_DB_POOL_SIZE: u32 = 8;
+pub const MAX_DB_POOL_SIZE: u32 = 64;
+pub const DEFAULT_DB_BUSY_TIMEOUT_MS: u64 = 5_000;
+// How long a request waits for a free connection before failing
+pub const DEFAULT_DB_POOL_TIMEOUT_MS: u64 = 30_000;
+
+// Backups: archives of the database, keys and documents
+pub const BACKUP_DIRNAME: &str = "backups";
+pub const DEFAULT_BACKUP_INTERVAL_HOURS: u64 = 24;
+pub const DEFAULT_BACKUP_KEEP: u32 = 7;
+
+// Files the admin tool removes wait here before they are purged
+pub const TRASH_DIRNAME: &str = ".trash";
+pub const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+// Configuration file, layered under DOCSERVER_* variables and flags
+pub const CONFIG_FILENAME: &str = "docserver.toml";
+
+// Upload settings: the largest file accepted, in megabytes
+pub const DEFAULT_UPLOAD_LIMIT_MB: u64 = 10;
+pub const MAX_UPLOAD_LIMIT_MB: u64 = 1024;
+
+// Document classification settings
+pub const CLASSIFIER_RULES_FILENAME: &str = "classifier_rules.json";
+
+// Tax rules settings: one parameter file per tax year
+pub const TAX_RULES_DIRNAME: &str = "tax_rules";
+
+// Encryption of sensitive columns such as social security numbers
+pub const FIELD_KEY_FILENAME: &str = "field.key";
+
+// Public link settings
+pub const SIGNING_KEY_FILENAME: &str = "signing.key";
+pub const DEFAULT_UPLOAD_LINK_HOURS: i64 = 72;
+pub const DEFAULT_SHARE_LINK_HOURS: i64 = 7 * 24;
+// Requests allowed per window: per caller address, and per link
+pub const PUBLIC_REQUESTS_PER_ADDRESS: usize = 30;
+pub const PUBLIC_REQUESTS_PER_LINK: usize = 10;
+pub const PUBLIC_RATE_WINDOW_SECS: u64 = 600;
 
-// File storage settings
-pub fn default_root_path() -> PathBuf {
-    std::env::temp_dir().join("docstore_files")
-}
+// Client portal settings
+pub const PORTAL_SESSION_HOURS: i64 = 12;
+pub const PORTAL_PASSWORD_MIN_LEN: usize = 8;
+// Sign-in attempts allowed per email address per public rate window
+pub const PORTAL_LOGINS_PER_EMAIL: usize = 10;
@@ -1,38 +1,93 @@
+mod backup;
 mod constants;
+mod relocate;
+mod settings;
 
+pub use backup::*;
 pub use constants::*;
+pub use relocate::*;
+pub use settings::*;
 use serde::Serialize;
-use std::path::PathBuf;
-use std::sync::RwLock;
-use crate::db::DbConnection;
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use crate::db::{clients, DbConnection, FieldKey, OfficeRepository, PoolMetrics, PooledRepository, PostgresStore};
+use crate::links::{RateLimiter, SigningKey};
+use crate::tax::RuleSet;
 
 #[derive(Default)]
 pub struct AppState {
-    root_path: RwLock<Option<PathBuf>>,
-    db: RwLock<Option<DbConnection>>,
+    // Shared with the backup schedule thread
+    settings: Arc<RwLock<Settings>>,
+    // Shared with a running relocation, as are the tax rules, the relocation
+    // progress and the field key lock
+    root_path: Arc<RwLock<Option<PathBuf>>>,
+    // The SQLite pool; `None` while a relocation reopens it, and always
+    // with the PostgreSQL backend
+    db: Arc<RwLock<Option<DbConnection>>>,
+    postgres: Option<Arc<PostgresStore>>,
+    tax_rules: Arc<RwLock<RuleSet>>,
+    signing_key: SigningKey,
+    // Shared with blocking tasks that run repository work
+    field_key: Arc<FieldKey>,
+    rate_limiter: RateLimiter,
+    relocation: Arc<Mutex<Option<RelocationProgress>>>,
+    backup_running: Arc<Mutex<()>>,
+    // The field key file, locked while this process relies on the key
+    field_key_lock: Arc<Mutex<Option<File>>>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
-        // Create the directory if it doesn't exist
-        let default_path = default_root_path();
-        if !default_path.exists() {
-            std::fs::create_dir_all(&default_path).unwrap_or_else(|e| {
-                eprintln!("Failed to create default directory: {}", e);
-            });
+    /// Opens the storage root, database and keys named in `settings`. Any
+    /// that cannot be used is an error, so the server does not start half
+    /// configured.
+    pub fn new(settings: Settings) -> Result<Self, ConfigError> {
+        let root_path = settings.root_path.clone();
+        std::fs::create_dir_all(&root_path).map_err(|e| ConfigError::Invalid {
+            setting: "root_path",
+            message: format!("cannot create {}: {}", root_path.display(), e),
+        })?;
+
+        // Load per-year tax parameters; bad files are reported, not fatal
+        let tax_rules = RuleSet::load(&root_path.join(TAX_RULES_DIRNAME));
+        for error in &tax_rules.errors {
+            eprintln!("Skipping tax rules file {}: {}", error.file, error.problems.join("; "));
         }
 
-        // Initialize database connection
-        let db_path = default_path.join("docstore.db");
-        let db = DbConnection::new(db_path).unwrap_or_else(|e| {
-            eprintln!("Failed to create database connection: {}", e);
-            panic!("Database connection is required for the application to function");
-        });
+        // Kept beside the database by default, so public links survive restarts
+        let signing_key = SigningKey::load_or_create(&settings.signing_key_path).map_err(|e| ConfigError::Invalid {
+            setting: "signing_key_path",
+            message: format!("cannot load signing key {}: {}", settings.signing_key_path.display(), e),
+        })?;
 
-        AppState {
-            root_path: RwLock::new(Some(default_path)),
-            db: RwLock::new(Some(db)),
-        }
+        // Encrypts sensitive columns; without it they cannot be read back
+        let field_key = FieldKey::load_or_create(&settings.field_key_path).map_err(|e| ConfigError::Invalid {
+            setting: "field_key_path",
+            message: format!("cannot load field encryption key {}: {}", settings.field_key_path.display(), e),
+        })?;
+
+        let (db, postgres) = match settings.db_backend {
+            DbBackend::Sqlite => (Some(open_sqlite(&settings, &field_key)?), None),
+            DbBackend::Postgres => (None, Some(Arc::new(connect_postgres(&settings, &field_key)?))),
+        };
+
+        Ok(AppState {
+            settings: Arc::new(RwLock::new(settings)),
+            root_path: Arc::new(RwLock::new(Some(root_path))),
+            db: Arc::new(RwLock::new(db)),
+            postgres,
+            tax_rules: Arc::new(RwLock::new(tax_rules)),
+            signing_key,
+            field_key: Arc::new(field_key),
+            rate_limiter: RateLimiter::default(),
+            relocation: Arc::default(),
+            backup_running: Arc::default(),
+            field_key_lock: Arc::default(),
+        })
+    }
+
+    pub fn settings(&self) -> RwLockReadGuard<'_, Settings> {
+        self.settings.read().expect("Settings lock poisoned")
     }
 
     pub fn get_root_path(&self) -> Option<PathBuf> {
@@ -43,9 +98,169 @@ impl AppState {
         self.db.read().ok()
     }
 
-    pub fn set_root_path(&self, path: PathBuf) {
-        *self.root_path.write().unwrap() = Some(path);
+    /// Storage for one request, on a pooled connection. Handlers reach it
+    /// through [`Repository`](crate::db::Repository) and
+    /// [`OfficeRepository`](crate::db::OfficeRepository).
+    pub fn repository(&self) -> PooledRepository<'_> {
+        let db = self.get_db().expect("Database connection should be available");
+        PooledRepository::new(db, self.postgres.as_deref(), &self.field_key)
+    }
+
+    /// Runs `work` against a repository on the blocking thread pool, so the
+    /// queries it makes do not hold up the async workers.
+    pub async fn with_repository<T, F>(&self, work: F) -> T
+    where
+        F: FnOnce(&dyn OfficeRepository) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        let postgres = self.postgres.clone();
+        let field_key = Arc::clone(&self.field_key);
+        unblock(move || {
+            let db = db.read().expect("Database connection should be available");
+            work(&*PooledRepository::new(db, postgres.as_deref(), &field_key))
+        }).await
+    }
+
+    /// Runs `work` against the storage root and a repository on the blocking
+    /// thread pool, or returns `None` while no root is set. A relocation
+    /// cannot switch roots while `work` runs, so what it writes under the
+    /// root is not left behind.
+    pub async fn with_root<T, F>(&self, work: F) -> Option<T>
+    where
+        F: FnOnce(&Path, &dyn OfficeRepository) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let root_path = Arc::clone(&self.root_path);
+        self.with_repository(move |repo| {
+            // The relocation switches roots while holding the database
+            let root_path = root_path.read().expect("Root path lock poisoned").clone()?;
+            Some(work(&root_path, repo))
+        }).await
+    }
+
+    pub fn get_tax_rules(&self) -> std::sync::RwLockReadGuard<'_, RuleSet> {
+        self.tax_rules.read().expect("Tax rules lock poisoned")
     }
+
+    /// Re-reads the tax rules directory under the current root path.
+    pub fn reload_tax_rules(&self) -> Option<std::sync::RwLockReadGuard<'_, RuleSet>> {
+        let root_path = self.get_root_path()?;
+        Some(self.replace_tax_rules(RuleSet::load(&root_path.join(TAX_RULES_DIRNAME))))
+    }
+
+    /// [`reload_tax_rules`](Self::reload_tax_rules), reading the directory on
+    /// the blocking thread pool.
+    pub async fn reload_tax_rules_in_background(&self) -> Option<std::sync::RwLockReadGuard<'_, RuleSet>> {
+        let root_path = self.get_root_path()?;
+        let rules = unblock(move || RuleSet::load(&root_path.join(TAX_RULES_DIRNAME))).await;
+        Some(self.replace_tax_rules(rules))
+    }
+
+    fn replace_tax_rules(&self, rules: RuleSet) -> std::sync::RwLockReadGuard<'_, RuleSet> {
+        *self.tax_rules.write().expect("Tax rules lock poisoned") = rules;
+        self.get_tax_rules()
+    }
+
+    /// Connection pool usage, read on the blocking thread pool since a
+    /// relocation may be holding the database.
+    pub async fn database_metrics(&self) -> Option<PoolMetrics> {
+        if let Some(postgres) = &self.postgres {
+            return Some(postgres.metrics());
+        }
+        let db = Arc::clone(&self.db);
+        unblock(move || db.read().ok()?.as_ref().map(DbConnection::metrics)).await
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    pub fn field_key(&self) -> &FieldKey {
+        &self.field_key
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Holds a shared lock on the field key file for as long as this state
+    /// lives, so the key cannot be rotated while it is in use. Fails while a
+    /// rotation is running.
+    pub fn share_field_key(&self) -> Result<(), ConfigError> {
+        self.lock_field_key(false)
+    }
+
+    /// Holds the field key file for this process alone. Fails while a server
+    /// or another admin command is using the key.
+    pub fn claim_field_key(&self) -> Result<(), ConfigError> {
+        self.lock_field_key(true)
+    }
+
+    fn lock_field_key(&self, exclusive: bool) -> Result<(), ConfigError> {
+        let file = lock_key_file(&self.settings().field_key_path, exclusive)?;
+        *self.field_key_lock.lock().expect("Field key lock poisoned") = Some(file);
+        Ok(())
+    }
+}
+
+/// Opens the SQLite database at `db_path`, encrypting any social security
+/// numbers still stored as plaintext.
+fn open_sqlite(settings: &Settings, field_key: &FieldKey) -> Result<DbConnection, ConfigError> {
+    if let Some(parent) = settings.db_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| ConfigError::Invalid {
+            setting: "db_path",
+            message: format!("cannot create {}: {}", parent.display(), e),
+        })?;
+    }
+
+    let db = DbConnection::with_options(&settings.db_path, settings.pool_options()).map_err(|e| ConfigError::Invalid {
+        setting: "db_path",
+        message: format!("cannot open database {}: {}", settings.db_path.display(), e),
+    })?;
+    {
+        let conn = db.conn().expect("Failed to check out a database connection");
+        if let Err(e) = clients::encrypt_plaintext_ssns(&conn, field_key) {
+            eprintln!("Failed to encrypt stored social security numbers: {}", e);
+        }
+    }
+    Ok(db)
+}
+
+/// Connects to and migrates the PostgreSQL database at `db_url`.
+fn connect_postgres(settings: &Settings, field_key: &FieldKey) -> Result<PostgresStore, ConfigError> {
+    let invalid = |message: String| ConfigError::Invalid { setting: "db_url", message };
+    let config = settings.db_url.as_deref()
+        .ok_or_else(|| invalid("the postgres backend needs a connection URL".to_string()))?
+        .parse::<postgres::Config>()
+        .map_err(|e| invalid(e.to_string()))?;
+    PostgresStore::connect(config, field_key.clone(), settings.pool_options())
+        .map_err(|e| invalid(format!("cannot open database: {}", e)))
+}
+
+/// Opens the field key file at `path` and locks it, shared or exclusive.
+fn lock_key_file(path: &Path, exclusive: bool) -> Result<File, ConfigError> {
+    let invalid = |message: String| ConfigError::Invalid { setting: "field_key_path", message };
+    let file = File::open(path).map_err(|e| invalid(format!("cannot open {}: {}", path.display(), e)))?;
+    let locked = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+    match locked {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) if exclusive => {
+            Err(invalid(format!("{} is in use by a running server or admin command", path.display())))
+        }
+        Err(TryLockError::WouldBlock) => Err(invalid(format!("{} is being rotated", path.display()))),
+        Err(TryLockError::Error(e)) => Err(invalid(format!("cannot lock {}: {}", path.display(), e))),
+    }
+}
+
+/// Runs file or database work on the blocking thread pool rather than an
+/// async worker. A panic in `work` is raised again in the caller.
+pub async fn unblock<T, F>(work: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
 }
 
 #[derive(Serialize)]
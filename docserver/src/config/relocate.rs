@@ -0,0 +1,527 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::{lock_key_file, AppState, ConfigError, DbBackend, Settings, TAX_RULES_DIRNAME};
+use crate::db::DbConnection;
+use crate::tax::RuleSet;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelocationMode {
+    // The originals are removed once the new root is in use
+    #[default]
+    Move,
+    Copy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelocationStage {
+    Copying,
+    Verifying,
+    Switching,
+    RemovingOriginals,
+    Done,
+    RolledBack,
+}
+
+/// How far a root relocation has got. Kept after it finishes so the
+/// outcome can still be read.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelocationProgress {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub mode: RelocationMode,
+    pub stage: RelocationStage,
+    pub files_total: usize,
+    pub files_done: usize,
+    pub bytes_total: u64,
+    pub bytes_done: u64,
+    pub error: Option<String>,
+    // Originals that could not be removed after a successful move
+    pub warnings: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum RelocationError {
+    InProgress,
+    NotADirectory(PathBuf),
+    InsideRoot,
+    // The target already has an entry the root would be copied over
+    Conflict(PathBuf),
+    Unsupported(PathBuf),
+    Io(String, io::Error),
+    ChecksumMismatch(PathBuf),
+    Database(String),
+    Config(ConfigError),
+}
+
+impl fmt::Display for RelocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelocationError::InProgress => write!(f, "A relocation is already running"),
+            RelocationError::NotADirectory(path) => write!(f, "Invalid path: {} is not a directory", path.display()),
+            RelocationError::InsideRoot => write!(f, "Invalid path: the new root cannot be inside the current one"),
+            RelocationError::Conflict(path) => write!(f, "Target already contains {}", path.display()),
+            RelocationError::Unsupported(path) => {
+                write!(f, "{} is not a regular file or directory and cannot be relocated", path.display())
+            }
+            RelocationError::Io(action, e) => write!(f, "Failed to {}: {}", action, e),
+            RelocationError::ChecksumMismatch(path) => write!(f, "Copy of {} does not match the original", path.display()),
+            RelocationError::Database(e) => write!(f, "Failed to open the relocated database: {}", e),
+            RelocationError::Config(e) => write!(f, "Failed to save the new root: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RelocationError {}
+
+fn io_error(action: impl Into<String>) -> impl FnOnce(io::Error) -> RelocationError {
+    let action = action.into();
+    move |e| RelocationError::Io(action, e)
+}
+
+/// What is under the root: directories first, then files with their sizes,
+/// as paths relative to the root.
+pub(super) struct Inventory {
+    pub(super) top_level: Vec<PathBuf>,
+    pub(super) directories: Vec<PathBuf>,
+    pub(super) files: Vec<(PathBuf, u64)>,
+}
+
+pub(super) fn take_inventory(root: &Path, database: &Path) -> Result<Inventory, RelocationError> {
+    // A closing pool connection can still remove SQLite's side files while
+    // the root is walked; after the checkpoint they hold nothing to copy
+    let database = database.parent().and_then(|dir| dir.canonicalize().ok())
+        .zip(database.file_name())
+        .map(|(dir, name)| dir.join(name))
+        .unwrap_or_else(|| database.to_path_buf());
+    let side_files: Vec<PathBuf> = ["-wal", "-shm"].iter().map(|suffix| {
+        let mut name = database.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    }).collect();
+
+    let mut inventory = Inventory { top_level: Vec::new(), directories: Vec::new(), files: Vec::new() };
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let mut entries: Vec<_> = fs::read_dir(root.join(&relative))
+            .map_err(io_error(format!("read {}", root.join(&relative).display())))?
+            .collect::<Result<_, _>>()
+            .map_err(io_error(format!("read {}", root.join(&relative).display())))?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = relative.join(entry.file_name());
+            if side_files.contains(&entry.path()) {
+                continue;
+            }
+            let metadata = fs::symlink_metadata(entry.path()).map_err(io_error(format!("inspect {}", entry.path().display())))?;
+            if relative.as_os_str().is_empty() {
+                inventory.top_level.push(path.clone());
+            }
+            if metadata.is_dir() {
+                inventory.directories.push(path.clone());
+                pending.push(path);
+            } else if metadata.is_file() {
+                inventory.files.push((path, metadata.len()));
+            } else {
+                return Err(RelocationError::Unsupported(entry.path()));
+            }
+        }
+    }
+    inventory.directories.sort();
+    Ok(inventory)
+}
+
+/// SHA-256 of a file's contents.
+pub(crate) fn checksum(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// `path` moved from under `from` to under `to`; paths outside `from`, such
+/// as a database configured elsewhere, stay where they are.
+fn rebase(path: &Path, from: &[&Path], to: &Path) -> PathBuf {
+    from.iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .map(|relative| to.join(relative))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// The parts of [`AppState`] a relocation changes, shared with the blocking
+/// task that runs it.
+struct Relocator {
+    settings: Arc<RwLock<Settings>>,
+    root_path: Arc<RwLock<Option<PathBuf>>>,
+    db: Arc<RwLock<Option<DbConnection>>>,
+    tax_rules: Arc<RwLock<RuleSet>>,
+    progress: Arc<Mutex<Option<RelocationProgress>>>,
+    field_key_lock: Arc<Mutex<Option<File>>>,
+}
+
+impl AppState {
+    /// The latest relocation, running or finished.
+    pub fn relocation_progress(&self) -> Option<RelocationProgress> {
+        self.relocation.lock().expect("Relocation lock poisoned").clone()
+    }
+
+    /// Whether a relocation has started and not yet finished.
+    pub fn relocation_running(&self) -> bool {
+        self.relocation_progress().is_some_and(|progress| progress.finished_at.is_none())
+    }
+
+    fn relocator(&self) -> Relocator {
+        Relocator {
+            settings: Arc::clone(&self.settings),
+            root_path: Arc::clone(&self.root_path),
+            db: Arc::clone(&self.db),
+            tax_rules: Arc::clone(&self.tax_rules),
+            progress: Arc::clone(&self.relocation),
+            field_key_lock: Arc::clone(&self.field_key_lock),
+        }
+    }
+
+    /// Moves or copies everything under the storage root into `target`,
+    /// together with the database and keys kept there. The database is
+    /// closed for the duration, so requests that need it wait. Every copy is
+    /// checked against its original before the new root is opened and saved
+    /// to the config file; any failure before then removes the copies and
+    /// reopens the old database.
+    pub fn relocate_root(&self, target: &Path, mode: RelocationMode) -> Result<RelocationProgress, RelocationError> {
+        let relocator = self.relocator();
+        let (source, target) = relocator.begin(target, mode)?;
+        relocator.run(&source, &target, mode)
+    }
+
+    /// Starts [`relocate_root`](Self::relocate_root) on the blocking thread
+    /// pool and returns once it is under way. Its progress and outcome are
+    /// read from [`relocation_progress`](Self::relocation_progress). Must be
+    /// called from within the Tokio runtime.
+    pub fn start_relocation(&self, target: &Path, mode: RelocationMode) -> Result<RelocationProgress, RelocationError> {
+        let relocator = self.relocator();
+        let (source, target) = relocator.begin(target, mode)?;
+        let progress = self.relocation_progress().expect("Relocation progress was just recorded");
+        // The outcome is recorded in the progress
+        tokio::task::spawn_blocking(move || relocator.run(&source, &target, mode));
+        Ok(progress)
+    }
+}
+
+impl Relocator {
+    fn progress(&self) -> Option<RelocationProgress> {
+        self.progress.lock().expect("Relocation lock poisoned").clone()
+    }
+
+    fn update(&self, update: impl FnOnce(&mut RelocationProgress)) {
+        if let Some(progress) = self.progress.lock().expect("Relocation lock poisoned").as_mut() {
+            update(progress);
+        }
+    }
+
+    /// Checks `target` and records a new relocation as started. Returns the
+    /// canonical source and target roots.
+    fn begin(&self, target: &Path, mode: RelocationMode) -> Result<(PathBuf, PathBuf), RelocationError> {
+        let target = target.canonicalize().ok().filter(|t| t.is_dir())
+            .ok_or_else(|| RelocationError::NotADirectory(target.to_path_buf()))?;
+        let root_path = self.settings.read().expect("Settings lock poisoned").root_path.clone();
+        let source = root_path.canonicalize().unwrap_or(root_path);
+        if target != source && target.starts_with(&source) {
+            return Err(RelocationError::InsideRoot);
+        }
+
+        let mut relocation = self.progress.lock().expect("Relocation lock poisoned");
+        if relocation.as_ref().is_some_and(|p| p.finished_at.is_none()) {
+            return Err(RelocationError::InProgress);
+        }
+        *relocation = Some(RelocationProgress {
+            from: source.clone(),
+            to: target.clone(),
+            mode,
+            stage: RelocationStage::Copying,
+            files_total: 0,
+            files_done: 0,
+            bytes_total: 0,
+            bytes_done: 0,
+            error: None,
+            warnings: Vec::new(),
+            started_at: Utc::now(),
+            finished_at: None,
+        });
+        Ok((source, target))
+    }
+
+    /// Carries out a relocation recorded by [`begin`](Self::begin) and
+    /// records how it ended.
+    fn run(&self, source: &Path, target: &Path, mode: RelocationMode) -> Result<RelocationProgress, RelocationError> {
+        let result = if target == source {
+            Ok(())
+        } else {
+            let settings = self.settings.read().expect("Settings lock poisoned").clone();
+            self.relocate_locked(&settings, source, target, mode)
+        };
+        self.update(|progress| {
+            progress.finished_at = Some(Utc::now());
+            match &result {
+                Ok(()) => progress.stage = RelocationStage::Done,
+                Err(e) => {
+                    progress.stage = RelocationStage::RolledBack;
+                    progress.error = Some(e.to_string());
+                }
+            }
+        });
+        result.map(|()| self.progress().expect("Relocation progress was just recorded"))
+    }
+
+    fn relocate_locked(
+        &self,
+        settings: &Settings,
+        source: &Path,
+        target: &Path,
+        mode: RelocationMode,
+    ) -> Result<(), RelocationError> {
+        // Closing every connection leaves the database file complete on disk,
+        // with the write-ahead log folded back in
+        let mut db = self.db.write().expect("Database lock poisoned");
+        if let Some(conn) = db.as_ref().and_then(|db| db.conn().ok()) {
+            if let Err(e) = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)") {
+                eprintln!("Failed to checkpoint the database before relocating: {}", e);
+            }
+        }
+        drop(db.take());
+
+        let mut copied = Vec::new();
+        let switched = self.copy_and_switch(settings, source, target, &mut db, &mut copied);
+        let inventory = match switched {
+            Ok(inventory) => inventory,
+            Err(e) => {
+                for path in copied.iter().rev() {
+                    let removed = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+                    if let Err(remove_error) = removed {
+                        eprintln!("Failed to remove {} while rolling back: {}", path.display(), remove_error);
+                    }
+                }
+                if settings.db_backend == DbBackend::Sqlite {
+                    match DbConnection::with_options(&settings.db_path, settings.pool_options()) {
+                        Ok(conn) => *db = Some(conn),
+                        Err(reopen_error) => eprintln!("Failed to reopen {}: {}", settings.db_path.display(), reopen_error),
+                    }
+                }
+                return Err(e);
+            }
+        };
+        drop(db);
+        *self.tax_rules.write().expect("Tax rules lock poisoned") = RuleSet::load(&target.join(TAX_RULES_DIRNAME));
+        if let Err(e) = self.follow_field_key() {
+            self.update(|progress| progress.warnings.push(e.to_string()));
+        }
+
+        if mode == RelocationMode::Move {
+            self.update(|progress| progress.stage = RelocationStage::RemovingOriginals);
+            let originals = inventory.top_level.iter().map(|name| source.join(name));
+            for path in originals {
+                let removed = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+                if let Err(e) = removed {
+                    self.update(|progress| {
+                        progress.warnings.push(format!("Failed to remove {}: {}", path.display(), e))
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies and verifies the root, then opens the database and saves the
+    /// paths at the new location. `copied` collects what was created in
+    /// `target` so a failure can undo it.
+    fn copy_and_switch(
+        &self,
+        settings: &Settings,
+        source: &Path,
+        target: &Path,
+        db: &mut Option<DbConnection>,
+        copied: &mut Vec<PathBuf>,
+    ) -> Result<Inventory, RelocationError> {
+        let inventory = take_inventory(source, &settings.db_path)?;
+        if let Some(name) = inventory.top_level.iter().find(|name| target.join(name).exists()) {
+            return Err(RelocationError::Conflict(target.join(name)));
+        }
+        self.update(|progress| {
+            progress.files_total = inventory.files.len();
+            progress.bytes_total = inventory.files.iter().map(|(_, size)| size).sum();
+        });
+
+        for directory in &inventory.directories {
+            let destination = target.join(directory);
+            fs::create_dir(&destination).map_err(io_error(format!("create {}", destination.display())))?;
+            if inventory.top_level.contains(directory) {
+                copied.push(destination);
+            }
+        }
+        for (file, size) in &inventory.files {
+            let destination = target.join(file);
+            if inventory.top_level.contains(file) {
+                copied.push(destination.clone());
+            }
+            fs::copy(source.join(file), &destination).map_err(io_error(format!("copy {}", file.display())))?;
+            self.update(|progress| {
+                progress.files_done += 1;
+                progress.bytes_done += size;
+            });
+        }
+
+        self.update(|progress| progress.stage = RelocationStage::Verifying);
+        for (file, _) in &inventory.files {
+            let original = checksum(&source.join(file)).map_err(io_error(format!("read {}", file.display())))?;
+            let copy = checksum(&target.join(file)).map_err(io_error(format!("read the copy of {}", file.display())))?;
+            if original != copy {
+                return Err(RelocationError::ChecksumMismatch(file.clone()));
+            }
+        }
+
+        self.update(|progress| progress.stage = RelocationStage::Switching);
+        let roots = [source, settings.root_path.as_path()];
+        let relocated = Settings {
+            root_path: target.to_path_buf(),
+            db_path: rebase(&settings.db_path, &roots, target),
+            signing_key_path: rebase(&settings.signing_key_path, &roots, target),
+            field_key_path: rebase(&settings.field_key_path, &roots, target),
+            backup_path: rebase(&settings.backup_path, &roots, target),
+            ..settings.clone()
+        };
+        // A PostgreSQL database stays where it is; only the files move
+        let conn = match relocated.db_backend {
+            DbBackend::Sqlite => Some(
+                DbConnection::with_options(&relocated.db_path, relocated.pool_options())
+                    .map_err(|e| RelocationError::Database(e.to_string()))?,
+            ),
+            DbBackend::Postgres => None,
+        };
+        relocated.save_paths().map_err(RelocationError::Config)?;
+
+        *db = conn;
+        *self.settings.write().expect("Settings lock poisoned") = relocated;
+        *self.root_path.write().expect("Root path lock poisoned") = Some(target.to_path_buf());
+        Ok(inventory)
+    }
+
+    /// Moves a held shared lock to the field key at its current path, after
+    /// the relocation has copied it.
+    fn follow_field_key(&self) -> Result<(), ConfigError> {
+        let mut held = self.field_key_lock.lock().expect("Field key lock poisoned");
+        if held.is_some() {
+            let path = self.settings.read().expect("Settings lock poisoned").field_key_path.clone();
+            *held = Some(lock_key_file(&path, false)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn state_in(dir: &Path) -> AppState {
+        let config = dir.join("docserver.toml");
+        fs::write(&config, "root_path = \"old\"\n").unwrap();
+        let args = vec!["--config".to_string(), config.to_string_lossy().to_string()];
+        AppState::new(Settings::resolve(&args, &|_| None).unwrap()).unwrap()
+    }
+
+    fn client_count(state: &AppState) -> i64 {
+        let db_lock = state.get_db().unwrap();
+        let conn = db_lock.as_ref().unwrap().conn().unwrap();
+        conn.query_row("SELECT COUNT(*) FROM clients", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_relocation_moves_files_and_database() {
+        let dir = TempDir::new().unwrap();
+        let state = state_in(dir.path());
+        let old_root = dir.path().join("old").canonicalize().unwrap();
+        fs::create_dir_all(old_root.join("1")).unwrap();
+        fs::write(old_root.join("1").join("w2.pdf"), "%PDF-1.4 wages").unwrap();
+        let clients = client_count(&state);
+
+        let new_root = dir.path().join("new");
+        fs::create_dir(&new_root).unwrap();
+        let progress = state.relocate_root(&new_root, RelocationMode::Move).unwrap();
+        assert_eq!(progress.stage, RelocationStage::Done);
+        assert_eq!(progress.files_done, progress.files_total);
+        assert!(progress.warnings.is_empty());
+
+        let new_root = new_root.canonicalize().unwrap();
+        assert_eq!(fs::read_to_string(new_root.join("1").join("w2.pdf")).unwrap(), "%PDF-1.4 wages");
+        assert!(!old_root.join("1").exists() && !old_root.join("docstore.db").exists());
+        assert_eq!(state.get_root_path(), Some(new_root.clone()));
+        assert_eq!(state.settings().db_path, new_root.join("docstore.db"));
+        assert_eq!(client_count(&state), clients);
+
+        let saved: toml::Table = fs::read_to_string(dir.path().join("docserver.toml")).unwrap().parse().unwrap();
+        assert_eq!(saved["db_path"].as_str(), new_root.join("docstore.db").to_str());
+    }
+
+    #[test]
+    fn test_relocation_runs_until_it_finishes() {
+        let dir = TempDir::new().unwrap();
+        let state = state_in(dir.path());
+        let new_root = dir.path().join("new");
+        fs::create_dir(&new_root).unwrap();
+
+        // Started but not yet run, as while the background task copies
+        let relocator = state.relocator();
+        let (source, target) = relocator.begin(&new_root, RelocationMode::Copy).unwrap();
+        assert!(state.relocation_running());
+        assert!(matches!(state.relocate_root(dir.path(), RelocationMode::Copy), Err(RelocationError::InProgress)));
+
+        relocator.run(&source, &target, RelocationMode::Copy).unwrap();
+        assert!(!state.relocation_running());
+        assert_eq!(state.relocation_progress().unwrap().stage, RelocationStage::Done);
+        assert!(source.join("docstore.db").exists());
+    }
+
+    #[test]
+    fn test_failed_relocation_rolls_back() {
+        let dir = TempDir::new().unwrap();
+        let state = state_in(dir.path());
+        let old_root = state.get_root_path().unwrap();
+
+        let taken = dir.path().join("taken");
+        fs::create_dir_all(taken.join("tax_rules")).unwrap();
+        assert!(matches!(state.relocate_root(&taken, RelocationMode::Move), Err(RelocationError::Conflict(_))));
+        assert!(matches!(state.relocate_root(&old_root.join("tax_rules"), RelocationMode::Copy), Err(RelocationError::InsideRoot)));
+
+        // Saving the config file fails after everything has been copied
+        let blocked = dir.path().join("blocked");
+        fs::create_dir(&blocked).unwrap();
+        fs::remove_file(dir.path().join("docserver.toml")).unwrap();
+        fs::create_dir(dir.path().join("docserver.toml")).unwrap();
+        let error = state.relocate_root(&blocked, RelocationMode::Move).unwrap_err();
+        assert!(matches!(error, RelocationError::Config(_)));
+
+        assert_eq!(fs::read_dir(&blocked).unwrap().count(), 0);
+        assert_eq!(state.get_root_path(), Some(old_root.clone()));
+        assert!(old_root.join("docstore.db").exists());
+        assert!(client_count(&state) > 0);
+        assert_eq!(state.relocation_progress().unwrap().stage, RelocationStage::RolledBack);
+    }
+}
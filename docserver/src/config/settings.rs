@@ -0,0 +1,549 @@
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::constants::*;
+use crate::db::PoolOptions;
+
+/// Settings that can be given in the config file, as `DOCSERVER_*`
+/// environment variables, or as command-line flags. The key names match
+/// across all three: `upload_limit_mb` is `DOCSERVER_UPLOAD_LIMIT_MB` and
+/// `--upload-limit-mb`.
+const KEYS: [&str; 13] = [
+    "root_path",
+    "db_backend",
+    "db_path",
+    "db_url",
+    "upload_limit_mb",
+    "signing_key_path",
+    "field_key_path",
+    "db_pool_size",
+    "db_busy_timeout_ms",
+    "db_pool_timeout_ms",
+    "backup_path",
+    "backup_interval_hours",
+    "backup_keep",
+];
+
+/// Where clients, returns and documents are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbBackend {
+    // One database file beside the documents
+    #[default]
+    Sqlite,
+    // A server shared between offices, reached at `db_url`
+    Postgres,
+}
+
+impl DbBackend {
+    fn parse(value: &str) -> Option<DbBackend> {
+        match value {
+            "sqlite" => Some(DbBackend::Sqlite),
+            "postgres" => Some(DbBackend::Postgres),
+            _ => None,
+        }
+    }
+}
+
+/// One source of settings. Anything left unset falls through to the source
+/// below it: flags, then the environment, then the file, then defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsLayer {
+    pub root_path: Option<PathBuf>,
+    pub db_backend: Option<DbBackend>,
+    pub db_path: Option<PathBuf>,
+    pub db_url: Option<String>,
+    pub upload_limit_mb: Option<u64>,
+    pub signing_key_path: Option<PathBuf>,
+    pub field_key_path: Option<PathBuf>,
+    pub db_pool_size: Option<u32>,
+    pub db_busy_timeout_ms: Option<u64>,
+    pub db_pool_timeout_ms: Option<u64>,
+    pub backup_path: Option<PathBuf>,
+    pub backup_interval_hours: Option<u64>,
+    pub backup_keep: Option<u32>,
+}
+
+impl SettingsLayer {
+    /// This layer, with gaps filled from `base`.
+    fn over(self, base: SettingsLayer) -> SettingsLayer {
+        SettingsLayer {
+            root_path: self.root_path.or(base.root_path),
+            db_backend: self.db_backend.or(base.db_backend),
+            db_path: self.db_path.or(base.db_path),
+            db_url: self.db_url.or(base.db_url),
+            upload_limit_mb: self.upload_limit_mb.or(base.upload_limit_mb),
+            signing_key_path: self.signing_key_path.or(base.signing_key_path),
+            field_key_path: self.field_key_path.or(base.field_key_path),
+            db_pool_size: self.db_pool_size.or(base.db_pool_size),
+            db_busy_timeout_ms: self.db_busy_timeout_ms.or(base.db_busy_timeout_ms),
+            db_pool_timeout_ms: self.db_pool_timeout_ms.or(base.db_pool_timeout_ms),
+            backup_path: self.backup_path.or(base.backup_path),
+            backup_interval_hours: self.backup_interval_hours.or(base.backup_interval_hours),
+            backup_keep: self.backup_keep.or(base.backup_keep),
+        }
+    }
+
+    fn set(&mut self, key: &str, name: &str, value: &str) -> Result<(), ConfigError> {
+        let path = Some(PathBuf::from(value));
+        let number = |expected| value.trim().parse::<u64>().map_err(|_| ConfigError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            expected,
+        });
+        match key {
+            "root_path" => self.root_path = path,
+            "db_path" => self.db_path = path,
+            "db_url" => self.db_url = Some(value.to_string()),
+            "db_backend" => {
+                self.db_backend = Some(DbBackend::parse(value.trim()).ok_or_else(|| ConfigError::InvalidValue {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    expected: "sqlite or postgres",
+                })?);
+            }
+            "signing_key_path" => self.signing_key_path = path,
+            "field_key_path" => self.field_key_path = path,
+            "backup_path" => self.backup_path = path,
+            "upload_limit_mb" => self.upload_limit_mb = Some(number("a whole number of megabytes")?),
+            "db_pool_size" => {
+                let size = number("a whole number of connections")?;
+                self.db_pool_size = Some(u32::try_from(size).unwrap_or(u32::MAX));
+            }
+            "db_busy_timeout_ms" => self.db_busy_timeout_ms = Some(number("a whole number of milliseconds")?),
+            "db_pool_timeout_ms" => self.db_pool_timeout_ms = Some(number("a whole number of milliseconds")?),
+            "backup_interval_hours" => self.backup_interval_hours = Some(number("a whole number of hours")?),
+            "backup_keep" => {
+                let keep = number("a whole number of backups")?;
+                self.backup_keep = Some(u32::try_from(keep).unwrap_or(u32::MAX));
+            }
+            _ => unreachable!("unknown settings key {}", key),
+        }
+        Ok(())
+    }
+
+    /// Paths in a config file are relative to the file's own directory.
+    fn relative_to(mut self, directory: &Path) -> SettingsLayer {
+        for path in [
+            &mut self.root_path,
+            &mut self.db_path,
+            &mut self.signing_key_path,
+            &mut self.field_key_path,
+            &mut self.backup_path,
+        ].into_iter().flatten() {
+            if path.is_relative() {
+                *path = directory.join(&*path);
+            }
+        }
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    File(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    // A flag or environment variable whose value cannot be read
+    InvalidValue { name: String, value: String, expected: &'static str },
+    UnknownArgument(String),
+    MissingValue(String),
+    // A setting that reads fine but cannot be used
+    Invalid { setting: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::File(path, e) => write!(f, "Cannot access config file {}: {}", path.display(), e),
+            ConfigError::Parse(path, message) => write!(f, "Invalid config file {}: {}", path.display(), message),
+            ConfigError::InvalidValue { name, value, expected } => {
+                write!(f, "{} must be {}, not \"{}\"", name, expected, value)
+            }
+            ConfigError::UnknownArgument(argument) => write!(
+                f,
+                "Unknown argument {}; expected --config or one of {}",
+                argument,
+                KEYS.map(flag_name).join(", ")
+            ),
+            ConfigError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ConfigError::Invalid { setting, message } => write!(f, "Invalid {}: {}", setting, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The settings the server runs with, every path absolute.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    // Where the root path is saved when it is changed through the API
+    pub config_file: PathBuf,
+    pub root_path: PathBuf,
+    pub db_backend: DbBackend,
+    pub db_path: PathBuf,
+    // Connection string for the PostgreSQL backend; unused with SQLite
+    pub db_url: Option<String>,
+    pub upload_limit_mb: u64,
+    pub signing_key_path: PathBuf,
+    pub field_key_path: PathBuf,
+    pub db_pool_size: u32,
+    pub db_busy_timeout_ms: u64,
+    pub db_pool_timeout_ms: u64,
+    pub backup_path: PathBuf,
+    // Hours between scheduled backups; 0 leaves backups to the API and CLI
+    pub backup_interval_hours: u64,
+    // Archives kept in `backup_path`; older ones are removed after each backup
+    pub backup_keep: u32,
+    // Set when a flag or environment variable chose the root, which then
+    // wins over whatever is saved in the file
+    pub root_overridden: bool,
+}
+
+impl Settings {
+    /// Settings for this process from its command line and environment.
+    pub fn load() -> Result<Settings, ConfigError> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        Settings::resolve(&args, &|name| std::env::var(name).ok())
+    }
+
+    /// Layers `args`, the environment seen through `env` and the config file
+    /// over the defaults. The file is `--config` or `DOCSERVER_CONFIG` when
+    /// given, and must then exist; otherwise the per-user default is read if
+    /// it is there.
+    pub fn resolve(args: &[String], env: &dyn Fn(&str) -> Option<String>) -> Result<Settings, ConfigError> {
+        let (flag_config, flags) = parse_args(args)?;
+        let mut environment = SettingsLayer::default();
+        for key in KEYS {
+            let name = env_name(key);
+            if let Some(value) = env(&name).filter(|v| !v.is_empty()) {
+                environment.set(key, &name, &value)?;
+            }
+        }
+
+        let explicit_config = flag_config.or_else(|| env("DOCSERVER_CONFIG").filter(|v| !v.is_empty()).map(PathBuf::from));
+        let config_file = absolute(explicit_config.clone().unwrap_or_else(|| default_config_path(env)))?;
+        let file = match fs::read_to_string(&config_file) {
+            Ok(contents) => toml::from_str::<SettingsLayer>(&contents)
+                .map_err(|e| ConfigError::Parse(config_file.clone(), e.message().to_string()))?
+                .relative_to(config_file.parent().unwrap_or(Path::new("/"))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && explicit_config.is_none() => SettingsLayer::default(),
+            Err(e) => return Err(ConfigError::File(config_file, e)),
+        };
+
+        let root_overridden = flags.root_path.is_some() || environment.root_path.is_some();
+        let merged = flags.over(environment).over(file);
+        let root_path = absolute(merged.root_path.unwrap_or_else(|| default_root_path(env)))?;
+        let in_root = |path: Option<PathBuf>, filename: &str| match path {
+            Some(path) => absolute(path),
+            None => Ok(root_path.join(filename)),
+        };
+
+        let settings = Settings {
+            db_backend: merged.db_backend.unwrap_or_default(),
+            db_path: in_root(merged.db_path, DEFAULT_DB_FILENAME)?,
+            db_url: merged.db_url,
+            signing_key_path: in_root(merged.signing_key_path, SIGNING_KEY_FILENAME)?,
+            field_key_path: in_root(merged.field_key_path, FIELD_KEY_FILENAME)?,
+            upload_limit_mb: merged.upload_limit_mb.unwrap_or(DEFAULT_UPLOAD_LIMIT_MB),
+            db_pool_size: merged.db_pool_size.unwrap_or(DEFAULT_DB_POOL_SIZE),
+            db_busy_timeout_ms: merged.db_busy_timeout_ms.unwrap_or(DEFAULT_DB_BUSY_TIMEOUT_MS),
+            db_pool_timeout_ms: merged.db_pool_timeout_ms.unwrap_or(DEFAULT_DB_POOL_TIMEOUT_MS),
+            backup_path: in_root(merged.backup_path, BACKUP_DIRNAME)?,
+            backup_interval_hours: merged.backup_interval_hours.unwrap_or(DEFAULT_BACKUP_INTERVAL_HOURS),
+            backup_keep: merged.backup_keep.unwrap_or(DEFAULT_BACKUP_KEEP),
+            config_file,
+            root_path,
+            root_overridden,
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !(1..=MAX_UPLOAD_LIMIT_MB).contains(&self.upload_limit_mb) {
+            return Err(ConfigError::Invalid {
+                setting: "upload_limit_mb",
+                message: format!("{} is outside 1 to {}", self.upload_limit_mb, MAX_UPLOAD_LIMIT_MB),
+            });
+        }
+        if !(1..=MAX_DB_POOL_SIZE).contains(&self.db_pool_size) {
+            return Err(ConfigError::Invalid {
+                setting: "db_pool_size",
+                message: format!("{} is outside 1 to {}", self.db_pool_size, MAX_DB_POOL_SIZE),
+            });
+        }
+        if self.db_pool_timeout_ms == 0 {
+            return Err(ConfigError::Invalid {
+                setting: "db_pool_timeout_ms",
+                message: "callers need some time to wait for a connection".to_string(),
+            });
+        }
+        if self.db_backend == DbBackend::Postgres {
+            let url = self.db_url.as_deref().ok_or_else(|| ConfigError::Invalid {
+                setting: "db_url",
+                message: "the postgres backend needs a connection URL".to_string(),
+            })?;
+            if let Err(e) = url.parse::<postgres::Config>() {
+                return Err(ConfigError::Invalid { setting: "db_url", message: e.to_string() });
+            }
+        }
+        if self.backup_keep == 0 {
+            return Err(ConfigError::Invalid {
+                setting: "backup_keep",
+                message: "at least the latest backup has to be kept".to_string(),
+            });
+        }
+        if self.backup_path.is_file() {
+            return Err(ConfigError::Invalid {
+                setting: "backup_path",
+                message: format!("{} is a file, not a directory", self.backup_path.display()),
+            });
+        }
+        if self.root_path.exists() && !self.root_path.is_dir() {
+            return Err(ConfigError::Invalid {
+                setting: "root_path",
+                message: format!("{} is not a directory", self.root_path.display()),
+            });
+        }
+        for (setting, path) in [
+            ("db_path", &self.db_path),
+            ("signing_key_path", &self.signing_key_path),
+            ("field_key_path", &self.field_key_path),
+        ] {
+            if path.is_dir() {
+                return Err(ConfigError::Invalid {
+                    setting,
+                    message: format!("{} is a directory, not a file", path.display()),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn upload_limit_bytes(&self) -> u64 {
+        self.upload_limit_mb * 1024 * 1024
+    }
+
+    pub fn pool_options(&self) -> PoolOptions {
+        PoolOptions {
+            size: self.db_pool_size,
+            busy_timeout: Duration::from_millis(self.db_busy_timeout_ms),
+            checkout_timeout: Duration::from_millis(self.db_pool_timeout_ms),
+        }
+    }
+
+    /// Saves the root, database, key and backup paths to the config file,
+    /// keeping its other settings. All are written, so a database left
+    /// outside a relocated root is still found after a restart.
+    pub fn save_paths(&self) -> Result<(), ConfigError> {
+        let mut table = match fs::read_to_string(&self.config_file) {
+            Ok(contents) => contents.parse::<toml::Table>()
+                .map_err(|e| ConfigError::Parse(self.config_file.clone(), e.message().to_string()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(ConfigError::File(self.config_file.clone(), e)),
+        };
+
+        let path_value = |path: &Path| toml::Value::String(path.to_string_lossy().to_string());
+        for (key, path) in [
+            ("root_path", &self.root_path),
+            ("db_path", &self.db_path),
+            ("signing_key_path", &self.signing_key_path),
+            ("field_key_path", &self.field_key_path),
+            ("backup_path", &self.backup_path),
+        ] {
+            table.insert(key.to_string(), path_value(path));
+        }
+
+        let file_error = |e| ConfigError::File(self.config_file.clone(), e);
+        if let Some(parent) = self.config_file.parent() {
+            fs::create_dir_all(parent).map_err(file_error)?;
+        }
+        // Written beside the file and renamed over it, so a crash leaves the old file
+        let staged = self.config_file.with_extension("toml.tmp");
+        let contents = toml::to_string(&table).expect("A TOML table always serializes");
+        fs::write(&staged, contents).map_err(file_error)?;
+        fs::rename(&staged, &self.config_file).map_err(file_error)
+    }
+}
+
+/// Reads `--key value` and `--key=value` flags, returning `--config`
+/// separately since it chooses the file rather than a setting.
+fn parse_args(args: &[String]) -> Result<(Option<PathBuf>, SettingsLayer), ConfigError> {
+    let mut config = None;
+    let mut layer = SettingsLayer::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let key = flag.strip_prefix("--").map(|name| name.replace('-', "_"));
+        let key = match key.as_deref() {
+            Some("config") => "config",
+            Some(key) => KEYS.into_iter().find(|k| *k == key).ok_or_else(|| ConfigError::UnknownArgument(arg.clone()))?,
+            None => return Err(ConfigError::UnknownArgument(arg.clone())),
+        };
+        let value = inline
+            .or_else(|| args.next().cloned())
+            .ok_or_else(|| ConfigError::MissingValue(flag.to_string()))?;
+
+        if key == "config" {
+            config = Some(PathBuf::from(value));
+        } else {
+            layer.set(key, flag, &value)?;
+        }
+    }
+    Ok((config, layer))
+}
+
+fn flag_name(key: &str) -> String {
+    format!("--{}", key.replace('_', "-"))
+}
+
+fn env_name(key: &str) -> String {
+    format!("DOCSERVER_{}", key.to_uppercase())
+}
+
+fn absolute(path: PathBuf) -> Result<PathBuf, ConfigError> {
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    std::env::current_dir()
+        .map(|cwd| cwd.join(&path))
+        .map_err(|e| ConfigError::Invalid {
+            setting: "path",
+            message: format!("cannot resolve {} against the working directory: {}", path.display(), e),
+        })
+}
+
+/// `$XDG_CONFIG_HOME/docserver/docserver.toml`, falling back to `~/.config`.
+fn default_config_path(env: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    let config_home = env("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env("HOME").map(|home| Path::new(&home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    config_home.join("docserver").join(CONFIG_FILENAME)
+}
+
+/// `$XDG_DATA_HOME/docserver`, falling back to `~/.local/share`. Only a
+/// process without a home directory keeps its data in the temp directory.
+fn default_root_path(env: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    env("XDG_DATA_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env("HOME").map(|home| Path::new(&home).join(".local").join("share")))
+        .map(|data_home| data_home.join("docserver"))
+        .unwrap_or_else(|| std::env::temp_dir().join("docstore_files"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_flags_override_environment_override_file() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("docserver.toml");
+        fs::write(&config, "root_path = \"data\"\nupload_limit_mb = 25\ndb_path = \"db/docs.db\"\n").unwrap();
+
+        let env = HashMap::from([
+            ("DOCSERVER_CONFIG", config.to_string_lossy().to_string()),
+            ("DOCSERVER_UPLOAD_LIMIT_MB", "50".to_string()),
+        ]);
+        let lookup = |name: &str| env.get(name).cloned();
+
+        let settings = Settings::resolve(&[], &lookup).unwrap();
+        assert_eq!(settings.root_path, dir.path().join("data"));
+        assert_eq!(settings.db_path, dir.path().join("db/docs.db"));
+        assert_eq!(settings.field_key_path, dir.path().join("data").join(FIELD_KEY_FILENAME));
+        assert_eq!(settings.upload_limit_mb, 50);
+        assert!(!settings.root_overridden);
+
+        let other_root = dir.path().join("elsewhere");
+        let flags = args(&["--upload-limit-mb=5", "--root-path", other_root.to_str().unwrap()]);
+        let settings = Settings::resolve(&flags, &lookup).unwrap();
+        assert_eq!((settings.upload_limit_mb, settings.root_path), (5, other_root));
+        assert!(settings.root_overridden);
+    }
+
+    #[test]
+    fn test_bad_settings_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let no_env = |_: &str| None;
+        let config = dir.path().join("docserver.toml");
+        let with_config = |extra: &[&str]| {
+            let mut all = args(&["--config", config.to_str().unwrap()]);
+            all.extend(args(extra));
+            Settings::resolve(&all, &no_env).unwrap_err().to_string()
+        };
+
+        // A named config file has to exist
+        assert!(with_config(&[]).starts_with("Cannot access config file"));
+
+        fs::write(&config, "root_pth = \"/srv\"\n").unwrap();
+        assert!(with_config(&[]).contains("unknown field `root_pth`"));
+
+        fs::write(&config, "").unwrap();
+        assert_eq!(with_config(&["--upload-limit-mb", "ten"]), "--upload-limit-mb must be a whole number of megabytes, not \"ten\"");
+        assert!(with_config(&["--upload-limit-mb", "0"]).starts_with("Invalid upload_limit_mb"));
+        assert!(with_config(&["--port", "80"]).starts_with("Unknown argument --port"));
+        assert_eq!(with_config(&["--db-path"]), "--db-path needs a value");
+        assert!(with_config(&["--db-pool-size", "0"]).starts_with("Invalid db_pool_size"));
+        assert!(with_config(&["--backup-keep", "0"]).starts_with("Invalid backup_keep"));
+        assert!(with_config(&["--root-path", config.to_str().unwrap()]).ends_with("is not a directory"));
+        assert_eq!(with_config(&["--db-backend", "mysql"]), "--db-backend must be sqlite or postgres, not \"mysql\"");
+        assert!(with_config(&["--db-backend", "postgres"]).starts_with("Invalid db_url"));
+    }
+
+    #[test]
+    fn test_postgres_backend_is_read_from_file() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("docserver.toml");
+        fs::write(&config, "db_backend = \"postgres\"\ndb_url = \"postgres://docserver@db.internal/docserver\"\n").unwrap();
+
+        let settings = Settings::resolve(&args(&["--config", config.to_str().unwrap()]), &|_| None).unwrap();
+        assert_eq!(settings.db_backend, DbBackend::Postgres);
+        assert_eq!(settings.db_url.as_deref(), Some("postgres://docserver@db.internal/docserver"));
+
+        // Without a backend setting the URL is ignored and SQLite is used
+        fs::write(&config, "db_url = \"not a url\"\n").unwrap();
+        let settings = Settings::resolve(&args(&["--config", config.to_str().unwrap()]), &|_| None).unwrap();
+        assert_eq!(settings.db_backend, DbBackend::Sqlite);
+    }
+
+    #[test]
+    fn test_save_paths_keeps_other_settings() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("conf").join("docserver.toml");
+        let old_root = dir.path().join("old");
+        let settings = Settings::resolve(
+            &args(&["--root-path", old_root.to_str().unwrap(), "--upload-limit-mb", "20"]),
+            &|_| None,
+        ).unwrap();
+        fs::create_dir_all(config.parent().unwrap()).unwrap();
+        fs::write(&config, "upload_limit_mb = 30\n").unwrap();
+
+        let new_root = dir.path().join("new");
+        let moved = Settings { config_file: config.clone(), root_path: new_root.clone(), ..settings };
+        moved.save_paths().unwrap();
+
+        let reloaded = Settings::resolve(&args(&["--config", config.to_str().unwrap()]), &|_| None).unwrap();
+        assert_eq!(reloaded.root_path, new_root);
+        assert_eq!(reloaded.db_path, old_root.join(DEFAULT_DB_FILENAME));
+        assert_eq!(reloaded.signing_key_path, old_root.join(SIGNING_KEY_FILENAME));
+        // Only paths are saved; the file's own limit stays and the flag's is not written
+        assert_eq!(reloaded.upload_limit_mb, 30);
+    }
+}
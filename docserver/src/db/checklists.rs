@@ -0,0 +1,133 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+
+use super::models::{ChecklistItem, ChecklistStatus, Document, DocumentType};
+
+const CHECKLIST_COLUMNS: &str = "item_id, client_id, tax_year, item_key, document_type, description,
+    status, received_document_id, received_at, created_at";
+
+fn map_item(row: &rusqlite::Row) -> Result<ChecklistItem> {
+    Ok(ChecklistItem {
+        item_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        tax_year: row.get(2)?,
+        item_key: row.get(3)?,
+        document_type: row.get(4)?,
+        description: row.get(5)?,
+        status: row.get(6)?,
+        received_document_id: row.get(7)?,
+        received_at: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+/// Adds an item unless one with the same key is already on the checklist.
+/// Returns whether it was added.
+pub fn add_item(
+    conn: &Connection,
+    client_id: i64,
+    tax_year: i32,
+    item_key: &str,
+    document_type: DocumentType,
+    description: &str,
+) -> Result<bool> {
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO checklist_items (client_id, tax_year, item_key, document_type, description)
+         VALUES (?, ?, ?, ?, ?)",
+        params![client_id, tax_year, item_key, document_type, description],
+    )?;
+    Ok(inserted > 0)
+}
+
+pub fn list_items(
+    conn: &Connection,
+    client_id: i64,
+    tax_year: Option<i32>,
+    status: Option<ChecklistStatus>,
+) -> Result<Vec<ChecklistItem>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM checklist_items
+         WHERE client_id = ?1
+           AND (?2 IS NULL OR tax_year = ?2)
+           AND (?3 IS NULL OR status = ?3)
+         ORDER BY tax_year DESC, document_type, item_id",
+        CHECKLIST_COLUMNS
+    ))?;
+
+    let items = stmt.query_map(params![client_id, tax_year, status], map_item)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(items)
+}
+
+/// Ticks off the oldest pending item the document satisfies: same client and
+/// form type, and the document's tax year if it has one (otherwise the most
+/// recent year still waiting on that form). Returns the item marked received.
+pub fn mark_received(conn: &Connection, document: &Document) -> Result<Option<i64>> {
+    let (Some(document_id), Some(document_type)) = (document.document_id, document.document_type) else {
+        return Ok(None);
+    };
+
+    // A reclassified document gives back any item it no longer satisfies
+    conn.execute(
+        "UPDATE checklist_items
+         SET status = 'pending', received_document_id = NULL, received_at = NULL
+         WHERE received_document_id = ?1
+           AND (document_type != ?2 OR (?3 IS NOT NULL AND tax_year != ?3))",
+        params![document_id, document_type, document.tax_year],
+    )?;
+
+    let already_matched: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM checklist_items WHERE received_document_id = ?)",
+        [document_id],
+        |row| row.get(0),
+    )?;
+    if already_matched {
+        return Ok(None);
+    }
+
+    let item_id: Option<i64> = conn.query_row(
+        "SELECT item_id FROM checklist_items
+         WHERE client_id = ?1 AND document_type = ?2 AND status = 'pending'
+           AND (?3 IS NULL OR tax_year = ?3)
+         ORDER BY tax_year DESC, item_id
+         LIMIT 1",
+        params![document.client_id, document_type, document.tax_year],
+        |row| row.get(0),
+    ).optional()?;
+
+    if let Some(item_id) = item_id {
+        conn.execute(
+            "UPDATE checklist_items
+             SET status = ?, received_document_id = ?, received_at = CURRENT_TIMESTAMP
+             WHERE item_id = ?",
+            params![ChecklistStatus::Received, document_id, item_id],
+        )?;
+    }
+    Ok(item_id)
+}
+
+/// Marks a specific item received by a document, as when a client answers a
+/// request link for that item. Any other item the document had been matched
+/// to goes back to pending. Returns false unless the item was pending.
+pub fn mark_item_received(conn: &Connection, item_id: i64, document_id: i64) -> Result<bool> {
+    conn.execute(
+        "UPDATE checklist_items
+         SET status = 'pending', received_document_id = NULL, received_at = NULL
+         WHERE received_document_id = ? AND item_id != ?",
+        params![document_id, item_id],
+    )?;
+    let updated = conn.execute(
+        "UPDATE checklist_items
+         SET status = ?, received_document_id = ?, received_at = CURRENT_TIMESTAMP
+         WHERE item_id = ? AND status = 'pending'",
+        params![ChecklistStatus::Received, document_id, item_id],
+    )?;
+    Ok(updated > 0)
+}
+
+pub fn get_item(conn: &Connection, item_id: i64) -> Result<Option<ChecklistItem>> {
+    conn.query_row(
+        &format!("SELECT {} FROM checklist_items WHERE item_id = ?", CHECKLIST_COLUMNS),
+        [item_id],
+        map_item,
+    ).optional()
+}
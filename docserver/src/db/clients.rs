@@ -0,0 +1,110 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::collections::HashSet;
+
+use super::connection::write_transaction;
+use super::encryption::{decrypt_column, FieldKey};
+use super::models::Client;
+
+pub(crate) const CLIENT_COLUMNS: &str = "client_id, first_name, last_name, social_security_number,
+    address, phone_number, email, created_at, updated_at";
+
+/// Maps a client row, decrypting the social security number.
+pub(crate) fn map_client(row: &rusqlite::Row, key: &FieldKey) -> Result<Client> {
+    Ok(Client {
+        client_id: Some(row.get(0)?),
+        first_name: row.get(1)?,
+        last_name: row.get(2)?,
+        social_security_number: decrypt_column(key, &row.get::<_, String>(3)?, 3)?,
+        address: row.get(4)?,
+        phone_number: row.get(5)?,
+        email: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+pub fn list_clients(conn: &Connection, key: &FieldKey) -> Result<Vec<Client>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM clients ORDER BY client_id", CLIENT_COLUMNS))?;
+    let clients = stmt.query_map([], |row| map_client(row, key))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(clients)
+}
+
+/// Every client id, without reading the rows themselves.
+pub fn client_ids(conn: &Connection) -> Result<HashSet<i64>> {
+    let mut stmt = conn.prepare("SELECT client_id FROM clients")?;
+    let ids = stmt.query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    Ok(ids)
+}
+
+pub fn get_client(conn: &Connection, key: &FieldKey, client_id: i64) -> Result<Option<Client>> {
+    conn.query_row(
+        &format!("SELECT {} FROM clients WHERE client_id = ?", CLIENT_COLUMNS),
+        [client_id],
+        |row| map_client(row, key),
+    ).optional()
+}
+
+/// Inserts a client, encrypting the social security number.
+pub fn insert_client(conn: &Connection, key: &FieldKey, client: &Client) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO clients (
+            first_name, last_name, social_security_number,
+            address, phone_number, email
+        ) VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            client.first_name,
+            client.last_name,
+            key.encrypt(&client.social_security_number),
+            client.address,
+            client.phone_number,
+            client.email,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Encrypts social security numbers still stored as plaintext, such as rows
+/// written before encryption was introduced. Returns how many were changed.
+pub fn encrypt_plaintext_ssns(conn: &Connection, key: &FieldKey) -> Result<usize> {
+    let tx = write_transaction(conn)?;
+    let plaintext: Vec<(i64, String)> = {
+        let mut stmt = tx.prepare("SELECT client_id, social_security_number FROM clients")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(i64, String)>>>()?;
+        rows.into_iter().filter(|(_, ssn)| !FieldKey::is_encrypted(ssn)).collect()
+    };
+
+    for (client_id, ssn) in &plaintext {
+        tx.execute(
+            "UPDATE clients SET social_security_number = ? WHERE client_id = ?",
+            params![key.encrypt(ssn), client_id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(plaintext.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+
+    #[test]
+    fn test_ssns_are_encrypted_at_rest() {
+        // The baseline schema seeds client 1 with a plaintext SSN
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        let key = FieldKey::generate();
+
+        assert_eq!(encrypt_plaintext_ssns(&conn, &key).unwrap(), 1);
+        assert_eq!(encrypt_plaintext_ssns(&conn, &key).unwrap(), 0);
+
+        let stored: String = conn.query_row(
+            "SELECT social_security_number FROM clients WHERE client_id = 1", [], |row| row.get(0),
+        ).unwrap();
+        assert!(FieldKey::is_encrypted(&stored));
+        assert_eq!(get_client(&conn, &key, 1).unwrap().unwrap().social_security_number, "123-45-6789");
+    }
+}
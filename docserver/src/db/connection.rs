@@ -1,19 +1,245 @@
-use std::sync::Mutex;
-use rusqlite::{Connection, Result};
-use std::path::Path;
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
+use super::migrations;
+use crate::config::{DEFAULT_DB_BUSY_TIMEOUT_MS, DEFAULT_DB_POOL_SIZE, DEFAULT_DB_POOL_TIMEOUT_MS};
+
+/// Begins a transaction that takes SQLite's write lock up front, so what it
+/// reads cannot change before it writes. A deferred transaction that read
+/// before another request committed would fail to write instead.
+pub(crate) fn write_transaction(conn: &Connection) -> rusqlite::Result<Transaction<'_>> {
+    Transaction::new_unchecked(conn, TransactionBehavior::Immediate)
+}
+
+/// Whether a UNIQUE constraint refused a row, as when two requests add the
+/// same thing at once.
+pub fn is_unique_violation(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(failure, _)
+        if failure.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE)
+}
+
+/// How the connection pool behaves: `size` connections at most, each
+/// waiting up to `busy_timeout` for SQLite's write lock, and callers
+/// waiting up to `checkout_timeout` for a free connection.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolOptions {
+    pub size: u32,
+    pub busy_timeout: Duration,
+    pub checkout_timeout: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions {
+            size: DEFAULT_DB_POOL_SIZE,
+            busy_timeout: Duration::from_millis(DEFAULT_DB_BUSY_TIMEOUT_MS),
+            checkout_timeout: Duration::from_millis(DEFAULT_DB_POOL_TIMEOUT_MS),
+        }
+    }
+}
+
+/// Opens SQLite connections for the pool.
+#[derive(Debug)]
+pub struct SqliteConnectionManager {
+    path: PathBuf,
+    busy_timeout: Duration,
+}
+
+impl r2d2::ManageConnection for SqliteConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(self.busy_timeout)?;
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> rusqlite::Result<()> {
+        conn.execute_batch("")
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+pub type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+#[derive(Debug)]
+pub enum ConnectionError {
+    Database(rusqlite::Error),
+    Pool(r2d2::Error),
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::Database(e) => write!(f, "{}", e),
+            ConnectionError::Pool(e) => write!(f, "No database connection available: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
+
+impl From<rusqlite::Error> for ConnectionError {
+    fn from(e: rusqlite::Error) -> Self {
+        ConnectionError::Database(e)
+    }
+}
+
+impl From<r2d2::Error> for ConnectionError {
+    fn from(e: r2d2::Error) -> Self {
+        ConnectionError::Pool(e)
+    }
+}
+
+/// Connection pool usage since the pool was opened.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolMetrics {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub in_use: u32,
+    pub checkouts: u64,
+    pub checkout_timeouts: u64,
+    pub average_wait_ms: f64,
+    pub max_wait_ms: f64,
+    pub busy_timeout_ms: u64,
+}
+
+/// Counts a pool's checkouts and how long callers waited for them.
+#[derive(Debug, Default)]
+pub(crate) struct CheckoutStats {
+    checkouts: AtomicU64,
+    checkout_timeouts: AtomicU64,
+    wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+impl CheckoutStats {
+    /// Checks a connection out of `pool`, waiting for one to be returned if
+    /// all are in use, and counts the wait.
+    pub(crate) fn checkout<M: r2d2::ManageConnection>(
+        &self,
+        pool: &r2d2::Pool<M>,
+    ) -> Result<r2d2::PooledConnection<M>, r2d2::Error> {
+        let started = Instant::now();
+        let conn = pool.get();
+        let waited = started.elapsed().as_micros() as u64;
+        self.wait_micros.fetch_add(waited, Ordering::Relaxed);
+        self.max_wait_micros.fetch_max(waited, Ordering::Relaxed);
+        match conn {
+            Ok(_) => self.checkouts.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.checkout_timeouts.fetch_add(1, Ordering::Relaxed),
+        };
+        conn
+    }
+
+    pub(crate) fn metrics<M: r2d2::ManageConnection>(&self, pool: &r2d2::Pool<M>, busy_timeout: Duration) -> PoolMetrics {
+        let state = pool.state();
+        let checkouts = self.checkouts.load(Ordering::Relaxed);
+        let attempts = checkouts + self.checkout_timeouts.load(Ordering::Relaxed);
+        let wait_ms = self.wait_micros.load(Ordering::Relaxed) as f64 / 1000.0;
+        PoolMetrics {
+            max_size: pool.max_size(),
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            in_use: state.connections - state.idle_connections,
+            checkouts,
+            checkout_timeouts: self.checkout_timeouts.load(Ordering::Relaxed),
+            average_wait_ms: if attempts == 0 { 0.0 } else { wait_ms / attempts as f64 },
+            max_wait_ms: self.max_wait_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            busy_timeout_ms: busy_timeout.as_millis() as u64,
+        }
+    }
+}
+
+/// A pool of connections to the database file, in WAL mode so readers do
+/// not wait for writers.
 pub struct DbConnection {
-    pub conn: Mutex<Connection>,
+    pool: r2d2::Pool<SqliteConnectionManager>,
+    busy_timeout: Duration,
+    stats: CheckoutStats,
 }
 
 impl DbConnection {
-    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
-        
-        // Initialize tables using schema.sql
-        let schema = include_str!("schema.sql");
-        conn.execute_batch(schema)?;
-
-        Ok(Self { conn: Mutex::new(conn) })
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, ConnectionError> {
+        DbConnection::with_options(db_path, PoolOptions::default())
+    }
+
+    pub fn with_options<P: AsRef<Path>>(db_path: P, options: PoolOptions) -> Result<Self, ConnectionError> {
+        let manager = SqliteConnectionManager {
+            path: db_path.as_ref().to_path_buf(),
+            busy_timeout: options.busy_timeout,
+        };
+
+        // WAL is a property of the file, so setting it once covers every
+        // connection; migrations run before the pool hands any out
+        {
+            let conn = r2d2::ManageConnection::connect(&manager)?;
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            migrations::run(&conn)?;
+        }
+
+        let pool = r2d2::Pool::builder()
+            .max_size(options.size)
+            .min_idle(Some(1))
+            .connection_timeout(options.checkout_timeout)
+            .build(manager)?;
+
+        Ok(DbConnection {
+            pool,
+            busy_timeout: options.busy_timeout,
+            stats: CheckoutStats::default(),
+        })
+    }
+
+    /// Checks a connection out of the pool, waiting for one to be returned
+    /// if all are in use. It goes back to the pool when dropped.
+    pub fn conn(&self) -> Result<PooledConnection, r2d2::Error> {
+        self.stats.checkout(&self.pool)
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        self.stats.metrics(&self.pool, self.busy_timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pool_hands_out_concurrent_connections() {
+        let dir = TempDir::new().unwrap();
+        let options = PoolOptions { size: 2, checkout_timeout: Duration::from_millis(100), ..PoolOptions::default() };
+        let db = DbConnection::with_options(dir.path().join("docstore.db"), options).unwrap();
+
+        let reader = db.conn().unwrap();
+        let writer = db.conn().unwrap();
+        let mode: String = reader.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+
+        // A write does not block a read on another connection
+        writer.execute_batch("BEGIN IMMEDIATE; UPDATE clients SET first_name = first_name; ").unwrap();
+        let clients: i64 = reader.query_row("SELECT COUNT(*) FROM clients", [], |row| row.get(0)).unwrap();
+        assert!(clients > 0);
+        writer.execute_batch("COMMIT").unwrap();
+
+        // Both connections are out, so a third caller times out
+        assert!(db.conn().is_err());
+        let metrics = db.metrics();
+        assert_eq!((metrics.max_size, metrics.in_use), (2, 2));
+        assert_eq!((metrics.checkouts, metrics.checkout_timeouts), (2, 1));
+
+        drop(reader);
+        assert!(db.conn().is_ok());
     }
 }
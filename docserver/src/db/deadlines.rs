@@ -0,0 +1,251 @@
+use chrono::NaiveDate;
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::fmt;
+
+use crate::tax::{self, Deadline, DeadlineInputs, DeadlineKind};
+use super::models::{ClientTaxProfile, ReturnExtension, TaxReturn};
+use super::connection::is_unique_violation;
+use super::repository::StoreError;
+use super::returns;
+
+#[derive(Debug)]
+pub enum ExtensionError {
+    ReturnNotFound,
+    // Amendments have no filing deadline to extend, and state returns
+    // follow the federal extension
+    NotOriginal,
+    AlreadyExtended,
+    AfterDueDate(NaiveDate),
+    Database(StoreError),
+}
+
+impl fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtensionError::ReturnNotFound => write!(f, "Tax return not found"),
+            ExtensionError::NotOriginal => {
+                write!(f, "Only an original federal return can be extended; its state returns follow it")
+            }
+            ExtensionError::AlreadyExtended => write!(f, "The return already has an extension on file"),
+            ExtensionError::AfterDueDate(due) => {
+                write!(f, "An extension must be filed by the original due date, {}", due)
+            }
+            ExtensionError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ExtensionError {
+    fn from(e: rusqlite::Error) -> Self {
+        ExtensionError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for ExtensionError {
+    fn from(e: StoreError) -> Self {
+        ExtensionError::Database(e)
+    }
+}
+
+const EXTENSION_COLUMNS: &str = "extension_id, tax_return_id, filed_on, estimated_tax_liability, amount_paid,
+    confirmation_number, created_at";
+
+fn map_extension(row: &rusqlite::Row) -> Result<ReturnExtension> {
+    Ok(ReturnExtension {
+        extension_id: Some(row.get(0)?),
+        tax_return_id: row.get(1)?,
+        filed_on: row.get(2)?,
+        estimated_tax_liability: row.get(3)?,
+        amount_paid: row.get(4)?,
+        confirmation_number: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+pub fn get_profile(conn: &Connection, client_id: i64) -> Result<Option<ClientTaxProfile>> {
+    conn.query_row(
+        "SELECT client_id, fiscal_year_end_month, home_state, return_type, updated_at
+         FROM client_tax_profiles WHERE client_id = ?",
+        [client_id],
+        |row| Ok(ClientTaxProfile {
+            client_id: row.get(0)?,
+            fiscal_year_end_month: row.get(1)?,
+            home_state: row.get(2)?,
+            return_type: row.get(3)?,
+            updated_at: row.get(4)?,
+        }),
+    ).optional()
+}
+
+pub fn set_profile(conn: &Connection, profile: &ClientTaxProfile) -> Result<()> {
+    conn.execute(
+        "INSERT INTO client_tax_profiles (client_id, fiscal_year_end_month, home_state, return_type)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (client_id) DO UPDATE SET
+             fiscal_year_end_month = ?2, home_state = ?3, return_type = ?4, updated_at = CURRENT_TIMESTAMP",
+        params![profile.client_id, profile.fiscal_year_end_month, profile.home_state, profile.return_type],
+    )?;
+    Ok(())
+}
+
+pub fn get_extension(conn: &Connection, tax_return_id: i64) -> Result<Option<ReturnExtension>> {
+    conn.query_row(
+        &format!("SELECT {} FROM return_extensions WHERE tax_return_id = ?", EXTENSION_COLUMNS),
+        [tax_return_id],
+        map_extension,
+    ).optional()
+}
+
+/// Records a Form 4868 extension, which has to be filed by the return's
+/// original federal due date.
+pub fn add_extension(conn: &Connection, extension: &ReturnExtension) -> std::result::Result<ReturnExtension, ExtensionError> {
+    let tax_return = returns::get_tax_return(conn, extension.tax_return_id)?
+        .ok_or(ExtensionError::ReturnNotFound)?;
+    if tax_return.parent_return_id.is_some() || !tax_return.jurisdiction.is_federal() {
+        return Err(ExtensionError::NotOriginal);
+    }
+    if get_extension(conn, extension.tax_return_id)?.is_some() {
+        return Err(ExtensionError::AlreadyExtended);
+    }
+    let original_due = return_deadlines(conn, &tax_return)?.into_iter()
+        .find(|d| d.jurisdiction == "federal" && d.kind == DeadlineKind::Original)
+        .map(|d| d.due_date);
+    if let Some(due) = original_due.filter(|due| extension.filed_on > *due) {
+        return Err(ExtensionError::AfterDueDate(due));
+    }
+
+    conn.execute(
+        "INSERT INTO return_extensions (
+            tax_return_id, filed_on, estimated_tax_liability, amount_paid, confirmation_number
+        ) VALUES (?, ?, ?, ?, ?)",
+        params![
+            extension.tax_return_id,
+            extension.filed_on,
+            extension.estimated_tax_liability,
+            extension.amount_paid,
+            extension.confirmation_number,
+        ],
+    ).map_err(|e| {
+        // Another request filed one since the check above
+        if is_unique_violation(&e) { ExtensionError::AlreadyExtended } else { ExtensionError::Database(StoreError::Sqlite(e)) }
+    })?;
+    get_extension(conn, extension.tax_return_id)?.ok_or(ExtensionError::ReturnNotFound)
+}
+
+pub fn remove_extension(conn: &Connection, tax_return_id: i64) -> Result<bool> {
+    let removed = conn.execute("DELETE FROM return_extensions WHERE tax_return_id = ?", [tax_return_id])?;
+    Ok(removed > 0)
+}
+
+/// The day a return was first e-filed, from its status history.
+pub fn filed_on(conn: &Connection, tax_return_id: i64) -> Result<Option<NaiveDate>> {
+    let changed_at: Option<chrono::DateTime<chrono::Utc>> = conn.query_row(
+        "SELECT MIN(changed_at) FROM return_status_history
+         WHERE tax_return_id = ? AND to_status = 'e_filed'",
+        [tax_return_id],
+        |row| row.get(0),
+    )?;
+    Ok(changed_at.map(|at| at.date_naive()))
+}
+
+/// The first return of the amendment chain `tax_return` belongs to.
+fn original_return_id(conn: &Connection, tax_return: &TaxReturn) -> Result<i64> {
+    let tax_return_id = tax_return.tax_return_id.expect("Stored returns have an id");
+    if tax_return.parent_return_id.is_none() {
+        return Ok(tax_return_id);
+    }
+    Ok(returns::return_chain(conn, tax_return_id)?.first().and_then(|r| r.tax_return_id).unwrap_or(tax_return_id))
+}
+
+/// A return's deadlines from its client's profile (fiscal year, return type
+/// and home state) and extension. An
+/// amendment's refund claim window runs from when the original was filed,
+/// and the original's extension carries through the chain. State returns
+/// share the extension of their federal return, and a federal return only
+/// shows the home state's dates while no state return is on file.
+pub fn return_deadlines(conn: &Connection, tax_return: &TaxReturn) -> Result<Vec<Deadline>> {
+    let profile = get_profile(conn, tax_return.client_id)?.unwrap_or_default();
+    let original_id = original_return_id(conn, tax_return)?;
+
+    let extended_return_id = match tax_return.federal_return_id {
+        Some(federal_id) => match returns::get_tax_return(conn, federal_id)? {
+            Some(federal) => original_return_id(conn, &federal)?,
+            None => federal_id,
+        },
+        None => original_id,
+    };
+    let home_state = if tax_return.jurisdiction.is_federal()
+        && returns::state_returns(conn, tax_return.tax_return_id.unwrap_or_default())?.is_empty()
+    {
+        profile.home_state.as_deref()
+    } else {
+        None
+    };
+
+    Ok(tax::return_deadlines(DeadlineInputs {
+        jurisdiction: &tax_return.jurisdiction,
+        tax_year: tax_return.tax_year,
+        fiscal_year_end_month: profile.fiscal_year_end_month,
+        return_type: profile.return_type,
+        home_state,
+        extended: get_extension(conn, extended_return_id)?.is_some(),
+        amendment: tax_return.parent_return_id.is_some(),
+        original_filed_on: filed_on(conn, original_id)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{migrations, workflow, ReturnStatus};
+    use crate::money::Money;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_extension_moves_deadlines() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        set_profile(&conn, &ClientTaxProfile { client_id: 1, home_state: Some("VA".to_string()), ..Default::default() }).unwrap();
+
+        let tax_return = returns::get_tax_return(&conn, 1).unwrap().unwrap();
+        let due: Vec<NaiveDate> = return_deadlines(&conn, &tax_return).unwrap().iter().map(|d| d.due_date).collect();
+        assert_eq!(due, vec![date(2024, 4, 15), date(2024, 5, 1)]);
+
+        let extension = |filed_on| ReturnExtension {
+            extension_id: None,
+            tax_return_id: 1,
+            filed_on,
+            estimated_tax_liability: Money::from_dollars(2_000),
+            amount_paid: Money::ZERO,
+            confirmation_number: None,
+            created_at: None,
+        };
+        assert!(matches!(add_extension(&conn, &extension(date(2024, 4, 16))), Err(ExtensionError::AfterDueDate(_))));
+        add_extension(&conn, &extension(date(2024, 4, 10))).unwrap();
+        assert!(matches!(add_extension(&conn, &extension(date(2024, 4, 10))), Err(ExtensionError::AlreadyExtended)));
+
+        let deadlines = return_deadlines(&conn, &tax_return).unwrap();
+        let current: Vec<NaiveDate> = tax::current_deadlines(&deadlines).iter().map(|d| d.due_date).collect();
+        assert_eq!(current, vec![date(2024, 10, 15), date(2024, 11, 1)]);
+
+        // An e-filed original opens the refund claim window for its amendment
+        for status in [
+            ReturnStatus::DocumentsReceived,
+            ReturnStatus::InPreparation,
+            ReturnStatus::InReview,
+            ReturnStatus::AwaitingSignature,
+            ReturnStatus::EFiled,
+        ] {
+            workflow::transition_return(&conn, 1, status, None, None).unwrap();
+        }
+        assert!(filed_on(&conn, 1).unwrap().is_some());
+        let amendment = returns::Amendment { amendment_reason: "Missed 1099".to_string(), ..Default::default() };
+        let amended = returns::amend_return(&conn, 1, amendment).unwrap();
+        let deadlines = return_deadlines(&conn, &amended).unwrap();
+        assert_eq!(deadlines.len(), 1);
+        assert_eq!(deadlines[0].kind, DeadlineKind::AmendedRefundClaim);
+    }
+}
@@ -0,0 +1,128 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::collections::HashMap;
+
+use super::models::{Document, DocumentType};
+
+pub(crate) const DOCUMENT_COLUMNS: &str = "document_id, client_id, file_name, document_type, tax_year,
+    confidence, type_overridden, extracted_text, created_at, updated_at";
+
+fn map_document(row: &rusqlite::Row) -> Result<Document> {
+    Ok(Document {
+        document_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        file_name: row.get(2)?,
+        document_type: row.get(3)?,
+        tax_year: row.get(4)?,
+        confidence: row.get(5)?,
+        type_overridden: row.get(6)?,
+        extracted_text: row.get(7)?,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+/// Inserts or refreshes the record for `(client_id, file_name)`. Re-uploading a
+/// file replaces its content, so any earlier override is discarded as well.
+pub fn upsert_document(conn: &Connection, document: &Document) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO documents (
+            client_id, file_name, document_type, tax_year, confidence,
+            type_overridden, extracted_text
+        ) VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (client_id, file_name) DO UPDATE SET
+            document_type = excluded.document_type,
+            tax_year = excluded.tax_year,
+            confidence = excluded.confidence,
+            type_overridden = excluded.type_overridden,
+            extracted_text = excluded.extracted_text,
+            updated_at = CURRENT_TIMESTAMP",
+        params![
+            document.client_id,
+            document.file_name,
+            document.document_type,
+            document.tax_year,
+            document.confidence,
+            document.type_overridden,
+            document.extracted_text,
+        ],
+    )?;
+
+    conn.query_row(
+        "SELECT document_id FROM documents WHERE client_id = ? AND file_name = ?",
+        params![document.client_id, document.file_name],
+        |row| row.get(0),
+    )
+}
+
+pub fn get_document(conn: &Connection, document_id: i64) -> Result<Option<Document>> {
+    conn.query_row(
+        &format!("SELECT {} FROM documents WHERE document_id = ?", DOCUMENT_COLUMNS),
+        [document_id],
+        map_document,
+    ).optional()
+}
+
+pub fn list_client_documents(conn: &Connection, client_id: i64) -> Result<Vec<Document>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM documents WHERE client_id = ? ORDER BY file_name",
+        DOCUMENT_COLUMNS
+    ))?;
+    let documents = stmt.query_map([client_id], map_document)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(documents)
+}
+
+/// Every recorded document, by client and file name.
+pub fn list_documents(conn: &Connection) -> Result<Vec<Document>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM documents ORDER BY client_id, file_name",
+        DOCUMENT_COLUMNS
+    ))?;
+    let documents = stmt.query_map([], map_document)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(documents)
+}
+
+/// Whether a document is recorded for a file in a client's folder.
+pub fn is_recorded(conn: &Connection, client_id: i64, file_name: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM documents WHERE client_id = ? AND file_name = ?)",
+        params![client_id, file_name],
+        |row| row.get(0),
+    )
+}
+
+/// Stores the SHA-256 of a document's file as uploaded.
+pub fn set_checksum(conn: &Connection, document_id: i64, sha256: &str) -> Result<()> {
+    conn.execute("UPDATE documents SET sha256 = ? WHERE document_id = ?", params![sha256, document_id])?;
+    Ok(())
+}
+
+/// The stored checksum of every document, `None` for those recorded before
+/// checksums were kept.
+pub fn list_checksums(conn: &Connection) -> Result<HashMap<i64, Option<String>>> {
+    let mut stmt = conn.prepare("SELECT document_id, sha256 FROM documents")?;
+    let checksums = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+    Ok(checksums)
+}
+
+/// Records a preparer's manual classification. Overrides are treated as certain.
+pub fn override_document_type(
+    conn: &Connection,
+    document_id: i64,
+    document_type: DocumentType,
+    tax_year: Option<i32>,
+) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE documents SET
+            document_type = ?,
+            tax_year = COALESCE(?, tax_year),
+            confidence = 1.0,
+            type_overridden = 1,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE document_id = ?",
+        params![document_type, tax_year, document_id],
+    )?;
+    Ok(updated > 0)
+}
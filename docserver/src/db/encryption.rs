@@ -0,0 +1,176 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use std::fmt;
+use std::fs;
+use std::io;
+use rusqlite::{params, Connection, Transaction, TransactionBehavior};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::links::{from_hex, restrict_permissions, to_hex};
+
+// Every column holding values encrypted with the field key, with its table's id
+pub(crate) const ENCRYPTED_COLUMNS: [(&str, &str, &str); 2] = [
+    ("clients", "client_id", "social_security_number"),
+    ("household_members", "member_id", "social_security_number"),
+];
+
+// Marks a stored value as ciphertext; values without it predate encryption
+const ENCRYPTED_PREFIX: &str = "enc1:";
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug)]
+pub struct DecryptError;
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Stored value could not be decrypted with the field key")
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+/// Server-side secret for sensitive columns such as social security numbers.
+/// Values are stored as `enc1:<hex nonce and ciphertext>` (AES-256-GCM).
+#[derive(Clone)]
+pub struct FieldKey {
+    key: Vec<u8>,
+}
+
+impl Default for FieldKey {
+    fn default() -> Self {
+        FieldKey::generate()
+    }
+}
+
+impl FieldKey {
+    pub fn generate() -> FieldKey {
+        let mut key = Vec::with_capacity(32);
+        key.extend_from_slice(Uuid::new_v4().as_bytes());
+        key.extend_from_slice(Uuid::new_v4().as_bytes());
+        FieldKey { key }
+    }
+
+    /// Reads the hex-encoded key at `path`, creating it on first use. Losing
+    /// this file makes every encrypted value unreadable.
+    pub fn load_or_create(path: &Path) -> io::Result<FieldKey> {
+        if path.exists() {
+            let contents = fs::read_to_string(path)?;
+            let key = from_hex(contents.trim()).filter(|key| key.len() == 32).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "field key file is not a 32-byte hex key")
+            })?;
+            return Ok(FieldKey { key });
+        }
+
+        let field_key = FieldKey::generate();
+        field_key.save(path)?;
+        Ok(field_key)
+    }
+
+    /// Writes the key to `path`, readable only by its owner.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, to_hex(&self.key))?;
+        restrict_permissions(path)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key))
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce_bytes = &Uuid::new_v4().into_bytes()[..NONCE_LEN];
+        let ciphertext = self.cipher()
+            .encrypt(Nonce::from_slice(nonce_bytes), plaintext.as_bytes())
+            .expect("AES-GCM encryption does not fail for in-memory input");
+
+        let mut stored = nonce_bytes.to_vec();
+        stored.extend_from_slice(&ciphertext);
+        format!("{}{}", ENCRYPTED_PREFIX, to_hex(&stored))
+    }
+
+    /// Reverses `encrypt`. Values stored before encryption was introduced are
+    /// returned as they are.
+    pub fn decrypt(&self, stored: &str) -> Result<String, DecryptError> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let bytes = from_hex(encoded).filter(|b| b.len() > NONCE_LEN).ok_or(DecryptError)?;
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self.cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| DecryptError)?;
+        String::from_utf8(plaintext).map_err(|_| DecryptError)
+    }
+
+    pub fn is_encrypted(stored: &str) -> bool {
+        stored.starts_with(ENCRYPTED_PREFIX)
+    }
+}
+
+/// Turns a failed decryption into the error a row mapper returns.
+pub(crate) fn decrypt_column(key: &FieldKey, stored: &str, column: usize) -> rusqlite::Result<String> {
+    key.decrypt(stored).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+/// Re-encrypts every encrypted column from `old` to `new` in one exclusive
+/// transaction, returning how many values were rewritten. A value `old`
+/// cannot decrypt stops the rotation with nothing changed.
+pub fn rotate_field_key(conn: &Connection, old: &FieldKey, new: &FieldKey) -> rusqlite::Result<usize> {
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Exclusive)?;
+    let mut rotated = 0;
+    for (table, id_column, column) in ENCRYPTED_COLUMNS {
+        let values: Vec<(i64, String)> = {
+            let mut stmt = tx.prepare(&format!("SELECT {}, {} FROM {}", id_column, column, table))?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, decrypt_column(old, &row.get::<_, String>(1)?, 1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for (id, plaintext) in &values {
+            tx.execute(
+                &format!("UPDATE {} SET {} = ? WHERE {} = ?", table, column, id_column),
+                params![new.encrypt(plaintext), id],
+            )?;
+        }
+        rotated += values.len();
+    }
+    tx.commit()?;
+    Ok(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{clients, migrations};
+
+    #[test]
+    fn test_round_trip() {
+        let key = FieldKey::generate();
+        let stored = key.encrypt("123-45-6789");
+        assert!(FieldKey::is_encrypted(&stored));
+        assert_ne!(stored, key.encrypt("123-45-6789"));
+        assert_eq!(key.decrypt(&stored).unwrap(), "123-45-6789");
+
+        // Plaintext from before encryption passes through; another key fails
+        assert_eq!(key.decrypt("987-65-4321").unwrap(), "987-65-4321");
+        assert!(FieldKey::generate().decrypt(&stored).is_err());
+    }
+
+    #[test]
+    fn test_rotate_field_key() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        let (old, new) = (FieldKey::generate(), FieldKey::generate());
+        clients::encrypt_plaintext_ssns(&conn, &old).unwrap();
+        let clients = clients::list_clients(&conn, &old).unwrap();
+
+        assert_eq!(rotate_field_key(&conn, &old, &new).unwrap(), clients.len());
+        let rotated = clients::list_clients(&conn, &new).unwrap();
+        assert_eq!(rotated[0].social_security_number, clients[0].social_security_number);
+        assert!(clients::list_clients(&conn, &old).is_err());
+
+        // Rotating with the wrong key leaves the stored values alone
+        assert!(rotate_field_key(&conn, &old, &FieldKey::generate()).is_err());
+        assert!(clients::list_clients(&conn, &new).is_ok());
+    }
+}
@@ -0,0 +1,156 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+
+use super::connection::write_transaction;
+use super::models::EstimatedPayment;
+
+const PAYMENT_COLUMNS: &str = "payment_id, client_id, tax_year, quarter, amount, paid_on, method, note, created_at";
+
+fn map_payment(row: &rusqlite::Row) -> Result<EstimatedPayment> {
+    Ok(EstimatedPayment {
+        payment_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        tax_year: row.get(2)?,
+        quarter: row.get(3)?,
+        amount: row.get(4)?,
+        paid_on: row.get(5)?,
+        method: row.get(6)?,
+        note: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+/// Records a payment and rolls it into the year's `taxes_paid`.
+pub fn add_payment(conn: &Connection, payment: &EstimatedPayment) -> Result<i64> {
+    let tx = write_transaction(conn)?;
+    tx.execute(
+        "INSERT INTO estimated_payments (client_id, tax_year, quarter, amount, paid_on, method, note)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![
+            payment.client_id,
+            payment.tax_year,
+            payment.quarter,
+            payment.amount,
+            payment.paid_on,
+            payment.method,
+            payment.note,
+        ],
+    )?;
+    let payment_id = tx.last_insert_rowid();
+    apply_to_return(&tx, payment.client_id, payment.tax_year)?;
+    tx.commit()?;
+    Ok(payment_id)
+}
+
+pub fn get_payment(conn: &Connection, payment_id: i64) -> Result<Option<EstimatedPayment>> {
+    conn.query_row(
+        &format!("SELECT {} FROM estimated_payments WHERE payment_id = ?", PAYMENT_COLUMNS),
+        [payment_id],
+        map_payment,
+    ).optional()
+}
+
+/// A client's payments, oldest first, optionally for one tax year.
+pub fn list_payments(conn: &Connection, client_id: i64, tax_year: Option<i32>) -> Result<Vec<EstimatedPayment>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM estimated_payments
+         WHERE client_id = ?1 AND (?2 IS NULL OR tax_year = ?2)
+         ORDER BY tax_year, quarter, paid_on, payment_id",
+        PAYMENT_COLUMNS
+    ))?;
+    let payments = stmt.query_map(params![client_id, tax_year], map_payment)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(payments)
+}
+
+/// Removes a payment and takes it back out of `taxes_paid`. Returns false if
+/// there was no such payment.
+pub fn remove_payment(conn: &Connection, payment_id: i64) -> Result<bool> {
+    let tx = write_transaction(conn)?;
+    let Some(payment) = get_payment(&tx, payment_id)? else {
+        return Ok(false);
+    };
+    tx.execute("DELETE FROM estimated_payments WHERE payment_id = ?", [payment_id])?;
+    apply_to_return(&tx, payment.client_id, payment.tax_year)?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Clients who made estimated payments for `tax_year` or the year before,
+/// and so are expected to keep making them.
+pub fn paying_clients(conn: &Connection, tax_year: i32) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT client_id FROM estimated_payments
+         WHERE tax_year IN (?1, ?1 - 1) ORDER BY client_id",
+    )?;
+    let client_ids = stmt.query_map([tax_year], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(client_ids)
+}
+
+/// Brings the effective federal return's `taxes_paid` in line with the client's
+/// estimated payments for the year, replacing whatever was rolled in before.
+/// Does nothing if the year has no return yet; creating it applies them.
+pub(crate) fn apply_to_return(conn: &Connection, client_id: i64, tax_year: i32) -> Result<()> {
+    conn.execute(
+        "UPDATE tax_returns
+         SET taxes_paid = taxes_paid - estimated_payments_applied + (
+                 SELECT COALESCE(SUM(amount), 0) FROM estimated_payments WHERE client_id = ?1 AND tax_year = ?2
+             ),
+             estimated_payments_applied = (
+                 SELECT COALESCE(SUM(amount), 0) FROM estimated_payments WHERE client_id = ?1 AND tax_year = ?2
+             ),
+             updated_at = CURRENT_TIMESTAMP
+         WHERE client_id = ?1 AND tax_year = ?2 AND return_kind != 'superseded' AND jurisdiction = 'federal'",
+        params![client_id, tax_year],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use crate::db::{migrations, returns, PaymentMethod};
+    use crate::money::Money;
+
+    fn payment(quarter: i32, dollars: i64) -> EstimatedPayment {
+        EstimatedPayment {
+            payment_id: None,
+            client_id: 1,
+            tax_year: 2023,
+            quarter,
+            amount: Money::from_dollars(dollars),
+            paid_on: NaiveDate::from_ymd_opt(2023, 4, 18).unwrap(),
+            method: PaymentMethod::DirectPay,
+            note: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_payments_roll_into_taxes_paid() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        let withheld = returns::get_tax_return(&conn, 1).unwrap().unwrap().taxes_paid;
+
+        let first = add_payment(&conn, &payment(1, 1_500)).unwrap();
+        add_payment(&conn, &payment(2, 1_500)).unwrap();
+        let tax_return = returns::get_tax_return(&conn, 1).unwrap().unwrap();
+        assert_eq!(tax_return.taxes_paid, withheld + Money::from_dollars(3_000));
+
+        // Amendments carry the payments forward and later changes land on them
+        let amendment = returns::Amendment {
+            amendment_reason: "Corrected interest".to_string(),
+            ..Default::default()
+        };
+        let amended = returns::amend_return(&conn, 1, amendment).unwrap();
+        assert_eq!(amended.taxes_paid, withheld + Money::from_dollars(3_000));
+
+        assert!(remove_payment(&conn, first).unwrap());
+        assert!(!remove_payment(&conn, first).unwrap());
+        let amended = returns::get_tax_return(&conn, amended.tax_return_id.unwrap()).unwrap().unwrap();
+        assert_eq!(amended.taxes_paid, withheld + Money::from_dollars(1_500));
+        assert_eq!(list_payments(&conn, 1, Some(2023)).unwrap().len(), 1);
+        assert_eq!(paying_clients(&conn, 2024).unwrap(), vec![1]);
+    }
+}
@@ -0,0 +1,217 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::fmt;
+
+use super::connection::{is_unique_violation, write_transaction};
+use super::encryption::{decrypt_column, FieldKey};
+use super::models::{HouseholdMember, MemberRole};
+use super::repository::StoreError;
+use super::returns;
+
+#[derive(Debug)]
+pub enum HouseholdError {
+    NotFound,
+    SpouseExists,
+    // A member named for a return belongs to another client
+    NotInHousehold(i64),
+    Database(StoreError),
+}
+
+impl fmt::Display for HouseholdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HouseholdError::NotFound => write!(f, "Not found"),
+            HouseholdError::SpouseExists => write!(f, "Client already has a spouse on file"),
+            HouseholdError::NotInHousehold(member_id) => {
+                write!(f, "Household member {} does not belong to the return's client", member_id)
+            }
+            HouseholdError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for HouseholdError {
+    fn from(e: rusqlite::Error) -> Self {
+        HouseholdError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for HouseholdError {
+    fn from(e: StoreError) -> Self {
+        HouseholdError::Database(e)
+    }
+}
+
+const MEMBER_COLUMNS: &str = "member_id, client_id, role, first_name, last_name, relationship,
+    birth_date, social_security_number, created_at, updated_at";
+
+fn map_member(row: &rusqlite::Row, key: &FieldKey) -> Result<HouseholdMember> {
+    Ok(HouseholdMember {
+        member_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        role: row.get(2)?,
+        first_name: row.get(3)?,
+        last_name: row.get(4)?,
+        relationship: row.get(5)?,
+        birth_date: row.get(6)?,
+        social_security_number: decrypt_column(key, &row.get::<_, String>(7)?, 7)?,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+pub fn add_member(
+    conn: &Connection,
+    key: &FieldKey,
+    member: &HouseholdMember,
+) -> std::result::Result<i64, HouseholdError> {
+    if member.role == MemberRole::Spouse {
+        let existing = list_members(conn, key, member.client_id)?;
+        if existing.iter().any(|m| m.role == MemberRole::Spouse) {
+            return Err(HouseholdError::SpouseExists);
+        }
+    }
+
+    conn.execute(
+        "INSERT INTO household_members (
+            client_id, role, first_name, last_name, relationship, birth_date, social_security_number
+        ) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![
+            member.client_id,
+            member.role,
+            member.first_name,
+            member.last_name,
+            member.relationship,
+            member.birth_date,
+            key.encrypt(&member.social_security_number),
+        ],
+    ).map_err(|e| {
+        // Another request added a spouse since the check above
+        if is_unique_violation(&e) { HouseholdError::SpouseExists } else { HouseholdError::Database(StoreError::Sqlite(e)) }
+    })?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_member(conn: &Connection, key: &FieldKey, member_id: i64) -> Result<Option<HouseholdMember>> {
+    conn.query_row(
+        &format!("SELECT {} FROM household_members WHERE member_id = ?", MEMBER_COLUMNS),
+        [member_id],
+        |row| map_member(row, key),
+    ).optional()
+}
+
+/// The client's household, spouse first, then dependents oldest first.
+pub fn list_members(conn: &Connection, key: &FieldKey, client_id: i64) -> Result<Vec<HouseholdMember>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM household_members
+         WHERE client_id = ?
+         ORDER BY role = 'dependent', birth_date, member_id",
+        MEMBER_COLUMNS
+    ))?;
+
+    let members = stmt.query_map([client_id], |row| map_member(row, key))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(members)
+}
+
+/// Removes a member from the household and from every return that covered them.
+pub fn remove_member(conn: &Connection, member_id: i64) -> Result<bool> {
+    let tx = write_transaction(conn)?;
+    tx.execute("DELETE FROM return_household_members WHERE member_id = ?", [member_id])?;
+    let removed = tx.execute("DELETE FROM household_members WHERE member_id = ?", [member_id])?;
+    tx.commit()?;
+    Ok(removed > 0)
+}
+
+/// Replaces the household members a return covers. Every member must belong
+/// to the return's client.
+pub fn set_return_members(
+    conn: &Connection,
+    key: &FieldKey,
+    tax_return_id: i64,
+    member_ids: &[i64],
+) -> std::result::Result<Vec<HouseholdMember>, HouseholdError> {
+    let tx = write_transaction(conn)?;
+    let tax_return = returns::get_tax_return(&tx, tax_return_id)?.ok_or(HouseholdError::NotFound)?;
+    for member_id in member_ids {
+        let member = get_member(&tx, key, *member_id)?;
+        if member.is_none_or(|m| m.client_id != tax_return.client_id) {
+            return Err(HouseholdError::NotInHousehold(*member_id));
+        }
+    }
+
+    tx.execute("DELETE FROM return_household_members WHERE tax_return_id = ?", [tax_return_id])?;
+    for member_id in member_ids {
+        tx.execute(
+            "INSERT OR IGNORE INTO return_household_members (tax_return_id, member_id) VALUES (?, ?)",
+            params![tax_return_id, member_id],
+        )?;
+    }
+
+    let members = return_members(&tx, key, tax_return_id)?;
+    tx.commit()?;
+    Ok(members)
+}
+
+/// The household members a return covers, spouse first.
+pub fn return_members(conn: &Connection, key: &FieldKey, tax_return_id: i64) -> Result<Vec<HouseholdMember>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM household_members
+         WHERE member_id IN (SELECT member_id FROM return_household_members WHERE tax_return_id = ?)
+         ORDER BY role = 'dependent', birth_date, member_id",
+        MEMBER_COLUMNS
+    ))?;
+
+    let members = stmt.query_map([tax_return_id], |row| map_member(row, key))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+    use chrono::NaiveDate;
+
+    fn member(role: MemberRole, first_name: &str, relationship: &str, born: (i32, u32, u32)) -> HouseholdMember {
+        HouseholdMember {
+            member_id: None,
+            client_id: 1,
+            role,
+            first_name: first_name.to_string(),
+            last_name: "Doe".to_string(),
+            relationship: relationship.to_string(),
+            birth_date: NaiveDate::from_ymd_opt(born.0, born.1, born.2).unwrap(),
+            social_security_number: "222-33-4444".to_string(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_household_and_return_links() {
+        // The baseline schema seeds client 1 with a 2023 return
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        let key = FieldKey::generate();
+
+        let spouse_id = add_member(&conn, &key, &member(MemberRole::Spouse, "Jane", "spouse", (1985, 4, 2))).unwrap();
+        let child_id = add_member(&conn, &key, &member(MemberRole::Dependent, "Sam", "son", (2015, 9, 30))).unwrap();
+        assert!(matches!(
+            add_member(&conn, &key, &member(MemberRole::Spouse, "Other", "spouse", (1980, 1, 1))),
+            Err(HouseholdError::SpouseExists)
+        ));
+
+        let stored: String = conn.query_row(
+            "SELECT social_security_number FROM household_members WHERE member_id = ?", [child_id], |row| row.get(0),
+        ).unwrap();
+        assert!(FieldKey::is_encrypted(&stored));
+        assert_eq!(get_member(&conn, &key, child_id).unwrap().unwrap().social_security_number, "222-33-4444");
+
+        let covered = set_return_members(&conn, &key, 1, &[child_id, spouse_id]).unwrap();
+        assert_eq!(covered.iter().map(|m| m.member_id).collect::<Vec<_>>(), vec![Some(spouse_id), Some(child_id)]);
+        assert!(matches!(set_return_members(&conn, &key, 1, &[999]), Err(HouseholdError::NotInHousehold(999))));
+
+        assert!(remove_member(&conn, child_id).unwrap());
+        assert_eq!(return_members(&conn, &key, 1).unwrap().len(), 1);
+    }
+}
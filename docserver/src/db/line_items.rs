@@ -0,0 +1,426 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::money::Money;
+use crate::tax::{is_known_category, other_category};
+use super::connection::write_transaction;
+use super::models::{LineItem, LineItemSection, ReturnKind, TaxReturn};
+use super::repository::StoreError;
+use super::returns;
+
+#[derive(Debug)]
+pub enum LineItemError {
+    NotFound,
+    ReturnNotFound,
+    // Superseded returns are history; changes go on an amendment
+    Superseded,
+    UnknownCategory { section: LineItemSection, category: String },
+    DocumentNotFound(i64),
+    Database(StoreError),
+}
+
+impl fmt::Display for LineItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineItemError::NotFound => write!(f, "Line item not found"),
+            LineItemError::ReturnNotFound => write!(f, "Tax return not found"),
+            LineItemError::Superseded => write!(f, "Tax return has been superseded; change its amendment instead"),
+            LineItemError::UnknownCategory { section, category } => {
+                write!(f, "Unknown {} category: {}", section.as_str(), category)
+            }
+            LineItemError::DocumentNotFound(id) => write!(f, "Document {} does not belong to the return's client", id),
+            LineItemError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LineItemError {
+    fn from(e: rusqlite::Error) -> Self {
+        LineItemError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for LineItemError {
+    fn from(e: StoreError) -> Self {
+        LineItemError::Database(e)
+    }
+}
+
+impl std::error::Error for LineItemError {}
+
+impl From<LineItemError> for StoreError {
+    fn from(e: LineItemError) -> Self {
+        match e {
+            LineItemError::Database(e) => e,
+            e => StoreError::Data(e.to_string()),
+        }
+    }
+}
+
+/// Every line item is filed under a category from [`crate::tax::CATEGORIES`];
+/// each insert, whichever backend, goes through this check.
+pub fn check_category(section: LineItemSection, category: &str) -> std::result::Result<(), LineItemError> {
+    if !is_known_category(section, category) {
+        return Err(LineItemError::UnknownCategory { section, category: category.to_string() });
+    }
+    Ok(())
+}
+
+/// Checks every entry in a return's maps, before any of them is written.
+pub fn check_maps(tax_return: &TaxReturn) -> std::result::Result<(), LineItemError> {
+    for section in LineItemSection::ALL {
+        for category in section_map(tax_return, section).keys() {
+            check_category(section, category)?;
+        }
+    }
+    Ok(())
+}
+
+const LINE_ITEM_COLUMNS: &str = "line_item_id, tax_return_id, section, category, payer, amount,
+    document_id, created_at, updated_at";
+
+fn map_line_item(row: &rusqlite::Row) -> Result<LineItem> {
+    Ok(LineItem {
+        line_item_id: Some(row.get(0)?),
+        tax_return_id: row.get(1)?,
+        section: row.get(2)?,
+        category: row.get(3)?,
+        payer: row.get(4)?,
+        amount: row.get(5)?,
+        document_id: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+/// The `TaxReturn` map a section is totalled into.
+pub fn section_map(tax_return: &TaxReturn, section: LineItemSection) -> &HashMap<String, Money> {
+    match section {
+        LineItemSection::Income => &tax_return.income_sources,
+        LineItemSection::Deduction => &tax_return.deductions,
+        LineItemSection::Credit => &tax_return.credits,
+    }
+}
+
+pub fn get_item(conn: &Connection, line_item_id: i64) -> Result<Option<LineItem>> {
+    conn.query_row(
+        &format!("SELECT {} FROM return_line_items WHERE line_item_id = ?", LINE_ITEM_COLUMNS),
+        [line_item_id],
+        map_line_item,
+    ).optional()
+}
+
+/// A return's line items in section order, optionally only one section.
+pub fn list_items(conn: &Connection, tax_return_id: i64, section: Option<LineItemSection>) -> Result<Vec<LineItem>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM return_line_items
+         WHERE tax_return_id = ?1 AND (?2 IS NULL OR section = ?2)
+         ORDER BY CASE section WHEN 'income' THEN 0 WHEN 'deduction' THEN 1 ELSE 2 END, category, line_item_id",
+        LINE_ITEM_COLUMNS
+    ))?;
+
+    let items = stmt.query_map(params![tax_return_id, section], map_line_item)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(items)
+}
+
+/// Line items across effective returns, for questions such as "every W-2
+/// wage line for 2023".
+pub fn search_items(
+    conn: &Connection,
+    section: Option<LineItemSection>,
+    category: Option<&str>,
+    client_id: Option<i64>,
+    tax_year: Option<i32>,
+) -> Result<Vec<LineItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT i.line_item_id, i.tax_return_id, i.section, i.category, i.payer, i.amount,
+                i.document_id, i.created_at, i.updated_at
+         FROM return_line_items i
+         JOIN tax_returns t ON t.tax_return_id = i.tax_return_id
+         WHERE t.return_kind != 'superseded'
+           AND (?1 IS NULL OR i.section = ?1)
+           AND (?2 IS NULL OR i.category = ?2)
+           AND (?3 IS NULL OR t.client_id = ?3)
+           AND (?4 IS NULL OR t.tax_year = ?4)
+         ORDER BY t.client_id, t.tax_year DESC, i.line_item_id"
+    )?;
+
+    let items = stmt.query_map(params![section, category, client_id, tax_year], map_line_item)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(items)
+}
+
+/// Checks that a line item can be written to its return.
+fn validate(conn: &Connection, item: &LineItem) -> std::result::Result<(), LineItemError> {
+    check_category(item.section, &item.category)?;
+    let tax_return = returns::get_tax_return(conn, item.tax_return_id)?.ok_or(LineItemError::ReturnNotFound)?;
+    if tax_return.return_kind == ReturnKind::Superseded {
+        return Err(LineItemError::Superseded);
+    }
+    if let Some(document_id) = item.document_id {
+        let owner: Option<i64> = conn.query_row(
+            "SELECT client_id FROM documents WHERE document_id = ?",
+            [document_id],
+            |row| row.get(0),
+        ).optional()?;
+        if owner != Some(tax_return.client_id) {
+            return Err(LineItemError::DocumentNotFound(document_id));
+        }
+    }
+    Ok(())
+}
+
+/// Inserts one line. Its return is not checked here; callers that take
+/// lines from outside use [`add_item`].
+pub(crate) fn insert_item(conn: &Connection, item: &LineItem) -> std::result::Result<i64, LineItemError> {
+    check_category(item.section, &item.category)?;
+    conn.execute(
+        "INSERT INTO return_line_items (tax_return_id, section, category, payer, amount, document_id)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        params![item.tax_return_id, item.section, item.category, item.payer, item.amount, item.document_id],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn add_item(conn: &Connection, item: &LineItem) -> std::result::Result<LineItem, LineItemError> {
+    let tx = write_transaction(conn)?;
+    validate(&tx, item)?;
+    let line_item_id = insert_item(&tx, item)?;
+    sync_return_maps(&tx, item.tax_return_id)?;
+
+    let added = get_item(&tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    tx.commit()?;
+    Ok(added)
+}
+
+/// Replaces a line item's section, category, payer, amount and document.
+pub fn update_item(
+    conn: &Connection,
+    line_item_id: i64,
+    item: &LineItem,
+) -> std::result::Result<LineItem, LineItemError> {
+    let tx = write_transaction(conn)?;
+    let existing = get_item(&tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    let item = LineItem { tax_return_id: existing.tax_return_id, ..item.clone() };
+    validate(&tx, &item)?;
+
+    tx.execute(
+        "UPDATE return_line_items
+         SET section = ?, category = ?, payer = ?, amount = ?, document_id = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE line_item_id = ?",
+        params![item.section, item.category, item.payer, item.amount, item.document_id, line_item_id],
+    )?;
+    sync_return_maps(&tx, existing.tax_return_id)?;
+
+    let updated = get_item(&tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    tx.commit()?;
+    Ok(updated)
+}
+
+pub fn remove_item(conn: &Connection, line_item_id: i64) -> std::result::Result<(), LineItemError> {
+    let tx = write_transaction(conn)?;
+    let existing = get_item(&tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    let tax_return = returns::get_tax_return(&tx, existing.tax_return_id)?.ok_or(LineItemError::ReturnNotFound)?;
+    if tax_return.return_kind == ReturnKind::Superseded {
+        return Err(LineItemError::Superseded);
+    }
+
+    tx.execute("DELETE FROM return_line_items WHERE line_item_id = ?", [line_item_id])?;
+    sync_return_maps(&tx, existing.tax_return_id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Adds one line per map entry, for returns written as maps.
+pub(crate) fn insert_items_from_map(
+    conn: &Connection,
+    tax_return_id: i64,
+    section: LineItemSection,
+    map: &HashMap<String, Money>,
+) -> std::result::Result<(), LineItemError> {
+    let mut categories: Vec<&String> = map.keys().collect();
+    categories.sort();
+    for category in categories {
+        insert_item(conn, &LineItem {
+            line_item_id: None,
+            tax_return_id,
+            section,
+            category: category.clone(),
+            payer: None,
+            amount: map[category],
+            document_id: None,
+            created_at: None,
+            updated_at: None,
+        })?;
+    }
+    Ok(())
+}
+
+/// Gives every return written before line items existed one line per map
+/// entry, dated like the return. Run once, by the migration that adds the
+/// table. Map keys were typed by hand, so one outside the vocabulary goes
+/// under its section's catch-all category with the key kept as the payer,
+/// and a map that is not valid JSON is read as empty, as returns are. The
+/// maps of such returns are rewritten to match their lines.
+pub(crate) fn backfill_from_maps(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT tax_return_id, income_sources, deductions, credits, updated_at FROM tax_returns ORDER BY tax_return_id"
+    )?;
+    let returns = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            [row.get::<_, String>(1)?, row.get(2)?, row.get(3)?],
+            row.get::<_, rusqlite::types::Value>(4)?,
+        ))
+    })?.collect::<Result<Vec<_>>>()?;
+
+    for (tax_return_id, maps, updated_at) in returns {
+        let mut rewrite_maps = false;
+        for (section, json) in LineItemSection::ALL.into_iter().zip(maps) {
+            let map: HashMap<String, Money> = serde_json::from_str(&json).unwrap_or_else(|_| {
+                rewrite_maps = true;
+                HashMap::new()
+            });
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            for key in keys {
+                let (category, payer) = if is_known_category(section, key) {
+                    (key.clone(), None)
+                } else {
+                    rewrite_maps = true;
+                    (other_category(section).to_string(), Some(key.clone()))
+                };
+                conn.execute(
+                    "INSERT INTO return_line_items (tax_return_id, section, category, payer, amount)
+                     VALUES (?, ?, ?, ?, ?)",
+                    params![tax_return_id, section, category, payer, map[key]],
+                )?;
+            }
+        }
+        if rewrite_maps {
+            sync_return_maps(conn, tax_return_id)?;
+            conn.execute(
+                "UPDATE tax_returns SET updated_at = ? WHERE tax_return_id = ?",
+                params![updated_at, tax_return_id],
+            )?;
+        }
+        conn.execute(
+            "UPDATE return_line_items
+             SET created_at = (SELECT created_at FROM tax_returns WHERE tax_return_id = ?1),
+                 updated_at = (SELECT updated_at FROM tax_returns WHERE tax_return_id = ?1)
+             WHERE tax_return_id = ?1",
+            [tax_return_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Copies one section's lines from one return to another, keeping payers
+/// and documents.
+pub(crate) fn copy_items(conn: &Connection, from_return_id: i64, to_return_id: i64, section: LineItemSection) -> Result<()> {
+    conn.execute(
+        "INSERT INTO return_line_items (tax_return_id, section, category, payer, amount, document_id, created_at)
+         SELECT ?, section, category, payer, amount, document_id, created_at
+         FROM return_line_items WHERE tax_return_id = ? AND section = ? ORDER BY line_item_id",
+        params![to_return_id, from_return_id, section],
+    )?;
+    Ok(())
+}
+
+/// Rewrites a return's `income_sources`, `deductions` and `credits` maps as
+/// the per-category totals of its line items.
+pub fn sync_return_maps(conn: &Connection, tax_return_id: i64) -> Result<()> {
+    let mut maps: HashMap<LineItemSection, HashMap<String, Money>> =
+        LineItemSection::ALL.iter().map(|section| (*section, HashMap::new())).collect();
+    for item in list_items(conn, tax_return_id, None)? {
+        *maps.entry(item.section).or_default().entry(item.category).or_default() += item.amount;
+    }
+
+    let to_json = |section: LineItemSection| {
+        serde_json::to_string(&maps[&section])
+            .map_err(|_| rusqlite::Error::InvalidParameterName("Serialization error".to_string()))
+    };
+    conn.execute(
+        "UPDATE tax_returns SET income_sources = ?, deductions = ?, credits = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE tax_return_id = ?",
+        params![
+            to_json(LineItemSection::Income)?,
+            to_json(LineItemSection::Deduction)?,
+            to_json(LineItemSection::Credit)?,
+            tax_return_id,
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+
+    fn setup() -> Connection {
+        // The baseline schema seeds client 1 with a 2023 return
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        conn
+    }
+
+    fn wages(payer: &str, dollars: i64) -> LineItem {
+        LineItem {
+            line_item_id: None,
+            tax_return_id: 1,
+            section: LineItemSection::Income,
+            category: "wages".to_string(),
+            payer: Some(payer.to_string()),
+            amount: Money::from_dollars(dollars),
+            document_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_migration_explodes_maps() {
+        let conn = setup();
+        let items = list_items(&conn, 1, None).unwrap();
+        let lines: Vec<(LineItemSection, &str, Money)> = items.iter()
+            .map(|i| (i.section, i.category.as_str(), i.amount))
+            .collect();
+        assert_eq!(lines, vec![
+            (LineItemSection::Income, "interest", Money::from_dollars(1_000)),
+            (LineItemSection::Income, "wages", Money::from_dollars(50_000)),
+            (LineItemSection::Deduction, "standard_deduction", Money::from_dollars(13_000)),
+            (LineItemSection::Credit, "child_tax_credit", Money::from_dollars(2_000)),
+        ]);
+    }
+
+    #[test]
+    fn test_maps_are_totals_of_line_items() {
+        let conn = setup();
+        let second_job = add_item(&conn, &wages("Acme Corp", 12_500)).unwrap();
+        assert_eq!(second_job.payer.as_deref(), Some("Acme Corp"));
+
+        let tax_return = returns::get_tax_return(&conn, 1).unwrap().unwrap();
+        assert_eq!(tax_return.income_sources["wages"], Money::from_dollars(62_500));
+        assert_eq!(tax_return.income_sources["interest"], Money::from_dollars(1_000));
+
+        let line_item_id = second_job.line_item_id.unwrap();
+        update_item(&conn, line_item_id, &wages("Acme Corp", 10_000)).unwrap();
+        assert_eq!(returns::get_tax_return(&conn, 1).unwrap().unwrap().income_sources["wages"], Money::from_dollars(60_000));
+
+        remove_item(&conn, line_item_id).unwrap();
+        assert_eq!(returns::get_tax_return(&conn, 1).unwrap().unwrap().income_sources["wages"], Money::from_dollars(50_000));
+        assert_eq!(search_items(&conn, None, Some("wages"), Some(1), Some(2023)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_category_is_rejected() {
+        let conn = setup();
+        let item = LineItem { category: "lottery_winnings_maybe".to_string(), ..wages("State", 100) };
+        assert!(matches!(add_item(&conn, &item), Err(LineItemError::UnknownCategory { .. })));
+        let item = LineItem { document_id: Some(999), ..wages("Acme Corp", 100) };
+        assert!(matches!(add_item(&conn, &item), Err(LineItemError::DocumentNotFound(999))));
+    }
+}
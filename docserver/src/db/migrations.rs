@@ -0,0 +1,145 @@
+use rusqlite::{Connection, Result, Transaction, TransactionBehavior};
+
+use super::line_items;
+
+// Ordered list of schema migrations. The index of each entry (plus one) is the
+// schema version it produces, tracked through SQLite's `user_version` pragma.
+// Never change the schema an entry produces once released; append a new one
+// instead.
+const MIGRATIONS: &[&str] = &[
+    include_str!("schema.sql"),
+    include_str!("migrations/0002_documents.sql"),
+    include_str!("migrations/0003_income_proposals.sql"),
+    include_str!("migrations/0004_money_cents.sql"),
+    include_str!("migrations/0005_amended_returns.sql"),
+    include_str!("migrations/0006_return_workflow.sql"),
+    include_str!("migrations/0007_checklists.sql"),
+    include_str!("migrations/0008_upload_links.sql"),
+    include_str!("migrations/0009_share_links.sql"),
+    include_str!("migrations/0010_client_portal.sql"),
+    include_str!("migrations/0011_household.sql"),
+    include_str!("migrations/0012_line_items.sql"),
+    include_str!("migrations/0013_estimated_payments.sql"),
+    include_str!("migrations/0014_deadlines.sql"),
+    include_str!("migrations/0015_jurisdictions.sql"),
+    include_str!("migrations/0016_document_checksums.sql"),
+    include_str!("migrations/0017_return_types.sql"),
+    include_str!("migrations/0018_effective_returns.sql"),
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Brings the database up to the latest schema version, applying each pending
+/// migration in its own transaction. The version is re-read under a write lock
+/// so concurrent openers never apply the same migration twice.
+pub fn run(conn: &Connection) -> Result<()> {
+    migrate_to(conn, latest_version())
+}
+
+fn migrate_to(conn: &Connection, target: i64) -> Result<()> {
+    for (index, sql) in MIGRATIONS.iter().enumerate() {
+        let version = index as i64 + 1;
+        if version > target {
+            break;
+        }
+        if version <= current_version(conn)? {
+            continue;
+        }
+
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+        if version <= current_version(&tx)? {
+            continue;
+        }
+        tx.execute_batch(sql)?;
+        after_sql(&tx, version)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// The parts of a migration SQL cannot do, run after its SQL in the same
+/// transaction.
+fn after_sql(tx: &Transaction, version: i64) -> Result<()> {
+    match version {
+        12 => line_items::backfill_from_maps(tx),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::LineItemSection;
+    use crate::money::Money;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_money_migration_keeps_cents() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_to(&conn, 3).unwrap();
+        conn.execute(
+            "INSERT INTO tax_returns (
+                client_id, tax_year, filing_status, income_sources, deductions, credits,
+                taxes_paid, tax_liability, refund_or_amount_due
+            ) VALUES (1, 2022, 'Single', '{\"wages\": 50000.1, \"interest\": 0.07}', '{}', '{}',
+                1234.56, 1000.10, 234.46)",
+            [],
+        ).unwrap();
+        let id = conn.last_insert_rowid();
+
+        run(&conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        let (income, taxes_paid, refund): (String, Money, Money) = conn.query_row(
+            "SELECT income_sources, taxes_paid, refund_or_amount_due FROM tax_returns WHERE tax_return_id = ?",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).unwrap();
+        let income: std::collections::HashMap<String, String> = serde_json::from_str(&income).unwrap();
+        assert_eq!(income["wages"], "50000.10");
+        assert_eq!(income["interest"], "0.07");
+        assert_eq!(taxes_paid, Money::from_cents(123456));
+        assert_eq!(refund, Money::from_cents(23446));
+
+        let lines: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM return_line_items WHERE tax_return_id = ?", [id], |row| row.get(0),
+        ).unwrap();
+        assert_eq!(lines, 2);
+    }
+
+    #[test]
+    fn test_line_item_backfill_keeps_unknown_categories() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_to(&conn, 11).unwrap();
+        conn.execute(
+            "UPDATE tax_returns SET income_sources = '{\"wages\": \"100.00\", \"Lottery win\": \"10.00\"}',
+                                    credits = 'not json'
+             WHERE tax_return_id = 1",
+            [],
+        ).unwrap();
+
+        run(&conn).unwrap();
+        let items = line_items::list_items(&conn, 1, None).unwrap();
+        let lottery = items.iter().find(|item| item.payer.as_deref() == Some("Lottery win")).unwrap();
+        assert_eq!((lottery.category.as_str(), lottery.amount), ("other_income", Money::from_cents(1000)));
+        assert!(items.iter().any(|item| item.category == "wages" && item.payer.is_none()));
+        assert!(items.iter().all(|item| item.section != LineItemSection::Credit));
+
+        let (income, credits): (String, String) = conn.query_row(
+            "SELECT income_sources, credits FROM tax_returns WHERE tax_return_id = 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        let income: HashMap<String, Money> = serde_json::from_str(&income).unwrap();
+        assert_eq!(income.get("other_income"), Some(&Money::from_cents(1000)));
+        assert_eq!(credits, "{}");
+    }
+}
@@ -0,0 +1,17 @@
+-- Create documents table: one row per uploaded file under <root>/<client_id>/
+CREATE TABLE IF NOT EXISTS documents (
+    document_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    document_type VARCHAR(20),          -- e.g. 'W-2', '1099-INT'; NULL when unrecognized
+    tax_year INTEGER,
+    confidence REAL NOT NULL DEFAULT 0,
+    type_overridden BOOLEAN NOT NULL DEFAULT 0,
+    extracted_text TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (client_id, file_name),
+    FOREIGN KEY (client_id) REFERENCES clients(client_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_documents_client_type ON documents(client_id, document_type);
@@ -0,0 +1,42 @@
+-- Amounts extracted from W-2/1099 documents, pending preparer review
+CREATE TABLE IF NOT EXISTS income_proposals (
+    proposal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    document_id INTEGER NOT NULL,
+    client_id INTEGER NOT NULL,
+    tax_year INTEGER,
+    status VARCHAR(10) NOT NULL DEFAULT 'pending',  -- pending, accepted, rejected
+    tax_return_id INTEGER,                           -- set once accepted
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at TIMESTAMP,
+    FOREIGN KEY (document_id) REFERENCES documents(document_id),
+    FOREIGN KEY (client_id) REFERENCES clients(client_id),
+    FOREIGN KEY (tax_return_id) REFERENCES tax_returns(tax_return_id)
+);
+
+CREATE TABLE IF NOT EXISTS income_proposal_items (
+    item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    proposal_id INTEGER NOT NULL,
+    target VARCHAR(20) NOT NULL,     -- income_source or taxes_paid
+    category VARCHAR(50) NOT NULL,
+    box_label VARCHAR(100) NOT NULL,
+    amount DECIMAL(10,2) NOT NULL,
+    FOREIGN KEY (proposal_id) REFERENCES income_proposals(proposal_id)
+);
+
+-- Source documents behind amounts accepted into a return
+CREATE TABLE IF NOT EXISTS income_provenance (
+    provenance_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tax_return_id INTEGER NOT NULL,
+    target VARCHAR(20) NOT NULL,
+    category VARCHAR(50) NOT NULL,
+    amount DECIMAL(10,2) NOT NULL,
+    document_id INTEGER NOT NULL,
+    proposal_id INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (tax_return_id) REFERENCES tax_returns(tax_return_id),
+    FOREIGN KEY (document_id) REFERENCES documents(document_id),
+    FOREIGN KEY (proposal_id) REFERENCES income_proposals(proposal_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_income_proposals_document ON income_proposals(document_id);
+CREATE INDEX IF NOT EXISTS idx_income_provenance_return ON income_provenance(tax_return_id);
@@ -0,0 +1,41 @@
+-- Store money as integer cents instead of REAL, and JSON map values as
+-- decimal strings ("1234.56"). SQLite cannot change a column's type in
+-- place, so tax_returns is rebuilt.
+CREATE TABLE tax_returns_new (
+    tax_return_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    tax_year INTEGER NOT NULL,
+    filing_status VARCHAR(20) NOT NULL,
+    income_sources TEXT NOT NULL,  -- Stored as JSON, amounts as decimal strings
+    deductions TEXT NOT NULL,      -- Stored as JSON, amounts as decimal strings
+    credits TEXT NOT NULL,         -- Stored as JSON, amounts as decimal strings
+    taxes_paid INTEGER NOT NULL,            -- cents
+    tax_liability INTEGER NOT NULL,         -- cents
+    refund_or_amount_due INTEGER NOT NULL,  -- cents
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id)
+);
+
+INSERT INTO tax_returns_new (
+    tax_return_id, client_id, tax_year, filing_status, income_sources, deductions, credits,
+    taxes_paid, tax_liability, refund_or_amount_due, created_at, updated_at
+)
+SELECT
+    tax_return_id, client_id, tax_year, filing_status,
+    (SELECT json_group_object(key, printf('%.2f', value)) FROM json_each(t.income_sources)),
+    (SELECT json_group_object(key, printf('%.2f', value)) FROM json_each(t.deductions)),
+    (SELECT json_group_object(key, printf('%.2f', value)) FROM json_each(t.credits)),
+    CAST(ROUND(taxes_paid * 100) AS INTEGER),
+    CAST(ROUND(tax_liability * 100) AS INTEGER),
+    CAST(ROUND(refund_or_amount_due * 100) AS INTEGER),
+    created_at, updated_at
+FROM tax_returns t;
+
+DROP TABLE tax_returns;
+ALTER TABLE tax_returns_new RENAME TO tax_returns;
+CREATE INDEX IF NOT EXISTS idx_tax_returns_client_year ON tax_returns(client_id, tax_year);
+
+-- Proposal and provenance amounts become cents as well
+UPDATE income_proposal_items SET amount = CAST(ROUND(amount * 100) AS INTEGER);
+UPDATE income_provenance SET amount = CAST(ROUND(amount * 100) AS INTEGER);
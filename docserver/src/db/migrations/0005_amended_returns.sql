@@ -0,0 +1,7 @@
+-- Amended return (1040-X) tracking. An amendment points at the return it
+-- replaces, which is then marked superseded.
+ALTER TABLE tax_returns ADD COLUMN return_kind VARCHAR(10) NOT NULL DEFAULT 'original';  -- original, amended, superseded
+ALTER TABLE tax_returns ADD COLUMN parent_return_id INTEGER REFERENCES tax_returns(tax_return_id);
+ALTER TABLE tax_returns ADD COLUMN amendment_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_tax_returns_parent ON tax_returns(parent_return_id);
@@ -0,0 +1,22 @@
+-- Engagement workflow: where each return is in the preparation process, who
+-- owns the current stage, and every stage change.
+ALTER TABLE tax_returns ADD COLUMN status VARCHAR(30) NOT NULL DEFAULT 'documents_requested';
+ALTER TABLE tax_returns ADD COLUMN assignee VARCHAR(100);
+
+CREATE TABLE IF NOT EXISTS return_status_history (
+    history_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tax_return_id INTEGER NOT NULL,
+    from_status VARCHAR(30),  -- NULL for the return's first stage
+    to_status VARCHAR(30) NOT NULL,
+    assignee VARCHAR(100),
+    note TEXT,
+    changed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (tax_return_id) REFERENCES tax_returns(tax_return_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tax_returns_status ON tax_returns(status);
+CREATE INDEX IF NOT EXISTS idx_return_status_history_return ON return_status_history(tax_return_id);
+
+-- Existing returns start their history at the default stage
+INSERT INTO return_status_history (tax_return_id, to_status, changed_at)
+SELECT tax_return_id, status, created_at FROM tax_returns;
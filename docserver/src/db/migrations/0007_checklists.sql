@@ -0,0 +1,20 @@
+-- Documents expected from each client for a tax year, generated from the
+-- prior year and ticked off as matching uploads are classified.
+CREATE TABLE IF NOT EXISTS checklist_items (
+    item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    tax_year INTEGER NOT NULL,
+    -- What the item was generated from, e.g. 'document:12' or 'income_sources:wages'
+    item_key VARCHAR(100) NOT NULL,
+    document_type VARCHAR(20) NOT NULL,
+    description TEXT NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'pending',  -- pending, received
+    received_document_id INTEGER,
+    received_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id),
+    FOREIGN KEY (received_document_id) REFERENCES documents(document_id),
+    UNIQUE (client_id, tax_year, item_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_checklist_items_client ON checklist_items(client_id, tax_year, status);
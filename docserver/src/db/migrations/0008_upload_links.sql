@@ -0,0 +1,19 @@
+-- Expiring links that let a client upload documents without an account.
+-- Only the nonce is stored; the token itself is signed and never persisted.
+CREATE TABLE IF NOT EXISTS upload_links (
+    link_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    checklist_item_id INTEGER,
+    nonce VARCHAR(32) NOT NULL UNIQUE,
+    note TEXT,
+    max_uploads INTEGER NOT NULL DEFAULT 1,
+    upload_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id),
+    FOREIGN KEY (checklist_item_id) REFERENCES checklist_items(item_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_upload_links_client ON upload_links(client_id);
@@ -0,0 +1,29 @@
+-- Expiring download links for sharing a stored file with a third party,
+-- and a record of every attempt to use one.
+CREATE TABLE IF NOT EXISTS share_links (
+    link_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER,  -- from the file's client folder, when it has one
+    file_path TEXT NOT NULL,  -- relative to the root path, as served by /files/<path>
+    recipient TEXT,
+    nonce VARCHAR(32) NOT NULL UNIQUE,
+    password_hash TEXT,
+    max_downloads INTEGER,  -- NULL for unlimited
+    download_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id)
+);
+
+CREATE TABLE IF NOT EXISTS share_link_accesses (
+    access_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    link_id INTEGER NOT NULL,
+    outcome VARCHAR(20) NOT NULL,  -- downloaded, password_required, wrong_password, unavailable
+    remote_addr VARCHAR(45),
+    user_agent TEXT,
+    accessed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (link_id) REFERENCES share_links(link_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_links_client ON share_links(client_id);
+CREATE INDEX IF NOT EXISTS idx_share_link_accesses_link ON share_link_accesses(link_id);
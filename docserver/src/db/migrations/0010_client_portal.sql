@@ -0,0 +1,41 @@
+-- Client portal: logins for clients, their sessions, and contact detail
+-- changes they request for staff to approve.
+CREATE TABLE IF NOT EXISTS portal_users (
+    user_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    email VARCHAR(100) NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    disabled BOOLEAN NOT NULL DEFAULT 0,
+    last_login_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id)
+);
+
+CREATE TABLE IF NOT EXISTS portal_sessions (
+    session_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    token_digest VARCHAR(64) NOT NULL UNIQUE,  -- SHA-256 of the bearer token
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES portal_users(user_id)
+);
+
+CREATE TABLE IF NOT EXISTS contact_change_requests (
+    request_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- Requested values; NULL leaves the current value alone
+    address TEXT,
+    phone_number VARCHAR(20),
+    email VARCHAR(100),
+    status VARCHAR(10) NOT NULL DEFAULT 'pending',  -- pending, approved, rejected
+    review_note TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id),
+    FOREIGN KEY (user_id) REFERENCES portal_users(user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_portal_users_client ON portal_users(client_id);
+CREATE INDEX IF NOT EXISTS idx_contact_change_requests_status ON contact_change_requests(status);
@@ -0,0 +1,27 @@
+-- Household: a client's spouse and dependents, and which of them each
+-- return covers. SSNs are stored encrypted, like the client's own.
+CREATE TABLE IF NOT EXISTS household_members (
+    member_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    role VARCHAR(10) NOT NULL,  -- spouse, dependent
+    first_name VARCHAR(50) NOT NULL,
+    last_name VARCHAR(50) NOT NULL,
+    relationship VARCHAR(30) NOT NULL,  -- spouse, son, daughter, parent, ...
+    birth_date DATE NOT NULL,
+    social_security_number TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id)
+);
+
+-- At most one spouse per client
+CREATE UNIQUE INDEX IF NOT EXISTS idx_household_members_spouse
+    ON household_members(client_id) WHERE role = 'spouse';
+
+CREATE TABLE IF NOT EXISTS return_household_members (
+    tax_return_id INTEGER NOT NULL,
+    member_id INTEGER NOT NULL,
+    PRIMARY KEY (tax_return_id, member_id),
+    FOREIGN KEY (tax_return_id) REFERENCES tax_returns(tax_return_id),
+    FOREIGN KEY (member_id) REFERENCES household_members(member_id)
+);
@@ -0,0 +1,21 @@
+-- Normalized income, deduction and credit lines. The JSON maps on
+-- tax_returns are kept as a derived total per category.
+CREATE TABLE IF NOT EXISTS return_line_items (
+    line_item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tax_return_id INTEGER NOT NULL,
+    section VARCHAR(10) NOT NULL,  -- income, deduction, credit
+    category VARCHAR(50) NOT NULL,
+    payer TEXT,                    -- employer, bank, lender, ...
+    amount INTEGER NOT NULL,       -- cents
+    document_id INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (tax_return_id) REFERENCES tax_returns(tax_return_id),
+    FOREIGN KEY (document_id) REFERENCES documents(document_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_return_line_items_return ON return_line_items(tax_return_id, section);
+CREATE INDEX IF NOT EXISTS idx_return_line_items_category ON return_line_items(section, category);
+
+-- One line per existing map entry is added by `line_items::backfill_from_maps`,
+-- which checks each category against the vocabulary.
@@ -0,0 +1,20 @@
+-- Form 1040-ES estimated tax payments, one row per payment.
+CREATE TABLE IF NOT EXISTS estimated_payments (
+    payment_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INTEGER NOT NULL,
+    tax_year INTEGER NOT NULL,
+    quarter INTEGER NOT NULL CHECK (quarter BETWEEN 1 AND 4),
+    amount INTEGER NOT NULL,  -- cents
+    paid_on DATE NOT NULL,
+    method VARCHAR(20) NOT NULL,  -- direct_pay, eftps, card, check, applied_overpayment
+    note TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_estimated_payments_client_year
+    ON estimated_payments(client_id, tax_year);
+
+-- The part of taxes_paid that came from estimated payments, so the total
+-- can be replaced when payments change without touching withholding.
+ALTER TABLE tax_returns ADD COLUMN estimated_payments_applied INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,22 @@
+-- Filing deadlines: what moves a return's due date away from April 15.
+
+-- Fiscal-year filers and the state a client files in
+CREATE TABLE IF NOT EXISTS client_tax_profiles (
+    client_id INTEGER PRIMARY KEY,
+    fiscal_year_end_month INTEGER CHECK (fiscal_year_end_month BETWEEN 1 AND 12),  -- NULL for calendar year
+    home_state CHAR(2),
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (client_id) REFERENCES clients(client_id)
+);
+
+-- Form 4868 automatic extensions, at most one per return
+CREATE TABLE IF NOT EXISTS return_extensions (
+    extension_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tax_return_id INTEGER NOT NULL UNIQUE,
+    filed_on DATE NOT NULL,
+    estimated_tax_liability INTEGER NOT NULL DEFAULT 0,  -- cents
+    amount_paid INTEGER NOT NULL DEFAULT 0,  -- cents
+    confirmation_number TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (tax_return_id) REFERENCES tax_returns(tax_return_id)
+);
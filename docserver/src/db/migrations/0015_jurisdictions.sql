@@ -0,0 +1,7 @@
+-- State returns: every return is filed with one jurisdiction, and a state
+-- return points at the federal return for the same client and year.
+ALTER TABLE tax_returns ADD COLUMN jurisdiction VARCHAR(10) NOT NULL DEFAULT 'federal';
+ALTER TABLE tax_returns ADD COLUMN federal_return_id INTEGER REFERENCES tax_returns(tax_return_id);
+
+CREATE INDEX IF NOT EXISTS idx_tax_returns_federal_return ON tax_returns(federal_return_id);
+CREATE INDEX IF NOT EXISTS idx_tax_returns_jurisdiction ON tax_returns(client_id, tax_year, jurisdiction);
@@ -0,0 +1,259 @@
+-- The SQLite schema as of its migration 0015, for a new PostgreSQL
+-- database. Money is integer cents and JSON maps are text, as in SQLite.
+-- Unlike schema.sql, no sample client is inserted.
+
+CREATE TABLE clients (
+    client_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    first_name VARCHAR(50) NOT NULL,
+    last_name VARCHAR(50) NOT NULL,
+    social_security_number TEXT NOT NULL,  -- encrypted with the field key
+    address VARCHAR(255) NOT NULL,
+    phone_number VARCHAR(20) NOT NULL,
+    email VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_clients_ssn ON clients(social_security_number);
+
+CREATE TABLE tax_returns (
+    tax_return_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    tax_year INTEGER NOT NULL,
+    filing_status VARCHAR(20) NOT NULL,
+    income_sources TEXT NOT NULL,  -- Stored as JSON, amounts as decimal strings
+    deductions TEXT NOT NULL,      -- Stored as JSON, amounts as decimal strings
+    credits TEXT NOT NULL,         -- Stored as JSON, amounts as decimal strings
+    taxes_paid BIGINT NOT NULL,            -- cents
+    tax_liability BIGINT NOT NULL,         -- cents
+    refund_or_amount_due BIGINT NOT NULL,  -- cents
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    return_kind VARCHAR(10) NOT NULL DEFAULT 'original',
+    parent_return_id BIGINT REFERENCES tax_returns(tax_return_id),
+    amendment_reason TEXT,
+    status VARCHAR(30) NOT NULL DEFAULT 'documents_requested',
+    assignee VARCHAR(100),
+    estimated_payments_applied BIGINT NOT NULL DEFAULT 0,  -- cents
+    jurisdiction VARCHAR(10) NOT NULL DEFAULT 'federal',
+    federal_return_id BIGINT REFERENCES tax_returns(tax_return_id)
+);
+CREATE INDEX idx_tax_returns_client_year ON tax_returns(client_id, tax_year);
+CREATE INDEX idx_tax_returns_parent ON tax_returns(parent_return_id);
+CREATE INDEX idx_tax_returns_status ON tax_returns(status);
+CREATE INDEX idx_tax_returns_federal_return ON tax_returns(federal_return_id);
+CREATE INDEX idx_tax_returns_jurisdiction ON tax_returns(client_id, tax_year, jurisdiction);
+
+CREATE TABLE documents (
+    document_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    file_name VARCHAR(255) NOT NULL,
+    document_type VARCHAR(20),          -- e.g. 'W-2', '1099-INT'; NULL when unrecognized
+    tax_year INTEGER,
+    confidence DOUBLE PRECISION NOT NULL DEFAULT 0,
+    type_overridden BOOLEAN NOT NULL DEFAULT FALSE,
+    extracted_text TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (client_id, file_name)
+);
+CREATE INDEX idx_documents_client_type ON documents(client_id, document_type);
+
+CREATE TABLE income_proposals (
+    proposal_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    document_id BIGINT NOT NULL REFERENCES documents(document_id),
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    tax_year INTEGER,
+    status VARCHAR(10) NOT NULL DEFAULT 'pending',  -- pending, accepted, rejected
+    tax_return_id BIGINT REFERENCES tax_returns(tax_return_id),  -- set once accepted
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at TIMESTAMPTZ
+);
+CREATE INDEX idx_income_proposals_document ON income_proposals(document_id);
+
+CREATE TABLE income_proposal_items (
+    item_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    proposal_id BIGINT NOT NULL REFERENCES income_proposals(proposal_id),
+    target VARCHAR(20) NOT NULL,     -- income_source or taxes_paid
+    category VARCHAR(50) NOT NULL,
+    box_label VARCHAR(100) NOT NULL,
+    amount BIGINT NOT NULL           -- cents
+);
+
+CREATE TABLE income_provenance (
+    provenance_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    tax_return_id BIGINT NOT NULL REFERENCES tax_returns(tax_return_id),
+    target VARCHAR(20) NOT NULL,
+    category VARCHAR(50) NOT NULL,
+    amount BIGINT NOT NULL,          -- cents
+    document_id BIGINT NOT NULL REFERENCES documents(document_id),
+    proposal_id BIGINT NOT NULL REFERENCES income_proposals(proposal_id),
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_income_provenance_return ON income_provenance(tax_return_id);
+
+CREATE TABLE return_status_history (
+    history_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    tax_return_id BIGINT NOT NULL REFERENCES tax_returns(tax_return_id),
+    from_status VARCHAR(30),  -- NULL for the return's first stage
+    to_status VARCHAR(30) NOT NULL,
+    assignee VARCHAR(100),
+    note TEXT,
+    changed_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_return_status_history_return ON return_status_history(tax_return_id);
+
+CREATE TABLE checklist_items (
+    item_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    tax_year INTEGER NOT NULL,
+    -- What the item was generated from, e.g. 'document:12' or 'income_sources:wages'
+    item_key VARCHAR(100) NOT NULL,
+    document_type VARCHAR(20) NOT NULL,
+    description TEXT NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'pending',  -- pending, received
+    received_document_id BIGINT REFERENCES documents(document_id),
+    received_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (client_id, tax_year, item_key)
+);
+CREATE INDEX idx_checklist_items_client ON checklist_items(client_id, tax_year, status);
+
+CREATE TABLE upload_links (
+    link_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    checklist_item_id BIGINT REFERENCES checklist_items(item_id),
+    nonce VARCHAR(32) NOT NULL UNIQUE,
+    note TEXT,
+    max_uploads INTEGER NOT NULL DEFAULT 1,
+    upload_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_upload_links_client ON upload_links(client_id);
+
+CREATE TABLE share_links (
+    link_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT REFERENCES clients(client_id),  -- from the file's client folder, when it has one
+    file_path TEXT NOT NULL,  -- relative to the root path, as served by /files/<path>
+    recipient TEXT,
+    nonce VARCHAR(32) NOT NULL UNIQUE,
+    password_hash TEXT,
+    max_downloads INTEGER,  -- NULL for unlimited
+    download_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_share_links_client ON share_links(client_id);
+
+CREATE TABLE share_link_accesses (
+    access_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    link_id BIGINT NOT NULL REFERENCES share_links(link_id),
+    outcome VARCHAR(20) NOT NULL,  -- downloaded, password_required, wrong_password, unavailable
+    remote_addr VARCHAR(45),
+    user_agent TEXT,
+    accessed_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_share_link_accesses_link ON share_link_accesses(link_id);
+
+CREATE TABLE portal_users (
+    user_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    email VARCHAR(100) NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    disabled BOOLEAN NOT NULL DEFAULT FALSE,
+    last_login_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_portal_users_client ON portal_users(client_id);
+
+CREATE TABLE portal_sessions (
+    session_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES portal_users(user_id),
+    token_digest VARCHAR(64) NOT NULL UNIQUE,  -- SHA-256 of the bearer token
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE contact_change_requests (
+    request_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    user_id BIGINT NOT NULL REFERENCES portal_users(user_id),
+    -- Requested values; NULL leaves the current value alone
+    address TEXT,
+    phone_number VARCHAR(20),
+    email VARCHAR(100),
+    status VARCHAR(10) NOT NULL DEFAULT 'pending',  -- pending, approved, rejected
+    review_note TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at TIMESTAMPTZ
+);
+CREATE INDEX idx_contact_change_requests_status ON contact_change_requests(status);
+
+CREATE TABLE household_members (
+    member_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    role VARCHAR(10) NOT NULL,  -- spouse, dependent
+    first_name VARCHAR(50) NOT NULL,
+    last_name VARCHAR(50) NOT NULL,
+    relationship VARCHAR(30) NOT NULL,  -- spouse, son, daughter, parent, ...
+    birth_date DATE NOT NULL,
+    social_security_number TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX idx_household_members_spouse
+    ON household_members(client_id) WHERE role = 'spouse';
+
+CREATE TABLE return_household_members (
+    tax_return_id BIGINT NOT NULL REFERENCES tax_returns(tax_return_id),
+    member_id BIGINT NOT NULL REFERENCES household_members(member_id),
+    PRIMARY KEY (tax_return_id, member_id)
+);
+
+CREATE TABLE return_line_items (
+    line_item_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    tax_return_id BIGINT NOT NULL REFERENCES tax_returns(tax_return_id),
+    section VARCHAR(10) NOT NULL,  -- income, deduction, credit
+    category VARCHAR(50) NOT NULL,
+    payer TEXT,                    -- employer, bank, lender, ...
+    amount BIGINT NOT NULL,        -- cents
+    document_id BIGINT REFERENCES documents(document_id),
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_return_line_items_return ON return_line_items(tax_return_id, section);
+CREATE INDEX idx_return_line_items_category ON return_line_items(section, category);
+
+CREATE TABLE estimated_payments (
+    payment_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id BIGINT NOT NULL REFERENCES clients(client_id),
+    tax_year INTEGER NOT NULL,
+    quarter INTEGER NOT NULL CHECK (quarter BETWEEN 1 AND 4),
+    amount BIGINT NOT NULL,  -- cents
+    paid_on DATE NOT NULL,
+    method VARCHAR(20) NOT NULL,  -- direct_pay, eftps, card, check, applied_overpayment
+    note TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_estimated_payments_client_year ON estimated_payments(client_id, tax_year);
+
+CREATE TABLE client_tax_profiles (
+    client_id BIGINT PRIMARY KEY REFERENCES clients(client_id),
+    fiscal_year_end_month INTEGER CHECK (fiscal_year_end_month BETWEEN 1 AND 12),  -- NULL for calendar year
+    home_state CHAR(2),
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE return_extensions (
+    extension_id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    tax_return_id BIGINT NOT NULL UNIQUE REFERENCES tax_returns(tax_return_id),
+    filed_on DATE NOT NULL,
+    estimated_tax_liability BIGINT NOT NULL DEFAULT 0,  -- cents
+    amount_paid BIGINT NOT NULL DEFAULT 0,  -- cents
+    confirmation_number TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
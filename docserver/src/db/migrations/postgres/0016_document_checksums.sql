@@ -0,0 +1,3 @@
+-- SHA-256 of each document's file as uploaded, so the consistency check can
+-- tell a file that changed on disk. NULL for documents recorded before this.
+ALTER TABLE documents ADD COLUMN sha256 VARCHAR(64);
@@ -0,0 +1,3 @@
+-- The kind of federal return a client files, which decides its due date:
+-- '1040', '1040-nr-without-wages' or '1040-abroad'.
+ALTER TABLE client_tax_profiles ADD COLUMN return_type VARCHAR(30) NOT NULL DEFAULT '1040';
@@ -0,0 +1,6 @@
+-- At most one effective return per client, year and jurisdiction: amending
+-- supersedes the parent before the amended return is inserted.
+DROP INDEX IF EXISTS idx_tax_returns_jurisdiction;
+CREATE UNIQUE INDEX idx_tax_returns_effective
+    ON tax_returns(client_id, tax_year, jurisdiction)
+    WHERE return_kind <> 'superseded';
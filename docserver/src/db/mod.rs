@@ -1,7 +1,29 @@
 mod models;
 mod schema;
 mod connection;
+mod encryption;
+pub mod checklists;
+pub mod clients;
+pub mod deadlines;
+pub mod documents;
+pub mod estimates;
+pub mod household;
+pub mod line_items;
+pub mod migrations;
+mod office;
+pub mod portal;
+pub mod postgres;
+pub mod proposals;
+mod repository;
+pub mod returns;
+pub mod share_links;
+pub mod upload_links;
+pub mod workflow;
 
 pub use models::*;
 pub use schema::*;
 pub use connection::*;
+pub use encryption::*;
+pub use repository::*;
+pub use office::*;
+pub use self::postgres::PostgresStore;
@@ -1,8 +1,12 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::money::Money;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Client {
     pub client_id: Option<i64>,
     pub first_name: String,
@@ -15,18 +19,978 @@ pub struct Client {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaxReturn {
     pub tax_return_id: Option<i64>,
     pub client_id: i64,
     pub tax_year: i32,
     pub filing_status: String,
-    pub income_sources: HashMap<String, f64>,
-    pub deductions: HashMap<String, f64>,
-    pub credits: HashMap<String, f64>,
-    pub taxes_paid: f64,
-    pub tax_liability: f64,
-    pub refund_or_amount_due: f64,
+    pub income_sources: HashMap<String, Money>,
+    pub deductions: HashMap<String, Money>,
+    pub credits: HashMap<String, Money>,
+    pub taxes_paid: Money,
+    pub tax_liability: Money,
+    pub refund_or_amount_due: Money,
+    #[serde(default)]
+    pub return_kind: ReturnKind,
+    #[serde(default)]
+    pub parent_return_id: Option<i64>,
+    #[serde(default)]
+    pub amendment_reason: Option<String>,
+    #[serde(default)]
+    pub status: ReturnStatus,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    #[serde(default)]
+    pub jurisdiction: Jurisdiction,
+    // For a state return, the federal return for the same client and year
+    #[serde(default)]
+    pub federal_return_id: Option<i64>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Who a return is filed with: the IRS, or a state identified by its
+/// two-letter postal code. Serialized as `"federal"` or the code (`"CA"`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Jurisdiction {
+    #[default]
+    Federal,
+    State(String),
+}
+
+impl Jurisdiction {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Jurisdiction::Federal => "federal",
+            Jurisdiction::State(code) => code,
+        }
+    }
+
+    /// Accepts `federal` (or `US`) and two-letter state codes in any case.
+    pub fn parse(value: &str) -> Option<Jurisdiction> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("federal") || value.eq_ignore_ascii_case("us") {
+            return Some(Jurisdiction::Federal);
+        }
+        if value.len() == 2 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Some(Jurisdiction::State(value.to_ascii_uppercase()));
+        }
+        None
+    }
+
+    pub fn is_federal(&self) -> bool {
+        *self == Jurisdiction::Federal
+    }
+}
+
+impl fmt::Display for Jurisdiction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Jurisdiction {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Jurisdiction {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Jurisdiction::parse(&value).ok_or_else(|| {
+            serde::de::Error::custom(format!("Unknown jurisdiction {:?}; use \"federal\" or a state code", value))
+        })
+    }
+}
+
+impl ToSql for Jurisdiction {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for Jurisdiction {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        Jurisdiction::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown jurisdiction: {}", value).into()))
+    }
+}
+
+/// Where a return sits in its amendment chain. A superseded return has been
+/// replaced by an amendment that points back at it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnKind {
+    #[default]
+    Original,
+    Amended,
+    Superseded,
+}
+
+impl ReturnKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnKind::Original => "original",
+            ReturnKind::Amended => "amended",
+            ReturnKind::Superseded => "superseded",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ReturnKind> {
+        match value {
+            "original" => Some(ReturnKind::Original),
+            "amended" => Some(ReturnKind::Amended),
+            "superseded" => Some(ReturnKind::Superseded),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for ReturnKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ReturnKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ReturnKind::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown return kind: {}", value).into()))
+    }
+}
+
+/// Engagement workflow stage of a return.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnStatus {
+    #[default]
+    DocumentsRequested,
+    DocumentsReceived,
+    InPreparation,
+    InReview,
+    AwaitingSignature,
+    EFiled,
+    Accepted,
+    Rejected,
+    Complete,
+}
+
+impl ReturnStatus {
+    pub const ALL: [ReturnStatus; 9] = [
+        ReturnStatus::DocumentsRequested,
+        ReturnStatus::DocumentsReceived,
+        ReturnStatus::InPreparation,
+        ReturnStatus::InReview,
+        ReturnStatus::AwaitingSignature,
+        ReturnStatus::EFiled,
+        ReturnStatus::Accepted,
+        ReturnStatus::Rejected,
+        ReturnStatus::Complete,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnStatus::DocumentsRequested => "documents_requested",
+            ReturnStatus::DocumentsReceived => "documents_received",
+            ReturnStatus::InPreparation => "in_preparation",
+            ReturnStatus::InReview => "in_review",
+            ReturnStatus::AwaitingSignature => "awaiting_signature",
+            ReturnStatus::EFiled => "e_filed",
+            ReturnStatus::Accepted => "accepted",
+            ReturnStatus::Rejected => "rejected",
+            ReturnStatus::Complete => "complete",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ReturnStatus> {
+        ReturnStatus::ALL.into_iter().find(|status| status.as_str() == value)
+    }
+
+    /// The stages a return may move to from this one. Besides the forward
+    /// path, work can be sent back for more documents or more preparation,
+    /// and a rejected e-file goes back into preparation.
+    pub fn next(&self) -> &'static [ReturnStatus] {
+        use ReturnStatus::*;
+        match self {
+            DocumentsRequested => &[DocumentsReceived],
+            DocumentsReceived => &[InPreparation, DocumentsRequested],
+            InPreparation => &[InReview, DocumentsRequested],
+            InReview => &[AwaitingSignature, InPreparation],
+            AwaitingSignature => &[EFiled, InPreparation],
+            EFiled => &[Accepted, Rejected],
+            Rejected => &[InPreparation],
+            Accepted => &[Complete],
+            Complete => &[],
+        }
+    }
+
+    pub fn can_transition_to(&self, to: ReturnStatus) -> bool {
+        self.next().contains(&to)
+    }
+
+    /// Whether the return has been sent to the IRS; a rejected e-file has to
+    /// be filed again.
+    pub fn is_filed(&self) -> bool {
+        matches!(self, ReturnStatus::EFiled | ReturnStatus::Accepted | ReturnStatus::Complete)
+    }
+}
+
+impl ToSql for ReturnStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ReturnStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ReturnStatus::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown return status: {}", value).into()))
+    }
+}
+
+/// One entry in a return's workflow history.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub history_id: Option<i64>,
+    pub tax_return_id: i64,
+    pub from_status: Option<ReturnStatus>,
+    pub to_status: ReturnStatus,
+    pub assignee: Option<String>,
+    pub note: Option<String>,
+    pub changed_at: Option<DateTime<Utc>>,
+}
+
+/// Tax form types the classifier knows how to recognize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DocumentType {
+    #[serde(rename = "W-2")]
+    W2,
+    #[serde(rename = "1099-INT")]
+    Form1099Int,
+    #[serde(rename = "1099-DIV")]
+    Form1099Div,
+    #[serde(rename = "1099-B")]
+    Form1099B,
+    #[serde(rename = "1099-NEC")]
+    Form1099Nec,
+    #[serde(rename = "1099-MISC")]
+    Form1099Misc,
+    #[serde(rename = "1099-R")]
+    Form1099R,
+    #[serde(rename = "1098")]
+    Form1098,
+    #[serde(rename = "1098-T")]
+    Form1098T,
+    #[serde(rename = "1095-A")]
+    Form1095A,
+    #[serde(rename = "K-1")]
+    K1,
+    #[serde(rename = "1040")]
+    Form1040,
+}
+
+impl DocumentType {
+    pub const ALL: [DocumentType; 12] = [
+        DocumentType::W2,
+        DocumentType::Form1099Int,
+        DocumentType::Form1099Div,
+        DocumentType::Form1099B,
+        DocumentType::Form1099Nec,
+        DocumentType::Form1099Misc,
+        DocumentType::Form1099R,
+        DocumentType::Form1098,
+        DocumentType::Form1098T,
+        DocumentType::Form1095A,
+        DocumentType::K1,
+        DocumentType::Form1040,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentType::W2 => "W-2",
+            DocumentType::Form1099Int => "1099-INT",
+            DocumentType::Form1099Div => "1099-DIV",
+            DocumentType::Form1099B => "1099-B",
+            DocumentType::Form1099Nec => "1099-NEC",
+            DocumentType::Form1099Misc => "1099-MISC",
+            DocumentType::Form1099R => "1099-R",
+            DocumentType::Form1098 => "1098",
+            DocumentType::Form1098T => "1098-T",
+            DocumentType::Form1095A => "1095-A",
+            DocumentType::K1 => "K-1",
+            DocumentType::Form1040 => "1040",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<DocumentType> {
+        DocumentType::ALL.into_iter().find(|t| t.as_str().eq_ignore_ascii_case(value))
+    }
+}
+
+impl ToSql for DocumentType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for DocumentType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let text = value.as_str()?;
+        DocumentType::parse(text).ok_or_else(|| FromSqlError::Other(
+            format!("Unknown document type: {}", text).into()
+        ))
+    }
+}
+
+/// An uploaded file under `<root>/<client_id>/`, with its classification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub document_id: Option<i64>,
+    pub client_id: i64,
+    pub file_name: String,
+    pub document_type: Option<DocumentType>,
+    pub tax_year: Option<i32>,
+    pub confidence: f64,
+    pub type_overridden: bool,
+    #[serde(skip)]
+    pub extracted_text: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// The part of a return a line item belongs to. Each section is totalled by
+/// category into one of the `TaxReturn` maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineItemSection {
+    Income,
+    Deduction,
+    Credit,
+}
+
+impl LineItemSection {
+    pub const ALL: [LineItemSection; 3] = [LineItemSection::Income, LineItemSection::Deduction, LineItemSection::Credit];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineItemSection::Income => "income",
+            LineItemSection::Deduction => "deduction",
+            LineItemSection::Credit => "credit",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<LineItemSection> {
+        match value {
+            "income" => Some(LineItemSection::Income),
+            "deduction" => Some(LineItemSection::Deduction),
+            "credit" => Some(LineItemSection::Credit),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for LineItemSection {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for LineItemSection {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        LineItemSection::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown line item section: {}", value).into()))
+    }
+}
+
+/// One income, deduction or credit entry on a return, such as a single
+/// employer's wages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineItem {
+    pub line_item_id: Option<i64>,
+    pub tax_return_id: i64,
+    pub section: LineItemSection,
+    pub category: String,
+    pub payer: Option<String>,
+    pub amount: Money,
+    pub document_id: Option<i64>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Where an extracted amount lands when a proposal is accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalTarget {
+    IncomeSource,
+    TaxesPaid,
+}
+
+impl ProposalTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProposalTarget::IncomeSource => "income_source",
+            ProposalTarget::TaxesPaid => "taxes_paid",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ProposalTarget> {
+        match value {
+            "income_source" => Some(ProposalTarget::IncomeSource),
+            "taxes_paid" => Some(ProposalTarget::TaxesPaid),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for ProposalTarget {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ProposalTarget {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ProposalTarget::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown proposal target: {}", value).into()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+impl ProposalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProposalStatus::Pending => "pending",
+            ProposalStatus::Accepted => "accepted",
+            ProposalStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ProposalStatus> {
+        match value {
+            "pending" => Some(ProposalStatus::Pending),
+            "accepted" => Some(ProposalStatus::Accepted),
+            "rejected" => Some(ProposalStatus::Rejected),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for ProposalStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ProposalStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ProposalStatus::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown proposal status: {}", value).into()))
+    }
+}
+
+/// A single box value read from a W-2 or 1099.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposedItem {
+    pub target: ProposalTarget,
+    pub category: String,
+    pub box_label: String,
+    pub amount: Money,
+}
+
+/// Amounts extracted from one document, awaiting a preparer's review.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncomeProposal {
+    pub proposal_id: Option<i64>,
+    pub document_id: i64,
+    pub client_id: i64,
+    pub tax_year: Option<i32>,
+    pub status: ProposalStatus,
+    pub tax_return_id: Option<i64>,
+    pub items: Vec<ProposedItem>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+}
+
+/// Records which document an accepted amount on a return came from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncomeProvenance {
+    pub tax_return_id: i64,
+    pub target: ProposalTarget,
+    pub category: String,
+    pub amount: Money,
+    pub document_id: i64,
+    pub proposal_id: i64,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChecklistStatus {
+    Pending,
+    Received,
+}
+
+impl ChecklistStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecklistStatus::Pending => "pending",
+            ChecklistStatus::Received => "received",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ChecklistStatus> {
+        match value {
+            "pending" => Some(ChecklistStatus::Pending),
+            "received" => Some(ChecklistStatus::Received),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for ChecklistStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ChecklistStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ChecklistStatus::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown checklist status: {}", value).into()))
+    }
+}
+
+/// A document expected from a client for a tax year.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    pub item_id: Option<i64>,
+    pub client_id: i64,
+    pub tax_year: i32,
+    pub item_key: String,
+    pub document_type: DocumentType,
+    pub description: String,
+    pub status: ChecklistStatus,
+    pub received_document_id: Option<i64>,
+    pub received_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Where a public link stands, derived from its expiry, use count and
+/// revocation rather than stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStatus {
+    Open,
+    Used,
+    Expired,
+    Revoked,
+}
+
+impl LinkStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Open => "open",
+            LinkStatus::Used => "used",
+            LinkStatus::Expired => "expired",
+            LinkStatus::Revoked => "revoked",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<LinkStatus> {
+        match value {
+            "open" => Some(LinkStatus::Open),
+            "used" => Some(LinkStatus::Used),
+            "expired" => Some(LinkStatus::Expired),
+            "revoked" => Some(LinkStatus::Revoked),
+            _ => None,
+        }
+    }
+
+    pub fn derive(
+        revoked_at: Option<DateTime<Utc>>,
+        expires_at: DateTime<Utc>,
+        count: i64,
+        max_count: Option<i64>,
+        now: DateTime<Utc>,
+    ) -> LinkStatus {
+        if revoked_at.is_some() {
+            LinkStatus::Revoked
+        } else if max_count.is_some_and(|max| count >= max) {
+            LinkStatus::Used
+        } else if expires_at <= now {
+            LinkStatus::Expired
+        } else {
+            LinkStatus::Open
+        }
+    }
+}
+
+/// A client-facing upload link. The token is only shown once, when the link
+/// is created.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadLink {
+    pub link_id: Option<i64>,
+    pub client_id: i64,
+    pub checklist_item_id: Option<i64>,
+    #[serde(skip)]
+    pub nonce: String,
+    pub note: Option<String>,
+    pub max_uploads: i64,
+    pub upload_count: i64,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub status: LinkStatus,
+}
+
+/// A download link for one stored file, shared with someone outside the firm.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareLink {
+    pub link_id: Option<i64>,
+    pub client_id: Option<i64>,
+    pub file_path: String,
+    pub recipient: Option<String>,
+    #[serde(skip)]
+    pub nonce: String,
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    pub password_protected: bool,
+    pub max_downloads: Option<i64>,
+    pub download_count: i64,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub status: LinkStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessOutcome {
+    Downloaded,
+    PasswordRequired,
+    WrongPassword,
+    Unavailable,
+}
+
+impl AccessOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessOutcome::Downloaded => "downloaded",
+            AccessOutcome::PasswordRequired => "password_required",
+            AccessOutcome::WrongPassword => "wrong_password",
+            AccessOutcome::Unavailable => "unavailable",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<AccessOutcome> {
+        match value {
+            "downloaded" => Some(AccessOutcome::Downloaded),
+            "password_required" => Some(AccessOutcome::PasswordRequired),
+            "wrong_password" => Some(AccessOutcome::WrongPassword),
+            "unavailable" => Some(AccessOutcome::Unavailable),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for AccessOutcome {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for AccessOutcome {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        AccessOutcome::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown access outcome: {}", value).into()))
+    }
+}
+
+/// One attempt to use a share link.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareLinkAccess {
+    pub access_id: Option<i64>,
+    pub link_id: i64,
+    pub outcome: AccessOutcome,
+    pub remote_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub accessed_at: Option<DateTime<Utc>>,
+}
+
+/// A client's login to the portal. Each portal user sees only its own client.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortalUser {
+    pub user_id: Option<i64>,
+    pub client_id: i64,
+    pub email: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub disabled: bool,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeRequestStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl ChangeRequestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeRequestStatus::Pending => "pending",
+            ChangeRequestStatus::Approved => "approved",
+            ChangeRequestStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ChangeRequestStatus> {
+        match value {
+            "pending" => Some(ChangeRequestStatus::Pending),
+            "approved" => Some(ChangeRequestStatus::Approved),
+            "rejected" => Some(ChangeRequestStatus::Rejected),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for ChangeRequestStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ChangeRequestStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "pending" => Ok(ChangeRequestStatus::Pending),
+            "approved" => Ok(ChangeRequestStatus::Approved),
+            "rejected" => Ok(ChangeRequestStatus::Rejected),
+            other => Err(FromSqlError::Other(format!("Unknown change request status: {}", other).into())),
+        }
+    }
+}
+
+/// Contact details a client asked to change through the portal. Fields left
+/// out of the request are `None` and keep their current value on approval.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContactChangeRequest {
+    pub request_id: Option<i64>,
+    pub client_id: i64,
+    pub user_id: i64,
+    pub address: Option<String>,
+    pub phone_number: Option<String>,
+    pub email: Option<String>,
+    pub status: ChangeRequestStatus,
+    pub review_note: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberRole {
+    Spouse,
+    Dependent,
+}
+
+impl MemberRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemberRole::Spouse => "spouse",
+            MemberRole::Dependent => "dependent",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<MemberRole> {
+        match value {
+            "spouse" => Some(MemberRole::Spouse),
+            "dependent" => Some(MemberRole::Dependent),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for MemberRole {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for MemberRole {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        MemberRole::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown household role: {}", value).into()))
+    }
+}
+
+/// A client's spouse or dependent. `social_security_number` is plaintext
+/// here and encrypted in the database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HouseholdMember {
+    pub member_id: Option<i64>,
+    pub client_id: i64,
+    pub role: MemberRole,
+    pub first_name: String,
+    pub last_name: String,
+    pub relationship: String,
+    pub birth_date: NaiveDate,
+    pub social_security_number: String,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
+
+/// How a 1040-ES payment reached the IRS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentMethod {
+    DirectPay,
+    Eftps,
+    Card,
+    Check,
+    // Last year's refund applied to this year's estimated tax
+    AppliedOverpayment,
+}
+
+impl PaymentMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentMethod::DirectPay => "direct_pay",
+            PaymentMethod::Eftps => "eftps",
+            PaymentMethod::Card => "card",
+            PaymentMethod::Check => "check",
+            PaymentMethod::AppliedOverpayment => "applied_overpayment",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<PaymentMethod> {
+        match value {
+            "direct_pay" => Some(PaymentMethod::DirectPay),
+            "eftps" => Some(PaymentMethod::Eftps),
+            "card" => Some(PaymentMethod::Card),
+            "check" => Some(PaymentMethod::Check),
+            "applied_overpayment" => Some(PaymentMethod::AppliedOverpayment),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for PaymentMethod {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for PaymentMethod {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        PaymentMethod::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown payment method: {}", value).into()))
+    }
+}
+
+/// An estimated tax payment toward one quarter (1-4) of a tax year.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EstimatedPayment {
+    pub payment_id: Option<i64>,
+    pub client_id: i64,
+    pub tax_year: i32,
+    pub quarter: i32,
+    pub amount: Money,
+    pub paid_on: NaiveDate,
+    pub method: PaymentMethod,
+    pub note: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// The kind of federal return a client files, as far as it moves the due
+/// date. A 1040-NR reporting wages subject to withholding is due with the
+/// 1040; one without is due two months later. Citizens and residents living
+/// abroad get two extra months automatically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReturnType {
+    #[default]
+    #[serde(rename = "1040")]
+    Form1040,
+    #[serde(rename = "1040-nr-without-wages")]
+    Form1040NrWithoutWages,
+    #[serde(rename = "1040-abroad")]
+    Form1040Abroad,
+}
+
+impl ReturnType {
+    pub const ALL: [ReturnType; 3] = [ReturnType::Form1040, ReturnType::Form1040NrWithoutWages, ReturnType::Form1040Abroad];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnType::Form1040 => "1040",
+            ReturnType::Form1040NrWithoutWages => "1040-nr-without-wages",
+            ReturnType::Form1040Abroad => "1040-abroad",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ReturnType> {
+        ReturnType::ALL.into_iter().find(|kind| kind.as_str() == value)
+    }
+}
+
+impl ToSql for ReturnType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ReturnType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        ReturnType::parse(value)
+            .ok_or_else(|| FromSqlError::Other(format!("Unknown return type: {}", value).into()))
+    }
+}
+
+/// Filing details that move a client's deadlines. A client without a
+/// profile files a calendar-year Form 1040 only.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientTaxProfile {
+    pub client_id: i64,
+    // Month the fiscal year ends in; `None` for calendar-year filers
+    pub fiscal_year_end_month: Option<u32>,
+    pub home_state: Option<String>,
+    #[serde(default)]
+    pub return_type: ReturnType,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// An automatic extension of time to file (Form 4868).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReturnExtension {
+    pub extension_id: Option<i64>,
+    pub tax_return_id: i64,
+    pub filed_on: NaiveDate,
+    pub estimated_tax_liability: Money,
+    pub amount_paid: Money,
+    pub confirmation_number: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,527 @@
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+use super::connection::write_transaction;
+use super::deadlines::{self, ExtensionError};
+use super::household::{self, HouseholdError};
+use super::line_items::{self, LineItemError};
+use super::models::*;
+use super::portal::{self, ReviewError};
+use super::proposals::{self, AcceptError};
+use super::repository::{Repository, SqliteStore, StoreResult};
+use super::workflow::{self, TransitionError};
+use super::{checklists, clients, encryption, estimates, migrations, share_links, upload_links, FieldKey};
+use crate::config::Settings;
+use crate::documents::{self, checklist, consistency};
+use crate::documents::consistency::{ConsistencyError, ConsistencyReport, RepairReport};
+use crate::tax::Deadline;
+
+/// Everything else handlers and tools store: checklists, links, the portal,
+/// households, line items, proposals, estimates, deadlines and the workflow,
+/// plus document ingest and the consistency check. The SQLite stores and
+/// [`PostgresStore`](super::PostgresStore) both implement it.
+pub trait OfficeRepository: Repository {
+    // Documents and their files under the root
+
+    /// Extracts, classifies and records a file just saved under
+    /// `<root>/<client_id>/<file_name>`.
+    fn ingest_upload(&self, root_path: &Path, client_id: i64, file_name: &str) -> StoreResult<Document>;
+    /// Sets a document's type and year by hand; `None` if there is no such document.
+    fn override_document(
+        &self,
+        root_path: &Path,
+        document_id: i64,
+        document_type: DocumentType,
+        tax_year: Option<i32>,
+    ) -> StoreResult<Option<Document>>;
+    fn list_documents(&self) -> StoreResult<Vec<Document>>;
+    /// Extracts and classifies a document's file again; `None` if the file is gone.
+    fn reindex_document(&self, root_path: &Path, document: &Document) -> StoreResult<Option<Document>>;
+    fn check_consistency(&self, settings: &Settings) -> Result<ConsistencyReport, ConsistencyError>;
+    fn repair_consistency(&self, settings: &Settings) -> Result<RepairReport, ConsistencyError>;
+
+    // Clients in bulk, and the stores' own upkeep
+
+    /// Adds every client or, if any fails, none.
+    fn import_clients(&self, clients: &[Client]) -> StoreResult<Vec<i64>>;
+    fn schema_version(&self) -> StoreResult<i64>;
+    /// Re-encrypts every encrypted column from this store's key to `new_key`.
+    fn rotate_field_key(&self, new_key: &FieldKey) -> StoreResult<usize>;
+
+    // Document checklists
+
+    fn generate_checklist(&self, client_id: i64, tax_year: i32) -> StoreResult<Vec<ChecklistItem>>;
+    fn list_checklist_items(
+        &self,
+        client_id: i64,
+        tax_year: Option<i32>,
+        status: Option<ChecklistStatus>,
+    ) -> StoreResult<Vec<ChecklistItem>>;
+    fn get_checklist_item(&self, item_id: i64) -> StoreResult<Option<ChecklistItem>>;
+    fn mark_checklist_item_received(&self, item_id: i64, document_id: i64) -> StoreResult<bool>;
+
+    // Upload links
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_upload_link(
+        &self,
+        client_id: i64,
+        checklist_item_id: Option<i64>,
+        note: Option<&str>,
+        max_uploads: i64,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> StoreResult<i64>;
+    fn get_upload_link(&self, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<UploadLink>>;
+    fn list_upload_links(
+        &self,
+        client_id: Option<i64>,
+        status: Option<LinkStatus>,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Vec<UploadLink>>;
+    fn revoke_upload_link(&self, link_id: i64) -> StoreResult<bool>;
+    /// Takes one of a link's uploads if the link is still open.
+    fn claim_upload(&self, link_id: i64, nonce: &str, now: DateTime<Utc>) -> StoreResult<Option<UploadLink>>;
+    /// Gives back an upload claimed for a request that stored nothing.
+    fn release_upload(&self, link_id: i64) -> StoreResult<()>;
+
+    // Share links
+
+    fn create_share_link(&self, link: &ShareLink) -> StoreResult<i64>;
+    fn get_share_link(&self, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<ShareLink>>;
+    fn list_share_links(
+        &self,
+        client_id: Option<i64>,
+        status: Option<LinkStatus>,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Vec<ShareLink>>;
+    fn revoke_share_link(&self, link_id: i64) -> StoreResult<bool>;
+    /// Takes one of a link's downloads if the link is still open.
+    fn claim_download(&self, link_id: i64, nonce: &str, now: DateTime<Utc>) -> StoreResult<bool>;
+    fn release_download(&self, link_id: i64) -> StoreResult<()>;
+    fn record_share_access(
+        &self,
+        link_id: i64,
+        outcome: AccessOutcome,
+        remote_addr: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> StoreResult<()>;
+    fn list_share_accesses(&self, link_id: i64) -> StoreResult<Vec<ShareLinkAccess>>;
+
+    // The client portal
+
+    fn create_portal_user(&self, client_id: i64, email: &str, password_hash: &str) -> StoreResult<i64>;
+    fn find_portal_user(&self, email: &str) -> StoreResult<Option<PortalUser>>;
+    fn list_portal_users(&self, client_id: i64) -> StoreResult<Vec<PortalUser>>;
+    fn create_portal_session(&self, user_id: i64, token_digest: &str, expires_at: DateTime<Utc>) -> StoreResult<i64>;
+    /// The user signed in with a live session, if any.
+    fn session_user(&self, token_digest: &str, now: DateTime<Utc>) -> StoreResult<Option<PortalUser>>;
+    fn revoke_portal_session(&self, token_digest: &str) -> StoreResult<bool>;
+    fn create_change_request(&self, request: &ContactChangeRequest) -> StoreResult<i64>;
+    fn get_change_request(&self, request_id: i64) -> StoreResult<Option<ContactChangeRequest>>;
+    fn list_change_requests(
+        &self,
+        client_id: Option<i64>,
+        status: Option<ChangeRequestStatus>,
+    ) -> StoreResult<Vec<ContactChangeRequest>>;
+    fn review_change_request(
+        &self,
+        request_id: i64,
+        approve: bool,
+        review_note: Option<&str>,
+    ) -> Result<ContactChangeRequest, ReviewError>;
+
+    // Households
+
+    fn add_household_member(&self, member: &HouseholdMember) -> Result<i64, HouseholdError>;
+    fn get_household_member(&self, member_id: i64) -> StoreResult<Option<HouseholdMember>>;
+    fn list_household(&self, client_id: i64) -> StoreResult<Vec<HouseholdMember>>;
+    fn remove_household_member(&self, member_id: i64) -> StoreResult<bool>;
+    /// Replaces the members a return covers.
+    fn set_return_household(&self, tax_return_id: i64, member_ids: &[i64]) -> Result<Vec<HouseholdMember>, HouseholdError>;
+    fn return_household(&self, tax_return_id: i64) -> StoreResult<Vec<HouseholdMember>>;
+
+    // Line items
+
+    fn list_line_items(&self, tax_return_id: i64, section: Option<LineItemSection>) -> StoreResult<Vec<LineItem>>;
+    fn add_line_item(&self, item: &LineItem) -> Result<LineItem, LineItemError>;
+    fn update_line_item(&self, line_item_id: i64, item: &LineItem) -> Result<LineItem, LineItemError>;
+    fn remove_line_item(&self, line_item_id: i64) -> Result<(), LineItemError>;
+    fn search_line_items(
+        &self,
+        section: Option<LineItemSection>,
+        category: Option<&str>,
+        client_id: Option<i64>,
+        tax_year: Option<i32>,
+    ) -> StoreResult<Vec<LineItem>>;
+
+    // Income proposals
+
+    fn latest_document_proposal(&self, document_id: i64) -> StoreResult<Option<IncomeProposal>>;
+    fn list_proposals(&self, client_id: Option<i64>, status: Option<ProposalStatus>) -> StoreResult<Vec<IncomeProposal>>;
+    fn accept_proposal(&self, proposal_id: i64, tax_return_id: Option<i64>) -> Result<IncomeProposal, AcceptError>;
+    fn reject_proposal(&self, proposal_id: i64) -> StoreResult<bool>;
+    fn list_return_provenance(&self, tax_return_id: i64) -> StoreResult<Vec<IncomeProvenance>>;
+
+    // Estimated payments
+
+    fn add_estimated_payment(&self, payment: &EstimatedPayment) -> StoreResult<i64>;
+    fn get_estimated_payment(&self, payment_id: i64) -> StoreResult<Option<EstimatedPayment>>;
+    fn list_estimated_payments(&self, client_id: i64, tax_year: Option<i32>) -> StoreResult<Vec<EstimatedPayment>>;
+    fn remove_estimated_payment(&self, payment_id: i64) -> StoreResult<bool>;
+    /// Clients with an estimated payment recorded for the year.
+    fn paying_clients(&self, tax_year: i32) -> StoreResult<Vec<i64>>;
+
+    // Deadlines and extensions
+
+    fn get_tax_profile(&self, client_id: i64) -> StoreResult<Option<ClientTaxProfile>>;
+    fn set_tax_profile(&self, profile: &ClientTaxProfile) -> StoreResult<()>;
+    fn get_extension(&self, tax_return_id: i64) -> StoreResult<Option<ReturnExtension>>;
+    fn add_extension(&self, extension: &ReturnExtension) -> Result<ReturnExtension, ExtensionError>;
+    fn remove_extension(&self, tax_return_id: i64) -> StoreResult<bool>;
+    fn return_deadlines(&self, tax_return: &TaxReturn) -> StoreResult<Vec<Deadline>>;
+
+    // The preparation workflow
+
+    /// Moves a return to `to`; `assignee` of `None` keeps the current one.
+    fn transition_return(
+        &self,
+        tax_return_id: i64,
+        to: ReturnStatus,
+        assignee: Option<Option<&str>>,
+        note: Option<&str>,
+    ) -> Result<TaxReturn, TransitionError>;
+    fn status_history(&self, tax_return_id: i64) -> StoreResult<Vec<StatusChange>>;
+    fn list_by_status(&self, status: Option<ReturnStatus>, assignee: Option<&str>) -> StoreResult<Vec<TaxReturn>>;
+}
+
+impl<S: SqliteStore> OfficeRepository for S {
+    fn ingest_upload(&self, root_path: &Path, client_id: i64, file_name: &str) -> StoreResult<Document> {
+        Ok(documents::ingest_upload(self.conn(), root_path, client_id, file_name)?)
+    }
+
+    fn override_document(
+        &self,
+        root_path: &Path,
+        document_id: i64,
+        document_type: DocumentType,
+        tax_year: Option<i32>,
+    ) -> StoreResult<Option<Document>> {
+        Ok(documents::override_type(self.conn(), root_path, document_id, document_type, tax_year)?)
+    }
+
+    fn list_documents(&self) -> StoreResult<Vec<Document>> {
+        Ok(super::documents::list_documents(self.conn())?)
+    }
+
+    fn reindex_document(&self, root_path: &Path, document: &Document) -> StoreResult<Option<Document>> {
+        Ok(documents::reindex_document(self.conn(), root_path, document)?)
+    }
+
+    fn check_consistency(&self, settings: &Settings) -> Result<ConsistencyReport, ConsistencyError> {
+        consistency::check_consistency(self.conn(), settings)
+    }
+
+    fn repair_consistency(&self, settings: &Settings) -> Result<RepairReport, ConsistencyError> {
+        consistency::repair_consistency(self.conn(), settings)
+    }
+
+    fn import_clients(&self, imported: &[Client]) -> StoreResult<Vec<i64>> {
+        let tx = write_transaction(self.conn())?;
+        let ids = imported.iter()
+            .map(|client| clients::insert_client(&tx, self.field_key(), client))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    fn schema_version(&self) -> StoreResult<i64> {
+        Ok(migrations::current_version(self.conn())?)
+    }
+
+    fn rotate_field_key(&self, new_key: &FieldKey) -> StoreResult<usize> {
+        Ok(encryption::rotate_field_key(self.conn(), self.field_key(), new_key)?)
+    }
+
+    fn generate_checklist(&self, client_id: i64, tax_year: i32) -> StoreResult<Vec<ChecklistItem>> {
+        Ok(checklist::generate(self.conn(), client_id, tax_year)?)
+    }
+
+    fn list_checklist_items(
+        &self,
+        client_id: i64,
+        tax_year: Option<i32>,
+        status: Option<ChecklistStatus>,
+    ) -> StoreResult<Vec<ChecklistItem>> {
+        Ok(checklists::list_items(self.conn(), client_id, tax_year, status)?)
+    }
+
+    fn get_checklist_item(&self, item_id: i64) -> StoreResult<Option<ChecklistItem>> {
+        Ok(checklists::get_item(self.conn(), item_id)?)
+    }
+
+    fn mark_checklist_item_received(&self, item_id: i64, document_id: i64) -> StoreResult<bool> {
+        Ok(checklists::mark_item_received(self.conn(), item_id, document_id)?)
+    }
+
+    fn create_upload_link(
+        &self,
+        client_id: i64,
+        checklist_item_id: Option<i64>,
+        note: Option<&str>,
+        max_uploads: i64,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> StoreResult<i64> {
+        Ok(upload_links::create_link(self.conn(), client_id, checklist_item_id, note, max_uploads, nonce, expires_at)?)
+    }
+
+    fn get_upload_link(&self, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<UploadLink>> {
+        Ok(upload_links::get_link(self.conn(), link_id, now)?)
+    }
+
+    fn list_upload_links(
+        &self,
+        client_id: Option<i64>,
+        status: Option<LinkStatus>,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Vec<UploadLink>> {
+        Ok(upload_links::list_links(self.conn(), client_id, status, now)?)
+    }
+
+    fn revoke_upload_link(&self, link_id: i64) -> StoreResult<bool> {
+        Ok(upload_links::revoke_link(self.conn(), link_id)?)
+    }
+
+    fn claim_upload(&self, link_id: i64, nonce: &str, now: DateTime<Utc>) -> StoreResult<Option<UploadLink>> {
+        Ok(upload_links::claim_upload(self.conn(), link_id, nonce, now)?)
+    }
+
+    fn release_upload(&self, link_id: i64) -> StoreResult<()> {
+        Ok(upload_links::release_upload(self.conn(), link_id)?)
+    }
+
+    fn create_share_link(&self, link: &ShareLink) -> StoreResult<i64> {
+        Ok(share_links::create_link(self.conn(), link)?)
+    }
+
+    fn get_share_link(&self, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<ShareLink>> {
+        Ok(share_links::get_link(self.conn(), link_id, now)?)
+    }
+
+    fn list_share_links(
+        &self,
+        client_id: Option<i64>,
+        status: Option<LinkStatus>,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Vec<ShareLink>> {
+        Ok(share_links::list_links(self.conn(), client_id, status, now)?)
+    }
+
+    fn revoke_share_link(&self, link_id: i64) -> StoreResult<bool> {
+        Ok(share_links::revoke_link(self.conn(), link_id)?)
+    }
+
+    fn claim_download(&self, link_id: i64, nonce: &str, now: DateTime<Utc>) -> StoreResult<bool> {
+        Ok(share_links::claim_download(self.conn(), link_id, nonce, now)?)
+    }
+
+    fn release_download(&self, link_id: i64) -> StoreResult<()> {
+        Ok(share_links::release_download(self.conn(), link_id)?)
+    }
+
+    fn record_share_access(
+        &self,
+        link_id: i64,
+        outcome: AccessOutcome,
+        remote_addr: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> StoreResult<()> {
+        Ok(share_links::record_access(self.conn(), link_id, outcome, remote_addr, user_agent)?)
+    }
+
+    fn list_share_accesses(&self, link_id: i64) -> StoreResult<Vec<ShareLinkAccess>> {
+        Ok(share_links::list_accesses(self.conn(), link_id)?)
+    }
+
+    fn create_portal_user(&self, client_id: i64, email: &str, password_hash: &str) -> StoreResult<i64> {
+        Ok(portal::create_user(self.conn(), client_id, email, password_hash)?)
+    }
+
+    fn find_portal_user(&self, email: &str) -> StoreResult<Option<PortalUser>> {
+        Ok(portal::find_user_by_email(self.conn(), email)?)
+    }
+
+    fn list_portal_users(&self, client_id: i64) -> StoreResult<Vec<PortalUser>> {
+        Ok(portal::list_users(self.conn(), client_id)?)
+    }
+
+    fn create_portal_session(&self, user_id: i64, token_digest: &str, expires_at: DateTime<Utc>) -> StoreResult<i64> {
+        Ok(portal::create_session(self.conn(), user_id, token_digest, expires_at)?)
+    }
+
+    fn session_user(&self, token_digest: &str, now: DateTime<Utc>) -> StoreResult<Option<PortalUser>> {
+        Ok(portal::session_user(self.conn(), token_digest, now)?)
+    }
+
+    fn revoke_portal_session(&self, token_digest: &str) -> StoreResult<bool> {
+        Ok(portal::revoke_session(self.conn(), token_digest)?)
+    }
+
+    fn create_change_request(&self, request: &ContactChangeRequest) -> StoreResult<i64> {
+        Ok(portal::create_change_request(self.conn(), request)?)
+    }
+
+    fn get_change_request(&self, request_id: i64) -> StoreResult<Option<ContactChangeRequest>> {
+        Ok(portal::get_change_request(self.conn(), request_id)?)
+    }
+
+    fn list_change_requests(
+        &self,
+        client_id: Option<i64>,
+        status: Option<ChangeRequestStatus>,
+    ) -> StoreResult<Vec<ContactChangeRequest>> {
+        Ok(portal::list_change_requests(self.conn(), client_id, status)?)
+    }
+
+    fn review_change_request(
+        &self,
+        request_id: i64,
+        approve: bool,
+        review_note: Option<&str>,
+    ) -> Result<ContactChangeRequest, ReviewError> {
+        portal::review_change_request(self.conn(), request_id, approve, review_note)
+    }
+
+    fn add_household_member(&self, member: &HouseholdMember) -> Result<i64, HouseholdError> {
+        household::add_member(self.conn(), self.field_key(), member)
+    }
+
+    fn get_household_member(&self, member_id: i64) -> StoreResult<Option<HouseholdMember>> {
+        Ok(household::get_member(self.conn(), self.field_key(), member_id)?)
+    }
+
+    fn list_household(&self, client_id: i64) -> StoreResult<Vec<HouseholdMember>> {
+        Ok(household::list_members(self.conn(), self.field_key(), client_id)?)
+    }
+
+    fn remove_household_member(&self, member_id: i64) -> StoreResult<bool> {
+        Ok(household::remove_member(self.conn(), member_id)?)
+    }
+
+    fn set_return_household(&self, tax_return_id: i64, member_ids: &[i64]) -> Result<Vec<HouseholdMember>, HouseholdError> {
+        household::set_return_members(self.conn(), self.field_key(), tax_return_id, member_ids)
+    }
+
+    fn return_household(&self, tax_return_id: i64) -> StoreResult<Vec<HouseholdMember>> {
+        Ok(household::return_members(self.conn(), self.field_key(), tax_return_id)?)
+    }
+
+    fn list_line_items(&self, tax_return_id: i64, section: Option<LineItemSection>) -> StoreResult<Vec<LineItem>> {
+        Ok(line_items::list_items(self.conn(), tax_return_id, section)?)
+    }
+
+    fn add_line_item(&self, item: &LineItem) -> Result<LineItem, LineItemError> {
+        line_items::add_item(self.conn(), item)
+    }
+
+    fn update_line_item(&self, line_item_id: i64, item: &LineItem) -> Result<LineItem, LineItemError> {
+        line_items::update_item(self.conn(), line_item_id, item)
+    }
+
+    fn remove_line_item(&self, line_item_id: i64) -> Result<(), LineItemError> {
+        line_items::remove_item(self.conn(), line_item_id)
+    }
+
+    fn search_line_items(
+        &self,
+        section: Option<LineItemSection>,
+        category: Option<&str>,
+        client_id: Option<i64>,
+        tax_year: Option<i32>,
+    ) -> StoreResult<Vec<LineItem>> {
+        Ok(line_items::search_items(self.conn(), section, category, client_id, tax_year)?)
+    }
+
+    fn latest_document_proposal(&self, document_id: i64) -> StoreResult<Option<IncomeProposal>> {
+        Ok(proposals::latest_document_proposal(self.conn(), document_id)?)
+    }
+
+    fn list_proposals(&self, client_id: Option<i64>, status: Option<ProposalStatus>) -> StoreResult<Vec<IncomeProposal>> {
+        Ok(proposals::list_proposals(self.conn(), client_id, status)?)
+    }
+
+    fn accept_proposal(&self, proposal_id: i64, tax_return_id: Option<i64>) -> Result<IncomeProposal, AcceptError> {
+        proposals::accept_proposal(self.conn(), proposal_id, tax_return_id)
+    }
+
+    fn reject_proposal(&self, proposal_id: i64) -> StoreResult<bool> {
+        Ok(proposals::reject_proposal(self.conn(), proposal_id)?)
+    }
+
+    fn list_return_provenance(&self, tax_return_id: i64) -> StoreResult<Vec<IncomeProvenance>> {
+        Ok(proposals::list_return_provenance(self.conn(), tax_return_id)?)
+    }
+
+    fn add_estimated_payment(&self, payment: &EstimatedPayment) -> StoreResult<i64> {
+        Ok(estimates::add_payment(self.conn(), payment)?)
+    }
+
+    fn get_estimated_payment(&self, payment_id: i64) -> StoreResult<Option<EstimatedPayment>> {
+        Ok(estimates::get_payment(self.conn(), payment_id)?)
+    }
+
+    fn list_estimated_payments(&self, client_id: i64, tax_year: Option<i32>) -> StoreResult<Vec<EstimatedPayment>> {
+        Ok(estimates::list_payments(self.conn(), client_id, tax_year)?)
+    }
+
+    fn remove_estimated_payment(&self, payment_id: i64) -> StoreResult<bool> {
+        Ok(estimates::remove_payment(self.conn(), payment_id)?)
+    }
+
+    fn paying_clients(&self, tax_year: i32) -> StoreResult<Vec<i64>> {
+        Ok(estimates::paying_clients(self.conn(), tax_year)?)
+    }
+
+    fn get_tax_profile(&self, client_id: i64) -> StoreResult<Option<ClientTaxProfile>> {
+        Ok(deadlines::get_profile(self.conn(), client_id)?)
+    }
+
+    fn set_tax_profile(&self, profile: &ClientTaxProfile) -> StoreResult<()> {
+        Ok(deadlines::set_profile(self.conn(), profile)?)
+    }
+
+    fn get_extension(&self, tax_return_id: i64) -> StoreResult<Option<ReturnExtension>> {
+        Ok(deadlines::get_extension(self.conn(), tax_return_id)?)
+    }
+
+    fn add_extension(&self, extension: &ReturnExtension) -> Result<ReturnExtension, ExtensionError> {
+        deadlines::add_extension(self.conn(), extension)
+    }
+
+    fn remove_extension(&self, tax_return_id: i64) -> StoreResult<bool> {
+        Ok(deadlines::remove_extension(self.conn(), tax_return_id)?)
+    }
+
+    fn return_deadlines(&self, tax_return: &TaxReturn) -> StoreResult<Vec<Deadline>> {
+        Ok(deadlines::return_deadlines(self.conn(), tax_return)?)
+    }
+
+    fn transition_return(
+        &self,
+        tax_return_id: i64,
+        to: ReturnStatus,
+        assignee: Option<Option<&str>>,
+        note: Option<&str>,
+    ) -> Result<TaxReturn, TransitionError> {
+        workflow::transition_return(self.conn(), tax_return_id, to, assignee, note)
+    }
+
+    fn status_history(&self, tax_return_id: i64) -> StoreResult<Vec<StatusChange>> {
+        Ok(workflow::status_history(self.conn(), tax_return_id)?)
+    }
+
+    fn list_by_status(&self, status: Option<ReturnStatus>, assignee: Option<&str>) -> StoreResult<Vec<TaxReturn>> {
+        Ok(workflow::list_by_status(self.conn(), status, assignee)?)
+    }
+}
@@ -0,0 +1,284 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::fmt;
+
+use super::connection::write_transaction;
+use super::models::{ChangeRequestStatus, ContactChangeRequest, PortalUser};
+use super::repository::StoreError;
+
+#[derive(Debug)]
+pub enum ReviewError {
+    NotFound,
+    AlreadyReviewed(ChangeRequestStatus),
+    Database(StoreError),
+}
+
+impl fmt::Display for ReviewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReviewError::NotFound => write!(f, "Change request not found"),
+            ReviewError::AlreadyReviewed(status) => {
+                write!(f, "Change request has already been {}", status.as_str())
+            }
+            ReviewError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ReviewError {
+    fn from(e: rusqlite::Error) -> Self {
+        ReviewError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for ReviewError {
+    fn from(e: StoreError) -> Self {
+        ReviewError::Database(e)
+    }
+}
+
+const PORTAL_USER_COLUMNS: &str = "user_id, client_id, email, password_hash, disabled, last_login_at, created_at";
+
+const CHANGE_REQUEST_COLUMNS: &str = "request_id, client_id, user_id, address, phone_number, email,
+    status, review_note, created_at, reviewed_at";
+
+fn map_portal_user(row: &rusqlite::Row) -> Result<PortalUser> {
+    Ok(PortalUser {
+        user_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        email: row.get(2)?,
+        password_hash: row.get(3)?,
+        disabled: row.get(4)?,
+        last_login_at: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+fn map_change_request(row: &rusqlite::Row) -> Result<ContactChangeRequest> {
+    Ok(ContactChangeRequest {
+        request_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        user_id: row.get(2)?,
+        address: row.get(3)?,
+        phone_number: row.get(4)?,
+        email: row.get(5)?,
+        status: row.get(6)?,
+        review_note: row.get(7)?,
+        created_at: row.get(8)?,
+        reviewed_at: row.get(9)?,
+    })
+}
+
+/// Emails are matched case-insensitively, so they are stored lowercased.
+pub fn create_user(conn: &Connection, client_id: i64, email: &str, password_hash: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO portal_users (client_id, email, password_hash) VALUES (?, ?, ?)",
+        params![client_id, email.trim().to_lowercase(), password_hash],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn find_user_by_email(conn: &Connection, email: &str) -> Result<Option<PortalUser>> {
+    conn.query_row(
+        &format!("SELECT {} FROM portal_users WHERE email = ?", PORTAL_USER_COLUMNS),
+        [email.trim().to_lowercase()],
+        map_portal_user,
+    ).optional()
+}
+
+pub fn list_users(conn: &Connection, client_id: i64) -> Result<Vec<PortalUser>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM portal_users WHERE client_id = ? ORDER BY user_id",
+        PORTAL_USER_COLUMNS
+    ))?;
+
+    let users = stmt.query_map([client_id], map_portal_user)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(users)
+}
+
+/// Starts a session for a user who has just signed in. Only the token's
+/// digest is stored, so the sessions table cannot be used to sign in.
+pub fn create_session(conn: &Connection, user_id: i64, token_digest: &str, expires_at: DateTime<Utc>) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO portal_sessions (user_id, token_digest, expires_at) VALUES (?, ?, ?)",
+        params![user_id, token_digest, expires_at],
+    )?;
+    let session_id = conn.last_insert_rowid();
+    conn.execute(
+        "UPDATE portal_users SET last_login_at = CURRENT_TIMESTAMP WHERE user_id = ?",
+        [user_id],
+    )?;
+    Ok(session_id)
+}
+
+/// The user behind a live session: not revoked, not expired, and not disabled.
+pub fn session_user(conn: &Connection, token_digest: &str, now: DateTime<Utc>) -> Result<Option<PortalUser>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM portal_users
+             WHERE disabled = 0 AND user_id = (
+                 SELECT user_id FROM portal_sessions
+                 WHERE token_digest = ? AND revoked_at IS NULL AND expires_at > ?
+             )",
+            PORTAL_USER_COLUMNS
+        ),
+        params![token_digest, now],
+        map_portal_user,
+    ).optional()
+}
+
+/// Returns false if there is no such session or it was already revoked.
+pub fn revoke_session(conn: &Connection, token_digest: &str) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE portal_sessions SET revoked_at = CURRENT_TIMESTAMP WHERE token_digest = ? AND revoked_at IS NULL",
+        [token_digest],
+    )?;
+    Ok(updated > 0)
+}
+
+pub fn create_change_request(conn: &Connection, request: &ContactChangeRequest) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO contact_change_requests (client_id, user_id, address, phone_number, email, status)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            request.client_id,
+            request.user_id,
+            request.address,
+            request.phone_number,
+            request.email,
+            ChangeRequestStatus::Pending,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_change_request(conn: &Connection, request_id: i64) -> Result<Option<ContactChangeRequest>> {
+    conn.query_row(
+        &format!("SELECT {} FROM contact_change_requests WHERE request_id = ?", CHANGE_REQUEST_COLUMNS),
+        [request_id],
+        map_change_request,
+    ).optional()
+}
+
+/// Change requests oldest first, optionally for one client and in one status.
+pub fn list_change_requests(
+    conn: &Connection,
+    client_id: Option<i64>,
+    status: Option<ChangeRequestStatus>,
+) -> Result<Vec<ContactChangeRequest>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM contact_change_requests
+         WHERE (?1 IS NULL OR client_id = ?1)
+           AND (?2 IS NULL OR status = ?2)
+         ORDER BY request_id",
+        CHANGE_REQUEST_COLUMNS
+    ))?;
+
+    let requests = stmt.query_map(params![client_id, status], map_change_request)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(requests)
+}
+
+/// Approves or rejects a pending request. Approval copies the requested
+/// values onto the client record; fields the client left out are unchanged.
+pub fn review_change_request(
+    conn: &Connection,
+    request_id: i64,
+    approve: bool,
+    review_note: Option<&str>,
+) -> std::result::Result<ContactChangeRequest, ReviewError> {
+    let tx = write_transaction(conn)?;
+    let request = get_change_request(&tx, request_id)?.ok_or(ReviewError::NotFound)?;
+    if request.status != ChangeRequestStatus::Pending {
+        return Err(ReviewError::AlreadyReviewed(request.status));
+    }
+
+    let status = if approve { ChangeRequestStatus::Approved } else { ChangeRequestStatus::Rejected };
+    if approve {
+        tx.execute(
+            "UPDATE clients SET
+                address = COALESCE(?, address),
+                phone_number = COALESCE(?, phone_number),
+                email = COALESCE(?, email),
+                updated_at = CURRENT_TIMESTAMP
+             WHERE client_id = ?",
+            params![request.address, request.phone_number, request.email, request.client_id],
+        )?;
+    }
+    tx.execute(
+        "UPDATE contact_change_requests SET status = ?, review_note = ?, reviewed_at = CURRENT_TIMESTAMP
+         WHERE request_id = ?",
+        params![status, review_note, request_id],
+    )?;
+
+    let reviewed = get_change_request(&tx, request_id)?.ok_or(ReviewError::NotFound)?;
+    tx.commit()?;
+    Ok(reviewed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{clients, is_unique_violation, migrations, FieldKey};
+    use chrono::Duration;
+
+    fn setup() -> Connection {
+        // The baseline schema seeds client 1
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_sessions() {
+        let conn = setup();
+        let now = Utc::now();
+        let user_id = create_user(&conn, 1, " Client@Example.com", "hash").unwrap();
+        assert_eq!(find_user_by_email(&conn, "client@example.COM").unwrap().unwrap().user_id, Some(user_id));
+        // Emails are stored normalized, so the same address cannot sign in twice
+        assert!(is_unique_violation(&create_user(&conn, 1, "client@example.com", "other").unwrap_err()));
+
+        create_session(&conn, user_id, "digest", now + Duration::hours(1)).unwrap();
+        assert_eq!(session_user(&conn, "digest", now).unwrap().unwrap().client_id, 1);
+        assert!(session_user(&conn, "digest", now + Duration::hours(2)).unwrap().is_none());
+        assert!(session_user(&conn, "other", now).unwrap().is_none());
+
+        assert!(revoke_session(&conn, "digest").unwrap());
+        assert!(session_user(&conn, "digest", now).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_approved_change_updates_only_requested_fields() {
+        let conn = setup();
+        let user_id = create_user(&conn, 1, "client@example.com", "hash").unwrap();
+        let key = FieldKey::generate();
+        let before = clients::get_client(&conn, &key, 1).unwrap().unwrap();
+
+        let request_id = create_change_request(&conn, &ContactChangeRequest {
+            request_id: None,
+            client_id: 1,
+            user_id,
+            address: None,
+            phone_number: Some("555-0100".to_string()),
+            email: None,
+            status: ChangeRequestStatus::Pending,
+            review_note: None,
+            created_at: None,
+            reviewed_at: None,
+        }).unwrap();
+        assert_eq!(clients::get_client(&conn, &key, 1).unwrap().unwrap().phone_number, before.phone_number);
+
+        let reviewed = review_change_request(&conn, request_id, true, Some("Confirmed by phone")).unwrap();
+        assert_eq!(reviewed.status, ChangeRequestStatus::Approved);
+        let after = clients::get_client(&conn, &key, 1).unwrap().unwrap();
+        assert_eq!(after.phone_number, "555-0100");
+        assert_eq!(after.address, before.address);
+        assert_eq!(after.email, before.email);
+
+        assert!(matches!(
+            review_change_request(&conn, request_id, false, None),
+            Err(ReviewError::AlreadyReviewed(ChangeRequestStatus::Approved))
+        ));
+    }
+}
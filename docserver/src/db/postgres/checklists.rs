@@ -0,0 +1,134 @@
+use postgres::{GenericClient, Row};
+
+use super::{list_tax_returns, parse_column};
+use super::documents::list_client_documents;
+use crate::db::models::{ChecklistItem, ChecklistStatus, Document, DocumentType, Jurisdiction};
+use crate::db::repository::StoreResult;
+use crate::documents::checklist;
+
+const CHECKLIST_COLUMNS: &str = "item_id, client_id, tax_year, item_key, document_type, description,
+    status, received_document_id, received_at, created_at";
+
+fn map_item(row: &Row) -> StoreResult<ChecklistItem> {
+    Ok(ChecklistItem {
+        item_id: Some(row.get(0)),
+        client_id: row.get(1),
+        tax_year: row.get(2),
+        item_key: row.get(3),
+        document_type: parse_column(row.get(4), DocumentType::parse, "document type")?,
+        description: row.get(5),
+        status: parse_column(row.get(6), ChecklistStatus::parse, "checklist status")?,
+        received_document_id: row.get(7),
+        received_at: row.get(8),
+        created_at: row.get(9),
+    })
+}
+
+/// Adds the items `checklist::plan` works out for `tax_year`, as
+/// `checklist::generate` does.
+pub(super) fn generate(client: &mut impl GenericClient, client_id: i64, tax_year: i32) -> StoreResult<Vec<ChecklistItem>> {
+    let client_documents = list_client_documents(client, client_id)?;
+    let prior_return = list_tax_returns(client, Some(client_id), Some(&Jurisdiction::Federal), false)?
+        .into_iter()
+        .find(|r| r.tax_year == tax_year - 1);
+
+    for item in checklist::plan(&client_documents, prior_return.as_ref(), tax_year) {
+        client.execute(
+            "INSERT INTO checklist_items (client_id, tax_year, item_key, document_type, description)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT DO NOTHING",
+            &[&client_id, &tax_year, &item.item_key, &item.document_type.as_str(), &item.description],
+        )?;
+    }
+    for document in client_documents.iter().filter(|d| d.tax_year == Some(tax_year)) {
+        mark_received(client, document)?;
+    }
+
+    list_items(client, client_id, Some(tax_year), None)
+}
+
+pub(super) fn list_items(
+    client: &mut impl GenericClient,
+    client_id: i64,
+    tax_year: Option<i32>,
+    status: Option<ChecklistStatus>,
+) -> StoreResult<Vec<ChecklistItem>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM checklist_items
+             WHERE client_id = $1
+               AND ($2::INTEGER IS NULL OR tax_year = $2)
+               AND ($3::TEXT IS NULL OR status = $3)
+             ORDER BY tax_year DESC, document_type, item_id",
+            CHECKLIST_COLUMNS
+        ),
+        &[&client_id, &tax_year, &status.map(|s| s.as_str())],
+    )?.iter().map(map_item).collect()
+}
+
+pub(super) fn get_item(client: &mut impl GenericClient, item_id: i64) -> StoreResult<Option<ChecklistItem>> {
+    client.query_opt(
+        &format!("SELECT {} FROM checklist_items WHERE item_id = $1", CHECKLIST_COLUMNS),
+        &[&item_id],
+    )?.as_ref().map(map_item).transpose()
+}
+
+/// Ticks off the oldest pending item the document satisfies, as
+/// `checklists::mark_received` does.
+pub(super) fn mark_received(client: &mut impl GenericClient, document: &Document) -> StoreResult<Option<i64>> {
+    let (Some(document_id), Some(document_type)) = (document.document_id, document.document_type) else {
+        return Ok(None);
+    };
+
+    // A reclassified document gives back any item it no longer satisfies
+    client.execute(
+        "UPDATE checklist_items
+         SET status = 'pending', received_document_id = NULL, received_at = NULL
+         WHERE received_document_id = $1
+           AND (document_type <> $2 OR ($3::INTEGER IS NOT NULL AND tax_year <> $3))",
+        &[&document_id, &document_type.as_str(), &document.tax_year],
+    )?;
+
+    let already_matched: bool = client.query_one(
+        "SELECT EXISTS(SELECT 1 FROM checklist_items WHERE received_document_id = $1)",
+        &[&document_id],
+    )?.get(0);
+    if already_matched {
+        return Ok(None);
+    }
+
+    let item_id: Option<i64> = client.query_opt(
+        "SELECT item_id FROM checklist_items
+         WHERE client_id = $1 AND document_type = $2 AND status = 'pending'
+           AND ($3::INTEGER IS NULL OR tax_year = $3)
+         ORDER BY tax_year DESC, item_id
+         LIMIT 1",
+        &[&document.client_id, &document_type.as_str(), &document.tax_year],
+    )?.map(|row| row.get(0));
+
+    if let Some(item_id) = item_id {
+        client.execute(
+            "UPDATE checklist_items
+             SET status = $1, received_document_id = $2, received_at = CURRENT_TIMESTAMP
+             WHERE item_id = $3",
+            &[&ChecklistStatus::Received.as_str(), &document_id, &item_id],
+        )?;
+    }
+    Ok(item_id)
+}
+
+pub(super) fn mark_item_received(client: &mut impl GenericClient, item_id: i64, document_id: i64) -> StoreResult<bool> {
+    client.execute(
+        "UPDATE checklist_items
+         SET status = 'pending', received_document_id = NULL, received_at = NULL
+         WHERE received_document_id = $1 AND item_id <> $2",
+        &[&document_id, &item_id],
+    )?;
+    let updated = client.execute(
+        "UPDATE checklist_items
+         SET status = $1, received_document_id = $2, received_at = CURRENT_TIMESTAMP
+         WHERE item_id = $3 AND status = 'pending'",
+        &[&ChecklistStatus::Received.as_str(), &document_id, &item_id],
+    )?;
+    Ok(updated > 0)
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use postgres::{GenericClient, Row};
+
+use super::{get_tax_return, parse_column, return_chain, state_returns};
+use crate::db::deadlines::ExtensionError;
+use crate::db::models::{ClientTaxProfile, ReturnExtension, ReturnType, TaxReturn};
+use crate::db::repository::{StoreError, StoreResult};
+use crate::money::Money;
+use crate::tax::{self, Deadline, DeadlineInputs, DeadlineKind};
+
+const EXTENSION_COLUMNS: &str = "extension_id, tax_return_id, filed_on, estimated_tax_liability, amount_paid,
+    confirmation_number, created_at";
+
+fn map_extension(row: &Row) -> ReturnExtension {
+    ReturnExtension {
+        extension_id: Some(row.get(0)),
+        tax_return_id: row.get(1),
+        filed_on: row.get(2),
+        estimated_tax_liability: Money::from_cents(row.get(3)),
+        amount_paid: Money::from_cents(row.get(4)),
+        confirmation_number: row.get(5),
+        created_at: row.get(6),
+    }
+}
+
+pub(super) fn get_profile(client: &mut impl GenericClient, client_id: i64) -> StoreResult<Option<ClientTaxProfile>> {
+    client.query_opt(
+        "SELECT client_id, fiscal_year_end_month, home_state, return_type, updated_at
+         FROM client_tax_profiles WHERE client_id = $1",
+        &[&client_id],
+    )?.map(|row| {
+        Ok(ClientTaxProfile {
+            client_id: row.get(0),
+            fiscal_year_end_month: row.get::<_, Option<i32>>(1).map(|month| month as u32),
+            home_state: row.get(2),
+            return_type: parse_column(row.get(3), ReturnType::parse, "return type")?,
+            updated_at: row.get(4),
+        })
+    }).transpose()
+}
+
+pub(super) fn set_profile(client: &mut impl GenericClient, profile: &ClientTaxProfile) -> StoreResult<()> {
+    client.execute(
+        "INSERT INTO client_tax_profiles (client_id, fiscal_year_end_month, home_state, return_type)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (client_id) DO UPDATE SET
+             fiscal_year_end_month = $2, home_state = $3, return_type = $4, updated_at = CURRENT_TIMESTAMP",
+        &[
+            &profile.client_id,
+            &profile.fiscal_year_end_month.map(|month| month as i32),
+            &profile.home_state,
+            &profile.return_type.as_str(),
+        ],
+    )?;
+    Ok(())
+}
+
+pub(super) fn get_extension(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<Option<ReturnExtension>> {
+    let row = client.query_opt(
+        &format!("SELECT {} FROM return_extensions WHERE tax_return_id = $1", EXTENSION_COLUMNS),
+        &[&tax_return_id],
+    )?;
+    Ok(row.as_ref().map(map_extension))
+}
+
+/// Records a Form 4868 extension as `deadlines::add_extension` does. The
+/// unique index on `tax_return_id` refuses a second one from a concurrent
+/// request.
+pub(super) fn add_extension(client: &mut impl GenericClient, extension: &ReturnExtension) -> Result<ReturnExtension, ExtensionError> {
+    let tax_return = get_tax_return(client, extension.tax_return_id)?.ok_or(ExtensionError::ReturnNotFound)?;
+    if tax_return.parent_return_id.is_some() || !tax_return.jurisdiction.is_federal() {
+        return Err(ExtensionError::NotOriginal);
+    }
+    if get_extension(client, extension.tax_return_id)?.is_some() {
+        return Err(ExtensionError::AlreadyExtended);
+    }
+    let original_due = return_deadlines(client, &tax_return)?.into_iter()
+        .find(|d| d.jurisdiction == "federal" && d.kind == DeadlineKind::Original)
+        .map(|d| d.due_date);
+    if let Some(due) = original_due.filter(|due| extension.filed_on > *due) {
+        return Err(ExtensionError::AfterDueDate(due));
+    }
+
+    let row = client.query_one(
+        &format!(
+            "INSERT INTO return_extensions (
+                tax_return_id, filed_on, estimated_tax_liability, amount_paid, confirmation_number
+            ) VALUES ($1, $2, $3, $4, $5)
+            RETURNING {}",
+            EXTENSION_COLUMNS
+        ),
+        &[
+            &extension.tax_return_id,
+            &extension.filed_on,
+            &extension.estimated_tax_liability.cents(),
+            &extension.amount_paid.cents(),
+            &extension.confirmation_number,
+        ],
+    ).map_err(|e| {
+        let e = StoreError::from(e);
+        if e.is_unique_violation() { ExtensionError::AlreadyExtended } else { ExtensionError::Database(e) }
+    })?;
+    Ok(map_extension(&row))
+}
+
+pub(super) fn remove_extension(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<bool> {
+    let removed = client.execute("DELETE FROM return_extensions WHERE tax_return_id = $1", &[&tax_return_id])?;
+    Ok(removed > 0)
+}
+
+/// The day a return was first e-filed, from its status history.
+fn filed_on(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<Option<NaiveDate>> {
+    let row = client.query_one(
+        "SELECT MIN(changed_at) FROM return_status_history
+         WHERE tax_return_id = $1 AND to_status = 'e_filed'",
+        &[&tax_return_id],
+    )?;
+    Ok(row.get::<_, Option<DateTime<Utc>>>(0).map(|at| at.date_naive()))
+}
+
+/// The first return of the amendment chain `tax_return` belongs to.
+fn original_return_id(client: &mut impl GenericClient, tax_return: &TaxReturn) -> StoreResult<i64> {
+    let tax_return_id = tax_return.tax_return_id.expect("Stored returns have an id");
+    if tax_return.parent_return_id.is_none() {
+        return Ok(tax_return_id);
+    }
+    Ok(return_chain(client, tax_return_id)?.first().and_then(|r| r.tax_return_id).unwrap_or(tax_return_id))
+}
+
+/// A return's deadlines, worked out as `deadlines::return_deadlines` does.
+pub(super) fn return_deadlines(client: &mut impl GenericClient, tax_return: &TaxReturn) -> StoreResult<Vec<Deadline>> {
+    let profile = get_profile(client, tax_return.client_id)?.unwrap_or_default();
+    let original_id = original_return_id(client, tax_return)?;
+
+    let extended_return_id = match tax_return.federal_return_id {
+        Some(federal_id) => match get_tax_return(client, federal_id)? {
+            Some(federal) => original_return_id(client, &federal)?,
+            None => federal_id,
+        },
+        None => original_id,
+    };
+    let home_state = if tax_return.jurisdiction.is_federal()
+        && state_returns(client, tax_return.tax_return_id.unwrap_or_default())?.is_empty()
+    {
+        profile.home_state.as_deref()
+    } else {
+        None
+    };
+
+    Ok(tax::return_deadlines(DeadlineInputs {
+        jurisdiction: &tax_return.jurisdiction,
+        tax_year: tax_return.tax_year,
+        fiscal_year_end_month: profile.fiscal_year_end_month,
+        return_type: profile.return_type,
+        home_state,
+        extended: get_extension(client, extended_return_id)?.is_some(),
+        amendment: tax_return.parent_return_id.is_some(),
+        original_filed_on: filed_on(client, original_id)?,
+    }))
+}
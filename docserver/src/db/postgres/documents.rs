@@ -0,0 +1,230 @@
+use postgres::{GenericClient, Row};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::{checklists, parse_column, proposals};
+use crate::config::Settings;
+use crate::db::documents::DOCUMENT_COLUMNS;
+use crate::db::models::{Document, DocumentType};
+use crate::db::repository::{StoreError, StoreResult};
+use crate::documents::consistency::{self, ConsistencyError, ConsistencyReport, Records, RepairReport};
+use crate::documents as ingest;
+
+fn map_document(row: &Row) -> StoreResult<Document> {
+    let document_type: Option<&str> = row.get(3);
+    Ok(Document {
+        document_id: Some(row.get(0)),
+        client_id: row.get(1),
+        file_name: row.get(2),
+        document_type: document_type.map(|t| parse_column(t, DocumentType::parse, "document type")).transpose()?,
+        tax_year: row.get(4),
+        confidence: row.get(5),
+        type_overridden: row.get(6),
+        extracted_text: row.get(7),
+        created_at: row.get(8),
+        updated_at: row.get(9),
+    })
+}
+
+/// Inserts or refreshes the record for `(client_id, file_name)`, as
+/// `documents::upsert_document` does.
+pub(super) fn upsert_document(client: &mut impl GenericClient, document: &Document) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO documents (
+            client_id, file_name, document_type, tax_year, confidence,
+            type_overridden, extracted_text
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (client_id, file_name) DO UPDATE SET
+            document_type = excluded.document_type,
+            tax_year = excluded.tax_year,
+            confidence = excluded.confidence,
+            type_overridden = excluded.type_overridden,
+            extracted_text = excluded.extracted_text,
+            updated_at = CURRENT_TIMESTAMP
+        RETURNING document_id",
+        &[
+            &document.client_id,
+            &document.file_name,
+            &document.document_type.map(|t| t.as_str()),
+            &document.tax_year,
+            &document.confidence,
+            &document.type_overridden,
+            &document.extracted_text,
+        ],
+    )?;
+    Ok(row.get(0))
+}
+
+pub(super) fn get_document(client: &mut impl GenericClient, document_id: i64) -> StoreResult<Option<Document>> {
+    client.query_opt(
+        &format!("SELECT {} FROM documents WHERE document_id = $1", DOCUMENT_COLUMNS),
+        &[&document_id],
+    )?.as_ref().map(map_document).transpose()
+}
+
+pub(super) fn list_client_documents(client: &mut impl GenericClient, client_id: i64) -> StoreResult<Vec<Document>> {
+    client.query(
+        &format!("SELECT {} FROM documents WHERE client_id = $1 ORDER BY file_name", DOCUMENT_COLUMNS),
+        &[&client_id],
+    )?.iter().map(map_document).collect()
+}
+
+pub(super) fn list_documents(client: &mut impl GenericClient) -> StoreResult<Vec<Document>> {
+    client.query(
+        &format!("SELECT {} FROM documents ORDER BY client_id, file_name", DOCUMENT_COLUMNS),
+        &[],
+    )?.iter().map(map_document).collect()
+}
+
+pub(super) fn override_document_type(
+    client: &mut impl GenericClient,
+    document_id: i64,
+    document_type: DocumentType,
+    tax_year: Option<i32>,
+) -> StoreResult<bool> {
+    let updated = client.execute(
+        "UPDATE documents SET
+            document_type = $1,
+            tax_year = COALESCE($2, tax_year),
+            confidence = 1.0,
+            type_overridden = TRUE,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE document_id = $3",
+        &[&document_type.as_str(), &tax_year, &document_id],
+    )?;
+    Ok(updated > 0)
+}
+
+fn set_checksum(client: &mut impl GenericClient, document_id: i64, sha256: &str) -> StoreResult<()> {
+    client.execute("UPDATE documents SET sha256 = $1 WHERE document_id = $2", &[&sha256, &document_id])?;
+    Ok(())
+}
+
+/// Re-reads W-2/1099 box values into a pending income proposal for review.
+fn refresh_proposal(client: &mut impl GenericClient, document: &Document) -> StoreResult<()> {
+    if let Some((document_id, items)) = ingest::proposed_items(document) {
+        proposals::replace_pending_proposal(client, document_id, document.client_id, document.tax_year, &items)?;
+    }
+    Ok(())
+}
+
+/// Records a file just saved under the root, as `documents::ingest_upload` does.
+pub(super) fn ingest_upload(
+    client: &mut impl GenericClient,
+    root_path: &Path,
+    client_id: i64,
+    file_name: &str,
+) -> StoreResult<Document> {
+    let mut document = ingest::read_document(root_path, client_id, file_name);
+    let document_id = upsert_document(client, &document)?;
+    document.document_id = Some(document_id);
+    if let Some(sha256) = ingest::file_checksum(root_path, &document) {
+        set_checksum(client, document_id, &sha256)?;
+    }
+    refresh_proposal(client, &document)?;
+    checklists::mark_received(client, &document)?;
+    Ok(document)
+}
+
+pub(super) fn reindex_document(
+    client: &mut impl GenericClient,
+    root_path: &Path,
+    stored: &Document,
+) -> StoreResult<Option<Document>> {
+    let Some(mut document) = ingest::reread_document(root_path, stored) else {
+        return Ok(None);
+    };
+    document.document_id = Some(upsert_document(client, &document)?);
+    refresh_proposal(client, &document)?;
+    checklists::mark_received(client, &document)?;
+    Ok(Some(document))
+}
+
+pub(super) fn override_type(
+    client: &mut impl GenericClient,
+    root_path: &Path,
+    document_id: i64,
+    document_type: DocumentType,
+    tax_year: Option<i32>,
+) -> StoreResult<Option<Document>> {
+    if !override_document_type(client, document_id, document_type, tax_year)? {
+        return Ok(None);
+    }
+    let document = get_document(client, document_id)?;
+
+    if let Some(document) = &document {
+        ingest::learn_override(root_path, document);
+        refresh_proposal(client, document)?;
+        checklists::mark_received(client, document)?;
+    }
+    Ok(document)
+}
+
+/// The consistency check's view of a PostgreSQL database.
+struct PgRecords<'a, C>(&'a mut C);
+
+impl<C: GenericClient> Records for PgRecords<'_, C> {
+    fn client_ids(&mut self) -> StoreResult<HashSet<i64>> {
+        let rows = self.0.query("SELECT client_id FROM clients", &[])?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn documents(&mut self) -> StoreResult<Vec<Document>> {
+        list_documents(self.0)
+    }
+
+    fn checksums(&mut self) -> StoreResult<HashMap<i64, Option<String>>> {
+        let rows = self.0.query("SELECT document_id, sha256 FROM documents", &[])?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    fn returns_without_client(&mut self) -> StoreResult<Vec<(i64, i64)>> {
+        let rows = self.0.query(
+            "SELECT tax_return_id, client_id FROM tax_returns
+             WHERE client_id NOT IN (SELECT client_id FROM clients)
+             ORDER BY tax_return_id",
+            &[],
+        )?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    fn is_recorded(&mut self, client_id: i64, file_name: &str) -> StoreResult<bool> {
+        let row = self.0.query_one(
+            "SELECT EXISTS(SELECT 1 FROM documents WHERE client_id = $1 AND file_name = $2)",
+            &[&client_id, &file_name],
+        )?;
+        Ok(row.get(0))
+    }
+
+    fn get_document(&mut self, document_id: i64) -> StoreResult<Option<Document>> {
+        get_document(self.0, document_id)
+    }
+
+    fn set_checksum(&mut self, document_id: i64, sha256: &str) -> StoreResult<()> {
+        set_checksum(self.0, document_id, sha256)
+    }
+}
+
+pub(super) fn check_consistency(
+    client: &mut impl GenericClient,
+    settings: &Settings,
+) -> Result<ConsistencyReport, ConsistencyError> {
+    consistency::check_settled(&mut PgRecords(client), settings, consistency::settle_cutoff())
+}
+
+/// Checks, then applies the safe fixes, as `consistency::repair_consistency`
+/// does. Locking the clients and documents tables keeps uploads from
+/// recording a document between the re-check of an orphan and its move.
+pub(super) fn repair_consistency(
+    client: &mut impl GenericClient,
+    settings: &Settings,
+) -> Result<RepairReport, ConsistencyError> {
+    let settled_before = consistency::settle_cutoff();
+    let report = consistency::check_settled(&mut PgRecords(client), settings, settled_before)?;
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    tx.batch_execute("LOCK TABLE clients, documents IN SHARE ROW EXCLUSIVE MODE")
+        .map_err(StoreError::from)?;
+    let repair = consistency::apply_repair(&mut PgRecords(&mut tx), settings, &report, settled_before)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(RepairReport { report, repair })
+}
@@ -0,0 +1,114 @@
+use postgres::{GenericClient, Row};
+
+use super::parse_column;
+use crate::db::models::{EstimatedPayment, PaymentMethod};
+use crate::db::repository::StoreResult;
+use crate::money::Money;
+
+const PAYMENT_COLUMNS: &str = "payment_id, client_id, tax_year, quarter, amount, paid_on, method, note, created_at";
+
+fn map_payment(row: &Row) -> StoreResult<EstimatedPayment> {
+    Ok(EstimatedPayment {
+        payment_id: Some(row.get(0)),
+        client_id: row.get(1),
+        tax_year: row.get(2),
+        quarter: row.get(3),
+        amount: Money::from_cents(row.get(4)),
+        paid_on: row.get(5),
+        method: parse_column(row.get(6), PaymentMethod::parse, "payment method")?,
+        note: row.get(7),
+        created_at: row.get(8),
+    })
+}
+
+/// Makes writers of a client's estimated payments and returns take turns,
+/// so the sum [`apply_to_return`] rolls in counts every committed payment.
+/// SQLite gets the same from its single writer.
+pub(super) fn lock_client(client: &mut impl GenericClient, client_id: i64) -> StoreResult<()> {
+    client.execute("SELECT 1 FROM clients WHERE client_id = $1 FOR NO KEY UPDATE", &[&client_id])?;
+    Ok(())
+}
+
+pub(super) fn add_payment(client: &mut impl GenericClient, payment: &EstimatedPayment) -> StoreResult<i64> {
+    let mut tx = client.transaction()?;
+    lock_client(&mut tx, payment.client_id)?;
+    let row = tx.query_one(
+        "INSERT INTO estimated_payments (client_id, tax_year, quarter, amount, paid_on, method, note)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING payment_id",
+        &[
+            &payment.client_id,
+            &payment.tax_year,
+            &payment.quarter,
+            &payment.amount.cents(),
+            &payment.paid_on,
+            &payment.method.as_str(),
+            &payment.note,
+        ],
+    )?;
+    apply_to_return(&mut tx, payment.client_id, payment.tax_year)?;
+    tx.commit()?;
+    Ok(row.get(0))
+}
+
+pub(super) fn get_payment(client: &mut impl GenericClient, payment_id: i64) -> StoreResult<Option<EstimatedPayment>> {
+    client.query_opt(
+        &format!("SELECT {} FROM estimated_payments WHERE payment_id = $1", PAYMENT_COLUMNS),
+        &[&payment_id],
+    )?.as_ref().map(map_payment).transpose()
+}
+
+pub(super) fn list_payments(client: &mut impl GenericClient, client_id: i64, tax_year: Option<i32>) -> StoreResult<Vec<EstimatedPayment>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM estimated_payments
+             WHERE client_id = $1 AND ($2::INTEGER IS NULL OR tax_year = $2)
+             ORDER BY tax_year, quarter, paid_on, payment_id",
+            PAYMENT_COLUMNS
+        ),
+        &[&client_id, &tax_year],
+    )?.iter().map(map_payment).collect()
+}
+
+pub(super) fn remove_payment(client: &mut impl GenericClient, payment_id: i64) -> StoreResult<bool> {
+    let mut tx = client.transaction()?;
+    let Some(payment) = get_payment(&mut tx, payment_id)? else {
+        return Ok(false);
+    };
+    lock_client(&mut tx, payment.client_id)?;
+    if tx.execute("DELETE FROM estimated_payments WHERE payment_id = $1", &[&payment_id])? == 0 {
+        // Removed by another request while this one waited for the lock
+        return Ok(false);
+    }
+    apply_to_return(&mut tx, payment.client_id, payment.tax_year)?;
+    tx.commit()?;
+    Ok(true)
+}
+
+pub(super) fn paying_clients(client: &mut impl GenericClient, tax_year: i32) -> StoreResult<Vec<i64>> {
+    let rows = client.query(
+        "SELECT DISTINCT client_id FROM estimated_payments
+         WHERE tax_year IN ($1, $1 - 1) ORDER BY client_id",
+        &[&tax_year],
+    )?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Rolls the client's estimated payments for the year into the effective
+/// federal return's `taxes_paid`, as `estimates::apply_to_return` does.
+/// Callers hold [`lock_client`].
+pub(super) fn apply_to_return(client: &mut impl GenericClient, client_id: i64, tax_year: i32) -> StoreResult<()> {
+    client.execute(
+        "UPDATE tax_returns
+         SET taxes_paid = taxes_paid - estimated_payments_applied + paid.total,
+             estimated_payments_applied = paid.total,
+             updated_at = CURRENT_TIMESTAMP
+         FROM (
+             SELECT COALESCE(SUM(amount), 0)::BIGINT AS total FROM estimated_payments
+             WHERE client_id = $1 AND tax_year = $2
+         ) AS paid
+         WHERE client_id = $1 AND tax_year = $2 AND return_kind <> 'superseded' AND jurisdiction = 'federal'",
+        &[&client_id, &tax_year],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use postgres::{GenericClient, Row};
+
+use super::{decrypt, get_tax_return, parse_column};
+use crate::db::encryption::FieldKey;
+use crate::db::household::HouseholdError;
+use crate::db::models::{HouseholdMember, MemberRole};
+use crate::db::repository::{StoreError, StoreResult};
+
+const MEMBER_COLUMNS: &str = "member_id, client_id, role, first_name, last_name, relationship,
+    birth_date, social_security_number, created_at, updated_at";
+
+fn map_member(row: &Row, key: &FieldKey) -> StoreResult<HouseholdMember> {
+    Ok(HouseholdMember {
+        member_id: Some(row.get(0)),
+        client_id: row.get(1),
+        role: parse_column(row.get(2), MemberRole::parse, "household role")?,
+        first_name: row.get(3),
+        last_name: row.get(4),
+        relationship: row.get(5),
+        birth_date: row.get(6),
+        social_security_number: decrypt(key, row.get(7))?,
+        created_at: row.get(8),
+        updated_at: row.get(9),
+    })
+}
+
+/// Adds a member; the partial unique index on spouses refuses a second one,
+/// even from a concurrent request.
+pub(super) fn add_member(
+    client: &mut impl GenericClient,
+    key: &FieldKey,
+    member: &HouseholdMember,
+) -> Result<i64, HouseholdError> {
+    let row = client.query_one(
+        "INSERT INTO household_members (
+            client_id, role, first_name, last_name, relationship, birth_date, social_security_number
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING member_id",
+        &[
+            &member.client_id,
+            &member.role.as_str(),
+            &member.first_name,
+            &member.last_name,
+            &member.relationship,
+            &member.birth_date,
+            &key.encrypt(&member.social_security_number),
+        ],
+    ).map_err(|e| {
+        let e = StoreError::from(e);
+        if e.is_unique_violation() { HouseholdError::SpouseExists } else { HouseholdError::Database(e) }
+    })?;
+    Ok(row.get(0))
+}
+
+pub(super) fn get_member(client: &mut impl GenericClient, key: &FieldKey, member_id: i64) -> StoreResult<Option<HouseholdMember>> {
+    client.query_opt(
+        &format!("SELECT {} FROM household_members WHERE member_id = $1", MEMBER_COLUMNS),
+        &[&member_id],
+    )?.map(|row| map_member(&row, key)).transpose()
+}
+
+pub(super) fn list_members(client: &mut impl GenericClient, key: &FieldKey, client_id: i64) -> StoreResult<Vec<HouseholdMember>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM household_members
+             WHERE client_id = $1
+             ORDER BY role = 'dependent', birth_date, member_id",
+            MEMBER_COLUMNS
+        ),
+        &[&client_id],
+    )?.iter().map(|row| map_member(row, key)).collect()
+}
+
+pub(super) fn remove_member(client: &mut impl GenericClient, member_id: i64) -> StoreResult<bool> {
+    let mut tx = client.transaction()?;
+    tx.execute("DELETE FROM return_household_members WHERE member_id = $1", &[&member_id])?;
+    let removed = tx.execute("DELETE FROM household_members WHERE member_id = $1", &[&member_id])?;
+    tx.commit()?;
+    Ok(removed > 0)
+}
+
+/// Replaces the household members a return covers. The return's row is
+/// locked so concurrent replacements apply one after the other.
+pub(super) fn set_return_members(
+    client: &mut impl GenericClient,
+    key: &FieldKey,
+    tax_return_id: i64,
+    member_ids: &[i64],
+) -> Result<Vec<HouseholdMember>, HouseholdError> {
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    tx.execute("SELECT 1 FROM tax_returns WHERE tax_return_id = $1 FOR UPDATE", &[&tax_return_id])
+        .map_err(StoreError::from)?;
+    let tax_return = get_tax_return(&mut tx, tax_return_id)?.ok_or(HouseholdError::NotFound)?;
+    for member_id in member_ids {
+        let member = get_member(&mut tx, key, *member_id)?;
+        if member.is_none_or(|m| m.client_id != tax_return.client_id) {
+            return Err(HouseholdError::NotInHousehold(*member_id));
+        }
+    }
+
+    tx.execute("DELETE FROM return_household_members WHERE tax_return_id = $1", &[&tax_return_id])
+        .map_err(StoreError::from)?;
+    for member_id in member_ids {
+        tx.execute(
+            "INSERT INTO return_household_members (tax_return_id, member_id) VALUES ($1, $2)
+             ON CONFLICT DO NOTHING",
+            &[&tax_return_id, member_id],
+        ).map_err(StoreError::from)?;
+    }
+
+    let members = return_members(&mut tx, key, tax_return_id)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(members)
+}
+
+pub(super) fn return_members(client: &mut impl GenericClient, key: &FieldKey, tax_return_id: i64) -> StoreResult<Vec<HouseholdMember>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM household_members
+             WHERE member_id IN (SELECT member_id FROM return_household_members WHERE tax_return_id = $1)
+             ORDER BY role = 'dependent', birth_date, member_id",
+            MEMBER_COLUMNS
+        ),
+        &[&tax_return_id],
+    )?.iter().map(|row| map_member(row, key)).collect()
+}
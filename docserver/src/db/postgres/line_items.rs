@@ -0,0 +1,237 @@
+use postgres::{GenericClient, Row};
+use std::collections::HashMap;
+
+use super::{get_tax_return, parse_column, to_json};
+use crate::db::line_items::{check_category, LineItemError};
+use crate::db::models::{LineItem, LineItemSection, ReturnKind};
+use crate::db::repository::{StoreError, StoreResult};
+use crate::money::Money;
+
+const LINE_ITEM_COLUMNS: &str = "line_item_id, tax_return_id, section, category, payer, amount,
+    document_id, created_at, updated_at";
+
+fn map_line_item(row: &Row) -> StoreResult<LineItem> {
+    Ok(LineItem {
+        line_item_id: Some(row.get(0)),
+        tax_return_id: row.get(1),
+        section: parse_column(row.get(2), LineItemSection::parse, "line item section")?,
+        category: row.get(3),
+        payer: row.get(4),
+        amount: Money::from_cents(row.get(5)),
+        document_id: row.get(6),
+        created_at: row.get(7),
+        updated_at: row.get(8),
+    })
+}
+
+fn get_item(client: &mut impl GenericClient, line_item_id: i64) -> StoreResult<Option<LineItem>> {
+    client.query_opt(
+        &format!("SELECT {} FROM return_line_items WHERE line_item_id = $1", LINE_ITEM_COLUMNS),
+        &[&line_item_id],
+    )?.as_ref().map(map_line_item).transpose()
+}
+
+pub(super) fn list_items(
+    client: &mut impl GenericClient,
+    tax_return_id: i64,
+    section: Option<LineItemSection>,
+) -> StoreResult<Vec<LineItem>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM return_line_items
+             WHERE tax_return_id = $1 AND ($2::TEXT IS NULL OR section = $2)
+             ORDER BY CASE section WHEN 'income' THEN 0 WHEN 'deduction' THEN 1 ELSE 2 END, category, line_item_id",
+            LINE_ITEM_COLUMNS
+        ),
+        &[&tax_return_id, &section.map(|s| s.as_str())],
+    )?.iter().map(map_line_item).collect()
+}
+
+pub(super) fn search_items(
+    client: &mut impl GenericClient,
+    section: Option<LineItemSection>,
+    category: Option<&str>,
+    client_id: Option<i64>,
+    tax_year: Option<i32>,
+) -> StoreResult<Vec<LineItem>> {
+    client.query(
+        "SELECT i.line_item_id, i.tax_return_id, i.section, i.category, i.payer, i.amount,
+                i.document_id, i.created_at, i.updated_at
+         FROM return_line_items i
+         JOIN tax_returns t ON t.tax_return_id = i.tax_return_id
+         WHERE t.return_kind <> 'superseded'
+           AND ($1::TEXT IS NULL OR i.section = $1)
+           AND ($2::TEXT IS NULL OR i.category = $2)
+           AND ($3::BIGINT IS NULL OR t.client_id = $3)
+           AND ($4::INTEGER IS NULL OR t.tax_year = $4)
+         ORDER BY t.client_id, t.tax_year DESC, i.line_item_id",
+        &[&section.map(|s| s.as_str()), &category, &client_id, &tax_year],
+    )?.iter().map(map_line_item).collect()
+}
+
+/// Locks a line item's return and checks that the item can be written to
+/// it, as `line_items::validate` does.
+fn validate(client: &mut impl GenericClient, item: &LineItem) -> Result<(), LineItemError> {
+    check_category(item.section, &item.category)?;
+    client.execute("SELECT 1 FROM tax_returns WHERE tax_return_id = $1 FOR UPDATE", &[&item.tax_return_id])
+        .map_err(StoreError::from)?;
+    let tax_return = get_tax_return(client, item.tax_return_id)?.ok_or(LineItemError::ReturnNotFound)?;
+    if tax_return.return_kind == ReturnKind::Superseded {
+        return Err(LineItemError::Superseded);
+    }
+    if let Some(document_id) = item.document_id {
+        let owner: Option<i64> = client.query_opt("SELECT client_id FROM documents WHERE document_id = $1", &[&document_id])
+            .map_err(StoreError::from)?
+            .map(|row| row.get(0));
+        if owner != Some(tax_return.client_id) {
+            return Err(LineItemError::DocumentNotFound(document_id));
+        }
+    }
+    Ok(())
+}
+
+/// Inserts one line without checking its return; see [`add_item`].
+pub(super) fn insert_item(client: &mut impl GenericClient, item: &LineItem) -> Result<i64, LineItemError> {
+    check_category(item.section, &item.category)?;
+    let row = client.query_one(
+        "INSERT INTO return_line_items (tax_return_id, section, category, payer, amount, document_id)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING line_item_id",
+        &[
+            &item.tax_return_id,
+            &item.section.as_str(),
+            &item.category,
+            &item.payer,
+            &item.amount.cents(),
+            &item.document_id,
+        ],
+    ).map_err(StoreError::from)?;
+    Ok(row.get(0))
+}
+
+pub(super) fn add_item(client: &mut impl GenericClient, item: &LineItem) -> Result<LineItem, LineItemError> {
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    validate(&mut tx, item)?;
+    let line_item_id = insert_item(&mut tx, item)?;
+    sync_return_maps(&mut tx, item.tax_return_id)?;
+
+    let added = get_item(&mut tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(added)
+}
+
+pub(super) fn update_item(
+    client: &mut impl GenericClient,
+    line_item_id: i64,
+    item: &LineItem,
+) -> Result<LineItem, LineItemError> {
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    let existing = get_item(&mut tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    let item = LineItem { tax_return_id: existing.tax_return_id, ..item.clone() };
+    validate(&mut tx, &item)?;
+
+    tx.execute(
+        "UPDATE return_line_items
+         SET section = $1, category = $2, payer = $3, amount = $4, document_id = $5, updated_at = CURRENT_TIMESTAMP
+         WHERE line_item_id = $6",
+        &[
+            &item.section.as_str(),
+            &item.category,
+            &item.payer,
+            &item.amount.cents(),
+            &item.document_id,
+            &line_item_id,
+        ],
+    ).map_err(StoreError::from)?;
+    sync_return_maps(&mut tx, existing.tax_return_id)?;
+
+    let updated = get_item(&mut tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(updated)
+}
+
+pub(super) fn remove_item(client: &mut impl GenericClient, line_item_id: i64) -> Result<(), LineItemError> {
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    let existing = get_item(&mut tx, line_item_id)?.ok_or(LineItemError::NotFound)?;
+    tx.execute("SELECT 1 FROM tax_returns WHERE tax_return_id = $1 FOR UPDATE", &[&existing.tax_return_id])
+        .map_err(StoreError::from)?;
+    let tax_return = get_tax_return(&mut tx, existing.tax_return_id)?.ok_or(LineItemError::ReturnNotFound)?;
+    if tax_return.return_kind == ReturnKind::Superseded {
+        return Err(LineItemError::Superseded);
+    }
+
+    tx.execute("DELETE FROM return_line_items WHERE line_item_id = $1", &[&line_item_id])
+        .map_err(StoreError::from)?;
+    sync_return_maps(&mut tx, existing.tax_return_id)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(())
+}
+
+/// Adds one line per map entry, for returns written as maps.
+pub(super) fn insert_items_from_map(
+    client: &mut impl GenericClient,
+    tax_return_id: i64,
+    section: LineItemSection,
+    map: &HashMap<String, Money>,
+) -> StoreResult<()> {
+    let mut categories: Vec<&String> = map.keys().collect();
+    categories.sort();
+    for category in categories {
+        insert_item(client, &LineItem {
+            line_item_id: None,
+            tax_return_id,
+            section,
+            category: category.clone(),
+            payer: None,
+            amount: map[category],
+            document_id: None,
+            created_at: None,
+            updated_at: None,
+        })?;
+    }
+    Ok(())
+}
+
+/// Copies one section's lines from one return to another, keeping payers
+/// and documents.
+pub(super) fn copy_items(
+    client: &mut impl GenericClient,
+    from_return_id: i64,
+    to_return_id: i64,
+    section: LineItemSection,
+) -> StoreResult<()> {
+    client.execute(
+        "INSERT INTO return_line_items (tax_return_id, section, category, payer, amount, document_id, created_at)
+         SELECT $1, section, category, payer, amount, document_id, created_at
+         FROM return_line_items WHERE tax_return_id = $2 AND section = $3 ORDER BY line_item_id",
+        &[&to_return_id, &from_return_id, &section.as_str()],
+    )?;
+    Ok(())
+}
+
+/// Rewrites a return's maps as the per-category totals of its line items.
+pub(super) fn sync_return_maps(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<()> {
+    let mut maps: HashMap<LineItemSection, HashMap<String, Money>> =
+        LineItemSection::ALL.iter().map(|section| (*section, HashMap::new())).collect();
+    let totals = client.query(
+        "SELECT section, category, SUM(amount)::BIGINT FROM return_line_items
+         WHERE tax_return_id = $1 GROUP BY section, category",
+        &[&tax_return_id],
+    )?;
+    for row in &totals {
+        let section = parse_column(row.get(0), LineItemSection::parse, "line item section")?;
+        maps.entry(section).or_default().insert(row.get(1), Money::from_cents(row.get(2)));
+    }
+
+    client.execute(
+        "UPDATE tax_returns SET income_sources = $1, deductions = $2, credits = $3, updated_at = CURRENT_TIMESTAMP
+         WHERE tax_return_id = $4",
+        &[
+            &to_json(&maps[&LineItemSection::Income])?,
+            &to_json(&maps[&LineItemSection::Deduction])?,
+            &to_json(&maps[&LineItemSection::Credit])?,
+            &tax_return_id,
+        ],
+    )?;
+    Ok(())
+}
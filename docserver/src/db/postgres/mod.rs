@@ -0,0 +1,492 @@
+use native_tls::TlsConnector;
+use postgres::{Config, GenericClient, Row};
+use postgres_native_tls::MakeTlsConnector;
+use r2d2_postgres::PostgresConnectionManager;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use crate::money::Money;
+use super::clients::CLIENT_COLUMNS;
+use super::encryption::FieldKey;
+use super::models::{Client, Document, DocumentType, Jurisdiction, LineItemSection, ReturnKind, ReturnStatus, TaxReturn};
+use super::repository::{Repository, StoreError, StoreResult};
+use super::returns::{self, Amendment, AmendError, StateReturn, StateReturnError, TAX_RETURN_COLUMNS};
+use super::line_items::{check_maps, section_map};
+use super::{CheckoutStats, PoolMetrics, PoolOptions};
+
+mod checklists;
+mod deadlines;
+mod documents;
+mod estimates;
+mod household;
+mod line_items;
+mod office;
+mod portal;
+mod proposals;
+mod share_links;
+mod upload_links;
+mod workflow;
+
+// Schema versions line up with the SQLite migrations, so a database of
+// either kind at version N has the same tables. PostgreSQL support starts
+// from the schema as of version 15; each later SQLite migration needs an
+// entry here too.
+const MIGRATIONS: &[(i64, &str)] = &[
+    (15, include_str!("../migrations/postgres/0015_baseline.sql")),
+    (16, include_str!("../migrations/postgres/0016_document_checksums.sql")),
+    (17, include_str!("../migrations/postgres/0017_return_types.sql")),
+    (18, include_str!("../migrations/postgres/0018_effective_returns.sql")),
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |(version, _)| *version)
+}
+
+pub fn current_version(client: &mut impl GenericClient) -> StoreResult<i64> {
+    let row = client.query_one("SELECT COALESCE(MAX(version), 0) FROM schema_version", &[])?;
+    Ok(row.get(0))
+}
+
+/// Brings the database up to the latest schema version, each migration in
+/// its own transaction. The version table is locked while a migration is
+/// checked and applied, so concurrent openers never apply one twice.
+pub fn migrate(client: &mut postgres::Client) -> StoreResult<()> {
+    client.batch_execute("CREATE TABLE IF NOT EXISTS schema_version (version BIGINT NOT NULL)")?;
+    for (version, sql) in MIGRATIONS {
+        let mut tx = client.transaction()?;
+        tx.batch_execute("LOCK TABLE schema_version IN EXCLUSIVE MODE")?;
+        if *version <= current_version(&mut tx)? {
+            continue;
+        }
+        tx.batch_execute(sql)?;
+        tx.execute("INSERT INTO schema_version (version) VALUES ($1)", &[version])?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Connects over TLS as the connection's `sslmode` asks: `prefer`, the
+/// default, encrypts whenever the server offers it, `require` refuses an
+/// unencrypted connection, and `disable` never encrypts. Certificates are
+/// checked against the system's trusted roots.
+pub fn tls_connector() -> StoreResult<MakeTlsConnector> {
+    Ok(MakeTlsConnector::new(TlsConnector::new()?))
+}
+
+/// Everything the server stores, in a PostgreSQL database shared between
+/// offices. Each call checks a connection out of the pool for its duration.
+pub struct PostgresStore {
+    // Only `None` while dropping; see the `Drop` impl
+    pool: Option<r2d2::Pool<PostgresConnectionManager<MakeTlsConnector>>>,
+    field_key: FieldKey,
+    stats: CheckoutStats,
+}
+
+impl PostgresStore {
+    /// Connects to the database `config` names and migrates it. A URL such
+    /// as `postgres://docserver@db.internal/docserver?sslmode=require` parses
+    /// into one. Connections use TLS as [`tls_connector`] describes.
+    pub fn connect(config: Config, field_key: FieldKey, options: PoolOptions) -> StoreResult<Self> {
+        let manager = PostgresConnectionManager::new(config, tls_connector()?);
+        let pool = r2d2::Pool::builder()
+            .max_size(options.size)
+            .connection_timeout(options.checkout_timeout)
+            .build(manager)?;
+        migrate(&mut *pool.get()?)?;
+        Ok(PostgresStore { pool: Some(pool), field_key, stats: CheckoutStats::default() })
+    }
+
+    fn pool(&self) -> &r2d2::Pool<PostgresConnectionManager<MakeTlsConnector>> {
+        self.pool.as_ref().expect("The pool is only taken when the store is dropped")
+    }
+
+    fn client(&self) -> StoreResult<r2d2::PooledConnection<PostgresConnectionManager<MakeTlsConnector>>> {
+        Ok(self.stats.checkout(self.pool())?)
+    }
+
+    /// Pool usage as for SQLite. The busy timeout is reported as zero:
+    /// writers here wait on row locks, for as long as the holder needs.
+    pub fn metrics(&self) -> PoolMetrics {
+        self.stats.metrics(self.pool(), Duration::ZERO)
+    }
+}
+
+impl Drop for PostgresStore {
+    /// Closes the pool on a thread of its own. Closing a connection blocks on
+    /// the driver's runtime, which panics on an async worker, where Rocket
+    /// drops its state.
+    fn drop(&mut self) {
+        let pool = self.pool.take();
+        if thread::spawn(move || drop(pool)).join().is_err() {
+            eprintln!("Failed to close the PostgreSQL connections");
+        }
+    }
+}
+
+fn parse_column<T>(value: &str, parse: impl Fn(&str) -> Option<T>, what: &str) -> StoreResult<T> {
+    parse(value).ok_or_else(|| StoreError::Data(format!("Unknown {}: {}", what, value)))
+}
+
+fn from_json(value: &str) -> HashMap<String, Money> {
+    serde_json::from_str(value).unwrap_or_default()
+}
+
+fn to_json(map: &HashMap<String, Money>) -> StoreResult<String> {
+    serde_json::to_string(map).map_err(|e| StoreError::Data(format!("Serialization error: {}", e)))
+}
+
+/// Turns a failed decryption into the error a row mapper returns.
+fn decrypt(key: &FieldKey, stored: &str) -> StoreResult<String> {
+    key.decrypt(stored).map_err(|e| StoreError::Data(format!("social_security_number: {}", e)))
+}
+
+fn map_client(row: &Row, key: &FieldKey) -> StoreResult<Client> {
+    Ok(Client {
+        client_id: Some(row.get(0)),
+        first_name: row.get(1),
+        last_name: row.get(2),
+        social_security_number: decrypt(key, row.get(3))?,
+        address: row.get(4),
+        phone_number: row.get(5),
+        email: row.get(6),
+        created_at: row.get(7),
+        updated_at: row.get(8),
+    })
+}
+
+fn map_tax_return(row: &Row) -> StoreResult<TaxReturn> {
+    Ok(TaxReturn {
+        tax_return_id: Some(row.get(0)),
+        client_id: row.get(1),
+        tax_year: row.get(2),
+        filing_status: row.get(3),
+        income_sources: from_json(row.get(4)),
+        deductions: from_json(row.get(5)),
+        credits: from_json(row.get(6)),
+        taxes_paid: Money::from_cents(row.get(7)),
+        tax_liability: Money::from_cents(row.get(8)),
+        refund_or_amount_due: Money::from_cents(row.get(9)),
+        return_kind: parse_column(row.get(10), ReturnKind::parse, "return kind")?,
+        parent_return_id: row.get(11),
+        amendment_reason: row.get(12),
+        status: parse_column(row.get(13), ReturnStatus::parse, "return status")?,
+        assignee: row.get(14),
+        created_at: row.get(15),
+        updated_at: row.get(16),
+        jurisdiction: parse_column(row.get(17), Jurisdiction::parse, "jurisdiction")?,
+        federal_return_id: row.get(18),
+    })
+}
+
+fn get_tax_return(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<Option<TaxReturn>> {
+    client.query_opt(
+        &format!("SELECT {} FROM tax_returns WHERE tax_return_id = $1", TAX_RETURN_COLUMNS),
+        &[&tax_return_id],
+    )?.as_ref().map(map_tax_return).transpose()
+}
+
+fn list_tax_returns(
+    client: &mut impl GenericClient,
+    client_id: Option<i64>,
+    jurisdiction: Option<&Jurisdiction>,
+    include_superseded: bool,
+) -> StoreResult<Vec<TaxReturn>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM tax_returns
+             WHERE ($1::BIGINT IS NULL OR client_id = $1)
+               AND ($2::TEXT IS NULL OR jurisdiction = $2)
+               AND ($3 OR return_kind <> 'superseded')
+             ORDER BY client_id, tax_year DESC, jurisdiction <> 'federal', jurisdiction, tax_return_id",
+            TAX_RETURN_COLUMNS
+        ),
+        &[&client_id, &jurisdiction.map(Jurisdiction::as_str), &include_superseded],
+    )?.iter().map(map_tax_return).collect()
+}
+
+fn return_chain(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+    let mut current = match get_tax_return(client, tax_return_id)? {
+        Some(tax_return) => tax_return,
+        None => return Ok(Vec::new()),
+    };
+
+    // Walk up to the original...
+    let mut chain = Vec::new();
+    while let Some(parent_id) = current.parent_return_id {
+        match get_tax_return(client, parent_id)? {
+            Some(parent) => {
+                chain.push(current);
+                current = parent;
+            }
+            None => break,
+        }
+    }
+    chain.push(current);
+    chain.reverse();
+
+    // ...then down through any later amendments
+    loop {
+        let last_id = chain.last().and_then(|r| r.tax_return_id);
+        let child = client.query_opt(
+            &format!(
+                "SELECT {} FROM tax_returns WHERE parent_return_id = $1 ORDER BY tax_return_id LIMIT 1",
+                TAX_RETURN_COLUMNS
+            ),
+            &[&last_id],
+        )?;
+        match child {
+            Some(child) => chain.push(map_tax_return(&child)?),
+            None => break,
+        }
+    }
+    Ok(chain)
+}
+
+/// Inserts the return row and its first status history entry, but no line items.
+fn insert_return_row(client: &mut impl GenericClient, tax_return: &TaxReturn) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO tax_returns (
+            client_id, tax_year, filing_status, income_sources,
+            deductions, credits, taxes_paid, tax_liability,
+            refund_or_amount_due, return_kind, parent_return_id, amendment_reason,
+            status, assignee, jurisdiction, federal_return_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+        RETURNING tax_return_id",
+        &[
+            &tax_return.client_id,
+            &tax_return.tax_year,
+            &tax_return.filing_status,
+            &to_json(&tax_return.income_sources)?,
+            &to_json(&tax_return.deductions)?,
+            &to_json(&tax_return.credits)?,
+            &tax_return.taxes_paid.cents(),
+            &tax_return.tax_liability.cents(),
+            &tax_return.refund_or_amount_due.cents(),
+            &tax_return.return_kind.as_str(),
+            &tax_return.parent_return_id,
+            &tax_return.amendment_reason,
+            &tax_return.status.as_str(),
+            &tax_return.assignee,
+            &tax_return.jurisdiction.as_str(),
+            &tax_return.federal_return_id,
+        ],
+    )?;
+    let tax_return_id: i64 = row.get(0);
+
+    client.execute(
+        "INSERT INTO return_status_history (tax_return_id, to_status, assignee) VALUES ($1, $2, $3)",
+        &[&tax_return_id, &tax_return.status.as_str(), &tax_return.assignee],
+    )?;
+    Ok(tax_return_id)
+}
+
+/// The effective state returns attached to a federal return.
+fn state_returns(client: &mut impl GenericClient, federal_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM tax_returns
+             WHERE federal_return_id = $1 AND return_kind <> 'superseded'
+             ORDER BY jurisdiction, tax_return_id",
+            TAX_RETURN_COLUMNS
+        ),
+        &[&federal_return_id],
+    )?.iter().map(map_tax_return).collect()
+}
+
+/// Inserts a client, encrypting the social security number.
+fn insert_client(client: &mut impl GenericClient, key: &FieldKey, new_client: &Client) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO clients (
+            first_name, last_name, social_security_number,
+            address, phone_number, email
+        ) VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING client_id",
+        &[
+            &new_client.first_name,
+            &new_client.last_name,
+            &key.encrypt(&new_client.social_security_number),
+            &new_client.address,
+            &new_client.phone_number,
+            &new_client.email,
+        ],
+    )?;
+    Ok(row.get(0))
+}
+
+impl Repository for PostgresStore {
+    fn list_clients(&self) -> StoreResult<Vec<Client>> {
+        let rows = self.client()?.query(&format!("SELECT {} FROM clients ORDER BY client_id", CLIENT_COLUMNS), &[])?;
+        rows.iter().map(|row| map_client(row, &self.field_key)).collect()
+    }
+
+    fn get_client(&self, client_id: i64) -> StoreResult<Option<Client>> {
+        let row = self.client()?.query_opt(
+            &format!("SELECT {} FROM clients WHERE client_id = $1", CLIENT_COLUMNS),
+            &[&client_id],
+        )?;
+        row.map(|row| map_client(&row, &self.field_key)).transpose()
+    }
+
+    fn create_client(&self, client: &Client) -> StoreResult<i64> {
+        insert_client(&mut *self.client()?, &self.field_key, client)
+    }
+
+    fn get_tax_return(&self, tax_return_id: i64) -> StoreResult<Option<TaxReturn>> {
+        get_tax_return(&mut *self.client()?, tax_return_id)
+    }
+
+    fn list_tax_returns(
+        &self,
+        client_id: Option<i64>,
+        jurisdiction: Option<&Jurisdiction>,
+        include_superseded: bool,
+    ) -> StoreResult<Vec<TaxReturn>> {
+        list_tax_returns(&mut *self.client()?, client_id, jurisdiction, include_superseded)
+    }
+
+    fn create_tax_return(&self, tax_return: &TaxReturn) -> StoreResult<i64> {
+        let mut client = self.client()?;
+        let mut tx = client.transaction()?;
+        estimates::lock_client(&mut tx, tax_return.client_id)?;
+        let tax_return_id = insert_return_row(&mut tx, tax_return)?;
+        for section in LineItemSection::ALL {
+            line_items::insert_items_from_map(&mut tx, tax_return_id, section, section_map(tax_return, section))?;
+        }
+        estimates::apply_to_return(&mut tx, tax_return.client_id, tax_return.tax_year)?;
+        tx.commit()?;
+        Ok(tax_return_id)
+    }
+
+    fn return_chain(&self, tax_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+        return_chain(&mut *self.client()?, tax_return_id)
+    }
+
+    fn amend_return(&self, tax_return_id: i64, amendment: Amendment) -> Result<TaxReturn, AmendError> {
+        if amendment.amendment_reason.trim().is_empty() {
+            return Err(AmendError::MissingReason);
+        }
+
+        let mut client = self.client()?;
+        let mut tx = client.transaction().map_err(StoreError::from)?;
+        // Locks the row so a concurrent amendment waits and then sees it superseded
+        tx.execute("SELECT 1 FROM tax_returns WHERE tax_return_id = $1 FOR UPDATE", &[&tax_return_id])
+            .map_err(StoreError::from)?;
+        let parent = get_tax_return(&mut tx, tax_return_id)?.ok_or(AmendError::NotFound)?;
+        if parent.return_kind == ReturnKind::Superseded {
+            let current = return_chain(&mut tx, tax_return_id)?.last().and_then(|r| r.tax_return_id);
+            return Err(AmendError::Superseded(current));
+        }
+
+        let (amended, overridden) = returns::amended_return(parent, tax_return_id, amendment);
+        check_maps(&amended)?;
+        // Only one return per client, year and jurisdiction is effective at a time
+        tx.execute(
+            "UPDATE tax_returns SET return_kind = $1, updated_at = CURRENT_TIMESTAMP WHERE tax_return_id = $2",
+            &[&ReturnKind::Superseded.as_str(), &tax_return_id],
+        ).map_err(StoreError::from)?;
+        let amended_id = insert_return_row(&mut tx, &amended)?;
+        for (section, replaced) in overridden {
+            if replaced {
+                line_items::insert_items_from_map(&mut tx, amended_id, section, section_map(&amended, section))?;
+            } else {
+                line_items::copy_items(&mut tx, tax_return_id, amended_id, section)?;
+            }
+        }
+        line_items::sync_return_maps(&mut tx, amended_id)?;
+
+        let carried_over = [
+            "INSERT INTO income_provenance (
+                tax_return_id, target, category, amount, document_id, proposal_id, created_at
+            )
+            SELECT $1, target, category, amount, document_id, proposal_id, created_at
+            FROM income_provenance WHERE tax_return_id = $2 ORDER BY provenance_id",
+            // The amendment covers the same household as the return it replaces
+            "INSERT INTO return_household_members (tax_return_id, member_id)
+             SELECT $1, member_id FROM return_household_members WHERE tax_return_id = $2",
+            // taxes_paid was carried over with the parent's estimated payments in it
+            "UPDATE tax_returns SET estimated_payments_applied = (
+                SELECT estimated_payments_applied FROM tax_returns WHERE tax_return_id = $2
+            ) WHERE tax_return_id = $1",
+            // State returns follow the federal return to its amendment
+            "UPDATE tax_returns SET federal_return_id = $1 WHERE federal_return_id = $2",
+        ];
+        for sql in carried_over {
+            tx.execute(sql, &[&amended_id, &tax_return_id]).map_err(StoreError::from)?;
+        }
+
+        let amended = get_tax_return(&mut tx, amended_id)?.ok_or(AmendError::NotFound)?;
+        tx.commit().map_err(StoreError::from)?;
+        Ok(amended)
+    }
+
+    fn state_returns(&self, federal_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+        state_returns(&mut *self.client()?, federal_return_id)
+    }
+
+    fn add_state_return(
+        &self,
+        federal_return_id: i64,
+        state_return: StateReturn,
+    ) -> Result<TaxReturn, StateReturnError> {
+        if state_return.state.is_federal() {
+            return Err(StateReturnError::NotAState);
+        }
+
+        let mut client = self.client()?;
+        let mut tx = client.transaction().map_err(StoreError::from)?;
+        tx.execute("SELECT 1 FROM tax_returns WHERE tax_return_id = $1 FOR UPDATE", &[&federal_return_id])
+            .map_err(StoreError::from)?;
+        let federal = get_tax_return(&mut tx, federal_return_id)?.ok_or(StateReturnError::FederalNotFound)?;
+        if !federal.jurisdiction.is_federal() {
+            return Err(StateReturnError::NotFederal);
+        }
+        if federal.return_kind == ReturnKind::Superseded {
+            let current = return_chain(&mut tx, federal_return_id)?.last().and_then(|r| r.tax_return_id);
+            return Err(StateReturnError::Superseded(current));
+        }
+        let existing = list_tax_returns(&mut tx, Some(federal.client_id), Some(&state_return.state), false)?;
+        if let Some(existing) = existing.iter().find(|r| r.tax_year == federal.tax_year) {
+            return Err(StateReturnError::Exists(existing.tax_return_id.unwrap_or_default()));
+        }
+
+        let tax_return = returns::state_return_for(federal, federal_return_id, state_return);
+        check_maps(&tax_return)?;
+        let tax_return_id = insert_return_row(&mut tx, &tax_return)?;
+        for section in LineItemSection::ALL {
+            line_items::insert_items_from_map(&mut tx, tax_return_id, section, section_map(&tax_return, section))?;
+        }
+
+        let created = get_tax_return(&mut tx, tax_return_id)?.ok_or(StateReturnError::FederalNotFound)?;
+        tx.commit().map_err(StoreError::from)?;
+        Ok(created)
+    }
+
+    fn get_document(&self, document_id: i64) -> StoreResult<Option<Document>> {
+        documents::get_document(&mut *self.client()?, document_id)
+    }
+
+    fn list_client_documents(&self, client_id: i64) -> StoreResult<Vec<Document>> {
+        documents::list_client_documents(&mut *self.client()?, client_id)
+    }
+
+    fn save_document(&self, document: &Document) -> StoreResult<i64> {
+        documents::upsert_document(&mut *self.client()?, document)
+    }
+
+    fn override_document_type(&self, document_id: i64, document_type: DocumentType, tax_year: Option<i32>) -> StoreResult<bool> {
+        documents::override_document_type(&mut *self.client()?, document_id, document_type, tax_year)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+
+    #[test]
+    fn test_schema_versions_match_sqlite() {
+        // A SQLite migration without a PostgreSQL counterpart fails here
+        assert_eq!(latest_version(), migrations::latest_version());
+    }
+}
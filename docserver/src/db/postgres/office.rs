@@ -0,0 +1,373 @@
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+use super::{
+    checklists, current_version, deadlines, decrypt, documents, estimates, household, insert_client, line_items,
+    portal, proposals, share_links, upload_links, workflow, PostgresStore,
+};
+use crate::config::Settings;
+use crate::db::deadlines::ExtensionError;
+use crate::db::encryption::ENCRYPTED_COLUMNS;
+use crate::db::household::HouseholdError;
+use crate::db::line_items::LineItemError;
+use crate::db::models::*;
+use crate::db::portal::ReviewError;
+use crate::db::proposals::AcceptError;
+use crate::db::repository::StoreResult;
+use crate::db::workflow::TransitionError;
+use crate::db::{FieldKey, OfficeRepository};
+use crate::documents::consistency::{ConsistencyError, ConsistencyReport, RepairReport};
+use crate::tax::Deadline;
+
+impl OfficeRepository for PostgresStore {
+    fn ingest_upload(&self, root_path: &Path, client_id: i64, file_name: &str) -> StoreResult<Document> {
+        documents::ingest_upload(&mut *self.client()?, root_path, client_id, file_name)
+    }
+
+    fn override_document(
+        &self,
+        root_path: &Path,
+        document_id: i64,
+        document_type: DocumentType,
+        tax_year: Option<i32>,
+    ) -> StoreResult<Option<Document>> {
+        documents::override_type(&mut *self.client()?, root_path, document_id, document_type, tax_year)
+    }
+
+    fn list_documents(&self) -> StoreResult<Vec<Document>> {
+        documents::list_documents(&mut *self.client()?)
+    }
+
+    fn reindex_document(&self, root_path: &Path, document: &Document) -> StoreResult<Option<Document>> {
+        documents::reindex_document(&mut *self.client()?, root_path, document)
+    }
+
+    fn check_consistency(&self, settings: &Settings) -> Result<ConsistencyReport, ConsistencyError> {
+        documents::check_consistency(&mut *self.client()?, settings)
+    }
+
+    fn repair_consistency(&self, settings: &Settings) -> Result<RepairReport, ConsistencyError> {
+        documents::repair_consistency(&mut *self.client()?, settings)
+    }
+
+    fn import_clients(&self, imported: &[Client]) -> StoreResult<Vec<i64>> {
+        let mut client = self.client()?;
+        let mut tx = client.transaction()?;
+        let ids = imported.iter()
+            .map(|client| insert_client(&mut tx, &self.field_key, client))
+            .collect::<StoreResult<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    fn schema_version(&self) -> StoreResult<i64> {
+        current_version(&mut *self.client()?)
+    }
+
+    /// Re-encrypts every encrypted column in one transaction, as
+    /// `encryption::rotate_field_key` does. The tables are locked against
+    /// writers so no value is written under the old key meanwhile.
+    fn rotate_field_key(&self, new_key: &FieldKey) -> StoreResult<usize> {
+        let mut client = self.client()?;
+        let mut tx = client.transaction()?;
+        tx.batch_execute("LOCK TABLE clients, household_members IN EXCLUSIVE MODE")?;
+        let mut rotated = 0;
+        for (table, id_column, column) in ENCRYPTED_COLUMNS {
+            let values = tx.query(&format!("SELECT {}, {} FROM {}", id_column, column, table), &[])?
+                .iter()
+                .map(|row| Ok((row.get::<_, i64>(0), decrypt(&self.field_key, row.get(1))?)))
+                .collect::<StoreResult<Vec<_>>>()?;
+            for (id, plaintext) in &values {
+                tx.execute(
+                    &format!("UPDATE {} SET {} = $1 WHERE {} = $2", table, column, id_column),
+                    &[&new_key.encrypt(plaintext), id],
+                )?;
+            }
+            rotated += values.len();
+        }
+        tx.commit()?;
+        Ok(rotated)
+    }
+
+    fn generate_checklist(&self, client_id: i64, tax_year: i32) -> StoreResult<Vec<ChecklistItem>> {
+        checklists::generate(&mut *self.client()?, client_id, tax_year)
+    }
+
+    fn list_checklist_items(
+        &self,
+        client_id: i64,
+        tax_year: Option<i32>,
+        status: Option<ChecklistStatus>,
+    ) -> StoreResult<Vec<ChecklistItem>> {
+        checklists::list_items(&mut *self.client()?, client_id, tax_year, status)
+    }
+
+    fn get_checklist_item(&self, item_id: i64) -> StoreResult<Option<ChecklistItem>> {
+        checklists::get_item(&mut *self.client()?, item_id)
+    }
+
+    fn mark_checklist_item_received(&self, item_id: i64, document_id: i64) -> StoreResult<bool> {
+        checklists::mark_item_received(&mut *self.client()?, item_id, document_id)
+    }
+
+    fn create_upload_link(
+        &self,
+        client_id: i64,
+        checklist_item_id: Option<i64>,
+        note: Option<&str>,
+        max_uploads: i64,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> StoreResult<i64> {
+        upload_links::create_link(&mut *self.client()?, client_id, checklist_item_id, note, max_uploads, nonce, expires_at)
+    }
+
+    fn get_upload_link(&self, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<UploadLink>> {
+        upload_links::get_link(&mut *self.client()?, link_id, now)
+    }
+
+    fn list_upload_links(
+        &self,
+        client_id: Option<i64>,
+        status: Option<LinkStatus>,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Vec<UploadLink>> {
+        upload_links::list_links(&mut *self.client()?, client_id, status, now)
+    }
+
+    fn revoke_upload_link(&self, link_id: i64) -> StoreResult<bool> {
+        upload_links::revoke_link(&mut *self.client()?, link_id)
+    }
+
+    fn claim_upload(&self, link_id: i64, nonce: &str, now: DateTime<Utc>) -> StoreResult<Option<UploadLink>> {
+        upload_links::claim_upload(&mut *self.client()?, link_id, nonce, now)
+    }
+
+    fn release_upload(&self, link_id: i64) -> StoreResult<()> {
+        upload_links::release_upload(&mut *self.client()?, link_id)
+    }
+
+    fn create_share_link(&self, link: &ShareLink) -> StoreResult<i64> {
+        share_links::create_link(&mut *self.client()?, link)
+    }
+
+    fn get_share_link(&self, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<ShareLink>> {
+        share_links::get_link(&mut *self.client()?, link_id, now)
+    }
+
+    fn list_share_links(
+        &self,
+        client_id: Option<i64>,
+        status: Option<LinkStatus>,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Vec<ShareLink>> {
+        share_links::list_links(&mut *self.client()?, client_id, status, now)
+    }
+
+    fn revoke_share_link(&self, link_id: i64) -> StoreResult<bool> {
+        share_links::revoke_link(&mut *self.client()?, link_id)
+    }
+
+    fn claim_download(&self, link_id: i64, nonce: &str, now: DateTime<Utc>) -> StoreResult<bool> {
+        share_links::claim_download(&mut *self.client()?, link_id, nonce, now)
+    }
+
+    fn release_download(&self, link_id: i64) -> StoreResult<()> {
+        share_links::release_download(&mut *self.client()?, link_id)
+    }
+
+    fn record_share_access(
+        &self,
+        link_id: i64,
+        outcome: AccessOutcome,
+        remote_addr: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> StoreResult<()> {
+        share_links::record_access(&mut *self.client()?, link_id, outcome, remote_addr, user_agent)
+    }
+
+    fn list_share_accesses(&self, link_id: i64) -> StoreResult<Vec<ShareLinkAccess>> {
+        share_links::list_accesses(&mut *self.client()?, link_id)
+    }
+
+    fn create_portal_user(&self, client_id: i64, email: &str, password_hash: &str) -> StoreResult<i64> {
+        portal::create_user(&mut *self.client()?, client_id, email, password_hash)
+    }
+
+    fn find_portal_user(&self, email: &str) -> StoreResult<Option<PortalUser>> {
+        portal::find_user_by_email(&mut *self.client()?, email)
+    }
+
+    fn list_portal_users(&self, client_id: i64) -> StoreResult<Vec<PortalUser>> {
+        portal::list_users(&mut *self.client()?, client_id)
+    }
+
+    fn create_portal_session(&self, user_id: i64, token_digest: &str, expires_at: DateTime<Utc>) -> StoreResult<i64> {
+        portal::create_session(&mut *self.client()?, user_id, token_digest, expires_at)
+    }
+
+    fn session_user(&self, token_digest: &str, now: DateTime<Utc>) -> StoreResult<Option<PortalUser>> {
+        portal::session_user(&mut *self.client()?, token_digest, now)
+    }
+
+    fn revoke_portal_session(&self, token_digest: &str) -> StoreResult<bool> {
+        portal::revoke_session(&mut *self.client()?, token_digest)
+    }
+
+    fn create_change_request(&self, request: &ContactChangeRequest) -> StoreResult<i64> {
+        portal::create_change_request(&mut *self.client()?, request)
+    }
+
+    fn get_change_request(&self, request_id: i64) -> StoreResult<Option<ContactChangeRequest>> {
+        portal::get_change_request(&mut *self.client()?, request_id)
+    }
+
+    fn list_change_requests(
+        &self,
+        client_id: Option<i64>,
+        status: Option<ChangeRequestStatus>,
+    ) -> StoreResult<Vec<ContactChangeRequest>> {
+        portal::list_change_requests(&mut *self.client()?, client_id, status)
+    }
+
+    fn review_change_request(
+        &self,
+        request_id: i64,
+        approve: bool,
+        review_note: Option<&str>,
+    ) -> Result<ContactChangeRequest, ReviewError> {
+        portal::review_change_request(&mut *self.client()?, request_id, approve, review_note)
+    }
+
+    fn add_household_member(&self, member: &HouseholdMember) -> Result<i64, HouseholdError> {
+        household::add_member(&mut *self.client()?, &self.field_key, member)
+    }
+
+    fn get_household_member(&self, member_id: i64) -> StoreResult<Option<HouseholdMember>> {
+        household::get_member(&mut *self.client()?, &self.field_key, member_id)
+    }
+
+    fn list_household(&self, client_id: i64) -> StoreResult<Vec<HouseholdMember>> {
+        household::list_members(&mut *self.client()?, &self.field_key, client_id)
+    }
+
+    fn remove_household_member(&self, member_id: i64) -> StoreResult<bool> {
+        household::remove_member(&mut *self.client()?, member_id)
+    }
+
+    fn set_return_household(&self, tax_return_id: i64, member_ids: &[i64]) -> Result<Vec<HouseholdMember>, HouseholdError> {
+        household::set_return_members(&mut *self.client()?, &self.field_key, tax_return_id, member_ids)
+    }
+
+    fn return_household(&self, tax_return_id: i64) -> StoreResult<Vec<HouseholdMember>> {
+        household::return_members(&mut *self.client()?, &self.field_key, tax_return_id)
+    }
+
+    fn list_line_items(&self, tax_return_id: i64, section: Option<LineItemSection>) -> StoreResult<Vec<LineItem>> {
+        line_items::list_items(&mut *self.client()?, tax_return_id, section)
+    }
+
+    fn add_line_item(&self, item: &LineItem) -> Result<LineItem, LineItemError> {
+        line_items::add_item(&mut *self.client()?, item)
+    }
+
+    fn update_line_item(&self, line_item_id: i64, item: &LineItem) -> Result<LineItem, LineItemError> {
+        line_items::update_item(&mut *self.client()?, line_item_id, item)
+    }
+
+    fn remove_line_item(&self, line_item_id: i64) -> Result<(), LineItemError> {
+        line_items::remove_item(&mut *self.client()?, line_item_id)
+    }
+
+    fn search_line_items(
+        &self,
+        section: Option<LineItemSection>,
+        category: Option<&str>,
+        client_id: Option<i64>,
+        tax_year: Option<i32>,
+    ) -> StoreResult<Vec<LineItem>> {
+        line_items::search_items(&mut *self.client()?, section, category, client_id, tax_year)
+    }
+
+    fn latest_document_proposal(&self, document_id: i64) -> StoreResult<Option<IncomeProposal>> {
+        proposals::latest_document_proposal(&mut *self.client()?, document_id)
+    }
+
+    fn list_proposals(&self, client_id: Option<i64>, status: Option<ProposalStatus>) -> StoreResult<Vec<IncomeProposal>> {
+        proposals::list_proposals(&mut *self.client()?, client_id, status)
+    }
+
+    fn accept_proposal(&self, proposal_id: i64, tax_return_id: Option<i64>) -> Result<IncomeProposal, AcceptError> {
+        proposals::accept_proposal(&mut *self.client()?, proposal_id, tax_return_id)
+    }
+
+    fn reject_proposal(&self, proposal_id: i64) -> StoreResult<bool> {
+        proposals::reject_proposal(&mut *self.client()?, proposal_id)
+    }
+
+    fn list_return_provenance(&self, tax_return_id: i64) -> StoreResult<Vec<IncomeProvenance>> {
+        proposals::list_return_provenance(&mut *self.client()?, tax_return_id)
+    }
+
+    fn add_estimated_payment(&self, payment: &EstimatedPayment) -> StoreResult<i64> {
+        estimates::add_payment(&mut *self.client()?, payment)
+    }
+
+    fn get_estimated_payment(&self, payment_id: i64) -> StoreResult<Option<EstimatedPayment>> {
+        estimates::get_payment(&mut *self.client()?, payment_id)
+    }
+
+    fn list_estimated_payments(&self, client_id: i64, tax_year: Option<i32>) -> StoreResult<Vec<EstimatedPayment>> {
+        estimates::list_payments(&mut *self.client()?, client_id, tax_year)
+    }
+
+    fn remove_estimated_payment(&self, payment_id: i64) -> StoreResult<bool> {
+        estimates::remove_payment(&mut *self.client()?, payment_id)
+    }
+
+    fn paying_clients(&self, tax_year: i32) -> StoreResult<Vec<i64>> {
+        estimates::paying_clients(&mut *self.client()?, tax_year)
+    }
+
+    fn get_tax_profile(&self, client_id: i64) -> StoreResult<Option<ClientTaxProfile>> {
+        deadlines::get_profile(&mut *self.client()?, client_id)
+    }
+
+    fn set_tax_profile(&self, profile: &ClientTaxProfile) -> StoreResult<()> {
+        deadlines::set_profile(&mut *self.client()?, profile)
+    }
+
+    fn get_extension(&self, tax_return_id: i64) -> StoreResult<Option<ReturnExtension>> {
+        deadlines::get_extension(&mut *self.client()?, tax_return_id)
+    }
+
+    fn add_extension(&self, extension: &ReturnExtension) -> Result<ReturnExtension, ExtensionError> {
+        deadlines::add_extension(&mut *self.client()?, extension)
+    }
+
+    fn remove_extension(&self, tax_return_id: i64) -> StoreResult<bool> {
+        deadlines::remove_extension(&mut *self.client()?, tax_return_id)
+    }
+
+    fn return_deadlines(&self, tax_return: &TaxReturn) -> StoreResult<Vec<Deadline>> {
+        deadlines::return_deadlines(&mut *self.client()?, tax_return)
+    }
+
+    fn transition_return(
+        &self,
+        tax_return_id: i64,
+        to: ReturnStatus,
+        assignee: Option<Option<&str>>,
+        note: Option<&str>,
+    ) -> Result<TaxReturn, TransitionError> {
+        workflow::transition_return(&mut *self.client()?, tax_return_id, to, assignee, note)
+    }
+
+    fn status_history(&self, tax_return_id: i64) -> StoreResult<Vec<StatusChange>> {
+        workflow::status_history(&mut *self.client()?, tax_return_id)
+    }
+
+    fn list_by_status(&self, status: Option<ReturnStatus>, assignee: Option<&str>) -> StoreResult<Vec<TaxReturn>> {
+        workflow::list_by_status(&mut *self.client()?, status, assignee)
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use postgres::{GenericClient, Row};
+
+use super::parse_column;
+use crate::db::models::{ChangeRequestStatus, ContactChangeRequest, PortalUser};
+use crate::db::portal::ReviewError;
+use crate::db::repository::{StoreError, StoreResult};
+
+const PORTAL_USER_COLUMNS: &str = "user_id, client_id, email, password_hash, disabled, last_login_at, created_at";
+
+const CHANGE_REQUEST_COLUMNS: &str = "request_id, client_id, user_id, address, phone_number, email,
+    status, review_note, created_at, reviewed_at";
+
+fn map_portal_user(row: &Row) -> PortalUser {
+    PortalUser {
+        user_id: Some(row.get(0)),
+        client_id: row.get(1),
+        email: row.get(2),
+        password_hash: row.get(3),
+        disabled: row.get(4),
+        last_login_at: row.get(5),
+        created_at: row.get(6),
+    }
+}
+
+fn map_change_request(row: &Row) -> StoreResult<ContactChangeRequest> {
+    Ok(ContactChangeRequest {
+        request_id: Some(row.get(0)),
+        client_id: row.get(1),
+        user_id: row.get(2),
+        address: row.get(3),
+        phone_number: row.get(4),
+        email: row.get(5),
+        status: parse_column(row.get(6), ChangeRequestStatus::parse, "change request status")?,
+        review_note: row.get(7),
+        created_at: row.get(8),
+        reviewed_at: row.get(9),
+    })
+}
+
+/// Emails are stored lowercased, as `portal::create_user` does.
+pub(super) fn create_user(client: &mut impl GenericClient, client_id: i64, email: &str, password_hash: &str) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO portal_users (client_id, email, password_hash) VALUES ($1, $2, $3) RETURNING user_id",
+        &[&client_id, &email.trim().to_lowercase(), &password_hash],
+    )?;
+    Ok(row.get(0))
+}
+
+pub(super) fn find_user_by_email(client: &mut impl GenericClient, email: &str) -> StoreResult<Option<PortalUser>> {
+    let row = client.query_opt(
+        &format!("SELECT {} FROM portal_users WHERE email = $1", PORTAL_USER_COLUMNS),
+        &[&email.trim().to_lowercase()],
+    )?;
+    Ok(row.as_ref().map(map_portal_user))
+}
+
+pub(super) fn list_users(client: &mut impl GenericClient, client_id: i64) -> StoreResult<Vec<PortalUser>> {
+    let rows = client.query(
+        &format!("SELECT {} FROM portal_users WHERE client_id = $1 ORDER BY user_id", PORTAL_USER_COLUMNS),
+        &[&client_id],
+    )?;
+    Ok(rows.iter().map(map_portal_user).collect())
+}
+
+pub(super) fn create_session(
+    client: &mut impl GenericClient,
+    user_id: i64,
+    token_digest: &str,
+    expires_at: DateTime<Utc>,
+) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO portal_sessions (user_id, token_digest, expires_at) VALUES ($1, $2, $3) RETURNING session_id",
+        &[&user_id, &token_digest, &expires_at],
+    )?;
+    client.execute(
+        "UPDATE portal_users SET last_login_at = CURRENT_TIMESTAMP WHERE user_id = $1",
+        &[&user_id],
+    )?;
+    Ok(row.get(0))
+}
+
+pub(super) fn session_user(client: &mut impl GenericClient, token_digest: &str, now: DateTime<Utc>) -> StoreResult<Option<PortalUser>> {
+    let row = client.query_opt(
+        &format!(
+            "SELECT {} FROM portal_users
+             WHERE NOT disabled AND user_id = (
+                 SELECT user_id FROM portal_sessions
+                 WHERE token_digest = $1 AND revoked_at IS NULL AND expires_at > $2
+             )",
+            PORTAL_USER_COLUMNS
+        ),
+        &[&token_digest, &now],
+    )?;
+    Ok(row.as_ref().map(map_portal_user))
+}
+
+pub(super) fn revoke_session(client: &mut impl GenericClient, token_digest: &str) -> StoreResult<bool> {
+    let updated = client.execute(
+        "UPDATE portal_sessions SET revoked_at = CURRENT_TIMESTAMP WHERE token_digest = $1 AND revoked_at IS NULL",
+        &[&token_digest],
+    )?;
+    Ok(updated > 0)
+}
+
+pub(super) fn create_change_request(client: &mut impl GenericClient, request: &ContactChangeRequest) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO contact_change_requests (client_id, user_id, address, phone_number, email, status)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING request_id",
+        &[
+            &request.client_id,
+            &request.user_id,
+            &request.address,
+            &request.phone_number,
+            &request.email,
+            &ChangeRequestStatus::Pending.as_str(),
+        ],
+    )?;
+    Ok(row.get(0))
+}
+
+pub(super) fn get_change_request(client: &mut impl GenericClient, request_id: i64) -> StoreResult<Option<ContactChangeRequest>> {
+    client.query_opt(
+        &format!("SELECT {} FROM contact_change_requests WHERE request_id = $1", CHANGE_REQUEST_COLUMNS),
+        &[&request_id],
+    )?.as_ref().map(map_change_request).transpose()
+}
+
+pub(super) fn list_change_requests(
+    client: &mut impl GenericClient,
+    client_id: Option<i64>,
+    status: Option<ChangeRequestStatus>,
+) -> StoreResult<Vec<ContactChangeRequest>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM contact_change_requests
+             WHERE ($1::BIGINT IS NULL OR client_id = $1)
+               AND ($2::TEXT IS NULL OR status = $2)
+             ORDER BY request_id",
+            CHANGE_REQUEST_COLUMNS
+        ),
+        &[&client_id, &status.map(|s| s.as_str())],
+    )?.iter().map(map_change_request).collect()
+}
+
+/// Approves or rejects a pending request as `portal::review_change_request`
+/// does. The request's row is locked, so a second reviewer waits and then
+/// finds it reviewed.
+pub(super) fn review_change_request(
+    client: &mut impl GenericClient,
+    request_id: i64,
+    approve: bool,
+    review_note: Option<&str>,
+) -> Result<ContactChangeRequest, ReviewError> {
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    tx.execute("SELECT 1 FROM contact_change_requests WHERE request_id = $1 FOR UPDATE", &[&request_id])
+        .map_err(StoreError::from)?;
+    let request = get_change_request(&mut tx, request_id)?.ok_or(ReviewError::NotFound)?;
+    if request.status != ChangeRequestStatus::Pending {
+        return Err(ReviewError::AlreadyReviewed(request.status));
+    }
+
+    let status = if approve { ChangeRequestStatus::Approved } else { ChangeRequestStatus::Rejected };
+    if approve {
+        tx.execute(
+            "UPDATE clients SET
+                address = COALESCE($1, address),
+                phone_number = COALESCE($2, phone_number),
+                email = COALESCE($3, email),
+                updated_at = CURRENT_TIMESTAMP
+             WHERE client_id = $4",
+            &[&request.address, &request.phone_number, &request.email, &request.client_id],
+        ).map_err(StoreError::from)?;
+    }
+    tx.execute(
+        "UPDATE contact_change_requests SET status = $1, review_note = $2, reviewed_at = CURRENT_TIMESTAMP
+         WHERE request_id = $3",
+        &[&status.as_str(), &review_note, &request_id],
+    ).map_err(StoreError::from)?;
+
+    let reviewed = get_change_request(&mut tx, request_id)?.ok_or(ReviewError::NotFound)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(reviewed)
+}
@@ -0,0 +1,262 @@
+use postgres::{GenericClient, Row};
+
+use super::{get_tax_return, line_items, parse_column, return_chain};
+use crate::db::models::{
+    IncomeProposal, IncomeProvenance, LineItem, LineItemSection, ProposalStatus, ProposalTarget, ProposedItem,
+    ReturnKind,
+};
+use crate::db::proposals::AcceptError;
+use crate::db::repository::{StoreError, StoreResult};
+use crate::money::Money;
+
+const PROPOSAL_COLUMNS: &str = "proposal_id, document_id, client_id, tax_year, status,
+    tax_return_id, created_at, reviewed_at";
+
+fn map_proposal(row: &Row) -> StoreResult<IncomeProposal> {
+    Ok(IncomeProposal {
+        proposal_id: Some(row.get(0)),
+        document_id: row.get(1),
+        client_id: row.get(2),
+        tax_year: row.get(3),
+        status: parse_column(row.get(4), ProposalStatus::parse, "proposal status")?,
+        tax_return_id: row.get(5),
+        items: Vec::new(),
+        created_at: row.get(6),
+        reviewed_at: row.get(7),
+    })
+}
+
+fn load_items(client: &mut impl GenericClient, proposal: &mut IncomeProposal) -> StoreResult<()> {
+    proposal.items = client.query(
+        "SELECT target, category, box_label, amount FROM income_proposal_items
+         WHERE proposal_id = $1 ORDER BY item_id",
+        &[&proposal.proposal_id],
+    )?.iter().map(|row| {
+        Ok(ProposedItem {
+            target: parse_column(row.get(0), ProposalTarget::parse, "proposal target")?,
+            category: row.get(1),
+            box_label: row.get(2),
+            amount: Money::from_cents(row.get(3)),
+        })
+    }).collect::<StoreResult<_>>()?;
+    Ok(())
+}
+
+/// Replaces any pending proposal for the document with freshly extracted
+/// items, as `proposals::replace_pending_proposal` does. The document's row
+/// is locked so two refreshes of it cannot both leave a pending proposal.
+pub(super) fn replace_pending_proposal(
+    client: &mut impl GenericClient,
+    document_id: i64,
+    client_id: i64,
+    tax_year: Option<i32>,
+    items: &[ProposedItem],
+) -> StoreResult<Option<i64>> {
+    let mut tx = client.transaction()?;
+    tx.execute("SELECT 1 FROM documents WHERE document_id = $1 FOR UPDATE", &[&document_id])?;
+    tx.execute(
+        "DELETE FROM income_proposal_items WHERE proposal_id IN (
+            SELECT proposal_id FROM income_proposals WHERE document_id = $1 AND status = 'pending'
+        )",
+        &[&document_id],
+    )?;
+    tx.execute(
+        "DELETE FROM income_proposals WHERE document_id = $1 AND status = 'pending'",
+        &[&document_id],
+    )?;
+
+    if items.is_empty() {
+        tx.commit()?;
+        return Ok(None);
+    }
+
+    let row = tx.query_one(
+        "INSERT INTO income_proposals (document_id, client_id, tax_year, status) VALUES ($1, $2, $3, $4)
+         RETURNING proposal_id",
+        &[&document_id, &client_id, &tax_year, &ProposalStatus::Pending.as_str()],
+    )?;
+    let proposal_id: i64 = row.get(0);
+
+    for item in items {
+        tx.execute(
+            "INSERT INTO income_proposal_items (proposal_id, target, category, box_label, amount)
+             VALUES ($1, $2, $3, $4, $5)",
+            &[&proposal_id, &item.target.as_str(), &item.category, &item.box_label, &item.amount.cents()],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(Some(proposal_id))
+}
+
+fn get_proposal(client: &mut impl GenericClient, proposal_id: i64) -> StoreResult<Option<IncomeProposal>> {
+    let proposal = client.query_opt(
+        &format!("SELECT {} FROM income_proposals WHERE proposal_id = $1", PROPOSAL_COLUMNS),
+        &[&proposal_id],
+    )?.as_ref().map(map_proposal).transpose()?;
+
+    match proposal {
+        Some(mut proposal) => {
+            load_items(client, &mut proposal)?;
+            Ok(Some(proposal))
+        }
+        None => Ok(None),
+    }
+}
+
+pub(super) fn latest_document_proposal(client: &mut impl GenericClient, document_id: i64) -> StoreResult<Option<IncomeProposal>> {
+    let proposal_id: Option<i64> = client.query_opt(
+        "SELECT proposal_id FROM income_proposals WHERE document_id = $1
+         ORDER BY proposal_id DESC LIMIT 1",
+        &[&document_id],
+    )?.map(|row| row.get(0));
+
+    match proposal_id {
+        Some(id) => get_proposal(client, id),
+        None => Ok(None),
+    }
+}
+
+pub(super) fn list_proposals(
+    client: &mut impl GenericClient,
+    client_id: Option<i64>,
+    status: Option<ProposalStatus>,
+) -> StoreResult<Vec<IncomeProposal>> {
+    let mut proposals = client.query(
+        &format!(
+            "SELECT {} FROM income_proposals
+             WHERE ($1::BIGINT IS NULL OR client_id = $1) AND ($2::TEXT IS NULL OR status = $2)
+             ORDER BY proposal_id",
+            PROPOSAL_COLUMNS
+        ),
+        &[&client_id, &status.map(|s| s.as_str())],
+    )?.iter().map(map_proposal).collect::<StoreResult<Vec<_>>>()?;
+
+    for proposal in &mut proposals {
+        load_items(client, proposal)?;
+    }
+    Ok(proposals)
+}
+
+/// Adds a pending proposal's amounts to a return as `proposals::accept_proposal`
+/// does. The proposal's row is locked first, so it is accepted only once,
+/// then the return's, so its `taxes_paid` is not read while another writer
+/// changes it.
+pub(super) fn accept_proposal(
+    client: &mut impl GenericClient,
+    proposal_id: i64,
+    tax_return_id: Option<i64>,
+) -> Result<IncomeProposal, AcceptError> {
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    tx.execute("SELECT 1 FROM income_proposals WHERE proposal_id = $1 FOR UPDATE", &[&proposal_id])
+        .map_err(StoreError::from)?;
+    let proposal = get_proposal(&mut tx, proposal_id)?.ok_or(AcceptError::NotFound)?;
+    if proposal.status != ProposalStatus::Pending {
+        return Err(AcceptError::NotPending(proposal.status));
+    }
+
+    let tax_return_id = match tax_return_id {
+        Some(id) => {
+            let chosen = get_tax_return(&mut tx, id)?.filter(|r| r.client_id == proposal.client_id);
+            match chosen {
+                Some(r) if !r.jurisdiction.is_federal() => return Err(AcceptError::NotFederal),
+                Some(r) if r.return_kind == ReturnKind::Superseded => {
+                    let current = return_chain(&mut tx, id)?.last().and_then(|r| r.tax_return_id);
+                    return Err(AcceptError::Superseded(current));
+                }
+                chosen => chosen.and(Some(id)),
+            }
+        }
+        None => tx.query_opt(
+            "SELECT tax_return_id FROM tax_returns
+             WHERE client_id = $1 AND tax_year = $2 AND return_kind <> 'superseded' AND jurisdiction = 'federal'",
+            &[&proposal.client_id, &proposal.tax_year],
+        ).map_err(StoreError::from)?.map(|row| row.get(0)),
+    };
+    let tax_return_id: i64 = tax_return_id.ok_or(AcceptError::NoMatchingReturn)?;
+
+    let row = tx.query_one(
+        "SELECT taxes_paid, return_kind FROM tax_returns WHERE tax_return_id = $1 FOR UPDATE",
+        &[&tax_return_id],
+    ).map_err(StoreError::from)?;
+    // Amended between the lookup and the lock
+    if row.get::<_, &str>(1) == ReturnKind::Superseded.as_str() {
+        let current = return_chain(&mut tx, tax_return_id)?.last().and_then(|r| r.tax_return_id);
+        return Err(AcceptError::Superseded(current));
+    }
+    let mut taxes_paid = Money::from_cents(row.get(0));
+
+    for item in &proposal.items {
+        match item.target {
+            ProposalTarget::IncomeSource => {
+                line_items::insert_item(&mut tx, &LineItem {
+                    line_item_id: None,
+                    tax_return_id,
+                    section: LineItemSection::Income,
+                    category: item.category.clone(),
+                    payer: None,
+                    amount: item.amount,
+                    document_id: Some(proposal.document_id),
+                    created_at: None,
+                    updated_at: None,
+                })?;
+            }
+            ProposalTarget::TaxesPaid => taxes_paid += item.amount,
+        }
+        tx.execute(
+            "INSERT INTO income_provenance (
+                tax_return_id, target, category, amount, document_id, proposal_id
+            ) VALUES ($1, $2, $3, $4, $5, $6)",
+            &[
+                &tax_return_id,
+                &item.target.as_str(),
+                &item.category,
+                &item.amount.cents(),
+                &proposal.document_id,
+                &proposal_id,
+            ],
+        ).map_err(StoreError::from)?;
+    }
+
+    line_items::sync_return_maps(&mut tx, tax_return_id)?;
+    tx.execute(
+        "UPDATE tax_returns SET taxes_paid = $1, updated_at = CURRENT_TIMESTAMP WHERE tax_return_id = $2",
+        &[&taxes_paid.cents(), &tax_return_id],
+    ).map_err(StoreError::from)?;
+    tx.execute(
+        "UPDATE income_proposals SET status = $1, tax_return_id = $2, reviewed_at = CURRENT_TIMESTAMP
+         WHERE proposal_id = $3",
+        &[&ProposalStatus::Accepted.as_str(), &tax_return_id, &proposal_id],
+    ).map_err(StoreError::from)?;
+
+    let accepted = get_proposal(&mut tx, proposal_id)?.ok_or(AcceptError::NotFound)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(accepted)
+}
+
+pub(super) fn reject_proposal(client: &mut impl GenericClient, proposal_id: i64) -> StoreResult<bool> {
+    let updated = client.execute(
+        "UPDATE income_proposals SET status = $1, reviewed_at = CURRENT_TIMESTAMP
+         WHERE proposal_id = $2 AND status = 'pending'",
+        &[&ProposalStatus::Rejected.as_str(), &proposal_id],
+    )?;
+    Ok(updated > 0)
+}
+
+pub(super) fn list_return_provenance(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<Vec<IncomeProvenance>> {
+    client.query(
+        "SELECT tax_return_id, target, category, amount, document_id, proposal_id, created_at
+         FROM income_provenance WHERE tax_return_id = $1 ORDER BY provenance_id",
+        &[&tax_return_id],
+    )?.iter().map(|row| {
+        Ok(IncomeProvenance {
+            tax_return_id: row.get(0),
+            target: parse_column(row.get(1), ProposalTarget::parse, "proposal target")?,
+            category: row.get(2),
+            amount: Money::from_cents(row.get(3)),
+            document_id: row.get(4),
+            proposal_id: row.get(5),
+            created_at: row.get(6),
+        })
+    }).collect()
+}
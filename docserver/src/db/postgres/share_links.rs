@@ -0,0 +1,137 @@
+use chrono::{DateTime, Utc};
+use postgres::{GenericClient, Row};
+
+use super::parse_column;
+use crate::db::models::{AccessOutcome, LinkStatus, ShareLink, ShareLinkAccess};
+use crate::db::repository::StoreResult;
+
+const SHARE_LINK_COLUMNS: &str = "link_id, client_id, file_path, recipient, nonce, password_hash,
+    max_downloads, download_count, expires_at, revoked_at, created_at";
+
+fn map_link(row: &Row, now: DateTime<Utc>) -> ShareLink {
+    let password_hash: Option<String> = row.get(5);
+    let max_downloads = row.get::<_, Option<i32>>(6).map(i64::from);
+    let download_count = i64::from(row.get::<_, i32>(7));
+    let expires_at: DateTime<Utc> = row.get(8);
+    let revoked_at: Option<DateTime<Utc>> = row.get(9);
+    ShareLink {
+        link_id: Some(row.get(0)),
+        client_id: row.get(1),
+        file_path: row.get(2),
+        recipient: row.get(3),
+        nonce: row.get(4),
+        password_protected: password_hash.is_some(),
+        password_hash,
+        max_downloads,
+        download_count,
+        expires_at,
+        revoked_at,
+        created_at: row.get(10),
+        status: LinkStatus::derive(revoked_at, expires_at, download_count, max_downloads, now),
+    }
+}
+
+pub(super) fn create_link(client: &mut impl GenericClient, link: &ShareLink) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO share_links (
+            client_id, file_path, recipient, nonce, password_hash, max_downloads, expires_at
+        ) VALUES ($1, $2, $3, $4, $5, $6::BIGINT, $7)
+        RETURNING link_id",
+        &[
+            &link.client_id,
+            &link.file_path,
+            &link.recipient,
+            &link.nonce,
+            &link.password_hash,
+            &link.max_downloads,
+            &link.expires_at,
+        ],
+    )?;
+    Ok(row.get(0))
+}
+
+pub(super) fn get_link(client: &mut impl GenericClient, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<ShareLink>> {
+    let row = client.query_opt(
+        &format!("SELECT {} FROM share_links WHERE link_id = $1", SHARE_LINK_COLUMNS),
+        &[&link_id],
+    )?;
+    Ok(row.map(|row| map_link(&row, now)))
+}
+
+pub(super) fn list_links(
+    client: &mut impl GenericClient,
+    client_id: Option<i64>,
+    status: Option<LinkStatus>,
+    now: DateTime<Utc>,
+) -> StoreResult<Vec<ShareLink>> {
+    let rows = client.query(
+        &format!(
+            "SELECT {} FROM share_links WHERE ($1::BIGINT IS NULL OR client_id = $1) ORDER BY link_id DESC",
+            SHARE_LINK_COLUMNS
+        ),
+        &[&client_id],
+    )?;
+    Ok(rows.iter()
+        .map(|row| map_link(row, now))
+        .filter(|link| status.is_none_or(|s| link.status == s))
+        .collect())
+}
+
+pub(super) fn revoke_link(client: &mut impl GenericClient, link_id: i64) -> StoreResult<bool> {
+    let updated = client.execute(
+        "UPDATE share_links SET revoked_at = CURRENT_TIMESTAMP WHERE link_id = $1 AND revoked_at IS NULL",
+        &[&link_id],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Counts a download against an open link in a single statement, as
+/// `share_links::claim_download` does.
+pub(super) fn claim_download(client: &mut impl GenericClient, link_id: i64, nonce: &str, now: DateTime<Utc>) -> StoreResult<bool> {
+    let claimed = client.execute(
+        "UPDATE share_links SET download_count = download_count + 1
+         WHERE link_id = $1 AND nonce = $2 AND revoked_at IS NULL
+           AND (max_downloads IS NULL OR download_count < max_downloads) AND expires_at > $3",
+        &[&link_id, &nonce, &now],
+    )?;
+    Ok(claimed > 0)
+}
+
+pub(super) fn release_download(client: &mut impl GenericClient, link_id: i64) -> StoreResult<()> {
+    client.execute(
+        "UPDATE share_links SET download_count = download_count - 1 WHERE link_id = $1 AND download_count > 0",
+        &[&link_id],
+    )?;
+    Ok(())
+}
+
+pub(super) fn record_access(
+    client: &mut impl GenericClient,
+    link_id: i64,
+    outcome: AccessOutcome,
+    remote_addr: Option<&str>,
+    user_agent: Option<&str>,
+) -> StoreResult<()> {
+    client.execute(
+        "INSERT INTO share_link_accesses (link_id, outcome, remote_addr, user_agent) VALUES ($1, $2, $3, $4)",
+        &[&link_id, &outcome.as_str(), &remote_addr, &user_agent],
+    )?;
+    Ok(())
+}
+
+pub(super) fn list_accesses(client: &mut impl GenericClient, link_id: i64) -> StoreResult<Vec<ShareLinkAccess>> {
+    client.query(
+        "SELECT access_id, link_id, outcome, remote_addr, user_agent, accessed_at
+         FROM share_link_accesses WHERE link_id = $1 ORDER BY access_id",
+        &[&link_id],
+    )?.iter().map(|row| {
+        Ok(ShareLinkAccess {
+            access_id: Some(row.get(0)),
+            link_id: row.get(1),
+            outcome: parse_column(row.get(2), AccessOutcome::parse, "access outcome")?,
+            remote_addr: row.get(3),
+            user_agent: row.get(4),
+            accessed_at: row.get(5),
+        })
+    }).collect()
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use postgres::{GenericClient, Row};
+
+use crate::db::models::{LinkStatus, UploadLink};
+use crate::db::repository::StoreResult;
+
+const UPLOAD_LINK_COLUMNS: &str = "link_id, client_id, checklist_item_id, nonce, note, max_uploads,
+    upload_count, expires_at, revoked_at, last_used_at, created_at";
+
+fn map_link(row: &Row, now: DateTime<Utc>) -> UploadLink {
+    let max_uploads = i64::from(row.get::<_, i32>(5));
+    let upload_count = i64::from(row.get::<_, i32>(6));
+    let expires_at: DateTime<Utc> = row.get(7);
+    let revoked_at: Option<DateTime<Utc>> = row.get(8);
+    UploadLink {
+        link_id: Some(row.get(0)),
+        client_id: row.get(1),
+        checklist_item_id: row.get(2),
+        nonce: row.get(3),
+        note: row.get(4),
+        max_uploads,
+        upload_count,
+        expires_at,
+        revoked_at,
+        last_used_at: row.get(9),
+        created_at: row.get(10),
+        status: LinkStatus::derive(revoked_at, expires_at, upload_count, Some(max_uploads), now),
+    }
+}
+
+pub(super) fn create_link(
+    client: &mut impl GenericClient,
+    client_id: i64,
+    checklist_item_id: Option<i64>,
+    note: Option<&str>,
+    max_uploads: i64,
+    nonce: &str,
+    expires_at: DateTime<Utc>,
+) -> StoreResult<i64> {
+    let row = client.query_one(
+        "INSERT INTO upload_links (client_id, checklist_item_id, nonce, note, max_uploads, expires_at)
+         VALUES ($1, $2, $3, $4, $5::BIGINT, $6)
+         RETURNING link_id",
+        &[&client_id, &checklist_item_id, &nonce, &note, &max_uploads, &expires_at],
+    )?;
+    Ok(row.get(0))
+}
+
+pub(super) fn get_link(client: &mut impl GenericClient, link_id: i64, now: DateTime<Utc>) -> StoreResult<Option<UploadLink>> {
+    let row = client.query_opt(
+        &format!("SELECT {} FROM upload_links WHERE link_id = $1", UPLOAD_LINK_COLUMNS),
+        &[&link_id],
+    )?;
+    Ok(row.map(|row| map_link(&row, now)))
+}
+
+pub(super) fn list_links(
+    client: &mut impl GenericClient,
+    client_id: Option<i64>,
+    status: Option<LinkStatus>,
+    now: DateTime<Utc>,
+) -> StoreResult<Vec<UploadLink>> {
+    let rows = client.query(
+        &format!(
+            "SELECT {} FROM upload_links WHERE ($1::BIGINT IS NULL OR client_id = $1) ORDER BY link_id DESC",
+            UPLOAD_LINK_COLUMNS
+        ),
+        &[&client_id],
+    )?;
+    Ok(rows.iter()
+        .map(|row| map_link(row, now))
+        .filter(|link| status.is_none_or(|s| link.status == s))
+        .collect())
+}
+
+pub(super) fn revoke_link(client: &mut impl GenericClient, link_id: i64) -> StoreResult<bool> {
+    let updated = client.execute(
+        "UPDATE upload_links SET revoked_at = CURRENT_TIMESTAMP WHERE link_id = $1 AND revoked_at IS NULL",
+        &[&link_id],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Takes one use of an open link whose nonce matches, in a single statement
+/// as `upload_links::claim_upload` does.
+pub(super) fn claim_upload(
+    client: &mut impl GenericClient,
+    link_id: i64,
+    nonce: &str,
+    now: DateTime<Utc>,
+) -> StoreResult<Option<UploadLink>> {
+    let row = client.query_opt(
+        &format!(
+            "UPDATE upload_links
+             SET upload_count = upload_count + 1, last_used_at = CURRENT_TIMESTAMP
+             WHERE link_id = $1 AND nonce = $2 AND revoked_at IS NULL
+               AND upload_count < max_uploads AND expires_at > $3
+             RETURNING {}",
+            UPLOAD_LINK_COLUMNS
+        ),
+        &[&link_id, &nonce, &now],
+    )?;
+    Ok(row.map(|row| map_link(&row, now)))
+}
+
+pub(super) fn release_upload(client: &mut impl GenericClient, link_id: i64) -> StoreResult<()> {
+    client.execute(
+        "UPDATE upload_links SET upload_count = upload_count - 1 WHERE link_id = $1 AND upload_count > 0",
+        &[&link_id],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use postgres::{GenericClient, Row};
+
+use super::{get_tax_return, map_tax_return, parse_column};
+use crate::db::models::{ReturnStatus, StatusChange, TaxReturn};
+use crate::db::repository::{StoreError, StoreResult};
+use crate::db::returns::TAX_RETURN_COLUMNS;
+use crate::db::workflow::TransitionError;
+
+fn map_status_change(row: &Row) -> StoreResult<StatusChange> {
+    let from_status: Option<&str> = row.get(2);
+    Ok(StatusChange {
+        history_id: Some(row.get(0)),
+        tax_return_id: row.get(1),
+        from_status: from_status.map(|s| parse_column(s, ReturnStatus::parse, "return status")).transpose()?,
+        to_status: parse_column(row.get(3), ReturnStatus::parse, "return status")?,
+        assignee: row.get(4),
+        note: row.get(5),
+        changed_at: row.get(6),
+    })
+}
+
+/// Moves a return to its next stage as `workflow::transition_return` does.
+/// The return's row is locked, so two moves from the same stage cannot
+/// both pass the check.
+pub(super) fn transition_return(
+    client: &mut impl GenericClient,
+    tax_return_id: i64,
+    to: ReturnStatus,
+    assignee: Option<Option<&str>>,
+    note: Option<&str>,
+) -> Result<TaxReturn, TransitionError> {
+    let mut tx = client.transaction().map_err(StoreError::from)?;
+    tx.execute("SELECT 1 FROM tax_returns WHERE tax_return_id = $1 FOR UPDATE", &[&tax_return_id])
+        .map_err(StoreError::from)?;
+    let tax_return = get_tax_return(&mut tx, tax_return_id)?.ok_or(TransitionError::NotFound)?;
+    if !tax_return.status.can_transition_to(to) {
+        return Err(TransitionError::NotAllowed { from: tax_return.status, to });
+    }
+    let assignee = match assignee {
+        Some(assignee) => assignee,
+        None => tax_return.assignee.as_deref(),
+    };
+
+    tx.execute(
+        "UPDATE tax_returns SET status = $1, assignee = $2, updated_at = CURRENT_TIMESTAMP
+         WHERE tax_return_id = $3",
+        &[&to.as_str(), &assignee, &tax_return_id],
+    ).map_err(StoreError::from)?;
+    tx.execute(
+        "INSERT INTO return_status_history (tax_return_id, from_status, to_status, assignee, note)
+         VALUES ($1, $2, $3, $4, $5)",
+        &[&tax_return_id, &tax_return.status.as_str(), &to.as_str(), &assignee, &note],
+    ).map_err(StoreError::from)?;
+
+    let updated = get_tax_return(&mut tx, tax_return_id)?.ok_or(TransitionError::NotFound)?;
+    tx.commit().map_err(StoreError::from)?;
+    Ok(updated)
+}
+
+pub(super) fn status_history(client: &mut impl GenericClient, tax_return_id: i64) -> StoreResult<Vec<StatusChange>> {
+    client.query(
+        "SELECT history_id, tax_return_id, from_status, to_status, assignee, note, changed_at
+         FROM return_status_history
+         WHERE tax_return_id = $1
+         ORDER BY history_id",
+        &[&tax_return_id],
+    )?.iter().map(map_status_change).collect()
+}
+
+pub(super) fn list_by_status(
+    client: &mut impl GenericClient,
+    status: Option<ReturnStatus>,
+    assignee: Option<&str>,
+) -> StoreResult<Vec<TaxReturn>> {
+    client.query(
+        &format!(
+            "SELECT {} FROM tax_returns
+             WHERE return_kind <> 'superseded'
+               AND ($1::TEXT IS NULL OR status = $1)
+               AND ($2::TEXT IS NULL OR assignee = $2)
+             ORDER BY updated_at, tax_return_id",
+            TAX_RETURN_COLUMNS
+        ),
+        &[&status.map(|s| s.as_str()), &assignee],
+    )?.iter().map(map_tax_return).collect()
+}
@@ -0,0 +1,407 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::fmt;
+
+use crate::money::Money;
+use super::connection::write_transaction;
+use super::line_items::{self, LineItemError};
+use super::models::{
+    IncomeProposal, IncomeProvenance, LineItem, LineItemSection, ProposalStatus, ProposalTarget, ProposedItem,
+    ReturnKind,
+};
+use super::repository::StoreError;
+use super::returns;
+
+#[derive(Debug)]
+pub enum AcceptError {
+    NotFound,
+    NotPending(ProposalStatus),
+    NoMatchingReturn,
+    // W-2 and 1099 income goes on the federal return
+    NotFederal,
+    // With the effective return of the chain, if any
+    Superseded(Option<i64>),
+    UnknownCategory(String),
+    LineItem(LineItemError),
+    Database(StoreError),
+}
+
+impl fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcceptError::NotFound => write!(f, "Proposal not found"),
+            AcceptError::NotPending(status) => write!(f, "Proposal has already been {}", status.as_str()),
+            AcceptError::NoMatchingReturn => write!(f, "No tax return found for this client and tax year"),
+            AcceptError::NotFederal => write!(f, "Proposals can only be accepted into a federal return"),
+            AcceptError::Superseded(Some(id)) => {
+                write!(f, "Tax return has been superseded; accept into return {} instead", id)
+            }
+            AcceptError::Superseded(None) => write!(f, "Tax return has been superseded"),
+            AcceptError::UnknownCategory(category) => write!(f, "Unknown income category: {}", category),
+            AcceptError::LineItem(e) => write!(f, "{}", e),
+            AcceptError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for AcceptError {
+    fn from(e: rusqlite::Error) -> Self {
+        AcceptError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for AcceptError {
+    fn from(e: StoreError) -> Self {
+        AcceptError::Database(e)
+    }
+}
+
+impl From<LineItemError> for AcceptError {
+    fn from(e: LineItemError) -> Self {
+        match e {
+            LineItemError::UnknownCategory { category, .. } => AcceptError::UnknownCategory(category),
+            LineItemError::Database(e) => AcceptError::Database(e),
+            e => AcceptError::LineItem(e),
+        }
+    }
+}
+
+fn map_proposal(row: &rusqlite::Row) -> Result<IncomeProposal> {
+    Ok(IncomeProposal {
+        proposal_id: Some(row.get(0)?),
+        document_id: row.get(1)?,
+        client_id: row.get(2)?,
+        tax_year: row.get(3)?,
+        status: row.get(4)?,
+        tax_return_id: row.get(5)?,
+        items: Vec::new(),
+        created_at: row.get(6)?,
+        reviewed_at: row.get(7)?,
+    })
+}
+
+const PROPOSAL_COLUMNS: &str = "proposal_id, document_id, client_id, tax_year, status,
+    tax_return_id, created_at, reviewed_at";
+
+fn load_items(conn: &Connection, proposal: &mut IncomeProposal) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT target, category, box_label, amount FROM income_proposal_items
+         WHERE proposal_id = ? ORDER BY item_id"
+    )?;
+    proposal.items = stmt.query_map([proposal.proposal_id], |row| {
+        Ok(ProposedItem {
+            target: row.get(0)?,
+            category: row.get(1)?,
+            box_label: row.get(2)?,
+            amount: row.get(3)?,
+        })
+    })?.collect::<Result<Vec<_>>>()?;
+    Ok(())
+}
+
+/// Replaces any pending proposal for the document with freshly extracted
+/// items. Reviewed proposals are kept for their provenance.
+pub fn replace_pending_proposal(
+    conn: &Connection,
+    document_id: i64,
+    client_id: i64,
+    tax_year: Option<i32>,
+    items: &[ProposedItem],
+) -> Result<Option<i64>> {
+    let tx = write_transaction(conn)?;
+    tx.execute(
+        "DELETE FROM income_proposal_items WHERE proposal_id IN (
+            SELECT proposal_id FROM income_proposals WHERE document_id = ? AND status = 'pending'
+        )",
+        [document_id],
+    )?;
+    tx.execute(
+        "DELETE FROM income_proposals WHERE document_id = ? AND status = 'pending'",
+        [document_id],
+    )?;
+
+    if items.is_empty() {
+        tx.commit()?;
+        return Ok(None);
+    }
+
+    tx.execute(
+        "INSERT INTO income_proposals (document_id, client_id, tax_year, status) VALUES (?, ?, ?, ?)",
+        params![document_id, client_id, tax_year, ProposalStatus::Pending],
+    )?;
+    let proposal_id = tx.last_insert_rowid();
+
+    for item in items {
+        tx.execute(
+            "INSERT INTO income_proposal_items (proposal_id, target, category, box_label, amount)
+             VALUES (?, ?, ?, ?, ?)",
+            params![proposal_id, item.target, item.category, item.box_label, item.amount],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(Some(proposal_id))
+}
+
+pub fn get_proposal(conn: &Connection, proposal_id: i64) -> Result<Option<IncomeProposal>> {
+    let proposal = conn.query_row(
+        &format!("SELECT {} FROM income_proposals WHERE proposal_id = ?", PROPOSAL_COLUMNS),
+        [proposal_id],
+        map_proposal,
+    ).optional()?;
+
+    match proposal {
+        Some(mut proposal) => {
+            load_items(conn, &mut proposal)?;
+            Ok(Some(proposal))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn latest_document_proposal(conn: &Connection, document_id: i64) -> Result<Option<IncomeProposal>> {
+    let proposal_id: Option<i64> = conn.query_row(
+        "SELECT proposal_id FROM income_proposals WHERE document_id = ?
+         ORDER BY proposal_id DESC LIMIT 1",
+        [document_id],
+        |row| row.get(0),
+    ).optional()?;
+
+    match proposal_id {
+        Some(id) => get_proposal(conn, id),
+        None => Ok(None),
+    }
+}
+
+pub fn list_proposals(
+    conn: &Connection,
+    client_id: Option<i64>,
+    status: Option<ProposalStatus>,
+) -> Result<Vec<IncomeProposal>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM income_proposals
+         WHERE (?1 IS NULL OR client_id = ?1) AND (?2 IS NULL OR status = ?2)
+         ORDER BY proposal_id",
+        PROPOSAL_COLUMNS
+    ))?;
+    let mut proposals = stmt.query_map(params![client_id, status], map_proposal)?
+        .collect::<Result<Vec<_>>>()?;
+
+    for proposal in &mut proposals {
+        load_items(conn, proposal)?;
+    }
+    Ok(proposals)
+}
+
+/// Adds a pending proposal's amounts to a return as income line items and
+/// `taxes_paid`, recording provenance for each. Without an explicit
+/// `tax_return_id` the client's effective federal return for the document's
+/// tax year is used; an explicit one must be such a return as well.
+pub fn accept_proposal(
+    conn: &Connection,
+    proposal_id: i64,
+    tax_return_id: Option<i64>,
+) -> std::result::Result<IncomeProposal, AcceptError> {
+    let tx = write_transaction(conn)?;
+    let proposal = get_proposal(&tx, proposal_id)?.ok_or(AcceptError::NotFound)?;
+    if proposal.status != ProposalStatus::Pending {
+        return Err(AcceptError::NotPending(proposal.status));
+    }
+
+    let tax_return_id = match tax_return_id {
+        Some(id) => {
+            let chosen = returns::get_tax_return(&tx, id)?.filter(|r| r.client_id == proposal.client_id);
+            match chosen {
+                Some(r) if !r.jurisdiction.is_federal() => return Err(AcceptError::NotFederal),
+                Some(r) if r.return_kind == ReturnKind::Superseded => {
+                    let current = returns::return_chain(&tx, id)?.last().and_then(|r| r.tax_return_id);
+                    return Err(AcceptError::Superseded(current));
+                }
+                chosen => chosen.and(Some(id)),
+            }
+        }
+        None => tx.query_row(
+            "SELECT tax_return_id FROM tax_returns
+             WHERE client_id = ? AND tax_year = ? AND return_kind != 'superseded' AND jurisdiction = 'federal'",
+            params![proposal.client_id, proposal.tax_year],
+            |row| row.get(0),
+        ).optional()?,
+    };
+    let tax_return_id: i64 = tax_return_id.ok_or(AcceptError::NoMatchingReturn)?;
+
+    let mut taxes_paid: Money = tx.query_row(
+        "SELECT taxes_paid FROM tax_returns WHERE tax_return_id = ?",
+        [tax_return_id],
+        |row| row.get(0),
+    )?;
+
+    for item in &proposal.items {
+        match item.target {
+            ProposalTarget::IncomeSource => {
+                line_items::insert_item(&tx, &LineItem {
+                    line_item_id: None,
+                    tax_return_id,
+                    section: LineItemSection::Income,
+                    category: item.category.clone(),
+                    payer: None,
+                    amount: item.amount,
+                    document_id: Some(proposal.document_id),
+                    created_at: None,
+                    updated_at: None,
+                })?;
+            }
+            ProposalTarget::TaxesPaid => taxes_paid += item.amount,
+        }
+        tx.execute(
+            "INSERT INTO income_provenance (
+                tax_return_id, target, category, amount, document_id, proposal_id
+            ) VALUES (?, ?, ?, ?, ?, ?)",
+            params![tax_return_id, item.target, item.category, item.amount, proposal.document_id, proposal_id],
+        )?;
+    }
+
+    line_items::sync_return_maps(&tx, tax_return_id)?;
+    tx.execute(
+        "UPDATE tax_returns SET taxes_paid = ?, updated_at = CURRENT_TIMESTAMP WHERE tax_return_id = ?",
+        params![taxes_paid, tax_return_id],
+    )?;
+    tx.execute(
+        "UPDATE income_proposals SET status = ?, tax_return_id = ?, reviewed_at = CURRENT_TIMESTAMP
+         WHERE proposal_id = ?",
+        params![ProposalStatus::Accepted, tax_return_id, proposal_id],
+    )?;
+
+    let accepted = get_proposal(&tx, proposal_id)?.ok_or(AcceptError::NotFound)?;
+    tx.commit()?;
+    Ok(accepted)
+}
+
+/// Marks a pending proposal rejected. Returns false if it was not pending.
+pub fn reject_proposal(conn: &Connection, proposal_id: i64) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE income_proposals SET status = ?, reviewed_at = CURRENT_TIMESTAMP
+         WHERE proposal_id = ? AND status = 'pending'",
+        params![ProposalStatus::Rejected, proposal_id],
+    )?;
+    Ok(updated > 0)
+}
+
+pub fn list_return_provenance(conn: &Connection, tax_return_id: i64) -> Result<Vec<IncomeProvenance>> {
+    let mut stmt = conn.prepare(
+        "SELECT tax_return_id, target, category, amount, document_id, proposal_id, created_at
+         FROM income_provenance WHERE tax_return_id = ? ORDER BY provenance_id"
+    )?;
+    let provenance = stmt.query_map([tax_return_id], |row| {
+        Ok(IncomeProvenance {
+            tax_return_id: row.get(0)?,
+            target: row.get(1)?,
+            category: row.get(2)?,
+            amount: row.get(3)?,
+            document_id: row.get(4)?,
+            proposal_id: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    })?.collect::<Result<Vec<_>>>()?;
+    Ok(provenance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::db::migrations;
+
+    fn w2_items() -> Vec<ProposedItem> {
+        vec![
+            ProposedItem {
+                target: ProposalTarget::IncomeSource,
+                category: "wages".to_string(),
+                box_label: "wages, tips, other compensation".to_string(),
+                amount: Money::from_dollars(12000),
+            },
+            ProposedItem {
+                target: ProposalTarget::TaxesPaid,
+                category: "federal_withholding".to_string(),
+                box_label: "federal income tax withheld".to_string(),
+                amount: Money::from_dollars(1500),
+            },
+        ]
+    }
+
+    fn setup() -> (Connection, i64) {
+        // The baseline schema seeds client 1 with a 2023 return
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO documents (client_id, file_name, document_type, tax_year) VALUES (1, 'w2.pdf', 'W-2', 2023)",
+            [],
+        ).unwrap();
+        let document_id = conn.last_insert_rowid();
+        (conn, document_id)
+    }
+
+    #[test]
+    fn test_accept_proposal_updates_return() {
+        let (conn, document_id) = setup();
+        let proposal_id = replace_pending_proposal(&conn, document_id, 1, Some(2023), &w2_items())
+            .unwrap()
+            .unwrap();
+
+        let accepted = accept_proposal(&conn, proposal_id, None).unwrap();
+        assert_eq!(accepted.status, ProposalStatus::Accepted);
+        let tax_return_id = accepted.tax_return_id.unwrap();
+
+        let (income_json, taxes_paid): (String, Money) = conn.query_row(
+            "SELECT income_sources, taxes_paid FROM tax_returns WHERE tax_return_id = ?",
+            [tax_return_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        let income: HashMap<String, Money> = serde_json::from_str(&income_json).unwrap();
+        assert_eq!(income.get("wages"), Some(&Money::from_dollars(62000)));
+        assert_eq!(taxes_paid, Money::from_dollars(11500));
+        let w2_lines = line_items::search_items(&conn, None, Some("wages"), Some(1), Some(2023)).unwrap();
+        assert_eq!(w2_lines.iter().filter(|i| i.document_id == Some(document_id)).count(), 1);
+
+        let provenance = list_return_provenance(&conn, tax_return_id).unwrap();
+        assert_eq!(provenance.len(), 2);
+        assert!(provenance.iter().all(|p| p.document_id == document_id));
+
+        // A proposal can only be accepted once
+        assert!(matches!(accept_proposal(&conn, proposal_id, None), Err(AcceptError::NotPending(_))));
+    }
+
+    #[test]
+    fn test_accept_without_matching_return() {
+        let (conn, document_id) = setup();
+        let proposal_id = replace_pending_proposal(&conn, document_id, 1, Some(2019), &w2_items())
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(accept_proposal(&conn, proposal_id, None), Err(AcceptError::NoMatchingReturn)));
+    }
+
+    #[test]
+    fn test_accept_into_chosen_return_must_be_effective_and_federal() {
+        let (conn, document_id) = setup();
+        let proposal_id = replace_pending_proposal(&conn, document_id, 1, Some(2023), &w2_items())
+            .unwrap()
+            .unwrap();
+        let federal_id: i64 = conn.query_row(
+            "SELECT tax_return_id FROM tax_returns WHERE client_id = 1 AND tax_year = 2023",
+            [],
+            |row| row.get(0),
+        ).unwrap();
+
+        let state_return: returns::StateReturn = serde_json::from_str(r#"{"state": "CA", "filing_status": null}"#).unwrap();
+        let state_id = returns::add_state_return(&conn, federal_id, state_return).unwrap().tax_return_id.unwrap();
+        assert!(matches!(accept_proposal(&conn, proposal_id, Some(state_id)), Err(AcceptError::NotFederal)));
+
+        let amendment = returns::Amendment { amendment_reason: "Corrected W-2".to_string(), ..Default::default() };
+        let amended_id = returns::amend_return(&conn, federal_id, amendment).unwrap().tax_return_id.unwrap();
+        assert!(matches!(
+            accept_proposal(&conn, proposal_id, Some(federal_id)),
+            Err(AcceptError::Superseded(Some(id))) if id == amended_id
+        ));
+
+        assert_eq!(accept_proposal(&conn, proposal_id, Some(amended_id)).unwrap().tax_return_id, Some(amended_id));
+    }
+}
@@ -0,0 +1,512 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::RwLockReadGuard;
+
+use super::encryption::FieldKey;
+use super::models::{Client, Document, DocumentType, Jurisdiction, TaxReturn};
+use super::returns::{Amendment, AmendError, StateReturn, StateReturnError};
+use super::{clients, documents, returns, DbConnection, OfficeRepository, PooledConnection, PostgresStore};
+
+/// A failure in whichever database backs the repository.
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    Postgres(postgres::Error),
+    // No connection could be checked out of the pool
+    Pool(r2d2::Error),
+    // Encryption for database connections could not be set up
+    Tls(native_tls::Error),
+    // A stored value the models cannot represent
+    Data(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(e) => write!(f, "{}", e),
+            StoreError::Postgres(e) => write!(f, "{}", e),
+            StoreError::Pool(e) => write!(f, "No database connection available: {}", e),
+            StoreError::Tls(e) => write!(f, "Cannot set up TLS for the database: {}", e),
+            StoreError::Data(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl StoreError {
+    /// Whether a UNIQUE constraint refused the write, as when two requests
+    /// add the same thing at once.
+    pub fn is_unique_violation(&self) -> bool {
+        match self {
+            StoreError::Sqlite(e) => super::is_unique_violation(e),
+            StoreError::Postgres(e) => e.code() == Some(&postgres::error::SqlState::UNIQUE_VIOLATION),
+            StoreError::Pool(_) | StoreError::Tls(_) | StoreError::Data(_) => false,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+impl From<postgres::Error> for StoreError {
+    fn from(e: postgres::Error) -> Self {
+        StoreError::Postgres(e)
+    }
+}
+
+impl From<r2d2::Error> for StoreError {
+    fn from(e: r2d2::Error) -> Self {
+        StoreError::Pool(e)
+    }
+}
+
+impl From<native_tls::Error> for StoreError {
+    fn from(e: native_tls::Error) -> Self {
+        StoreError::Tls(e)
+    }
+}
+
+pub type StoreResult<T> = std::result::Result<T, StoreError>;
+
+/// A client's returns for one year: the federal return and the state
+/// returns filed with it.
+#[derive(Debug, Serialize)]
+pub struct ReturnYear {
+    pub tax_year: i32,
+    pub federal: Option<TaxReturn>,
+    pub states: Vec<TaxReturn>,
+}
+
+/// Client, return and document storage. Handlers and tools go through this
+/// rather than the table modules, so the storage behind it can be swapped
+/// or faked in tests. SQLite databases and pooled connections implement it,
+/// and [`PostgresStore`] does for a shared server.
+pub trait Repository {
+    fn list_clients(&self) -> StoreResult<Vec<Client>>;
+    fn get_client(&self, client_id: i64) -> StoreResult<Option<Client>>;
+    fn create_client(&self, client: &Client) -> StoreResult<i64>;
+
+    fn get_tax_return(&self, tax_return_id: i64) -> StoreResult<Option<TaxReturn>>;
+    /// Returns for one client or all, newest year first. Without
+    /// `include_superseded` only the effective return of each amendment
+    /// chain is listed.
+    fn list_tax_returns(
+        &self,
+        client_id: Option<i64>,
+        jurisdiction: Option<&Jurisdiction>,
+        include_superseded: bool,
+    ) -> StoreResult<Vec<TaxReturn>>;
+    fn create_tax_return(&self, tax_return: &TaxReturn) -> StoreResult<i64>;
+    fn return_chain(&self, tax_return_id: i64) -> StoreResult<Vec<TaxReturn>>;
+    fn amend_return(&self, tax_return_id: i64, amendment: Amendment) -> std::result::Result<TaxReturn, AmendError>;
+    fn state_returns(&self, federal_return_id: i64) -> StoreResult<Vec<TaxReturn>>;
+    fn add_state_return(
+        &self,
+        federal_return_id: i64,
+        state_return: StateReturn,
+    ) -> std::result::Result<TaxReturn, StateReturnError>;
+
+    fn get_document(&self, document_id: i64) -> StoreResult<Option<Document>>;
+    fn list_client_documents(&self, client_id: i64) -> StoreResult<Vec<Document>>;
+    /// Inserts a document, or updates the one with the same client and file name.
+    fn save_document(&self, document: &Document) -> StoreResult<i64>;
+    fn override_document_type(&self, document_id: i64, document_type: DocumentType, tax_year: Option<i32>) -> StoreResult<bool>;
+
+    /// Every return for the client, superseded ones included, newest year first.
+    fn get_client_tax_returns(&self, client_id: i64) -> StoreResult<Vec<TaxReturn>> {
+        self.list_tax_returns(Some(client_id), None, true)
+    }
+
+    /// A client's effective returns grouped by year, newest first.
+    fn return_years(&self, client_id: i64) -> StoreResult<Vec<ReturnYear>> {
+        let mut years: BTreeMap<i32, ReturnYear> = BTreeMap::new();
+        for tax_return in self.list_tax_returns(Some(client_id), None, false)? {
+            let year = years.entry(tax_return.tax_year).or_insert_with(|| ReturnYear {
+                tax_year: tax_return.tax_year,
+                federal: None,
+                states: Vec::new(),
+            });
+            if tax_return.jurisdiction.is_federal() {
+                year.federal = Some(tax_return);
+            } else {
+                year.states.push(tax_return);
+            }
+        }
+        Ok(years.into_values().rev().collect())
+    }
+}
+
+/// A SQLite connection and the key for its encrypted columns. Anything
+/// that has both is a `Repository` and an
+/// [`OfficeRepository`](super::OfficeRepository); the connection itself
+/// stays inside the crate.
+pub(crate) trait SqliteStore {
+    fn conn(&self) -> &Connection;
+    fn field_key(&self) -> &FieldKey;
+}
+
+impl<S: SqliteStore> Repository for S {
+    fn list_clients(&self) -> StoreResult<Vec<Client>> {
+        Ok(clients::list_clients(self.conn(), self.field_key())?)
+    }
+
+    fn get_client(&self, client_id: i64) -> StoreResult<Option<Client>> {
+        Ok(clients::get_client(self.conn(), self.field_key(), client_id)?)
+    }
+
+    fn create_client(&self, client: &Client) -> StoreResult<i64> {
+        Ok(clients::insert_client(self.conn(), self.field_key(), client)?)
+    }
+
+    fn get_tax_return(&self, tax_return_id: i64) -> StoreResult<Option<TaxReturn>> {
+        Ok(returns::get_tax_return(self.conn(), tax_return_id)?)
+    }
+
+    fn list_tax_returns(
+        &self,
+        client_id: Option<i64>,
+        jurisdiction: Option<&Jurisdiction>,
+        include_superseded: bool,
+    ) -> StoreResult<Vec<TaxReturn>> {
+        Ok(returns::list_tax_returns(self.conn(), client_id, jurisdiction, include_superseded)?)
+    }
+
+    fn create_tax_return(&self, tax_return: &TaxReturn) -> StoreResult<i64> {
+        returns::insert_tax_return(self.conn(), tax_return)
+    }
+
+    fn return_chain(&self, tax_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+        Ok(returns::return_chain(self.conn(), tax_return_id)?)
+    }
+
+    fn amend_return(&self, tax_return_id: i64, amendment: Amendment) -> std::result::Result<TaxReturn, AmendError> {
+        returns::amend_return(self.conn(), tax_return_id, amendment)
+    }
+
+    fn state_returns(&self, federal_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+        Ok(returns::state_returns(self.conn(), federal_return_id)?)
+    }
+
+    fn add_state_return(
+        &self,
+        federal_return_id: i64,
+        state_return: StateReturn,
+    ) -> std::result::Result<TaxReturn, StateReturnError> {
+        returns::add_state_return(self.conn(), federal_return_id, state_return)
+    }
+
+    fn get_document(&self, document_id: i64) -> StoreResult<Option<Document>> {
+        Ok(documents::get_document(self.conn(), document_id)?)
+    }
+
+    fn list_client_documents(&self, client_id: i64) -> StoreResult<Vec<Document>> {
+        Ok(documents::list_client_documents(self.conn(), client_id)?)
+    }
+
+    fn save_document(&self, document: &Document) -> StoreResult<i64> {
+        Ok(documents::upsert_document(self.conn(), document)?)
+    }
+
+    fn override_document_type(&self, document_id: i64, document_type: DocumentType, tax_year: Option<i32>) -> StoreResult<bool> {
+        Ok(documents::override_document_type(self.conn(), document_id, document_type, tax_year)?)
+    }
+}
+
+/// The repository for one request: a pooled SQLite connection or the
+/// PostgreSQL store, whichever the server was configured with, held with
+/// the database lock so a root relocation waits for the request to finish.
+/// Handlers use it as an [`OfficeRepository`].
+pub struct PooledRepository<'a> {
+    // Declared first so the connection is returned before the lock is released
+    store: Store<'a>,
+    _db: RwLockReadGuard<'a, Option<DbConnection>>,
+}
+
+enum Store<'a> {
+    Sqlite(SqliteConnection<'a>),
+    Postgres(&'a PostgresStore),
+}
+
+struct SqliteConnection<'a> {
+    conn: PooledConnection,
+    field_key: &'a FieldKey,
+}
+
+impl SqliteStore for SqliteConnection<'_> {
+    fn conn(&self) -> &Connection {
+        &self.conn
+    }
+
+    fn field_key(&self) -> &FieldKey {
+        self.field_key
+    }
+}
+
+impl<'a> PooledRepository<'a> {
+    /// Uses `postgres` when given, otherwise a connection from the SQLite
+    /// pool behind `db`.
+    pub fn new(
+        db: RwLockReadGuard<'a, Option<DbConnection>>,
+        postgres: Option<&'a PostgresStore>,
+        field_key: &'a FieldKey,
+    ) -> Self {
+        let store = match postgres {
+            Some(postgres) => Store::Postgres(postgres),
+            None => {
+                let conn = db.as_ref()
+                    .expect("Database should be initialized")
+                    .conn()
+                    .expect("Failed to check out a database connection");
+                Store::Sqlite(SqliteConnection { conn, field_key })
+            }
+        };
+        PooledRepository { store, _db: db }
+    }
+}
+
+impl<'a> Deref for PooledRepository<'a> {
+    type Target = dyn OfficeRepository + 'a;
+
+    fn deref(&self) -> &Self::Target {
+        match &self.store {
+            Store::Sqlite(sqlite) => sqlite,
+            Store::Postgres(postgres) => *postgres,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use crate::db::ReturnKind;
+
+    /// Keeps clients, returns and documents in memory, with ids handed out
+    /// in order as the tables would.
+    #[derive(Default)]
+    struct FakeRepository {
+        clients: RefCell<Vec<Client>>,
+        returns: RefCell<Vec<TaxReturn>>,
+        documents: RefCell<Vec<Document>>,
+    }
+
+    impl FakeRepository {
+        fn insert_return(&self, mut tax_return: TaxReturn) -> TaxReturn {
+            let mut returns = self.returns.borrow_mut();
+            tax_return.tax_return_id = Some(returns.len() as i64 + 1);
+            returns.push(tax_return.clone());
+            tax_return
+        }
+
+        fn set_kind(&self, tax_return_id: i64, return_kind: ReturnKind) {
+            let mut returns = self.returns.borrow_mut();
+            if let Some(stored) = returns.iter_mut().find(|r| r.tax_return_id == Some(tax_return_id)) {
+                stored.return_kind = return_kind;
+            }
+        }
+    }
+
+    fn tax_return(client_id: i64, tax_year: i32, jurisdiction: Jurisdiction) -> TaxReturn {
+        TaxReturn {
+            tax_return_id: None,
+            client_id,
+            tax_year,
+            filing_status: "Single".to_string(),
+            income_sources: HashMap::new(),
+            deductions: HashMap::new(),
+            credits: HashMap::new(),
+            taxes_paid: Default::default(),
+            tax_liability: Default::default(),
+            refund_or_amount_due: Default::default(),
+            return_kind: Default::default(),
+            parent_return_id: None,
+            amendment_reason: None,
+            status: Default::default(),
+            assignee: None,
+            jurisdiction,
+            federal_return_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    impl Repository for FakeRepository {
+        fn list_clients(&self) -> StoreResult<Vec<Client>> {
+            Ok(self.clients.borrow().clone())
+        }
+
+        fn get_client(&self, client_id: i64) -> StoreResult<Option<Client>> {
+            Ok(self.clients.borrow().iter().find(|c| c.client_id == Some(client_id)).cloned())
+        }
+
+        fn create_client(&self, client: &Client) -> StoreResult<i64> {
+            let mut clients = self.clients.borrow_mut();
+            let client_id = clients.len() as i64 + 1;
+            clients.push(Client { client_id: Some(client_id), ..client.clone() });
+            Ok(client_id)
+        }
+
+        fn get_tax_return(&self, tax_return_id: i64) -> StoreResult<Option<TaxReturn>> {
+            Ok(self.returns.borrow().iter().find(|r| r.tax_return_id == Some(tax_return_id)).cloned())
+        }
+
+        fn list_tax_returns(
+            &self,
+            client_id: Option<i64>,
+            jurisdiction: Option<&Jurisdiction>,
+            include_superseded: bool,
+        ) -> StoreResult<Vec<TaxReturn>> {
+            let mut returns: Vec<TaxReturn> = self.returns.borrow().iter()
+                .filter(|r| client_id.is_none_or(|id| r.client_id == id))
+                .filter(|r| jurisdiction.is_none_or(|j| &r.jurisdiction == j))
+                .filter(|r| include_superseded || r.return_kind != ReturnKind::Superseded)
+                .cloned()
+                .collect();
+            returns.sort_by_key(|r| std::cmp::Reverse(r.tax_year));
+            Ok(returns)
+        }
+
+        fn create_tax_return(&self, tax_return: &TaxReturn) -> StoreResult<i64> {
+            Ok(self.insert_return(tax_return.clone()).tax_return_id.unwrap_or_default())
+        }
+
+        fn return_chain(&self, tax_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+            let Some(mut current) = self.get_tax_return(tax_return_id)? else {
+                return Ok(Vec::new());
+            };
+            while let Some(parent) = current.parent_return_id.and_then(|id| self.get_tax_return(id).ok().flatten()) {
+                current = parent;
+            }
+            let mut chain = vec![current];
+            while let Some(child) = self.returns.borrow().iter()
+                .find(|r| r.parent_return_id == chain.last().and_then(|c| c.tax_return_id))
+                .cloned()
+            {
+                chain.push(child);
+            }
+            Ok(chain)
+        }
+
+        fn amend_return(&self, tax_return_id: i64, amendment: Amendment) -> std::result::Result<TaxReturn, AmendError> {
+            if amendment.amendment_reason.trim().is_empty() {
+                return Err(AmendError::MissingReason);
+            }
+            let parent = self.get_tax_return(tax_return_id)?.ok_or(AmendError::NotFound)?;
+            if parent.return_kind == ReturnKind::Superseded {
+                let current = self.return_chain(tax_return_id)?.last().and_then(|r| r.tax_return_id);
+                return Err(AmendError::Superseded(current));
+            }
+            let (amended, _) = returns::amended_return(parent, tax_return_id, amendment);
+            self.set_kind(tax_return_id, ReturnKind::Superseded);
+            Ok(self.insert_return(amended))
+        }
+
+        fn state_returns(&self, federal_return_id: i64) -> StoreResult<Vec<TaxReturn>> {
+            let returns = self.returns.borrow();
+            Ok(returns.iter().filter(|r| r.federal_return_id == Some(federal_return_id)).cloned().collect())
+        }
+
+        fn add_state_return(
+            &self,
+            federal_return_id: i64,
+            state_return: StateReturn,
+        ) -> std::result::Result<TaxReturn, StateReturnError> {
+            if state_return.state.is_federal() {
+                return Err(StateReturnError::NotAState);
+            }
+            let federal = self.get_tax_return(federal_return_id)?.ok_or(StateReturnError::FederalNotFound)?;
+            if !federal.jurisdiction.is_federal() {
+                return Err(StateReturnError::NotFederal);
+            }
+            if federal.return_kind == ReturnKind::Superseded {
+                let current = self.return_chain(federal_return_id)?.last().and_then(|r| r.tax_return_id);
+                return Err(StateReturnError::Superseded(current));
+            }
+            let existing = self.list_tax_returns(Some(federal.client_id), Some(&state_return.state), false)?;
+            if let Some(existing) = existing.iter().find(|r| r.tax_year == federal.tax_year) {
+                return Err(StateReturnError::Exists(existing.tax_return_id.unwrap_or_default()));
+            }
+            Ok(self.insert_return(returns::state_return_for(federal, federal_return_id, state_return)))
+        }
+
+        fn get_document(&self, document_id: i64) -> StoreResult<Option<Document>> {
+            Ok(self.documents.borrow().iter().find(|d| d.document_id == Some(document_id)).cloned())
+        }
+
+        fn list_client_documents(&self, client_id: i64) -> StoreResult<Vec<Document>> {
+            Ok(self.documents.borrow().iter().filter(|d| d.client_id == client_id).cloned().collect())
+        }
+
+        fn save_document(&self, document: &Document) -> StoreResult<i64> {
+            let mut documents = self.documents.borrow_mut();
+            if let Some(stored) = documents.iter_mut()
+                .find(|d| d.client_id == document.client_id && d.file_name == document.file_name)
+            {
+                *stored = Document { document_id: stored.document_id, ..document.clone() };
+                return Ok(stored.document_id.unwrap_or_default());
+            }
+            let document_id = documents.len() as i64 + 1;
+            documents.push(Document { document_id: Some(document_id), ..document.clone() });
+            Ok(document_id)
+        }
+
+        fn override_document_type(&self, document_id: i64, document_type: DocumentType, tax_year: Option<i32>) -> StoreResult<bool> {
+            let mut documents = self.documents.borrow_mut();
+            let Some(stored) = documents.iter_mut().find(|d| d.document_id == Some(document_id)) else {
+                return Ok(false);
+            };
+            stored.document_type = Some(document_type);
+            stored.tax_year = tax_year;
+            stored.type_overridden = true;
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_return_years_groups_state_returns_under_federal() {
+        let virginia = Jurisdiction::State("VA".to_string());
+        let repository = FakeRepository::default();
+        repository.create_tax_return(&tax_return(1, 2022, Jurisdiction::Federal)).unwrap();
+        let federal = repository.create_tax_return(&tax_return(1, 2023, Jurisdiction::Federal)).unwrap();
+        let state = StateReturn {
+            state: virginia.clone(),
+            filing_status: None,
+            income_sources: HashMap::new(),
+            deductions: HashMap::new(),
+            credits: HashMap::new(),
+            taxes_paid: Default::default(),
+            tax_liability: Default::default(),
+            refund_or_amount_due: Default::default(),
+        };
+        repository.add_state_return(federal, state).unwrap();
+
+        let years = repository.return_years(1).unwrap();
+        assert_eq!(years.iter().map(|y| y.tax_year).collect::<Vec<_>>(), vec![2023, 2022]);
+        assert_eq!(years[0].federal.as_ref().and_then(|r| r.tax_return_id), Some(federal));
+        assert_eq!(years[0].states.len(), 1);
+        assert_eq!(years[0].states[0].jurisdiction, virginia);
+        assert!(years[1].states.is_empty());
+        assert!(repository.return_years(2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_return_years_show_only_the_effective_amendment() {
+        let repository = FakeRepository::default();
+        let original = repository.create_tax_return(&tax_return(1, 2023, Jurisdiction::Federal)).unwrap();
+        let amendment = Amendment { amendment_reason: "Corrected W-2".to_string(), ..Default::default() };
+        let amended = repository.amend_return(original, amendment).unwrap();
+
+        let years = repository.return_years(1).unwrap();
+        assert_eq!(years.len(), 1);
+        assert_eq!(years[0].federal.as_ref().and_then(|r| r.tax_return_id), amended.tax_return_id);
+        assert_eq!(repository.get_client_tax_returns(1).unwrap().len(), 2);
+    }
+}
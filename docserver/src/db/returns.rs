@@ -0,0 +1,604 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::money::Money;
+use super::connection::write_transaction;
+use super::{estimates, line_items};
+use super::line_items::LineItemError;
+use super::repository::StoreError;
+use super::models::{Jurisdiction, LineItemSection, ReturnKind, ReturnStatus, TaxReturn};
+
+/// The figures an amendment changes; anything left out is carried over from
+/// the return being amended.
+#[derive(Debug, Default, Deserialize)]
+pub struct Amendment {
+    pub amendment_reason: String,
+    pub filing_status: Option<String>,
+    pub income_sources: Option<HashMap<String, Money>>,
+    pub deductions: Option<HashMap<String, Money>>,
+    pub credits: Option<HashMap<String, Money>>,
+    pub taxes_paid: Option<Money>,
+    pub tax_liability: Option<Money>,
+    pub refund_or_amount_due: Option<Money>,
+}
+
+#[derive(Debug)]
+pub enum AmendError {
+    NotFound,
+    Superseded(Option<i64>),
+    MissingReason,
+    UnknownCategory { section: LineItemSection, category: String },
+    Database(StoreError),
+}
+
+impl fmt::Display for AmendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmendError::NotFound => write!(f, "Tax return not found"),
+            AmendError::Superseded(Some(id)) => {
+                write!(f, "Tax return has been superseded; amend the current return {} instead", id)
+            }
+            AmendError::Superseded(None) => write!(f, "Tax return has been superseded"),
+            AmendError::MissingReason => write!(f, "An amendment reason is required"),
+            AmendError::UnknownCategory { section, category } => {
+                write!(f, "Unknown {} category: {}", section.as_str(), category)
+            }
+            AmendError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for AmendError {
+    fn from(e: rusqlite::Error) -> Self {
+        AmendError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for AmendError {
+    fn from(e: StoreError) -> Self {
+        AmendError::Database(e)
+    }
+}
+
+impl From<LineItemError> for AmendError {
+    fn from(e: LineItemError) -> Self {
+        match e {
+            LineItemError::UnknownCategory { section, category } => AmendError::UnknownCategory { section, category },
+            e => AmendError::Database(e.into()),
+        }
+    }
+}
+
+/// A state return filed alongside a federal one. Its client, year and filing
+/// status come from the federal return unless given; its figures are its own.
+#[derive(Debug, Deserialize)]
+pub struct StateReturn {
+    pub state: Jurisdiction,
+    pub filing_status: Option<String>,
+    #[serde(default)]
+    pub income_sources: HashMap<String, Money>,
+    #[serde(default)]
+    pub deductions: HashMap<String, Money>,
+    #[serde(default)]
+    pub credits: HashMap<String, Money>,
+    #[serde(default)]
+    pub taxes_paid: Money,
+    #[serde(default)]
+    pub tax_liability: Money,
+    #[serde(default)]
+    pub refund_or_amount_due: Money,
+}
+
+#[derive(Debug)]
+pub enum StateReturnError {
+    FederalNotFound,
+    // The return named as the federal one is itself a state return
+    NotFederal,
+    NotAState,
+    Superseded(Option<i64>),
+    // The client already has a return for this state and year
+    Exists(i64),
+    UnknownCategory { section: LineItemSection, category: String },
+    Database(StoreError),
+}
+
+impl fmt::Display for StateReturnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateReturnError::FederalNotFound => write!(f, "Federal return not found"),
+            StateReturnError::NotFederal => write!(f, "State returns attach to a federal return"),
+            StateReturnError::NotAState => write!(f, "state must be a state code, not \"federal\""),
+            StateReturnError::Superseded(Some(id)) => {
+                write!(f, "Federal return has been superseded; attach to the current return {} instead", id)
+            }
+            StateReturnError::Superseded(None) => write!(f, "Federal return has been superseded"),
+            StateReturnError::Exists(id) => write!(f, "The client already has this state's return for the year ({})", id),
+            StateReturnError::UnknownCategory { section, category } => {
+                write!(f, "Unknown {} category: {}", section.as_str(), category)
+            }
+            StateReturnError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StateReturnError {
+    fn from(e: rusqlite::Error) -> Self {
+        StateReturnError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for StateReturnError {
+    fn from(e: StoreError) -> Self {
+        StateReturnError::Database(e)
+    }
+}
+
+impl From<LineItemError> for StateReturnError {
+    fn from(e: LineItemError) -> Self {
+        match e {
+            LineItemError::UnknownCategory { section, category } => StateReturnError::UnknownCategory { section, category },
+            e => StateReturnError::Database(e.into()),
+        }
+    }
+}
+
+pub(crate) const TAX_RETURN_COLUMNS: &str = "tax_return_id, client_id, tax_year, filing_status, income_sources,
+    deductions, credits, taxes_paid, tax_liability, refund_or_amount_due,
+    return_kind, parent_return_id, amendment_reason, status, assignee, created_at, updated_at,
+    jurisdiction, federal_return_id";
+
+pub(crate) fn map_tax_return(row: &rusqlite::Row) -> Result<TaxReturn> {
+    Ok(TaxReturn {
+        tax_return_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        tax_year: row.get(2)?,
+        filing_status: row.get(3)?,
+        income_sources: serde_json::from_str(&row.get::<_, String>(4)?).unwrap_or_default(),
+        deductions: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or_default(),
+        credits: serde_json::from_str(&row.get::<_, String>(6)?).unwrap_or_default(),
+        taxes_paid: row.get(7)?,
+        tax_liability: row.get(8)?,
+        refund_or_amount_due: row.get(9)?,
+        return_kind: row.get(10)?,
+        parent_return_id: row.get(11)?,
+        amendment_reason: row.get(12)?,
+        status: row.get(13)?,
+        assignee: row.get(14)?,
+        created_at: row.get(15)?,
+        updated_at: row.get(16)?,
+        jurisdiction: row.get(17)?,
+        federal_return_id: row.get(18)?,
+    })
+}
+
+fn to_json(map: &HashMap<String, Money>) -> Result<String> {
+    serde_json::to_string(map)
+        .map_err(|_| rusqlite::Error::InvalidParameterName("Serialization error".to_string()))
+}
+
+/// Inserts the return row and its first status history entry, but no line items.
+fn insert_return_row(conn: &Connection, tax_return: &TaxReturn) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO tax_returns (
+            client_id, tax_year, filing_status, income_sources,
+            deductions, credits, taxes_paid, tax_liability,
+            refund_or_amount_due, return_kind, parent_return_id, amendment_reason,
+            status, assignee, jurisdiction, federal_return_id
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            tax_return.client_id,
+            tax_return.tax_year,
+            tax_return.filing_status,
+            to_json(&tax_return.income_sources)?,
+            to_json(&tax_return.deductions)?,
+            to_json(&tax_return.credits)?,
+            tax_return.taxes_paid,
+            tax_return.tax_liability,
+            tax_return.refund_or_amount_due,
+            tax_return.return_kind,
+            tax_return.parent_return_id,
+            tax_return.amendment_reason,
+            tax_return.status,
+            tax_return.assignee,
+            tax_return.jurisdiction,
+            tax_return.federal_return_id,
+        ],
+    )?;
+    let tax_return_id = conn.last_insert_rowid();
+
+    conn.execute(
+        "INSERT INTO return_status_history (tax_return_id, to_status, assignee) VALUES (?, ?, ?)",
+        params![tax_return_id, tax_return.status, tax_return.assignee],
+    )?;
+
+    Ok(tax_return_id)
+}
+
+/// Inserts a return, recording one line item per entry in its maps. Estimated
+/// payments already made for the year are added to its `taxes_paid`.
+pub fn insert_tax_return(conn: &Connection, tax_return: &TaxReturn) -> std::result::Result<i64, StoreError> {
+    let tx = write_transaction(conn)?;
+    let tax_return_id = insert_return_row(&tx, tax_return)?;
+    for section in LineItemSection::ALL {
+        line_items::insert_items_from_map(&tx, tax_return_id, section, line_items::section_map(tax_return, section))?;
+    }
+    estimates::apply_to_return(&tx, tax_return.client_id, tax_return.tax_year)?;
+    tx.commit()?;
+    Ok(tax_return_id)
+}
+
+pub fn get_tax_return(conn: &Connection, tax_return_id: i64) -> Result<Option<TaxReturn>> {
+    conn.query_row(
+        &format!("SELECT {} FROM tax_returns WHERE tax_return_id = ?", TAX_RETURN_COLUMNS),
+        [tax_return_id],
+        map_tax_return,
+    ).optional()
+}
+
+/// Lists returns, newest year first, federal before state. Superseded
+/// returns are left out unless `include_superseded` is set, leaving one
+/// effective return per year and jurisdiction.
+pub fn list_tax_returns(
+    conn: &Connection,
+    client_id: Option<i64>,
+    jurisdiction: Option<&Jurisdiction>,
+    include_superseded: bool,
+) -> Result<Vec<TaxReturn>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tax_returns
+         WHERE (?1 IS NULL OR client_id = ?1)
+           AND (?2 IS NULL OR jurisdiction = ?2)
+           AND (?3 OR return_kind != 'superseded')
+         ORDER BY client_id, tax_year DESC, jurisdiction != 'federal', jurisdiction, tax_return_id",
+        TAX_RETURN_COLUMNS
+    ))?;
+
+    let tax_returns = stmt.query_map(params![client_id, jurisdiction, include_superseded], map_tax_return)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(tax_returns)
+}
+
+/// `(tax_return_id, client_id)` of every return whose client does not exist.
+pub fn returns_without_client(conn: &Connection) -> Result<Vec<(i64, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT tax_return_id, client_id FROM tax_returns
+         WHERE client_id NOT IN (SELECT client_id FROM clients)
+         ORDER BY tax_return_id",
+    )?;
+    let returns = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(returns)
+}
+
+/// The effective state returns attached to a federal return.
+pub fn state_returns(conn: &Connection, federal_return_id: i64) -> Result<Vec<TaxReturn>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tax_returns
+         WHERE federal_return_id = ? AND return_kind != 'superseded'
+         ORDER BY jurisdiction, tax_return_id",
+        TAX_RETURN_COLUMNS
+    ))?;
+    let tax_returns = stmt.query_map([federal_return_id], map_tax_return)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(tax_returns)
+}
+
+/// The return to insert for `state_return`, filed against `federal`.
+pub(crate) fn state_return_for(federal: TaxReturn, federal_return_id: i64, state_return: StateReturn) -> TaxReturn {
+    TaxReturn {
+        tax_return_id: None,
+        client_id: federal.client_id,
+        tax_year: federal.tax_year,
+        filing_status: state_return.filing_status.unwrap_or(federal.filing_status),
+        income_sources: state_return.income_sources,
+        deductions: state_return.deductions,
+        credits: state_return.credits,
+        taxes_paid: state_return.taxes_paid,
+        tax_liability: state_return.tax_liability,
+        refund_or_amount_due: state_return.refund_or_amount_due,
+        return_kind: ReturnKind::Original,
+        parent_return_id: None,
+        amendment_reason: None,
+        status: ReturnStatus::default(),
+        assignee: federal.assignee,
+        jurisdiction: state_return.state,
+        federal_return_id: Some(federal_return_id),
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+/// Files a state return against the client's effective federal return for
+/// the year.
+pub fn add_state_return(
+    conn: &Connection,
+    federal_return_id: i64,
+    state_return: StateReturn,
+) -> std::result::Result<TaxReturn, StateReturnError> {
+    if state_return.state.is_federal() {
+        return Err(StateReturnError::NotAState);
+    }
+
+    let tx = write_transaction(conn)?;
+    let federal = get_tax_return(&tx, federal_return_id)?.ok_or(StateReturnError::FederalNotFound)?;
+    if !federal.jurisdiction.is_federal() {
+        return Err(StateReturnError::NotFederal);
+    }
+    if federal.return_kind == ReturnKind::Superseded {
+        let current = return_chain(&tx, federal_return_id)?.last().and_then(|r| r.tax_return_id);
+        return Err(StateReturnError::Superseded(current));
+    }
+    let existing = list_tax_returns(&tx, Some(federal.client_id), Some(&state_return.state), false)?;
+    if let Some(existing) = existing.iter().find(|r| r.tax_year == federal.tax_year) {
+        return Err(StateReturnError::Exists(existing.tax_return_id.unwrap_or_default()));
+    }
+
+    let tax_return = state_return_for(federal, federal_return_id, state_return);
+    let tax_return_id = insert_return_row(&tx, &tax_return)?;
+    for section in LineItemSection::ALL {
+        line_items::insert_items_from_map(&tx, tax_return_id, section, line_items::section_map(&tax_return, section))?;
+    }
+
+    let created = get_tax_return(&tx, tax_return_id)?.ok_or(StateReturnError::FederalNotFound)?;
+    tx.commit()?;
+    Ok(created)
+}
+
+/// The amendment chain a return belongs to, from the original to the
+/// current effective return.
+pub fn return_chain(conn: &Connection, tax_return_id: i64) -> Result<Vec<TaxReturn>> {
+    let mut current = match get_tax_return(conn, tax_return_id)? {
+        Some(tax_return) => tax_return,
+        None => return Ok(Vec::new()),
+    };
+
+    // Walk up to the original...
+    let mut chain = Vec::new();
+    while let Some(parent_id) = current.parent_return_id {
+        match get_tax_return(conn, parent_id)? {
+            Some(parent) => {
+                chain.push(current);
+                current = parent;
+            }
+            None => break,
+        }
+    }
+    chain.push(current);
+    chain.reverse();
+
+    // ...then down through any later amendments
+    loop {
+        let last_id = chain.last().and_then(|r| r.tax_return_id);
+        let child = conn.query_row(
+            &format!("SELECT {} FROM tax_returns WHERE parent_return_id = ? ORDER BY tax_return_id LIMIT 1", TAX_RETURN_COLUMNS),
+            [last_id],
+            map_tax_return,
+        ).optional()?;
+        match child {
+            Some(child) => chain.push(child),
+            None => break,
+        }
+    }
+
+    Ok(chain)
+}
+
+/// The return an amendment files in place of `parent`, and for each
+/// section whether the amendment replaces its lines or copies the parent's.
+pub(crate) fn amended_return(
+    parent: TaxReturn,
+    tax_return_id: i64,
+    amendment: Amendment,
+) -> (TaxReturn, [(LineItemSection, bool); 3]) {
+    // Sections given in full replace the parent's lines; the rest are copied
+    let overridden = [
+        (LineItemSection::Income, amendment.income_sources.is_some()),
+        (LineItemSection::Deduction, amendment.deductions.is_some()),
+        (LineItemSection::Credit, amendment.credits.is_some()),
+    ];
+    let amended = TaxReturn {
+        tax_return_id: None,
+        client_id: parent.client_id,
+        tax_year: parent.tax_year,
+        filing_status: amendment.filing_status.unwrap_or(parent.filing_status),
+        income_sources: amendment.income_sources.unwrap_or(parent.income_sources),
+        deductions: amendment.deductions.unwrap_or(parent.deductions),
+        credits: amendment.credits.unwrap_or(parent.credits),
+        taxes_paid: amendment.taxes_paid.unwrap_or(parent.taxes_paid),
+        tax_liability: amendment.tax_liability.unwrap_or(parent.tax_liability),
+        refund_or_amount_due: amendment.refund_or_amount_due.unwrap_or(parent.refund_or_amount_due),
+        return_kind: ReturnKind::Amended,
+        parent_return_id: Some(tax_return_id),
+        amendment_reason: Some(amendment.amendment_reason.trim().to_string()),
+        // The figures are already known, so an amendment starts in preparation
+        status: ReturnStatus::InPreparation,
+        assignee: parent.assignee,
+        jurisdiction: parent.jurisdiction,
+        federal_return_id: parent.federal_return_id,
+        created_at: None,
+        updated_at: None,
+    };
+    (amended, overridden)
+}
+
+/// Files an amendment to a return. The amendment starts from the amended
+/// return's line items, provenance and household, and the amended return
+/// becomes superseded.
+pub fn amend_return(
+    conn: &Connection,
+    tax_return_id: i64,
+    amendment: Amendment,
+) -> std::result::Result<TaxReturn, AmendError> {
+    if amendment.amendment_reason.trim().is_empty() {
+        return Err(AmendError::MissingReason);
+    }
+
+    let tx = write_transaction(conn)?;
+    let parent = get_tax_return(&tx, tax_return_id)?.ok_or(AmendError::NotFound)?;
+    if parent.return_kind == ReturnKind::Superseded {
+        let current = return_chain(&tx, tax_return_id)?.last().and_then(|r| r.tax_return_id);
+        return Err(AmendError::Superseded(current));
+    }
+
+    let (amended, overridden) = amended_return(parent, tax_return_id, amendment);
+    // Only one return per client, year and jurisdiction is effective at a time
+    tx.execute(
+        "UPDATE tax_returns SET return_kind = ?, updated_at = CURRENT_TIMESTAMP WHERE tax_return_id = ?",
+        params![ReturnKind::Superseded, tax_return_id],
+    )?;
+    let amended_id = insert_return_row(&tx, &amended)?;
+    for (section, replaced) in overridden {
+        if replaced {
+            line_items::insert_items_from_map(&tx, amended_id, section, line_items::section_map(&amended, section))?;
+        } else {
+            line_items::copy_items(&tx, tax_return_id, amended_id, section)?;
+        }
+    }
+    line_items::sync_return_maps(&tx, amended_id)?;
+
+    tx.execute(
+        "INSERT INTO income_provenance (
+            tax_return_id, target, category, amount, document_id, proposal_id, created_at
+        )
+        SELECT ?, target, category, amount, document_id, proposal_id, created_at
+        FROM income_provenance WHERE tax_return_id = ? ORDER BY provenance_id",
+        params![amended_id, tax_return_id],
+    )?;
+    // The amendment covers the same household as the return it replaces
+    tx.execute(
+        "INSERT INTO return_household_members (tax_return_id, member_id)
+         SELECT ?, member_id FROM return_household_members WHERE tax_return_id = ?",
+        params![amended_id, tax_return_id],
+    )?;
+    // taxes_paid was carried over with the parent's estimated payments in it
+    tx.execute(
+        "UPDATE tax_returns SET estimated_payments_applied = (
+            SELECT estimated_payments_applied FROM tax_returns WHERE tax_return_id = ?
+        ) WHERE tax_return_id = ?",
+        params![tax_return_id, amended_id],
+    )?;
+    // State returns follow the federal return to its amendment
+    tx.execute(
+        "UPDATE tax_returns SET federal_return_id = ? WHERE federal_return_id = ?",
+        params![amended_id, tax_return_id],
+    )?;
+
+    let amended = get_tax_return(&tx, amended_id)?.ok_or(AmendError::NotFound)?;
+    tx.commit()?;
+    Ok(amended)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+
+    fn setup() -> Connection {
+        // The baseline schema seeds client 1 with a 2023 return
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_amendment_supersedes_parent() {
+        let conn = setup();
+        let amended = amend_return(&conn, 1, Amendment {
+            amendment_reason: "Missing 1099-INT".to_string(),
+            income_sources: Some(HashMap::from([
+                ("wages".to_string(), Money::from_dollars(75_000)),
+                ("interest".to_string(), Money::from_cents(21_542)),
+            ])),
+            ..Amendment::default()
+        }).unwrap();
+
+        assert_eq!(amended.return_kind, ReturnKind::Amended);
+        assert_eq!(amended.parent_return_id, Some(1));
+        assert_eq!(amended.amendment_reason.as_deref(), Some("Missing 1099-INT"));
+        assert_eq!(amended.taxes_paid, get_tax_return(&conn, 1).unwrap().unwrap().taxes_paid);
+        assert_eq!(get_tax_return(&conn, 1).unwrap().unwrap().return_kind, ReturnKind::Superseded);
+
+        // Replaced income is re-entered as lines; untouched sections are copied
+        let amended_id = amended.tax_return_id.unwrap();
+        let income = line_items::list_items(&conn, amended_id, Some(LineItemSection::Income)).unwrap();
+        assert_eq!(income.len(), 2);
+        assert_eq!(line_items::list_items(&conn, amended_id, Some(LineItemSection::Credit)).unwrap().len(), 1);
+
+        let effective = list_tax_returns(&conn, Some(1), None, false).unwrap();
+        assert_eq!(effective.len(), 1);
+        assert_eq!(effective[0].tax_return_id, amended.tax_return_id);
+        assert_eq!(list_tax_returns(&conn, Some(1), None, true).unwrap().len(), 2);
+
+        let chain: Vec<_> = return_chain(&conn, 1).unwrap().iter().map(|r| r.tax_return_id).collect();
+        assert_eq!(chain, vec![Some(1), amended.tax_return_id]);
+
+        match amend_return(&conn, 1, Amendment { amendment_reason: "Again".to_string(), ..Amendment::default() }) {
+            Err(AmendError::Superseded(current)) => assert_eq!(current, amended.tax_return_id),
+            other => panic!("expected Superseded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_one_effective_return_per_year_and_jurisdiction() {
+        let conn = setup();
+        let mut duplicate = get_tax_return(&conn, 1).unwrap().unwrap();
+        duplicate.tax_return_id = None;
+        assert!(insert_tax_return(&conn, &duplicate).is_err());
+
+        // The amendment takes the superseded return's place
+        amend_return(&conn, 1, Amendment { amendment_reason: "Typo".to_string(), ..Amendment::default() }).unwrap();
+        assert!(insert_tax_return(&conn, &duplicate).is_err());
+        duplicate.tax_year = 2022;
+        assert!(insert_tax_return(&conn, &duplicate).is_ok());
+    }
+
+    #[test]
+    fn test_amendment_requires_reason() {
+        let conn = setup();
+        assert!(matches!(amend_return(&conn, 1, Amendment::default()), Err(AmendError::MissingReason)));
+        assert!(matches!(
+            amend_return(&conn, 999, Amendment { amendment_reason: "Typo".to_string(), ..Amendment::default() }),
+            Err(AmendError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_state_return_follows_federal_amendment() {
+        let conn = setup();
+        let state_return = |code: &str| StateReturn {
+            state: Jurisdiction::parse(code).unwrap(),
+            filing_status: None,
+            income_sources: HashMap::from([("wages".to_string(), Money::from_dollars(50_000))]),
+            deductions: HashMap::new(),
+            credits: HashMap::new(),
+            taxes_paid: Money::from_dollars(2_000),
+            tax_liability: Money::from_dollars(1_800),
+            refund_or_amount_due: Money::from_dollars(200),
+        };
+
+        let virginia = add_state_return(&conn, 1, state_return("va")).unwrap();
+        assert_eq!(virginia.jurisdiction.as_str(), "VA");
+        assert_eq!((virginia.tax_year, virginia.federal_return_id), (2023, Some(1)));
+        assert_eq!(virginia.filing_status, get_tax_return(&conn, 1).unwrap().unwrap().filing_status);
+        let virginia_id = virginia.tax_return_id.unwrap();
+        assert_eq!(line_items::list_items(&conn, virginia_id, None).unwrap().len(), 1);
+
+        assert!(matches!(add_state_return(&conn, 1, state_return("VA")), Err(StateReturnError::Exists(id)) if id == virginia_id));
+        assert!(matches!(add_state_return(&conn, 1, state_return("federal")), Err(StateReturnError::NotAState)));
+        assert!(matches!(add_state_return(&conn, virginia_id, state_return("MD")), Err(StateReturnError::NotFederal)));
+        assert_eq!(list_tax_returns(&conn, Some(1), Some(&Jurisdiction::Federal), false).unwrap().len(), 1);
+
+        let amended = amend_return(&conn, 1, Amendment {
+            amendment_reason: "Corrected W-2".to_string(),
+            ..Amendment::default()
+        }).unwrap();
+        assert_eq!(amended.jurisdiction, Jurisdiction::Federal);
+        let amended_id = amended.tax_return_id.unwrap();
+        let states: Vec<_> = state_returns(&conn, amended_id).unwrap().iter().map(|r| r.tax_return_id).collect();
+        assert_eq!(states, vec![Some(virginia_id)]);
+        assert!(state_returns(&conn, 1).unwrap().is_empty());
+        assert!(matches!(add_state_return(&conn, 1, state_return("MD")), Err(StateReturnError::Superseded(Some(id))) if id == amended_id));
+    }
+}
@@ -1,147 +1,35 @@
-use rusqlite::{Connection, Result, params, OptionalExtension};
-use serde_json;
+use rusqlite::{Connection, Result};
 
-use super::models::{Client, TaxReturn};
+use super::encryption::FieldKey;
+use super::migrations;
+use super::repository::SqliteStore;
 
+/// A single connection to a database file, for tools and tests that run
+/// outside the server. Its client, return and document operations come
+/// from [`Repository`](super::Repository).
 pub struct Database {
     conn: Connection,
+    field_key: FieldKey,
 }
 
 impl Database {
-    pub fn new(db_path: &str) -> Result<Self> {
+    pub fn new(db_path: &str, field_key: FieldKey) -> Result<Self> {
         let conn = Connection::open(db_path)?;
-        Ok(Self { conn })
+        Ok(Self { conn, field_key })
     }
 
     pub fn init(&self) -> Result<()> {
-        let schema = include_str!("schema.sql");
-        self.conn.execute_batch(schema)?;
-        Ok(())
+        migrations::run(&self.conn)
     }
+}
 
-    // Client operations
-    pub fn create_client(&self, client: &Client) -> Result<i64> {
-        let mut stmt = self.conn.prepare(
-            "INSERT INTO clients (
-                first_name, last_name, social_security_number,
-                address, phone_number, email
-            ) VALUES (?, ?, ?, ?, ?, ?)"
-        )?;
-
-        stmt.execute(params![
-            client.first_name,
-            client.last_name,
-            client.social_security_number,
-            client.address,
-            client.phone_number,
-            client.email,
-        ])?;
-
-        Ok(self.conn.last_insert_rowid())
-    }
-
-    pub fn get_client(&self, client_id: i64) -> Result<Option<Client>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT * FROM clients WHERE client_id = ?"
-        )?;
-
-        let client = stmt.query_row([client_id], |row| {
-            Ok(Client {
-                client_id: Some(row.get(0)?),
-                first_name: row.get(1)?,
-                last_name: row.get(2)?,
-                social_security_number: row.get(3)?,
-                address: row.get(4)?,
-                phone_number: row.get(5)?,
-                email: row.get(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
-            })
-        }).optional()?;
-
-        Ok(client)
-    }
-
-    // Tax Return operations
-    pub fn create_tax_return(&self, tax_return: &TaxReturn) -> Result<i64> {
-        let income_sources_json = serde_json::to_string(&tax_return.income_sources)
-            .map_err(|_| rusqlite::Error::InvalidParameterName("Serialization error".to_string()))?;
-        let deductions_json = serde_json::to_string(&tax_return.deductions)
-            .map_err(|_| rusqlite::Error::InvalidParameterName("Serialization error".to_string()))?;
-        let credits_json = serde_json::to_string(&tax_return.credits)
-            .map_err(|_| rusqlite::Error::InvalidParameterName("Serialization error".to_string()))?;
-
-        let mut stmt = self.conn.prepare(
-            "INSERT INTO tax_returns (
-                client_id, tax_year, filing_status, income_sources,
-                deductions, credits, taxes_paid, tax_liability,
-                refund_or_amount_due
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
-        )?;
-
-        stmt.execute(params![
-            tax_return.client_id,
-            tax_return.tax_year,
-            tax_return.filing_status,
-            income_sources_json,
-            deductions_json,
-            credits_json,
-            tax_return.taxes_paid,
-            tax_return.tax_liability,
-            tax_return.refund_or_amount_due,
-        ])?;
-
-        Ok(self.conn.last_insert_rowid())
-    }
-
-    pub fn get_tax_return(&self, tax_return_id: i64) -> Result<Option<TaxReturn>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT * FROM tax_returns WHERE tax_return_id = ?"
-        )?;
-
-        let tax_return = stmt.query_row([tax_return_id], |row| {
-            Ok(TaxReturn {
-                tax_return_id: Some(row.get(0)?),
-                client_id: row.get(1)?,
-                tax_year: row.get(2)?,
-                filing_status: row.get(3)?,
-                income_sources: serde_json::from_str(&row.get::<_, String>(4)?).unwrap(),
-                deductions: serde_json::from_str(&row.get::<_, String>(5)?).unwrap(),
-                credits: serde_json::from_str(&row.get::<_, String>(6)?).unwrap(),
-                taxes_paid: row.get(7)?,
-                tax_liability: row.get(8)?,
-                refund_or_amount_due: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        }).optional()?;
-
-        Ok(tax_return)
+impl SqliteStore for Database {
+    fn conn(&self) -> &Connection {
+        &self.conn
     }
 
-    pub fn get_client_tax_returns(&self, client_id: i64) -> Result<Vec<TaxReturn>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT * FROM tax_returns WHERE client_id = ? ORDER BY tax_year DESC"
-        )?;
-
-        let tax_returns = stmt.query_map([client_id], |row| {
-            Ok(TaxReturn {
-                tax_return_id: Some(row.get(0)?),
-                client_id: row.get(1)?,
-                tax_year: row.get(2)?,
-                filing_status: row.get(3)?,
-                income_sources: serde_json::from_str(&row.get::<_, String>(4)?).unwrap(),
-                deductions: serde_json::from_str(&row.get::<_, String>(5)?).unwrap(),
-                credits: serde_json::from_str(&row.get::<_, String>(6)?).unwrap(),
-                taxes_paid: row.get(7)?,
-                tax_liability: row.get(8)?,
-                refund_or_amount_due: row.get(9)?,
-                created_at: row.get(10)?,
-                updated_at: row.get(11)?,
-            })
-        })?.collect::<Result<Vec<_>>>()?;
-
-        Ok(tax_returns)
+    fn field_key(&self) -> &FieldKey {
+        &self.field_key
     }
 }
 
@@ -150,11 +38,13 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
     use std::collections::HashMap;
+    use crate::money::Money;
+    use crate::db::{Client, Repository, ReturnKind, TaxReturn};
 
     fn create_test_db() -> (Database, tempfile::TempDir) {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("test.db");
-        let db = Database::new(db_path.to_str().unwrap()).unwrap();
+        let db = Database::new(db_path.to_str().unwrap(), FieldKey::generate()).unwrap();
         db.init().unwrap();
         (db, temp_dir)
     }
@@ -182,6 +72,12 @@ mod tests {
         assert_eq!(retrieved_client.first_name, client.first_name);
         assert_eq!(retrieved_client.last_name, client.last_name);
         assert_eq!(retrieved_client.email, client.email);
+        assert_eq!(retrieved_client.social_security_number, client.social_security_number);
+
+        let stored: String = db.conn().query_row(
+            "SELECT social_security_number FROM clients WHERE client_id = ?", [client_id], |row| row.get(0),
+        ).unwrap();
+        assert!(FieldKey::is_encrypted(&stored));
     }
 
     #[test]
@@ -205,13 +101,13 @@ mod tests {
 
         // Create a tax return
         let mut income_sources = HashMap::new();
-        income_sources.insert("wages".to_string(), 50000.0);
+        income_sources.insert("wages".to_string(), Money::from_dollars(50000));
         
         let mut deductions = HashMap::new();
-        deductions.insert("standard_deduction".to_string(), 12950.0);
+        deductions.insert("standard_deduction".to_string(), Money::from_dollars(12950));
         
         let mut credits = HashMap::new();
-        credits.insert("child_tax_credit".to_string(), 2000.0);
+        credits.insert("child_tax_credit".to_string(), Money::from_dollars(2000));
 
         let tax_return = TaxReturn {
             tax_return_id: None,
@@ -221,9 +117,16 @@ mod tests {
             income_sources,
             deductions,
             credits,
-            taxes_paid: 8000.0,
-            tax_liability: 7000.0,
-            refund_or_amount_due: 1000.0,
+            taxes_paid: Money::from_dollars(8000),
+            tax_liability: Money::from_dollars(7000),
+            refund_or_amount_due: Money::from_dollars(1000),
+            return_kind: ReturnKind::Original,
+            parent_return_id: None,
+            amendment_reason: None,
+            status: Default::default(),
+            assignee: None,
+            jurisdiction: Default::default(),
+            federal_return_id: None,
             created_at: None,
             updated_at: None,
         };
@@ -235,7 +138,7 @@ mod tests {
         assert_eq!(retrieved_return.client_id, client_id);
         assert_eq!(retrieved_return.tax_year, 2023);
         assert_eq!(retrieved_return.filing_status, "Single");
-        assert_eq!(retrieved_return.income_sources.get("wages"), Some(&50000.0));
+        assert_eq!(retrieved_return.income_sources.get("wages"), Some(&Money::from_dollars(50000)));
     }
 
     #[test]
@@ -260,7 +163,7 @@ mod tests {
         // Create multiple tax returns for different years
         for year in [2021, 2022, 2023] {
             let mut income_sources = HashMap::new();
-            income_sources.insert("wages".to_string(), 50000.0 * (year - 2020) as f64);
+            income_sources.insert("wages".to_string(), Money::from_dollars(50000 * (year - 2020) as i64));
             
             let tax_return = TaxReturn {
                 tax_return_id: None,
@@ -270,9 +173,16 @@ mod tests {
                 income_sources,
                 deductions: HashMap::new(),
                 credits: HashMap::new(),
-                taxes_paid: 5000.0,
-                tax_liability: 4500.0,
-                refund_or_amount_due: 500.0,
+                taxes_paid: Money::from_dollars(5000),
+                tax_liability: Money::from_dollars(4500),
+                refund_or_amount_due: Money::from_dollars(500),
+                return_kind: ReturnKind::Original,
+                parent_return_id: None,
+                amendment_reason: None,
+                status: Default::default(),
+                assignee: None,
+                jurisdiction: Default::default(),
+                federal_return_id: None,
                 created_at: None,
                 updated_at: None,
             };
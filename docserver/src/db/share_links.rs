@@ -0,0 +1,173 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Result, params};
+
+use super::models::{AccessOutcome, LinkStatus, ShareLink, ShareLinkAccess};
+
+const SHARE_LINK_COLUMNS: &str = "link_id, client_id, file_path, recipient, nonce, password_hash,
+    max_downloads, download_count, expires_at, revoked_at, created_at";
+
+fn map_link(row: &rusqlite::Row, now: DateTime<Utc>) -> Result<ShareLink> {
+    let password_hash: Option<String> = row.get(5)?;
+    let max_downloads: Option<i64> = row.get(6)?;
+    let download_count: i64 = row.get(7)?;
+    let expires_at: DateTime<Utc> = row.get(8)?;
+    let revoked_at: Option<DateTime<Utc>> = row.get(9)?;
+    Ok(ShareLink {
+        link_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        file_path: row.get(2)?,
+        recipient: row.get(3)?,
+        nonce: row.get(4)?,
+        password_protected: password_hash.is_some(),
+        password_hash,
+        max_downloads,
+        download_count,
+        expires_at,
+        revoked_at,
+        created_at: row.get(10)?,
+        status: LinkStatus::derive(revoked_at, expires_at, download_count, max_downloads, now),
+    })
+}
+
+pub fn create_link(conn: &Connection, link: &ShareLink) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO share_links (
+            client_id, file_path, recipient, nonce, password_hash, max_downloads, expires_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![
+            link.client_id,
+            link.file_path,
+            link.recipient,
+            link.nonce,
+            link.password_hash,
+            link.max_downloads,
+            link.expires_at,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_link(conn: &Connection, link_id: i64, now: DateTime<Utc>) -> Result<Option<ShareLink>> {
+    conn.query_row(
+        &format!("SELECT {} FROM share_links WHERE link_id = ?", SHARE_LINK_COLUMNS),
+        [link_id],
+        |row| map_link(row, now),
+    ).optional()
+}
+
+/// Links newest first, optionally for one client and in one status.
+pub fn list_links(
+    conn: &Connection,
+    client_id: Option<i64>,
+    status: Option<LinkStatus>,
+    now: DateTime<Utc>,
+) -> Result<Vec<ShareLink>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM share_links WHERE (?1 IS NULL OR client_id = ?1) ORDER BY link_id DESC",
+        SHARE_LINK_COLUMNS
+    ))?;
+
+    let links = stmt.query_map([client_id], |row| map_link(row, now))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(links.into_iter().filter(|link| status.is_none_or(|s| link.status == s)).collect())
+}
+
+/// Returns false if there is no such link or it was already revoked.
+pub fn revoke_link(conn: &Connection, link_id: i64) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE share_links SET revoked_at = CURRENT_TIMESTAMP WHERE link_id = ? AND revoked_at IS NULL",
+        [link_id],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Counts a download against an open link, in a single statement so
+/// concurrent requests cannot exceed the download limit.
+pub fn claim_download(conn: &Connection, link_id: i64, nonce: &str, now: DateTime<Utc>) -> Result<bool> {
+    let claimed = conn.execute(
+        "UPDATE share_links SET download_count = download_count + 1
+         WHERE link_id = ? AND nonce = ? AND revoked_at IS NULL
+           AND (max_downloads IS NULL OR download_count < max_downloads) AND expires_at > ?",
+        params![link_id, nonce, now],
+    )?;
+    Ok(claimed > 0)
+}
+
+/// Gives back a download counted by `claim_download` when the file could not be served.
+pub fn release_download(conn: &Connection, link_id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE share_links SET download_count = download_count - 1 WHERE link_id = ? AND download_count > 0",
+        [link_id],
+    )?;
+    Ok(())
+}
+
+pub fn record_access(
+    conn: &Connection,
+    link_id: i64,
+    outcome: AccessOutcome,
+    remote_addr: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO share_link_accesses (link_id, outcome, remote_addr, user_agent) VALUES (?, ?, ?, ?)",
+        params![link_id, outcome, remote_addr, user_agent],
+    )?;
+    Ok(())
+}
+
+pub fn list_accesses(conn: &Connection, link_id: i64) -> Result<Vec<ShareLinkAccess>> {
+    let mut stmt = conn.prepare(
+        "SELECT access_id, link_id, outcome, remote_addr, user_agent, accessed_at
+         FROM share_link_accesses WHERE link_id = ? ORDER BY access_id"
+    )?;
+    let accesses = stmt.query_map([link_id], |row| {
+        Ok(ShareLinkAccess {
+            access_id: Some(row.get(0)?),
+            link_id: row.get(1)?,
+            outcome: row.get(2)?,
+            remote_addr: row.get(3)?,
+            user_agent: row.get(4)?,
+            accessed_at: row.get(5)?,
+        })
+    })?.collect::<Result<Vec<_>>>()?;
+    Ok(accesses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+    use chrono::Duration;
+
+    #[test]
+    fn test_download_limit() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        let now = Utc::now();
+
+        let link_id = create_link(&conn, &ShareLink {
+            link_id: None,
+            client_id: Some(1),
+            file_path: "1/return.pdf".to_string(),
+            recipient: Some("Lender".to_string()),
+            nonce: "n".to_string(),
+            password_hash: None,
+            password_protected: false,
+            max_downloads: Some(2),
+            download_count: 0,
+            expires_at: now + Duration::days(7),
+            revoked_at: None,
+            created_at: None,
+            status: LinkStatus::Open,
+        }).unwrap();
+
+        assert!(claim_download(&conn, link_id, "n", now).unwrap());
+        assert!(claim_download(&conn, link_id, "n", now).unwrap());
+        assert!(!claim_download(&conn, link_id, "n", now).unwrap());
+        assert_eq!(get_link(&conn, link_id, now).unwrap().unwrap().status, LinkStatus::Used);
+
+        record_access(&conn, link_id, AccessOutcome::Downloaded, Some("127.0.0.1"), None).unwrap();
+        assert_eq!(list_accesses(&conn, link_id).unwrap()[0].outcome, AccessOutcome::Downloaded);
+    }
+}
@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Result, params};
+
+use super::models::{LinkStatus, UploadLink};
+
+const UPLOAD_LINK_COLUMNS: &str = "link_id, client_id, checklist_item_id, nonce, note, max_uploads,
+    upload_count, expires_at, revoked_at, last_used_at, created_at";
+
+fn map_link(row: &rusqlite::Row, now: DateTime<Utc>) -> Result<UploadLink> {
+    let max_uploads: i64 = row.get(5)?;
+    let upload_count: i64 = row.get(6)?;
+    let expires_at: DateTime<Utc> = row.get(7)?;
+    let revoked_at: Option<DateTime<Utc>> = row.get(8)?;
+    Ok(UploadLink {
+        link_id: Some(row.get(0)?),
+        client_id: row.get(1)?,
+        checklist_item_id: row.get(2)?,
+        nonce: row.get(3)?,
+        note: row.get(4)?,
+        max_uploads,
+        upload_count,
+        expires_at,
+        revoked_at,
+        last_used_at: row.get(9)?,
+        created_at: row.get(10)?,
+        status: LinkStatus::derive(revoked_at, expires_at, upload_count, Some(max_uploads), now),
+    })
+}
+
+pub fn create_link(
+    conn: &Connection,
+    client_id: i64,
+    checklist_item_id: Option<i64>,
+    note: Option<&str>,
+    max_uploads: i64,
+    nonce: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO upload_links (client_id, checklist_item_id, nonce, note, max_uploads, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        params![client_id, checklist_item_id, nonce, note, max_uploads, expires_at],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_link(conn: &Connection, link_id: i64, now: DateTime<Utc>) -> Result<Option<UploadLink>> {
+    conn.query_row(
+        &format!("SELECT {} FROM upload_links WHERE link_id = ?", UPLOAD_LINK_COLUMNS),
+        [link_id],
+        |row| map_link(row, now),
+    ).optional()
+}
+
+/// Links newest first, optionally for one client and in one status.
+pub fn list_links(
+    conn: &Connection,
+    client_id: Option<i64>,
+    status: Option<LinkStatus>,
+    now: DateTime<Utc>,
+) -> Result<Vec<UploadLink>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM upload_links WHERE (?1 IS NULL OR client_id = ?1) ORDER BY link_id DESC",
+        UPLOAD_LINK_COLUMNS
+    ))?;
+
+    let links = stmt.query_map([client_id], |row| map_link(row, now))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(links.into_iter().filter(|link| status.is_none_or(|s| link.status == s)).collect())
+}
+
+/// Returns false if there is no such link or it was already revoked.
+pub fn revoke_link(conn: &Connection, link_id: i64) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE upload_links SET revoked_at = CURRENT_TIMESTAMP WHERE link_id = ? AND revoked_at IS NULL",
+        [link_id],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Takes one use of an open link whose nonce matches, in a single statement
+/// so concurrent uploads cannot both take a link's last use.
+pub fn claim_upload(conn: &Connection, link_id: i64, nonce: &str, now: DateTime<Utc>) -> Result<Option<UploadLink>> {
+    let claimed = conn.execute(
+        "UPDATE upload_links
+         SET upload_count = upload_count + 1, last_used_at = CURRENT_TIMESTAMP
+         WHERE link_id = ? AND nonce = ? AND revoked_at IS NULL
+           AND upload_count < max_uploads AND expires_at > ?",
+        params![link_id, nonce, now],
+    )?;
+    if claimed == 0 {
+        return Ok(None);
+    }
+    get_link(conn, link_id, now)
+}
+
+/// Gives back a use taken by `claim_upload` when nothing was saved.
+pub fn release_upload(conn: &Connection, link_id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE upload_links SET upload_count = upload_count - 1 WHERE link_id = ? AND upload_count > 0",
+        [link_id],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+    use chrono::Duration;
+
+    #[test]
+    fn test_link_lifecycle() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        let now = Utc::now();
+
+        let link_id = create_link(&conn, 1, None, Some("Missing 1099"), 1, "abc", now + Duration::days(3)).unwrap();
+        assert_eq!(get_link(&conn, link_id, now).unwrap().unwrap().status, LinkStatus::Open);
+        assert_eq!(
+            get_link(&conn, link_id, now + Duration::days(4)).unwrap().unwrap().status,
+            LinkStatus::Expired
+        );
+
+        assert!(claim_upload(&conn, link_id, "wrong", now).unwrap().is_none());
+        let claimed = claim_upload(&conn, link_id, "abc", now).unwrap().unwrap();
+        assert_eq!(claimed.status, LinkStatus::Used);
+        assert!(claim_upload(&conn, link_id, "abc", now).unwrap().is_none());
+
+        release_upload(&conn, link_id).unwrap();
+        assert!(revoke_link(&conn, link_id).unwrap());
+        assert!(claim_upload(&conn, link_id, "abc", now).unwrap().is_none());
+        assert_eq!(list_links(&conn, Some(1), Some(LinkStatus::Revoked), now).unwrap().len(), 1);
+    }
+}
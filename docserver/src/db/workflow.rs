@@ -0,0 +1,168 @@
+use rusqlite::{Connection, Result, params};
+use std::fmt;
+
+use super::connection::write_transaction;
+use super::models::{ReturnStatus, StatusChange, TaxReturn};
+use super::repository::StoreError;
+use super::returns::{self, TAX_RETURN_COLUMNS};
+
+#[derive(Debug)]
+pub enum TransitionError {
+    NotFound,
+    NotAllowed { from: ReturnStatus, to: ReturnStatus },
+    Database(StoreError),
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::NotFound => write!(f, "Tax return not found"),
+            TransitionError::NotAllowed { from, to } => {
+                let allowed: Vec<&str> = from.next().iter().map(|s| s.as_str()).collect();
+                write!(
+                    f,
+                    "Cannot move a return from {} to {} (allowed: {})",
+                    from.as_str(),
+                    to.as_str(),
+                    if allowed.is_empty() { "none".to_string() } else { allowed.join(", ") },
+                )
+            }
+            TransitionError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for TransitionError {
+    fn from(e: rusqlite::Error) -> Self {
+        TransitionError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for TransitionError {
+    fn from(e: StoreError) -> Self {
+        TransitionError::Database(e)
+    }
+}
+
+fn map_status_change(row: &rusqlite::Row) -> Result<StatusChange> {
+    Ok(StatusChange {
+        history_id: Some(row.get(0)?),
+        tax_return_id: row.get(1)?,
+        from_status: row.get(2)?,
+        to_status: row.get(3)?,
+        assignee: row.get(4)?,
+        note: row.get(5)?,
+        changed_at: row.get(6)?,
+    })
+}
+
+/// Moves a return to its next stage and hands that stage to `assignee`,
+/// recording the change in the return's history. `None` keeps the current
+/// assignee; `Some(None)` leaves the return unassigned.
+pub fn transition_return(
+    conn: &Connection,
+    tax_return_id: i64,
+    to: ReturnStatus,
+    assignee: Option<Option<&str>>,
+    note: Option<&str>,
+) -> std::result::Result<TaxReturn, TransitionError> {
+    let tx = write_transaction(conn)?;
+    let tax_return = returns::get_tax_return(&tx, tax_return_id)?.ok_or(TransitionError::NotFound)?;
+    if !tax_return.status.can_transition_to(to) {
+        return Err(TransitionError::NotAllowed { from: tax_return.status, to });
+    }
+    let assignee = match assignee {
+        Some(assignee) => assignee,
+        None => tax_return.assignee.as_deref(),
+    };
+
+    tx.execute(
+        "UPDATE tax_returns SET status = ?, assignee = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE tax_return_id = ?",
+        params![to, assignee, tax_return_id],
+    )?;
+    tx.execute(
+        "INSERT INTO return_status_history (tax_return_id, from_status, to_status, assignee, note)
+         VALUES (?, ?, ?, ?, ?)",
+        params![tax_return_id, tax_return.status, to, assignee, note],
+    )?;
+
+    let updated = returns::get_tax_return(&tx, tax_return_id)?.ok_or(TransitionError::NotFound)?;
+    tx.commit()?;
+    Ok(updated)
+}
+
+pub fn status_history(conn: &Connection, tax_return_id: i64) -> Result<Vec<StatusChange>> {
+    let mut stmt = conn.prepare(
+        "SELECT history_id, tax_return_id, from_status, to_status, assignee, note, changed_at
+         FROM return_status_history
+         WHERE tax_return_id = ?
+         ORDER BY history_id"
+    )?;
+
+    let history = stmt.query_map([tax_return_id], map_status_change)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(history)
+}
+
+/// Effective returns in the given stage (or every stage), optionally only
+/// those assigned to one person. Superseded returns are never on the board.
+pub fn list_by_status(
+    conn: &Connection,
+    status: Option<ReturnStatus>,
+    assignee: Option<&str>,
+) -> Result<Vec<TaxReturn>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tax_returns
+         WHERE return_kind != 'superseded'
+           AND (?1 IS NULL OR status = ?1)
+           AND (?2 IS NULL OR assignee = ?2)
+         ORDER BY updated_at, tax_return_id",
+        TAX_RETURN_COLUMNS
+    ))?;
+
+    let tax_returns = stmt.query_map(params![status, assignee], returns::map_tax_return)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(tax_returns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+
+    fn setup() -> Connection {
+        // The baseline schema seeds client 1 with a 2023 return
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_transitions_are_enforced_and_recorded() {
+        let conn = setup();
+
+        let result = transition_return(&conn, 1, ReturnStatus::InReview, Some(Some("sam")), None);
+        assert!(matches!(result, Err(TransitionError::NotAllowed { .. })));
+
+        let updated = transition_return(&conn, 1, ReturnStatus::DocumentsReceived, Some(Some("sam")), Some("All in")).unwrap();
+        assert_eq!(updated.status, ReturnStatus::DocumentsReceived);
+        assert_eq!(updated.assignee.as_deref(), Some("sam"));
+
+        let history = status_history(&conn, 1).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].to_status, ReturnStatus::DocumentsRequested);
+        assert_eq!(history[1].from_status, Some(ReturnStatus::DocumentsRequested));
+        assert_eq!(history[1].note.as_deref(), Some("All in"));
+
+        assert_eq!(list_by_status(&conn, Some(ReturnStatus::DocumentsReceived), None).unwrap().len(), 1);
+        assert_eq!(list_by_status(&conn, None, Some("alex")).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_complete_is_final() {
+        assert!(ReturnStatus::Complete.next().is_empty());
+        assert!(ReturnStatus::Rejected.can_transition_to(ReturnStatus::InPreparation));
+        assert!(!ReturnStatus::EFiled.can_transition_to(ReturnStatus::Complete));
+    }
+}
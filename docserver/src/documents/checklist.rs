@@ -0,0 +1,164 @@
+use rusqlite::{Connection, Result};
+use std::collections::HashSet;
+
+use crate::db::{checklists, documents, returns, ChecklistItem, Document, DocumentType, Jurisdiction, TaxReturn};
+
+// The form that normally backs each key on a return
+const KEY_FORMS: &[(&str, &str, DocumentType)] = &[
+    ("income_sources", "wages", DocumentType::W2),
+    ("income_sources", "interest", DocumentType::Form1099Int),
+    ("income_sources", "dividends", DocumentType::Form1099Div),
+    ("income_sources", "capital_gain_distributions", DocumentType::Form1099Div),
+    ("income_sources", "capital_gains", DocumentType::Form1099B),
+    ("income_sources", "self_employment", DocumentType::Form1099Nec),
+    ("income_sources", "rents", DocumentType::Form1099Misc),
+    ("income_sources", "royalties", DocumentType::Form1099Misc),
+    ("income_sources", "other_income", DocumentType::Form1099Misc),
+    ("income_sources", "retirement_distributions", DocumentType::Form1099R),
+    ("income_sources", "partnership_income", DocumentType::K1),
+    ("deductions", "mortgage_interest", DocumentType::Form1098),
+    ("deductions", "tuition_and_fees", DocumentType::Form1098T),
+];
+
+fn form_for(map: &str, key: &str) -> Option<DocumentType> {
+    KEY_FORMS.iter()
+        .find(|(m, k, _)| *m == map && *k == key)
+        .map(|(_, _, document_type)| *document_type)
+}
+
+/// An item [`plan`] puts on a checklist.
+pub(crate) struct PlannedItem {
+    pub item_key: String,
+    pub document_type: DocumentType,
+    pub description: String,
+}
+
+/// Works out the checklist for `tax_year` from the year before: one item per
+/// form classified last year (so each employer's W-2 is chased separately),
+/// plus one per key on last year's federal return whose form was not among
+/// last year's uploads. Shared by every backend's `generate_checklist`.
+pub(crate) fn plan(client_documents: &[Document], prior_return: Option<&TaxReturn>, tax_year: i32) -> Vec<PlannedItem> {
+    let prior_year = tax_year - 1;
+    let mut planned = Vec::new();
+
+    let mut covered = HashSet::new();
+    for document in client_documents.iter().filter(|d| d.tax_year == Some(prior_year)) {
+        let (Some(document_id), Some(document_type)) = (document.document_id, document.document_type) else {
+            continue;
+        };
+        // Last year's own return is not something the client sends again
+        if document_type == DocumentType::Form1040 {
+            continue;
+        }
+        planned.push(PlannedItem {
+            item_key: format!("document:{}", document_id),
+            document_type,
+            description: format!("{} (last year: {})", document_type.as_str(), document.file_name),
+        });
+        covered.insert(document_type);
+    }
+
+    if let Some(prior_return) = prior_return {
+        let maps = [
+            ("income_sources", &prior_return.income_sources),
+            ("deductions", &prior_return.deductions),
+        ];
+        for (map, values) in maps {
+            // Sorted so the generated items come out in a stable order
+            let mut keys: Vec<&String> = values.keys().collect();
+            keys.sort();
+            for key in keys {
+                let Some(document_type) = form_for(map, key) else { continue };
+                if !covered.insert(document_type) {
+                    continue;
+                }
+                planned.push(PlannedItem {
+                    item_key: format!("{}:{}", map, key),
+                    document_type,
+                    description: format!("{} for {} reported last year", document_type.as_str(), key),
+                });
+            }
+        }
+    }
+    planned
+}
+
+/// Adds the items [`plan`] works out for `tax_year` to the client's
+/// checklist. Documents already uploaded for the year are ticked off
+/// straight away.
+pub fn generate(conn: &Connection, client_id: i64, tax_year: i32) -> Result<Vec<ChecklistItem>> {
+    let client_documents = documents::list_client_documents(conn, client_id)?;
+    let prior_return = returns::list_tax_returns(conn, Some(client_id), Some(&Jurisdiction::Federal), false)?
+        .into_iter()
+        .find(|r| r.tax_year == tax_year - 1);
+
+    for item in plan(&client_documents, prior_return.as_ref(), tax_year) {
+        checklists::add_item(conn, client_id, tax_year, &item.item_key, item.document_type, &item.description)?;
+    }
+    for document in client_documents.iter().filter(|d| d.tax_year == Some(tax_year)) {
+        checklists::mark_received(conn, document)?;
+    }
+
+    checklists::list_items(conn, client_id, Some(tax_year), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{migrations, ChecklistStatus};
+    use rusqlite::params;
+
+    fn document(conn: &Connection, file_name: &str, document_type: DocumentType, tax_year: i32) -> Document {
+        let mut document = Document {
+            document_id: None,
+            client_id: 1,
+            file_name: file_name.to_string(),
+            document_type: Some(document_type),
+            tax_year: Some(tax_year),
+            confidence: 0.9,
+            type_overridden: false,
+            extracted_text: None,
+            created_at: None,
+            updated_at: None,
+        };
+        document.document_id = Some(documents::upsert_document(conn, &document).unwrap());
+        document
+    }
+
+    #[test]
+    fn test_generate_from_prior_year() {
+        // The baseline schema seeds client 1 with a 2023 return
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        conn.execute(
+            "UPDATE tax_returns SET income_sources = ?, deductions = ? WHERE tax_return_id = 1",
+            params![
+                r#"{"wages": "50000.00", "interest": "120.00"}"#,
+                r#"{"mortgage_interest": "9000.00", "charitable": "500.00"}"#,
+            ],
+        ).unwrap();
+        document(&conn, "w2_acme.pdf", DocumentType::W2, 2023);
+        document(&conn, "w2_globex.pdf", DocumentType::W2, 2023);
+
+        let items = generate(&conn, 1, 2024).unwrap();
+        let types: Vec<&str> = items.iter().map(|i| i.document_type.as_str()).collect();
+        assert_eq!(types, vec!["1098", "1099-INT", "W-2", "W-2"]);
+
+        // Generating again does not duplicate anything
+        assert_eq!(generate(&conn, 1, 2024).unwrap().len(), 4);
+
+        let upload = document(&conn, "acme_2024.pdf", DocumentType::W2, 2024);
+        assert!(checklists::mark_received(&conn, &upload).unwrap().is_some());
+        assert!(checklists::mark_received(&conn, &upload).unwrap().is_none());
+
+        let pending = checklists::list_items(&conn, 1, Some(2024), Some(ChecklistStatus::Pending)).unwrap();
+        assert_eq!(pending.len(), 3);
+
+        // Reclassifying the upload puts its W-2 item back on the list
+        let reclassified = Document { document_type: Some(DocumentType::Form1098), ..upload };
+        checklists::mark_received(&conn, &reclassified).unwrap();
+        let pending = checklists::list_items(&conn, 1, Some(2024), Some(ChecklistStatus::Pending)).unwrap();
+        let types: Vec<&str> = pending.iter().map(|i| i.document_type.as_str()).collect();
+        assert_eq!(types, vec!["1099-INT", "W-2", "W-2"]);
+    }
+}
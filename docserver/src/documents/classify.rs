@@ -0,0 +1,389 @@
+use chrono::{Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::db::DocumentType;
+
+// Below this score a match is too weak to label the document at all.
+const MIN_CONFIDENCE: f64 = 0.3;
+// Custom rules come from preparer overrides, so they outrank the built-ins
+// unless a built-in found the form's own title.
+const CUSTOM_RULE_CONFIDENCE: f64 = 0.95;
+const TITLE_WEIGHT: f64 = 0.6;
+const KEYWORD_WEIGHT: f64 = 0.4;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Classification {
+    pub document_type: Option<DocumentType>,
+    pub tax_year: Option<i32>,
+    pub confidence: f64,
+}
+
+struct Rule {
+    document_type: DocumentType,
+    // Form titles and numbers; any one of these is strong evidence
+    titles: &'static [&'static str],
+    // Box labels and phrases printed on the form
+    keywords: &'static [&'static str],
+}
+
+const BUILTIN_RULES: &[Rule] = &[
+    Rule {
+        document_type: DocumentType::W2,
+        titles: &["form w-2", "w-2 wage and tax statement", "wage and tax statement"],
+        keywords: &[
+            "wages, tips, other compensation",
+            "federal income tax withheld",
+            "social security wages",
+            "medicare wages and tips",
+            "employer identification number",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1099Int,
+        titles: &["1099-int"],
+        keywords: &[
+            "interest income",
+            "early withdrawal penalty",
+            "interest on u.s. savings bonds",
+            "payer's tin",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1099Div,
+        titles: &["1099-div"],
+        keywords: &[
+            "dividends and distributions",
+            "total ordinary dividends",
+            "qualified dividends",
+            "total capital gain distr",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1099B,
+        titles: &["1099-b"],
+        keywords: &[
+            "proceeds from broker and barter exchange transactions",
+            "cost or other basis",
+            "date acquired",
+            "date sold or disposed",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1099Nec,
+        titles: &["1099-nec"],
+        keywords: &["nonemployee compensation", "payer's tin", "recipient's tin"],
+    },
+    Rule {
+        document_type: DocumentType::Form1099Misc,
+        titles: &["1099-misc"],
+        keywords: &["miscellaneous information", "rents", "royalties", "other income"],
+    },
+    Rule {
+        document_type: DocumentType::Form1099R,
+        titles: &["1099-r"],
+        keywords: &[
+            "distributions from pensions",
+            "gross distribution",
+            "taxable amount",
+            "distribution code",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1098,
+        titles: &["form 1098", "mortgage interest statement"],
+        keywords: &[
+            "mortgage interest received",
+            "outstanding mortgage principal",
+            "mortgage origination date",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1098T,
+        titles: &["1098-t", "tuition statement"],
+        keywords: &[
+            "payments received for qualified tuition",
+            "scholarships or grants",
+            "student's tin",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1095A,
+        titles: &["1095-a", "health insurance marketplace statement"],
+        keywords: &[
+            "monthly enrollment premiums",
+            "second lowest cost silver plan",
+            "advance payment of premium tax credit",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::K1,
+        titles: &["schedule k-1"],
+        keywords: &[
+            "partner's share of income",
+            "shareholder's share of income",
+            "beneficiary's share of income",
+            "ordinary business income",
+        ],
+    },
+    Rule {
+        document_type: DocumentType::Form1040,
+        titles: &["form 1040", "u.s. individual income tax return"],
+        keywords: &["filing status", "adjusted gross income", "taxable income", "total tax"],
+    },
+];
+
+/// A rule learned from a preparer override, stored in the custom rules file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomRule {
+    pub document_type: DocumentType,
+    // The document's heading must consist of exactly these terms
+    pub terms: Vec<String>,
+    pub source_document_id: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CustomRules {
+    pub rules: Vec<CustomRule>,
+}
+
+impl CustomRules {
+    /// Reads the rules file, treating a missing or unreadable file as empty.
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid classifier rules file {:?}: {}", path, e);
+                CustomRules::default()
+            }),
+            Err(_) => CustomRules::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(path, contents)
+    }
+
+    /// Learns from an override by keying the chosen type on the document's
+    /// heading. Returns false when the text has nothing distinctive to key on.
+    pub fn learn(&mut self, document_type: DocumentType, text: &str, document_id: Option<i64>) -> bool {
+        let terms = match heading_of(text) {
+            Some(terms) => terms,
+            None => return false,
+        };
+
+        match self.rules.iter_mut().find(|rule| rule.terms == terms) {
+            Some(rule) => {
+                rule.document_type = document_type;
+                rule.source_document_id = document_id;
+            }
+            None => self.rules.push(CustomRule {
+                document_type,
+                terms,
+                source_document_id: document_id,
+            }),
+        }
+        true
+    }
+}
+
+pub fn classify(text: &str, custom_rules: &CustomRules) -> Classification {
+    let normalized = normalize(text);
+    let tax_year = detect_tax_year(&normalized);
+
+    let best = BUILTIN_RULES
+        .iter()
+        .map(|rule| (rule.document_type, score(rule, &normalized)))
+        .filter(|(_, score)| *score >= MIN_CONFIDENCE)
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+
+    // A learned rule keys on a heading, which may be boilerplate printed on
+    // many forms, so it only decides documents the built-ins cannot place
+    // by title
+    let heading = heading_of(text);
+    let custom_match = custom_rules.rules.iter().find(|rule| {
+        !rule.terms.is_empty() && heading.as_ref() == Some(&rule.terms)
+    });
+    if let Some(rule) = custom_match.filter(|_| best.is_none_or(|(_, score)| score < TITLE_WEIGHT)) {
+        return Classification {
+            document_type: Some(rule.document_type),
+            tax_year,
+            confidence: CUSTOM_RULE_CONFIDENCE,
+        };
+    }
+
+    match best {
+        Some((document_type, score)) => Classification {
+            document_type: Some(document_type),
+            tax_year,
+            confidence: (score * 100.0).round() / 100.0,
+        },
+        None => Classification {
+            document_type: None,
+            tax_year,
+            confidence: 0.0,
+        },
+    }
+}
+
+fn score(rule: &Rule, text: &str) -> f64 {
+    let title_hit = rule.titles.iter().any(|title| contains_term(text, title));
+    let keyword_hits = rule.keywords.iter().filter(|keyword| contains_term(text, keyword)).count();
+
+    let title_score = if title_hit { TITLE_WEIGHT } else { 0.0 };
+    title_score + KEYWORD_WEIGHT * keyword_hits as f64 / rule.keywords.len() as f64
+}
+
+/// Lowercases and collapses all whitespace to single spaces.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Matches `term` only at word boundaries, so "form 1098" does not match
+/// inside "form 1098-t" and "1099-r" does not match "1099-rev".
+fn contains_term(text: &str, term: &str) -> bool {
+    let term = normalize(term);
+    if term.is_empty() {
+        return false;
+    }
+
+    text.match_indices(&term).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + term.len()..].chars().next();
+        !before.is_some_and(|c| c.is_alphanumeric())
+            && !after.is_some_and(|c| c.is_alphanumeric() || c == '-')
+    })
+}
+
+/// Picks the most frequently printed plausible year, preferring the later
+/// one on ties (forms often mention the prior year in instructions).
+fn detect_tax_year(text: &str) -> Option<i32> {
+    let latest = Utc::now().year() + 1;
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    while start < bytes.len() {
+        if !bytes[start].is_ascii_digit() {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+        if end - start == 4 {
+            if let Ok(year) = text[start..end].parse::<i32>() {
+                if (2000..=latest).contains(&year) {
+                    *counts.entry(year).or_default() += 1;
+                }
+            }
+        }
+        start = end;
+    }
+
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)))
+        .map(|(year, _)| year)
+}
+
+/// The first line with enough words to key on, as the runs of words between
+/// any years in it, so a rule learned from one year's form matches the next.
+fn heading_of(text: &str) -> Option<Vec<String>> {
+    let line = text.lines()
+        .map(normalize)
+        .find(|line| line.chars().filter(|c| c.is_alphabetic()).count() >= 8)?;
+    let line: String = line.chars().take(80).collect();
+
+    let mut terms = vec![Vec::new()];
+    for word in line.split(' ') {
+        if is_year(word) {
+            terms.push(Vec::new());
+        } else if let Some(term) = terms.last_mut() {
+            term.push(word);
+        }
+    }
+    Some(terms.into_iter().filter(|words| !words.is_empty()).map(|words| words.join(" ")).collect())
+}
+
+fn is_year(word: &str) -> bool {
+    let digits = word.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    digits.len() == 4 && digits.chars().all(|c| c.is_ascii_digit()) && (digits.starts_with("19") || digits.starts_with("20"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_w2() {
+        let text = "2023 Form W-2 Wage and Tax Statement\n\
+                    1 Wages, tips, other compensation 52000.00\n\
+                    2 Federal income tax withheld 6100.00\n\
+                    3 Social security wages 52000.00";
+        let result = classify(text, &CustomRules::default());
+        assert_eq!(result.document_type, Some(DocumentType::W2));
+        assert_eq!(result.tax_year, Some(2023));
+        assert!(result.confidence > 0.8);
+    }
+
+    #[test]
+    fn test_1098_does_not_match_1098_t() {
+        let text = "Form 1098-T Tuition Statement 2022\nScholarships or grants 1500.00";
+        let result = classify(text, &CustomRules::default());
+        assert_eq!(result.document_type, Some(DocumentType::Form1098T));
+    }
+
+    #[test]
+    fn test_unrecognized_text() {
+        let result = classify("Grocery receipt: apples 3.50", &CustomRules::default());
+        assert_eq!(result.document_type, None);
+        assert_eq!(result.confidence, 0.0);
+    }
+
+    #[test]
+    fn test_learned_rule_takes_precedence() {
+        let text = "Acme Brokerage Consolidated Statement\nTotal ordinary dividends 120.00";
+        let mut rules = CustomRules::default();
+        assert!(rules.learn(DocumentType::Form1099B, text, Some(7)));
+
+        let result = classify(text, &rules);
+        assert_eq!(result.document_type, Some(DocumentType::Form1099B));
+        assert_eq!(result.confidence, CUSTOM_RULE_CONFIDENCE);
+    }
+
+    #[test]
+    fn test_learned_rule_applies_to_other_years() {
+        let mut rules = CustomRules::default();
+        assert!(rules.learn(DocumentType::Form1099B, "Acme Brokerage 2023 Consolidated Statement\nTotal 120.00", None));
+        assert_eq!(rules.rules[0].terms, vec!["acme brokerage", "consolidated statement"]);
+
+        let result = classify("Acme Brokerage 2024 Consolidated Statement\nTotal 95.00", &rules);
+        assert_eq!(result.document_type, Some(DocumentType::Form1099B));
+        assert_eq!(result.tax_year, Some(2024));
+    }
+
+    #[test]
+    fn test_learned_rule_on_generic_heading_does_not_hijack_forms() {
+        let mut rules = CustomRules::default();
+        let statement = "Department of the Treasury Internal Revenue Service\nAcme Brokerage summary";
+        assert!(rules.learn(DocumentType::Form1099B, statement, Some(3)));
+        assert_eq!(classify(statement, &rules).document_type, Some(DocumentType::Form1099B));
+
+        // The same boilerplate heading on a clear W-2
+        let w2 = "Department of the Treasury Internal Revenue Service\n\
+                  2023 Form W-2 Wage and Tax Statement\n\
+                  1 Wages, tips, other compensation 52000.00";
+        assert_eq!(classify(w2, &rules).document_type, Some(DocumentType::W2));
+
+        // Nor does a heading that merely appears further down
+        let later = "Acme Payroll\nDepartment of the Treasury Internal Revenue Service";
+        assert_ne!(classify(later, &rules).document_type, Some(DocumentType::Form1099B));
+    }
+}
@@ -0,0 +1,482 @@
+//! Compares the database with the files under the root. Every document
+//! record should have its file in `<root>/<client_id>/`, unchanged since it
+//! was uploaded; every folder there named by a client id should belong to a
+//! client and every file in it to a record; and every return should belong to
+//! a client. Folders not named by a client id hold uploads stored as-is;
+//! they are reported, but a repair never moves them.
+
+use chrono::Utc;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::trash::TrashBatch;
+use crate::config::{checksum, Settings, TAX_RULES_DIRNAME, TRASH_DIRNAME};
+use crate::db::{clients, documents, returns, write_transaction, Document, StoreError, StoreResult};
+use crate::links::to_hex;
+
+// An upload's file is written before its document is recorded, so anything
+// changed this recently may still be on its way in and is never an orphan
+const SETTLE_TIME: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Serialize)]
+pub struct OrphanDirectory {
+    // Relative to the root
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissingFile {
+    pub document_id: i64,
+    pub client_id: i64,
+    pub file_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChecksumMismatch {
+    pub document_id: i64,
+    pub client_id: i64,
+    pub file_name: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DanglingReturn {
+    pub tax_return_id: i64,
+    pub client_id: i64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ConsistencyReport {
+    // Folders under the root that are not a known client's
+    pub orphan_directories: Vec<OrphanDirectory>,
+    // Files in client folders, relative to the root, that nothing records
+    pub orphan_files: Vec<PathBuf>,
+    // Records whose file is gone
+    pub missing_files: Vec<MissingFile>,
+    pub checksum_mismatches: Vec<ChecksumMismatch>,
+    // Documents recorded before checksums were kept
+    pub unchecksummed: Vec<i64>,
+    pub dangling_returns: Vec<DanglingReturn>,
+}
+
+impl ConsistencyReport {
+    /// Whether nothing is wrong. Documents without a checksum are not a
+    /// fault; repairing records one for each.
+    pub fn is_consistent(&self) -> bool {
+        self.orphan_directories.is_empty()
+            && self.orphan_files.is_empty()
+            && self.missing_files.is_empty()
+            && self.checksum_mismatches.is_empty()
+            && self.dangling_returns.is_empty()
+    }
+}
+
+/// What a repair changed. Orphan folders and files are moved to one trash
+/// batch; folders not named by a client id, missing files, changed files and
+/// dangling returns need a person to decide and are left alone.
+#[derive(Debug, Default, Serialize)]
+pub struct RepairSummary {
+    pub trash_batch: Option<PathBuf>,
+    pub trashed: Vec<PathBuf>,
+    pub checksums_recorded: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepairReport {
+    // The state found before repairing
+    pub report: ConsistencyReport,
+    pub repair: RepairSummary,
+}
+
+#[derive(Debug)]
+pub enum ConsistencyError {
+    Database(StoreError),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsistencyError::Database(e) => write!(f, "Database error: {}", e),
+            ConsistencyError::Io(path, e) => write!(f, "Cannot access {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConsistencyError {}
+
+impl From<rusqlite::Error> for ConsistencyError {
+    fn from(e: rusqlite::Error) -> Self {
+        ConsistencyError::Database(StoreError::Sqlite(e))
+    }
+}
+
+impl From<StoreError> for ConsistencyError {
+    fn from(e: StoreError) -> Self {
+        ConsistencyError::Database(e)
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> ConsistencyError {
+    let path = path.to_path_buf();
+    move |e| ConsistencyError::Io(path, e)
+}
+
+fn sha256(path: &Path) -> Result<String, ConsistencyError> {
+    checksum(path).map(|sum| to_hex(&sum)).map_err(io_error(path))
+}
+
+/// Whether `path` was last changed before `settled_before`. A time that
+/// cannot be read counts as recent.
+fn is_settled(path: &Path, settled_before: SystemTime) -> bool {
+    fs::symlink_metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified <= settled_before)
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<fs::DirEntry>, ConsistencyError> {
+    let mut entries: Vec<_> = fs::read_dir(dir).map_err(io_error(dir))?
+        .collect::<Result<_, _>>().map_err(io_error(dir))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+/// Folders under the root the server keeps for itself: tax rules, the trash,
+/// and those holding the backups, database or keys.
+fn reserved_directories(settings: &Settings) -> Vec<PathBuf> {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let root = &settings.root_path;
+    let mut reserved = vec![root.join(TAX_RULES_DIRNAME), root.join(TRASH_DIRNAME), settings.backup_path.clone()];
+    for file in [&settings.db_path, &settings.field_key_path, &settings.signing_key_path] {
+        reserved.extend(file.parent().map(Path::to_path_buf));
+    }
+    reserved.iter().map(|path| canonical(path)).collect()
+}
+
+/// The records the check compares with the files, so each backend can
+/// supply them from its own queries.
+pub(crate) trait Records {
+    fn client_ids(&mut self) -> StoreResult<HashSet<i64>>;
+    fn documents(&mut self) -> StoreResult<Vec<Document>>;
+    fn checksums(&mut self) -> StoreResult<HashMap<i64, Option<String>>>;
+    /// `(tax_return_id, client_id)` of every return whose client does not exist.
+    fn returns_without_client(&mut self) -> StoreResult<Vec<(i64, i64)>>;
+    fn is_recorded(&mut self, client_id: i64, file_name: &str) -> StoreResult<bool>;
+    fn get_document(&mut self, document_id: i64) -> StoreResult<Option<Document>>;
+    fn set_checksum(&mut self, document_id: i64, sha256: &str) -> StoreResult<()>;
+}
+
+impl Records for &Connection {
+    fn client_ids(&mut self) -> StoreResult<HashSet<i64>> {
+        Ok(clients::client_ids(self)?)
+    }
+
+    fn documents(&mut self) -> StoreResult<Vec<Document>> {
+        Ok(documents::list_documents(self)?)
+    }
+
+    fn checksums(&mut self) -> StoreResult<HashMap<i64, Option<String>>> {
+        Ok(documents::list_checksums(self)?)
+    }
+
+    fn returns_without_client(&mut self) -> StoreResult<Vec<(i64, i64)>> {
+        Ok(returns::returns_without_client(self)?)
+    }
+
+    fn is_recorded(&mut self, client_id: i64, file_name: &str) -> StoreResult<bool> {
+        Ok(documents::is_recorded(self, client_id, file_name)?)
+    }
+
+    fn get_document(&mut self, document_id: i64) -> StoreResult<Option<Document>> {
+        Ok(documents::get_document(self, document_id)?)
+    }
+
+    fn set_checksum(&mut self, document_id: i64, sha256: &str) -> StoreResult<()> {
+        Ok(documents::set_checksum(self, document_id, sha256)?)
+    }
+}
+
+/// Files and folders changed after this may still be on their way in.
+pub(crate) fn settle_cutoff() -> SystemTime {
+    SystemTime::now() - SETTLE_TIME
+}
+
+/// Checks the database against the client folders under the configured root.
+pub fn check_consistency(conn: &Connection, settings: &Settings) -> Result<ConsistencyReport, ConsistencyError> {
+    let mut records = conn;
+    check_settled(&mut records, settings, settle_cutoff())
+}
+
+/// Checks, counting only folders and files last changed before
+/// `settled_before` as orphans.
+pub(crate) fn check_settled(
+    records: &mut impl Records,
+    settings: &Settings,
+    settled_before: SystemTime,
+) -> Result<ConsistencyReport, ConsistencyError> {
+    let root_path = &settings.root_path;
+    let mut report = ConsistencyReport::default();
+    let client_ids = records.client_ids()?;
+    let checksums = records.checksums()?;
+
+    let mut recorded = HashSet::new();
+    for document in records.documents()? {
+        let document_id = document.document_id.unwrap_or_default();
+        let relative = Path::new(&document.client_id.to_string()).join(&document.file_name);
+        let path = root_path.join(&relative);
+        recorded.insert(relative);
+        if !path.is_file() {
+            report.missing_files.push(MissingFile { document_id, client_id: document.client_id, file_name: document.file_name });
+            continue;
+        }
+        match checksums.get(&document_id).cloned().flatten() {
+            Some(expected) => {
+                let actual = sha256(&path)?;
+                if actual != expected {
+                    report.checksum_mismatches.push(ChecksumMismatch {
+                        document_id,
+                        client_id: document.client_id,
+                        file_name: document.file_name,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+            None => report.unchecksummed.push(document_id),
+        }
+    }
+
+    let reserved = reserved_directories(settings);
+    for folder in sorted_entries(root_path)? {
+        if !folder.file_type().map_err(io_error(&folder.path()))?.is_dir()
+            || folder.path().canonicalize().is_ok_and(|path| reserved.iter().any(|r| r.starts_with(&path)))
+        {
+            continue;
+        }
+        let name = folder.file_name();
+        match name.to_str().and_then(|name| name.parse::<i64>().ok()) {
+            Some(client_id) if client_ids.contains(&client_id) => {}
+            Some(client_id) => {
+                if is_settled(&folder.path(), settled_before) {
+                    let reason = format!("client {} does not exist", client_id);
+                    report.orphan_directories.push(OrphanDirectory { path: PathBuf::from(&name), reason });
+                }
+                continue;
+            }
+            // May hold uploads stored as-is, so it is only reported
+            None => {
+                if is_settled(&folder.path(), settled_before) {
+                    let reason = "client id is not numeric".to_string();
+                    report.orphan_directories.push(OrphanDirectory { path: PathBuf::from(&name), reason });
+                }
+                continue;
+            }
+        }
+
+        for entry in sorted_entries(&folder.path())? {
+            let relative = Path::new(&name).join(entry.file_name());
+            if !is_settled(&entry.path(), settled_before) {
+                continue;
+            }
+            if entry.file_type().map_err(io_error(&entry.path()))?.is_dir() {
+                // Uploads never make folders inside a client's
+                report.orphan_directories.push(OrphanDirectory { path: relative, reason: "inside a client folder".to_string() });
+            } else if !recorded.contains(&relative) {
+                report.orphan_files.push(relative);
+            }
+        }
+    }
+
+    for (tax_return_id, client_id) in records.returns_without_client()? {
+        report.dangling_returns.push(DanglingReturn { tax_return_id, client_id });
+    }
+    Ok(report)
+}
+
+/// Whether a folder or file the check reported is still an orphan: its
+/// client still missing, or its file still unrecorded and not changed since.
+fn still_orphaned(
+    records: &mut impl Records,
+    root_path: &Path,
+    relative: &Path,
+    settled_before: SystemTime,
+) -> Result<bool, ConsistencyError> {
+    if !is_settled(&root_path.join(relative), settled_before) {
+        return Ok(false);
+    }
+    let mut components = relative.components().map(|component| match component {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    });
+    // Folders not named by a client id are never moved
+    let Some(client_id) = components.next().flatten().and_then(|name| name.parse::<i64>().ok()) else {
+        return Ok(false);
+    };
+    if !records.client_ids()?.contains(&client_id) {
+        return Ok(true);
+    }
+    match (components.next(), components.next()) {
+        (Some(Some(file_name)), None) if root_path.join(relative).is_file() => {
+            Ok(!records.is_recorded(client_id, file_name)?)
+        }
+        // Uploads never make folders inside a client's
+        (Some(_), None) => Ok(true),
+        _ => Ok(false),
+    }
+}
+
+/// Checks, then applies the safe fixes: orphan folders and files go to the
+/// trash, and documents without a checksum get one from their current file.
+pub fn repair_consistency(conn: &Connection, settings: &Settings) -> Result<RepairReport, ConsistencyError> {
+    let settled_before = settle_cutoff();
+    let mut records = conn;
+    let report = check_settled(&mut records, settings, settled_before)?;
+    // Holding the write lock keeps uploads from recording a document between
+    // the re-check of an orphan and its move
+    let tx = write_transaction(conn)?;
+    let mut records: &Connection = &tx;
+    let repair = apply_repair(&mut records, settings, &report, settled_before)?;
+    tx.commit()?;
+    Ok(RepairReport { report, repair })
+}
+
+/// Moves the orphans `report` found that are still orphaned and records the
+/// missing checksums. Callers hold their database's write lock, so uploads
+/// cannot record a document between an orphan's re-check and its move.
+pub(crate) fn apply_repair(
+    records: &mut impl Records,
+    settings: &Settings,
+    report: &ConsistencyReport,
+    settled_before: SystemTime,
+) -> Result<RepairSummary, ConsistencyError> {
+    let root_path = &settings.root_path;
+    let mut repair = RepairSummary::default();
+
+    let orphans = report.orphan_directories.iter().map(|orphan| &orphan.path).chain(&report.orphan_files);
+    let mut batch: Option<TrashBatch> = None;
+    for relative in orphans {
+        if !still_orphaned(records, root_path, relative, settled_before)? {
+            continue;
+        }
+        if batch.is_none() {
+            let created = TrashBatch::create(root_path, Utc::now()).map_err(io_error(&root_path.join(TRASH_DIRNAME)))?;
+            repair.trash_batch = Some(created.path().to_path_buf());
+            batch = Some(created);
+        }
+        let source = root_path.join(relative);
+        batch.as_ref().expect("Created above").put(&source, relative).map_err(io_error(&source))?;
+        repair.trashed.push(relative.clone());
+    }
+
+    for document_id in &report.unchecksummed {
+        let Some(document) = records.get_document(*document_id)? else {
+            continue;
+        };
+        let path = root_path.join(document.client_id.to_string()).join(&document.file_name);
+        records.set_checksum(*document_id, &sha256(&path)?)?;
+        repair.checksums_recorded += 1;
+    }
+    Ok(repair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+    use tempfile::tempdir;
+
+    fn record(conn: &Connection, client_id: i64, file_name: &str) -> i64 {
+        documents::upsert_document(conn, &Document {
+            document_id: None,
+            client_id,
+            file_name: file_name.to_string(),
+            document_type: None,
+            tax_year: None,
+            confidence: 0.0,
+            type_overridden: false,
+            extracted_text: None,
+            created_at: None,
+            updated_at: None,
+        }).unwrap()
+    }
+
+    // Backdates a file or folder past the settle time
+    fn settle(path: &Path) {
+        let long_ago = SystemTime::now() - SETTLE_TIME * 2;
+        fs::File::open(path).unwrap().set_modified(long_ago).unwrap();
+    }
+
+    #[test]
+    fn test_check_and_repair() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrations::run(&conn).unwrap();
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let settings = Settings {
+            root_path: root.clone(),
+            db_path: root.join("docstore.db"),
+            field_key_path: root.join("field.key"),
+            signing_key_path: root.join("signing.key"),
+            backup_path: root.join("backups"),
+            ..Default::default()
+        };
+        let client_id: i64 = conn.query_row("SELECT MIN(client_id) FROM clients", [], |row| row.get(0)).unwrap();
+        let client_dir = root.join(client_id.to_string());
+        for folder in [&client_dir, &root.join("backups"), &root.join("tax_rules"), &root.join("9999"), &root.join("upload-abc")] {
+            fs::create_dir_all(folder).unwrap();
+        }
+        fs::write(client_dir.join("w2.pdf"), b"w-2").unwrap();
+        fs::write(client_dir.join("1099.pdf"), b"1099").unwrap();
+        fs::write(client_dir.join("stray.pdf"), b"?").unwrap();
+
+        let w2 = record(&conn, client_id, "w2.pdf");
+        documents::set_checksum(&conn, w2, &sha256(&client_dir.join("w2.pdf")).unwrap()).unwrap();
+        let form_1099 = record(&conn, client_id, "1099.pdf");
+        documents::set_checksum(&conn, form_1099, "0".repeat(64).as_str()).unwrap();
+        let gone = record(&conn, client_id, "bank.pdf");
+        fs::write(client_dir.join("old.pdf"), b"old").unwrap();
+        let old = record(&conn, client_id, "old.pdf");
+        conn.execute("UPDATE tax_returns SET client_id = 4242 WHERE tax_return_id = (SELECT MIN(tax_return_id) FROM tax_returns)", []).unwrap();
+        for path in [client_dir.join("stray.pdf"), root.join("9999"), root.join("upload-abc")] {
+            settle(&path);
+        }
+        // Not yet recorded, as when an upload is being ingested
+        fs::write(client_dir.join("arriving.pdf"), b"new").unwrap();
+
+        let report = check_consistency(&conn, &settings).unwrap();
+        assert!(!report.is_consistent());
+        let orphans: Vec<_> = report.orphan_directories.iter().map(|orphan| orphan.path.clone()).collect();
+        assert_eq!(orphans, vec![PathBuf::from("9999"), PathBuf::from("upload-abc")]);
+        assert_eq!(report.orphan_directories[1].reason, "client id is not numeric");
+        assert_eq!(report.orphan_files, vec![Path::new(&client_id.to_string()).join("stray.pdf")]);
+        assert_eq!(report.missing_files.len(), 1);
+        assert_eq!(report.missing_files[0].document_id, gone);
+        assert_eq!(report.checksum_mismatches.len(), 1);
+        assert_eq!(report.checksum_mismatches[0].document_id, form_1099);
+        assert_eq!(report.unchecksummed, vec![old]);
+        assert_eq!(report.dangling_returns.len(), 1);
+        assert_eq!(report.dangling_returns[0].client_id, 4242);
+
+        let repaired = repair_consistency(&conn, &settings).unwrap();
+        assert_eq!(repaired.repair.trashed.len(), 2);
+        assert_eq!(repaired.repair.checksums_recorded, 1);
+        assert!(!root.join("9999").exists() && !client_dir.join("stray.pdf").exists());
+        assert!(repaired.repair.trash_batch.unwrap().join("9999").is_dir());
+        assert!(root.join("upload-abc").is_dir() && client_dir.join("arriving.pdf").is_file());
+
+        // What needs a person is still reported
+        let after = check_consistency(&conn, &settings).unwrap();
+        let orphans: Vec<_> = after.orphan_directories.iter().map(|orphan| orphan.path.clone()).collect();
+        assert_eq!(orphans, vec![PathBuf::from("upload-abc")]);
+        assert!(after.orphan_files.is_empty() && after.unchecksummed.is_empty());
+        assert_eq!((after.missing_files.len(), after.checksum_mismatches.len(), after.dangling_returns.len()), (1, 1, 1));
+    }
+}
@@ -0,0 +1,26 @@
+use std::fs;
+use std::path::Path;
+
+/// Pulls plain text out of an uploaded file. Text-based PDFs are read through
+/// their content streams; scanned PDFs and other binaries yield `None`.
+pub fn extract_text(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+
+    let text = if bytes.starts_with(b"%PDF") {
+        extract_pdf_text(&bytes)?
+    } else {
+        String::from_utf8(bytes).ok()?
+    };
+
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+fn extract_pdf_text(bytes: &[u8]) -> Option<String> {
+    let document = lopdf::Document::load_mem(bytes).ok()?;
+    let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+    document.extract_text(&pages).ok()
+}
@@ -0,0 +1,247 @@
+use crate::db::{DocumentType, ProposedItem, ProposalTarget};
+use crate::money::Money;
+
+struct BoxMapping {
+    // Box number as printed, for forms that label boxes "Box 1"
+    number: &'static str,
+    label: &'static str,
+    target: ProposalTarget,
+    // Key in `TaxReturn.income_sources`; unused for withholding
+    category: &'static str,
+}
+
+const fn income(number: &'static str, label: &'static str, category: &'static str) -> BoxMapping {
+    BoxMapping { number, label, target: ProposalTarget::IncomeSource, category }
+}
+
+const fn withholding(number: &'static str) -> BoxMapping {
+    BoxMapping {
+        number,
+        label: "federal income tax withheld",
+        target: ProposalTarget::TaxesPaid,
+        category: "federal_withholding",
+    }
+}
+
+const W2_BOXES: &[BoxMapping] = &[income("1", "wages, tips, other compensation", "wages"), withholding("2")];
+const INT_BOXES: &[BoxMapping] = &[income("1", "interest income", "interest"), withholding("4")];
+const DIV_BOXES: &[BoxMapping] = &[
+    income("1a", "total ordinary dividends", "dividends"),
+    income("2a", "total capital gain distr", "capital_gain_distributions"),
+    withholding("4"),
+];
+const NEC_BOXES: &[BoxMapping] = &[income("1", "nonemployee compensation", "self_employment"), withholding("4")];
+const MISC_BOXES: &[BoxMapping] = &[
+    income("1", "rents", "rents"),
+    income("2", "royalties", "royalties"),
+    income("3", "other income", "other_income"),
+    withholding("4"),
+];
+const R_BOXES: &[BoxMapping] = &[income("2a", "taxable amount", "retirement_distributions"), withholding("4")];
+
+// Labels of the boxes not read, so a blank box's scan stops at them
+// instead of taking the next box's amount
+const W2_OTHER_LABELS: &[&str] = &[
+    "social security wages", "social security tax withheld", "medicare wages and tips", "medicare tax withheld",
+    "social security tips", "allocated tips", "dependent care benefits", "nonqualified plans",
+    "state wages, tips, etc", "state income tax",
+];
+const INT_OTHER_LABELS: &[&str] = &[
+    "early withdrawal penalty", "interest on u.s. savings bonds", "investment expenses", "foreign tax paid",
+    "tax-exempt interest", "state tax withheld",
+];
+const DIV_OTHER_LABELS: &[&str] = &[
+    "qualified dividends", "unrecap. sec. 1250 gain", "section 1202 gain", "section 199a dividends",
+    "investment expenses", "foreign tax paid", "nondividend distributions", "exempt-interest dividends",
+    "state tax withheld",
+];
+const NEC_OTHER_LABELS: &[&str] = &["payer made direct sales", "state tax withheld", "state income"];
+const MISC_OTHER_LABELS: &[&str] = &[
+    "fishing boat proceeds", "medical and health care payments", "substitute payments", "crop insurance proceeds",
+    "gross proceeds paid to an attorney", "section 409a deferrals", "state tax withheld", "state income",
+];
+const R_OTHER_LABELS: &[&str] = &[
+    "gross distribution", "taxable amount not determined", "total distribution", "capital gain",
+    "employee contributions", "net unrealized appreciation", "distribution code", "state tax withheld",
+];
+
+// How far past a box label to look for its amount
+const VALUE_WINDOW: usize = 80;
+
+fn boxes_for(document_type: DocumentType) -> (&'static [BoxMapping], &'static [&'static str]) {
+    match document_type {
+        DocumentType::W2 => (W2_BOXES, W2_OTHER_LABELS),
+        DocumentType::Form1099Int => (INT_BOXES, INT_OTHER_LABELS),
+        DocumentType::Form1099Div => (DIV_BOXES, DIV_OTHER_LABELS),
+        DocumentType::Form1099Nec => (NEC_BOXES, NEC_OTHER_LABELS),
+        DocumentType::Form1099Misc => (MISC_BOXES, MISC_OTHER_LABELS),
+        DocumentType::Form1099R => (R_BOXES, R_OTHER_LABELS),
+        _ => (&[], &[]),
+    }
+}
+
+/// Reads the box values of a W-2 or 1099 out of its extracted text. Boxes that
+/// are blank or unreadable are left out; other form types yield nothing.
+pub fn extract_amounts(document_type: DocumentType, text: &str) -> Vec<ProposedItem> {
+    let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let (boxes, other_labels) = boxes_for(document_type);
+    let labels: Vec<&str> = boxes.iter().map(|mapping| mapping.label).chain(other_labels.iter().copied()).collect();
+
+    boxes
+        .iter()
+        .filter_map(|mapping| {
+            let start = find_label(&normalized, mapping.label, &labels)
+                .map(|at| at + mapping.label.len())
+                .or_else(|| {
+                    let marker = format!("box {}", mapping.number);
+                    find_word(&normalized, &marker, 0).map(|at| at + marker.len())
+                })?;
+            let end = next_box(&normalized, start, &labels);
+            let window: String = normalized[start..end].chars().take(VALUE_WINDOW).collect();
+            let amount = first_amount(&window)?;
+            Some(ProposedItem {
+                target: mapping.target,
+                category: mapping.category.to_string(),
+                box_label: mapping.label.to_string(),
+                amount,
+            })
+        })
+        .collect()
+}
+
+/// The first whole-word occurrence of `label` that is not the start of a
+/// longer known label, such as "taxable amount" in "taxable amount not
+/// determined".
+fn find_label(text: &str, label: &str, labels: &[&str]) -> Option<usize> {
+    let mut from = 0;
+    while let Some(at) = find_word(text, label, from) {
+        let longer = labels.iter().any(|other| {
+            other.len() > label.len() && other.starts_with(label) && find_word(&text[at..], other, 0) == Some(0)
+        });
+        if !longer {
+            return Some(at);
+        }
+        from = at + label.len();
+    }
+    None
+}
+
+/// Where the next box begins after `from`: the next known label or "box N"
+/// marker, or the end of the text.
+fn next_box(text: &str, from: usize, labels: &[&str]) -> usize {
+    let next_label = labels.iter().filter_map(|label| find_word(text, label, from)).min();
+    let mut next_marker = None;
+    let mut search = from;
+    while let Some(at) = find_word(text, "box", search) {
+        let rest = &text[at + "box".len()..];
+        if rest.strip_prefix(' ').is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())) {
+            next_marker = Some(at);
+            break;
+        }
+        search = at + "box".len();
+    }
+    next_label.into_iter().chain(next_marker).min().unwrap_or(text.len())
+}
+
+/// Finds `needle` at or after `from` with no letter or digit directly on
+/// either side, so "box 1" does not match "box 10" and "rents" not "parents".
+fn find_word(text: &str, needle: &str, from: usize) -> Option<usize> {
+    let mut search = from;
+    while let Some(offset) = text.get(search..)?.find(needle) {
+        let at = search + offset;
+        let end = at + needle.len();
+        let before = text[..at].chars().next_back();
+        let after = text[end..].chars().next();
+        if !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric) {
+            return Some(at);
+        }
+        search = at + text[at..].chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/// Finds the first token shaped like a printed dollar amount ("$1,234.56").
+/// Requiring cents keeps box numbers and dates from being mistaken for values.
+fn first_amount(window: &str) -> Option<Money> {
+    window
+        .split(|c: char| c.is_whitespace() || c == '$')
+        .map(|token| token.trim_end_matches([',', ';', ')']))
+        .find(|token| {
+            let (whole, cents) = match token.rsplit_once('.') {
+                Some(parts) => parts,
+                None => return false,
+            };
+            !whole.is_empty()
+                && whole.chars().all(|c| c.is_ascii_digit() || c == ',')
+                && whole.starts_with(|c: char| c.is_ascii_digit())
+                && cents.len() == 2
+                && cents.chars().all(|c| c.is_ascii_digit())
+        })
+        .and_then(|token| token.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_w2_amounts() {
+        let text = "2023 Form W-2 Wage and Tax Statement\n\
+                    1 Wages, tips, other compensation $52,000.00\n\
+                    2 Federal income tax withheld 6,100.50\n\
+                    3 Social security wages 52000.00";
+        let items = extract_amounts(DocumentType::W2, text);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].category, "wages");
+        assert_eq!(items[0].amount, Money::from_dollars(52000));
+        assert_eq!(items[1].target, ProposalTarget::TaxesPaid);
+        assert_eq!(items[1].amount, Money::from_cents(610050));
+    }
+
+    #[test]
+    fn test_blank_boxes_are_skipped() {
+        let text = "Form 1099-INT 2023\n1 Interest income 215.42\n4 Federal income tax withheld";
+        let items = extract_amounts(DocumentType::Form1099Int, text);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].category, "interest");
+        assert_eq!(items[0].amount, Money::from_cents(21542));
+    }
+
+    #[test]
+    fn test_blank_box_does_not_take_the_next_amount() {
+        let text = "Form W-2 Wage and Tax Statement 2023\n\
+                    1 Wages, tips, other compensation\n\
+                    2 Federal income tax withheld 6,100.00\n\
+                    3 Social security wages 52,000.00";
+        let items = extract_amounts(DocumentType::W2, text);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].category, "federal_withholding");
+        assert_eq!(items[0].amount, Money::from_dollars(6100));
+    }
+
+    #[test]
+    fn test_box_numbers_match_whole_words() {
+        // Box 1's blank value must not be read from box 10 or box 12
+        let text = "Form W-2 2023\nBox 10 500.00\nBox 12 1,200.00\nBox 1\nBox 2 6,100.00";
+        let items = extract_amounts(DocumentType::W2, text);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].category, "federal_withholding");
+
+        let text = "Form W-2 2023\nBox 10 500.00\nBox 1 52,000.00";
+        let items = extract_amounts(DocumentType::W2, text);
+        assert_eq!(items[0].amount, Money::from_dollars(52000));
+    }
+
+    #[test]
+    fn test_longer_label_is_not_taken_for_a_shorter_one() {
+        let text = "Form 1099-R 2023\n2b Taxable amount not determined X\n2a Taxable amount 4,000.00";
+        let items = extract_amounts(DocumentType::Form1099R, text);
+        assert_eq!(items[0].category, "retirement_distributions");
+        assert_eq!(items[0].amount, Money::from_dollars(4000));
+    }
+
+    #[test]
+    fn test_unsupported_form_yields_nothing() {
+        assert!(extract_amounts(DocumentType::Form1098, "Mortgage interest received 9000.00").is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+pub mod checklist;
+pub mod classify;
+pub mod extract;
+pub mod forms;
+pub mod consistency;
+pub mod trash;
+
+
+use rusqlite::{Connection, Result};
+use std::path::Path;
+
+use crate::config::{checksum, CLASSIFIER_RULES_FILENAME};
+use crate::db::{checklists, documents, proposals, Document, DocumentType, ProposedItem};
+use crate::links::to_hex;
+use classify::CustomRules;
+
+/// Extracts, classifies and records a file that was just saved under
+/// `<root>/<client_id>/<file_name>`.
+pub fn ingest_upload(conn: &Connection, root_path: &Path, client_id: i64, file_name: &str) -> Result<Document> {
+    let mut document = read_document(root_path, client_id, file_name);
+    let document_id = documents::upsert_document(conn, &document)?;
+    document.document_id = Some(document_id);
+    if let Some(sha256) = file_checksum(root_path, &document) {
+        documents::set_checksum(conn, document_id, &sha256)?;
+    }
+    refresh_proposal(conn, &document)?;
+    checklists::mark_received(conn, &document)?;
+    Ok(document)
+}
+
+/// Extracts and classifies a recorded document's file again, for when the
+/// extractor or the classifier rules have changed since it was uploaded.
+/// Returns `None` when the file is gone, leaving the record as it was.
+pub fn reindex_document(conn: &Connection, root_path: &Path, stored: &Document) -> Result<Option<Document>> {
+    let Some(mut document) = reread_document(root_path, stored) else {
+        return Ok(None);
+    };
+    document.document_id = Some(documents::upsert_document(conn, &document)?);
+    refresh_proposal(conn, &document)?;
+    checklists::mark_received(conn, &document)?;
+    Ok(Some(document))
+}
+
+pub(crate) fn read_document(root_path: &Path, client_id: i64, file_name: &str) -> Document {
+    let file_path = root_path.join(client_id.to_string()).join(file_name);
+    let extracted_text = extract::extract_text(&file_path);
+
+    let custom_rules = CustomRules::load(&root_path.join(CLASSIFIER_RULES_FILENAME));
+    let classification = extracted_text
+        .as_deref()
+        .map(|text| classify::classify(text, &custom_rules));
+
+    Document {
+        document_id: None,
+        client_id,
+        file_name: file_name.to_string(),
+        document_type: classification.as_ref().and_then(|c| c.document_type),
+        tax_year: classification.as_ref().and_then(|c| c.tax_year),
+        confidence: classification.as_ref().map_or(0.0, |c| c.confidence),
+        type_overridden: false,
+        extracted_text,
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+/// The SHA-256 of a document's file, kept so the consistency check can tell
+/// if the file changes on disk. `None`, after logging why, if it cannot be read.
+pub(crate) fn file_checksum(root_path: &Path, document: &Document) -> Option<String> {
+    let file_path = root_path.join(document.client_id.to_string()).join(&document.file_name);
+    match checksum(&file_path) {
+        Ok(sha256) => Some(to_hex(&sha256)),
+        Err(e) => {
+            eprintln!("Failed to checksum {}: {}", file_path.display(), e);
+            None
+        }
+    }
+}
+
+/// Reads a recorded document's file again. A preparer's override stands;
+/// only the text under it is refreshed. `None` if the file is gone.
+pub(crate) fn reread_document(root_path: &Path, stored: &Document) -> Option<Document> {
+    if !root_path.join(stored.client_id.to_string()).join(&stored.file_name).is_file() {
+        return None;
+    }
+
+    let mut document = read_document(root_path, stored.client_id, &stored.file_name);
+    if stored.type_overridden {
+        document.document_type = stored.document_type;
+        document.tax_year = stored.tax_year;
+        document.confidence = stored.confidence;
+        document.type_overridden = true;
+    }
+    Some(document)
+}
+
+/// Applies a preparer's override and feeds it back into the custom rules file
+/// so similar documents are classified the same way next time.
+pub fn override_type(
+    conn: &Connection,
+    root_path: &Path,
+    document_id: i64,
+    document_type: DocumentType,
+    tax_year: Option<i32>,
+) -> Result<Option<Document>> {
+    if !documents::override_document_type(conn, document_id, document_type, tax_year)? {
+        return Ok(None);
+    }
+    let document = documents::get_document(conn, document_id)?;
+
+    if let Some(document) = &document {
+        learn_override(root_path, document);
+        refresh_proposal(conn, document)?;
+        checklists::mark_received(conn, document)?;
+    }
+    Ok(document)
+}
+
+/// Teaches the custom rules under the root a document's overridden type.
+pub(crate) fn learn_override(root_path: &Path, document: &Document) {
+    let (Some(document_type), Some(text)) = (document.document_type, document.extracted_text.as_deref()) else {
+        return;
+    };
+    let rules_path = root_path.join(CLASSIFIER_RULES_FILENAME);
+    let mut custom_rules = CustomRules::load(&rules_path);
+    if custom_rules.learn(document_type, text, document.document_id) {
+        if let Err(e) = custom_rules.save(&rules_path) {
+            eprintln!("Failed to save classifier rules: {}", e);
+        }
+    }
+}
+
+/// The W-2/1099 box values to propose for a classified document, or `None`
+/// if it has no type or text to read them from.
+pub(crate) fn proposed_items(document: &Document) -> Option<(i64, Vec<ProposedItem>)> {
+    let (Some(document_id), Some(document_type), Some(text)) = (
+        document.document_id,
+        document.document_type,
+        document.extracted_text.as_deref(),
+    ) else {
+        return None;
+    };
+    Some((document_id, forms::extract_amounts(document_type, text)))
+}
+
+/// Re-reads W-2/1099 box values into a pending income proposal for review.
+fn refresh_proposal(conn: &Connection, document: &Document) -> Result<()> {
+    if let Some((document_id, items)) = proposed_items(document) {
+        proposals::replace_pending_proposal(conn, document_id, document.client_id, document.tax_year, &items)?;
+    }
+    Ok(())
+}
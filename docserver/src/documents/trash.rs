@@ -0,0 +1,137 @@
+//! Files set aside instead of deleted. Each operation that removes files
+//! moves them into a batch of its own, `<root>/.trash/<UTC time>/`, keeping
+//! their paths, so they can be put back by hand until the trash is purged.
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::TRASH_DIRNAME;
+
+const BATCH_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+pub struct TrashBatch {
+    path: PathBuf,
+}
+
+impl TrashBatch {
+    /// Creates a new, empty batch under `root`. Batches started within the
+    /// same second get a numbered suffix.
+    pub fn create(root: &Path, now: DateTime<Utc>) -> io::Result<TrashBatch> {
+        let trash = root.join(TRASH_DIRNAME);
+        fs::create_dir_all(&trash)?;
+        let stamp = now.format(BATCH_FORMAT).to_string();
+        let mut path = trash.join(&stamp);
+        let mut suffix = 1;
+        loop {
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TrashBatch { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    path = trash.join(format!("{}-{}", stamp, suffix));
+                    suffix += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves `source` into the batch as `relative`, returning where it went.
+    /// Files on another filesystem are copied, then removed.
+    pub fn put(&self, source: &Path, relative: &Path) -> io::Result<PathBuf> {
+        let destination = self.path.join(relative);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::rename(source, &destination).is_err() {
+            fs::copy(source, &destination)?;
+            fs::remove_file(source)?;
+        }
+        Ok(destination)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeSummary {
+    pub batches: usize,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Deletes the batches set aside more than `older_than` before `now`.
+/// Directories in the trash that are not batches are left alone.
+pub fn purge_trash(root: &Path, older_than: Duration, now: DateTime<Utc>) -> io::Result<PurgeSummary> {
+    let mut summary = PurgeSummary::default();
+    let trash = root.join(TRASH_DIRNAME);
+    let entries = match fs::read_dir(&trash) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(summary),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stamp = name.split('-').next().unwrap_or_default();
+        let Ok(taken) = NaiveDateTime::parse_from_str(stamp, BATCH_FORMAT) else {
+            continue;
+        };
+        if !entry.file_type()?.is_dir() || now - taken.and_utc() <= older_than {
+            continue;
+        }
+
+        let (files, bytes) = measure(&entry.path())?;
+        fs::remove_dir_all(entry.path())?;
+        summary.batches += 1;
+        summary.files += files;
+        summary.bytes += bytes;
+    }
+    Ok(summary)
+}
+
+fn measure(dir: &Path) -> io::Result<(usize, u64)> {
+    let (mut files, mut bytes) = (0, 0);
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            let (nested_files, nested_bytes) = measure(&entry.path())?;
+            files += nested_files;
+            bytes += nested_bytes;
+        } else {
+            files += 1;
+            bytes += metadata.len();
+        }
+    }
+    Ok((files, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_purge_removes_only_old_batches() {
+        let root = tempdir().unwrap();
+        let now = Utc::now();
+        let source = root.path().join("1").join("w2.pdf");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, b"w-2").unwrap();
+
+        let old = TrashBatch::create(root.path(), now - Duration::days(40)).unwrap();
+        let moved = old.put(&source, Path::new("1/w2.pdf")).unwrap();
+        assert!(!source.exists() && moved.is_file());
+        let recent = TrashBatch::create(root.path(), now).unwrap();
+        assert_ne!(TrashBatch::create(root.path(), now).unwrap().path(), recent.path());
+
+        let summary = purge_trash(root.path(), Duration::days(30), now).unwrap();
+        assert_eq!((summary.batches, summary.files, summary.bytes), (1, 1, 3));
+        assert!(!old.path().exists() && recent.path().exists());
+    }
+}
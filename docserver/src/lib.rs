@@ -1,6 +1,11 @@
 pub mod db;
 pub mod config;
+pub mod documents;
+pub mod links;
+pub mod money;
 pub mod routes;
+pub mod tax;
 
 // Re-export key types to make them easily accessible
 pub use db::{Database, Client, TaxReturn};
+pub use money::Money;
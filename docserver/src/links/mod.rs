@@ -0,0 +1,124 @@
+mod password;
+mod rate_limit;
+mod signing;
+
+pub use password::*;
+pub use rate_limit::*;
+pub use signing::*;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+/// What a link grants. Part of the signed message, so an upload token can
+/// never be replayed as a download token or the other way round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkPurpose {
+    Upload,
+    Download,
+}
+
+impl LinkPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LinkPurpose::Upload => "upload",
+            LinkPurpose::Download => "download",
+        }
+    }
+}
+
+/// The parts of a public link token: `<link_id>.<nonce>.<expires>.<signature>`.
+/// The signature is checked before the database is consulted, so forged or
+/// tampered tokens are turned away cheaply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkToken {
+    pub link_id: i64,
+    pub nonce: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A fresh random nonce identifying one link's token.
+pub fn new_nonce() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// An expiry `duration` from now, truncated to whole seconds so it matches
+/// the timestamp carried in the token exactly.
+pub fn expiry_after(now: DateTime<Utc>, duration: chrono::Duration) -> DateTime<Utc> {
+    let expires_at = now + duration;
+    Utc.timestamp_opt(expires_at.timestamp(), 0).single().unwrap_or(expires_at)
+}
+
+impl LinkToken {
+    fn message(&self, purpose: LinkPurpose) -> String {
+        format!("{}:{}:{}:{}", purpose.as_str(), self.link_id, self.nonce, self.expires_at.timestamp())
+    }
+
+    pub fn encode(&self, key: &SigningKey, purpose: LinkPurpose) -> String {
+        format!(
+            "{}.{}.{}.{}",
+            self.link_id,
+            self.nonce,
+            self.expires_at.timestamp(),
+            key.sign(&self.message(purpose))
+        )
+    }
+
+    /// Parses and authenticates a token. Expiry is checked here too, but the
+    /// database remains the authority on revocation and use counts.
+    pub fn decode(token: &str, key: &SigningKey, purpose: LinkPurpose, now: DateTime<Utc>) -> Option<LinkToken> {
+        let mut parts = token.split('.');
+        let link_id = parts.next()?.parse().ok()?;
+        let nonce = parts.next()?.to_string();
+        let expires_at = Utc.timestamp_opt(parts.next()?.parse().ok()?, 0).single()?;
+        let signature = parts.next()?;
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let token = LinkToken { link_id, nonce, expires_at };
+        if !key.verify(&token.message(purpose), signature) || token.expires_at <= now {
+            return None;
+        }
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_token_round_trip() {
+        let key = SigningKey::generate();
+        let now = Utc::now();
+        let token = LinkToken { link_id: 7, nonce: new_nonce(), expires_at: expiry_after(now, Duration::hours(1)) };
+        let encoded = token.encode(&key, LinkPurpose::Upload);
+
+        assert_eq!(LinkToken::decode(&encoded, &key, LinkPurpose::Upload, now), Some(token));
+        assert_eq!(LinkToken::decode(&encoded, &key, LinkPurpose::Download, now), None);
+        assert_eq!(LinkToken::decode(&encoded, &SigningKey::generate(), LinkPurpose::Upload, now), None);
+        assert_eq!(LinkToken::decode(&encoded, &key, LinkPurpose::Upload, now + Duration::hours(2)), None);
+
+        let tampered = encoded.replacen("7.", "8.", 1);
+        assert_eq!(LinkToken::decode(&tampered, &key, LinkPurpose::Upload, now), None);
+    }
+
+    #[test]
+    fn test_password_hashing() {
+        let hash = hash_password("correct horse");
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::default();
+        let window = std::time::Duration::from_secs(60);
+        assert!(limiter.check("ip:1", 2, window));
+        assert!(limiter.check("ip:1", 2, window));
+        assert!(!limiter.check("ip:1", 2, window));
+        assert!(limiter.check("ip:2", 2, window));
+    }
+}
@@ -0,0 +1,27 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+/// Hashes a password into a self-describing PHC string (algorithm, parameters
+/// and salt included), so the parameters can be raised later.
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes()).expect("A 16-byte salt is always valid");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Argon2 with default parameters accepts any password")
+        .to_string()
+}
+
+/// A hash of a password nobody has, checked against when there is no stored
+/// hash, so an unknown account takes as long to turn away as a known one.
+pub fn dummy_password_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| hash_password(&Uuid::new_v4().to_string()))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
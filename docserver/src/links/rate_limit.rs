@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sliding-window request counter for the public, unauthenticated endpoints.
+#[derive(Default)]
+pub struct RateLimiter {
+    hits: Mutex<HashMap<String, Vec<Instant>>>,
+}
+
+impl RateLimiter {
+    /// Records a request under `key` and returns whether it is within `limit`
+    /// requests per `window`. Rejected requests are not counted.
+    pub fn check(&self, key: &str, limit: usize, window: Duration) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().expect("Rate limiter lock poisoned");
+
+        // Forget keys that have gone quiet so the map does not grow unbounded
+        hits.retain(|_, times| times.last().is_some_and(|last| now.duration_since(*last) < window));
+
+        let times = hits.entry(key.to_string()).or_default();
+        times.retain(|time| now.duration_since(*time) < window);
+        if times.len() >= limit {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
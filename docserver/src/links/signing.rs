@@ -0,0 +1,99 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Server-side secret used to sign the tokens in public links.
+#[derive(Clone)]
+pub struct SigningKey {
+    key: Vec<u8>,
+}
+
+impl Default for SigningKey {
+    fn default() -> Self {
+        SigningKey::generate()
+    }
+}
+
+impl SigningKey {
+    pub fn generate() -> SigningKey {
+        let mut key = Vec::with_capacity(32);
+        key.extend_from_slice(Uuid::new_v4().as_bytes());
+        key.extend_from_slice(Uuid::new_v4().as_bytes());
+        SigningKey { key }
+    }
+
+    /// Reads the hex-encoded key at `path`, creating it on first use so links
+    /// stay valid across restarts.
+    pub fn load_or_create(path: &Path) -> io::Result<SigningKey> {
+        if path.exists() {
+            let contents = fs::read_to_string(path)?;
+            let key = from_hex(contents.trim()).filter(|key| key.len() >= 32).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "signing key file is not a 32-byte hex key")
+            })?;
+            return Ok(SigningKey { key });
+        }
+
+        let signing_key = SigningKey::generate();
+        fs::write(path, to_hex(&signing_key.key))?;
+        restrict_permissions(path)?;
+        Ok(signing_key)
+    }
+
+    pub fn sign(&self, message: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(message.as_bytes());
+        to_hex(&mac.finalize().into_bytes())
+    }
+
+    /// Checks a signature in constant time.
+    pub fn verify(&self, message: &str, signature: &str) -> bool {
+        let Some(signature) = from_hex(signature) else {
+            return false;
+        };
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(message.as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+/// A random bearer token for a session; only its digest is stored.
+pub fn new_session_token() -> String {
+    to_hex(&SigningKey::generate().key)
+}
+
+/// SHA-256 of a bearer token, as stored and looked up.
+pub fn token_digest(token: &str) -> String {
+    use sha2::Digest;
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+/// Makes a file with secrets in it readable by its owner only.
+#[cfg(unix)]
+pub fn restrict_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+pub fn restrict_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
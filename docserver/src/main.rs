@@ -1,26 +1,120 @@
-mod config;
-mod db;
-mod routes;
-
 #[cfg(test)]
+#[allow(clippy::module_inception, clippy::len_zero)]
 mod tests;
 
-use rocket::{launch, routes};
-use crate::config::AppState;
+use rocket::{launch, routes, Build, Rocket};
+use docserver::config::{AppState, Settings};
+use docserver::routes;
 
 #[launch]
 pub fn rocket() -> _ {
+    // Configuration problems stop the server before it listens
+    let state = Settings::load().and_then(AppState::new).and_then(|state| {
+        state.share_field_key()?;
+        Ok(state)
+    }).unwrap_or_else(|e| {
+        eprintln!("Configuration error: {}", e);
+        std::process::exit(2);
+    });
+    state.start_backup_schedule();
+    server(state)
+}
+
+pub fn server(state: AppState) -> Rocket<Build> {
     rocket::build()
-        .manage(AppState::new())
+        .manage(state)
         .mount("/", routes![
             routes::index, 
             routes::get_file, 
             routes::upload_files,
+            routes::create_upload_link,
+            routes::list_upload_links,
+            routes::revoke_upload_link,
+            routes::public_upload,
+            routes::create_share_link,
+            routes::list_share_links,
+            routes::list_share_link_accesses,
+            routes::revoke_share_link,
+            routes::public_download,
+            routes::public_download_with_password,
             routes::list_clients,
             routes::get_client,
             routes::list_client_files,
+            routes::add_household_member,
+            routes::list_household,
+            routes::remove_household_member,
+            routes::create_portal_user,
+            routes::list_portal_users,
+            routes::list_contact_change_requests,
+            routes::approve_contact_change,
+            routes::reject_contact_change,
+            routes::list_client_documents,
+            routes::get_document,
+            routes::generate_checklist,
+            routes::get_checklist,
+            routes::list_outstanding_documents,
+            routes::override_document_type,
+            routes::get_document_proposal,
+            routes::list_proposals,
+            routes::accept_proposal,
+            routes::reject_proposal,
             routes::list_returns,
-            routes::get_return
+            routes::amend_return,
+            routes::get_return_chain,
+            routes::get_amendment_diff,
+            routes::transition_return,
+            routes::get_return_history,
+            routes::set_return_household,
+            routes::list_return_line_items,
+            routes::add_line_item,
+            routes::update_line_item,
+            routes::delete_line_item,
+            routes::search_line_items,
+            routes::list_line_item_categories,
+            routes::get_year_comparison,
+            routes::get_year_comparison_csv,
+            routes::add_estimated_payment,
+            routes::list_estimated_payments,
+            routes::delete_estimated_payment,
+            routes::get_safe_harbor,
+            routes::list_clients_behind,
+            routes::get_tax_profile,
+            routes::set_tax_profile,
+            routes::get_return_deadlines,
+            routes::add_return_extension,
+            routes::remove_return_extension,
+            routes::list_deadlines,
+            routes::deadline_calendar,
+            routes::list_client_returns,
+            routes::add_state_return,
+            routes::list_state_returns,
+            routes::get_return_household,
+            routes::get_workflow_board,
+            routes::get_return,
+            routes::get_return_provenance,
+            routes::get_return_computation,
+            routes::list_tax_rules,
+            routes::get_tax_rules,
+            routes::reload_tax_rules,
+            routes::get_database_metrics
+        ])
+        .mount("/portal", routes![
+            routes::portal_login,
+            routes::portal_logout,
+            routes::portal_me,
+            routes::portal_returns,
+            routes::portal_files,
+            routes::portal_file,
+            routes::portal_request_contact_change,
+            routes::portal_contact_changes
+        ])
+        .mount("/config", routes![routes::get_root_path, routes::set_root_path, routes::get_relocation])
+        .mount("/admin", routes![
+            routes::list_backups,
+            routes::create_backup,
+            routes::verify_backup,
+            routes::restore_backup,
+            routes::check_consistency,
+            routes::repair_consistency
         ])
-        .mount("/config", routes![routes::get_root_path, routes::set_root_path])
 }
@@ -0,0 +1,254 @@
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// An exact amount of US dollars, held as integer cents.
+///
+/// Stored in SQLite as an INTEGER number of cents and serialized to JSON as a
+/// decimal string (`"1234.56"`) so clients never round-trip through floats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMoneyError(String);
+
+impl fmt::Display for ParseMoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid amount: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseMoneyError {}
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    pub const fn from_dollars(dollars: i64) -> Self {
+        Money(dollars * 100)
+    }
+
+    pub const fn cents(&self) -> i64 {
+        self.0
+    }
+
+    pub const fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub const fn abs(&self) -> Money {
+        Money(self.0.abs())
+    }
+
+    /// Converts a legacy floating point amount, rounding to the nearest cent.
+    pub fn from_f64_lossy(amount: f64) -> Self {
+        Money((amount * 100.0).round() as i64)
+    }
+
+    /// For display-only ratios such as percentage changes; never for storage.
+    pub fn to_f64(&self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+impl FromStr for Money {
+    type Err = ParseMoneyError;
+
+    /// Accepts `1234`, `1234.5`, `-1,234.56` and `$1,234.56`. More than two
+    /// decimal places is rejected rather than silently rounded.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseMoneyError(s.to_string());
+        let trimmed = s.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        let unsigned = unsigned.strip_prefix('$').unwrap_or(unsigned).replace(',', "");
+
+        let (whole, fraction) = match unsigned.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (unsigned.as_str(), ""),
+        };
+        if (whole.is_empty() && fraction.is_empty())
+            || fraction.len() > 2
+            || !whole.chars().all(|c| c.is_ascii_digit())
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(error());
+        }
+
+        let dollars: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| error())? };
+        let cents: i64 = match fraction.len() {
+            0 => 0,
+            1 => fraction.parse::<i64>().map_err(|_| error())? * 10,
+            _ => fraction.parse().map_err(|_| error())?,
+        };
+        let total = dollars
+            .checked_mul(100)
+            .and_then(|d| d.checked_add(cents))
+            .ok_or_else(error)?;
+
+        Ok(Money(if negative { -total } else { total }))
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MoneyVisitor;
+
+        impl Visitor<'_> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a decimal amount such as \"1234.56\"")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Money, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Money, E> {
+                value.checked_mul(100).map(Money).ok_or_else(|| E::custom("amount out of range"))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Money, E> {
+                i64::try_from(value)
+                    .ok()
+                    .and_then(|v| v.checked_mul(100))
+                    .map(Money)
+                    .ok_or_else(|| E::custom("amount out of range"))
+            }
+
+            // Older clients and stored JSON send plain numbers
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Money, E> {
+                Ok(Money::from_f64_lossy(value))
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+impl ToSql for Money {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+impl FromSql for Money {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(cents) => Ok(Money(cents)),
+            ValueRef::Real(amount) => Ok(Money::from_f64_lossy(amount)),
+            ValueRef::Text(_) => value.as_str()?
+                .parse()
+                .map_err(|e| FromSqlError::Other(Box::new(e))),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("1234.56".parse::<Money>().unwrap(), Money::from_cents(123456));
+        assert_eq!("$1,234.5".parse::<Money>().unwrap(), Money::from_cents(123450));
+        assert_eq!("-0.05".parse::<Money>().unwrap(), Money::from_cents(-5));
+        assert_eq!("12".parse::<Money>().unwrap(), Money::from_dollars(12));
+        assert!("1.234".parse::<Money>().is_err());
+        assert!("abc".parse::<Money>().is_err());
+        assert!(".".parse::<Money>().is_err());
+
+        assert_eq!(Money::from_cents(-123456).to_string(), "-1234.56");
+        assert_eq!(Money::from_cents(5).to_string(), "0.05");
+    }
+
+    #[test]
+    fn test_sums_are_exact() {
+        let total: Money = ["0.10", "0.20", "0.30"].iter().map(|s| s.parse::<Money>().unwrap()).sum();
+        assert_eq!(total, "0.60".parse().unwrap());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let amount: Money = serde_json::from_str("\"10000.01\"").unwrap();
+        assert_eq!(serde_json::to_string(&amount).unwrap(), "\"10000.01\"");
+
+        // Legacy numeric JSON is still accepted
+        let legacy: Money = serde_json::from_str("8000.1").unwrap();
+        assert_eq!(legacy, Money::from_cents(800010));
+    }
+}
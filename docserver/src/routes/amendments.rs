@@ -0,0 +1,63 @@
+use rocket::{get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::returns::{AmendError, Amendment};
+use crate::db::TaxReturn;
+use crate::tax::{self, ReturnDiff};
+use super::error;
+
+/// Files an amended return (1040-X) against the current effective return.
+#[post("/returns/<tax_return_id>/amend", format = "json", data = "<amendment>")]
+pub async fn amend_return(
+    state: &State<AppState>,
+    tax_return_id: i64,
+    amendment: Json<Amendment>,
+) -> Result<Json<TaxReturn>, status::Custom<Json<ApiResponse>>> {
+    let amendment = amendment.into_inner();
+    state.with_repository(move |repo| repo.amend_return(tax_return_id, amendment)).await
+        .map(Json)
+        .map_err(|e| {
+            let code = match e {
+                AmendError::NotFound => Status::NotFound,
+                AmendError::Superseded(_) => Status::Conflict,
+                AmendError::MissingReason | AmendError::UnknownCategory { .. } => Status::UnprocessableEntity,
+                AmendError::Database(_) => Status::InternalServerError,
+            };
+            error(code, e.to_string())
+        })
+}
+
+/// The original return and every amendment filed against it, in order.
+#[get("/returns/<tax_return_id>/chain")]
+pub async fn get_return_chain(state: &State<AppState>, tax_return_id: i64) -> Option<Json<Vec<TaxReturn>>> {
+    let chain = state.with_repository(move |repo| repo.return_chain(tax_return_id)).await
+        .expect("Failed to execute query");
+    if chain.is_empty() {
+        None
+    } else {
+        Some(Json(chain))
+    }
+}
+
+/// Every figure of an amended return compared with the original it amends.
+#[get("/returns/<tax_return_id>/amendment-diff")]
+pub async fn get_amendment_diff(
+    state: &State<AppState>,
+    tax_return_id: i64,
+) -> Result<Json<ReturnDiff>, status::Custom<Json<ApiResponse>>> {
+    let chain = state.with_repository(move |repo| repo.return_chain(tax_return_id)).await
+        .expect("Failed to execute query");
+    let position = chain.iter().position(|r| r.tax_return_id == Some(tax_return_id))
+        .ok_or_else(|| error(Status::NotFound, format!("Tax return {} not found", tax_return_id)))?;
+    if position == 0 {
+        return Err(error(
+            Status::UnprocessableEntity,
+            format!("Tax return {} is an original return, not an amendment", tax_return_id),
+        ));
+    }
+
+    Ok(Json(tax::diff_returns(&chain[0], &chain[position])))
+}
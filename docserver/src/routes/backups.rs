@@ -0,0 +1,67 @@
+use rocket::{get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::config::{self, AppState, ApiResponse, BackupError, BackupInfo, BackupManifest, BackupSummary, RestoreReport};
+use super::error;
+
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    // An empty or missing directory to restore into
+    target: String,
+}
+
+fn backup_error(e: BackupError) -> status::Custom<Json<ApiResponse>> {
+    let code = match &e {
+        BackupError::NotFound(_) => Status::NotFound,
+        BackupError::InProgress | BackupError::NotSqlite | BackupError::TargetNotEmpty(_) => Status::Conflict,
+        BackupError::NotADirectory(_) => Status::BadRequest,
+        BackupError::Invalid(_)
+        | BackupError::Missing(_)
+        | BackupError::ChecksumMismatch(_)
+        | BackupError::NewerSchema(_) => Status::UnprocessableEntity,
+        BackupError::Io(..) | BackupError::Database(_) | BackupError::Inventory(_) | BackupError::Changed(_) => {
+            Status::InternalServerError
+        }
+    };
+    error(code, e.to_string())
+}
+
+/// Archives in the backup directory, newest first.
+#[get("/backups")]
+pub async fn list_backups(state: &State<AppState>) -> Result<Json<Vec<BackupInfo>>, status::Custom<Json<ApiResponse>>> {
+    let backup_path = state.settings().backup_path.clone();
+    config::unblock(move || config::list_backups(&backup_path)).await.map(Json).map_err(backup_error)
+}
+
+/// Takes a backup now, then removes archives beyond `backup_keep`.
+#[post("/backups")]
+pub async fn create_backup(state: &State<AppState>) -> Result<Json<BackupSummary>, status::Custom<Json<ApiResponse>>> {
+    state.backup_in_background().await.map(Json).map_err(backup_error)
+}
+
+/// Reads a whole archive back and checks it against its manifest.
+#[post("/backups/<name>/verify")]
+pub async fn verify_backup(state: &State<AppState>, name: &str) -> Result<Json<BackupManifest>, status::Custom<Json<ApiResponse>>> {
+    let backup_path = state.settings().backup_path.clone();
+    let archive = config::find_backup(&backup_path, name).map_err(backup_error)?;
+    config::unblock(move || config::verify_backup(&archive)).await.map(Json).map_err(backup_error)
+}
+
+/// Restores an archive into an empty directory. The running server keeps
+/// its own root; the restored one is opened by starting a server with
+/// `--root-path`.
+#[post("/backups/<name>/restore", format = "json", data = "<request>")]
+pub async fn restore_backup(
+    state: &State<AppState>,
+    name: &str,
+    request: Json<RestoreRequest>,
+) -> Result<Json<RestoreReport>, status::Custom<Json<ApiResponse>>> {
+    let backup_path = state.settings().backup_path.clone();
+    let archive = config::find_backup(&backup_path, name).map_err(backup_error)?;
+    let target = PathBuf::from(&request.target);
+    config::unblock(move || config::restore_backup(&archive, &target)).await.map(Json).map_err(backup_error)
+}
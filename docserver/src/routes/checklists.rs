@@ -0,0 +1,34 @@
+use rocket::{get, post, State};
+use rocket::serde::json::Json;
+
+use crate::config::AppState;
+use crate::db::{ChecklistItem, ChecklistStatus};
+
+/// Generates (or tops up) a client's checklist for a year from the prior
+/// year's return and documents. Safe to run again as the prior year changes.
+#[post("/clients/<client_id>/checklists/<tax_year>/generate")]
+pub async fn generate_checklist(state: &State<AppState>, client_id: i64, tax_year: i32) -> Json<Vec<ChecklistItem>> {
+    let items = state.with_repository(move |repo| repo.generate_checklist(client_id, tax_year)).await;
+    Json(items.expect("Failed to generate checklist"))
+}
+
+#[get("/clients/<client_id>/checklists/<tax_year>")]
+pub async fn get_checklist(state: &State<AppState>, client_id: i64, tax_year: i32) -> Json<Vec<ChecklistItem>> {
+    let items = state.with_repository(move |repo| repo.list_checklist_items(client_id, Some(tax_year), None)).await;
+    Json(items.expect("Failed to execute query"))
+}
+
+/// Checklist items still waiting on an upload, across every year unless
+/// `tax_year` is given.
+#[get("/clients/<client_id>/outstanding-documents?<tax_year>")]
+pub async fn list_outstanding_documents(
+    state: &State<AppState>,
+    client_id: i64,
+    tax_year: Option<i32>,
+) -> Json<Vec<ChecklistItem>> {
+    let items = state
+        .with_repository(move |repo| repo.list_checklist_items(client_id, tax_year, Some(ChecklistStatus::Pending)))
+        .await
+        .expect("Failed to execute query");
+    Json(items)
+}
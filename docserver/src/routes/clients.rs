@@ -1,174 +1,95 @@
 use rocket::{get, State};
+use rocket::http::Status;
+use rocket::response::status;
 use rocket::serde::json::Json;
-use crate::config::AppState;
-use crate::db::{Client, TaxReturn};
+use serde::Serialize;
+use std::path::Path;
+use crate::config::{AppState, ApiResponse};
+use crate::db::{Client, Document, Jurisdiction, ReturnYear, TaxReturn};
+use super::error;
 
 #[get("/clients")]
 pub async fn list_clients(state: &State<AppState>) -> Json<Vec<Client>> {
-    let db_lock = state.get_db().expect("Database connection should be available");
-    let db = db_lock.as_ref().expect("Database should be initialized");
-    
-    let conn = db.conn.lock().expect("Failed to acquire database connection lock");
-    // Execute query to get all clients
-    let mut stmt = conn.prepare("
-        SELECT client_id, first_name, last_name, social_security_number, 
-               address, phone_number, email, created_at, updated_at 
-        FROM clients
-    ").expect("Failed to prepare statement");
-
-    let clients = stmt.query_map([], |row| {
-        Ok(Client {
-            client_id: Some(row.get(0)?),
-            first_name: row.get(1)?,
-            last_name: row.get(2)?,
-            social_security_number: row.get(3)?,
-            address: row.get(4)?,
-            phone_number: row.get(5)?,
-            email: row.get(6)?,
-            created_at: Some(row.get(7)?),
-            updated_at: Some(row.get(8)?),
-        })
-    }).expect("Failed to execute query")
-    .filter_map(Result::ok)
-    .collect();
-
-    Json(clients)
+    Json(state.with_repository(|repo| repo.list_clients()).await.expect("Failed to execute query"))
 }
 
 #[get("/clients/<client_id>")]
 pub async fn get_client(state: &State<AppState>, client_id: i64) -> Option<Json<Client>> {
-    let db_lock = state.get_db().expect("Database connection should be available");
-    let db = db_lock.as_ref().expect("Database should be initialized");
-    
-    let conn = db.conn.lock().expect("Failed to acquire database connection lock");
-    // Execute query to get specific client
-    let mut stmt = conn.prepare("
-        SELECT client_id, first_name, last_name, social_security_number, 
-               address, phone_number, email, created_at, updated_at 
-        FROM clients 
-        WHERE client_id = ?
-    ").expect("Failed to prepare statement");
-
-    let client = stmt.query_row([client_id], |row| {
-        Ok(Client {
-            client_id: Some(row.get(0)?),
-            first_name: row.get(1)?,
-            last_name: row.get(2)?,
-            social_security_number: row.get(3)?,
-            address: row.get(4)?,
-            phone_number: row.get(5)?,
-            email: row.get(6)?,
-            created_at: Some(row.get(7)?),
-            updated_at: Some(row.get(8)?),
-        })
-    }).ok();
+    state.with_repository(move |repo| repo.get_client(client_id)).await.ok().flatten().map(Json)
+}
 
-    client.map(Json)
+/// A file in a client's folder with the document record classifying it.
+#[derive(Serialize)]
+pub struct ClientFile {
+    file_name: String,
+    // None for files that were never recorded, such as ones copied in by hand
+    document: Option<Document>,
 }
 
 #[get("/clients/<client_id>/files")]
-pub async fn list_client_files(state: &State<AppState>, client_id: i64) -> Json<Vec<String>> {
-    let root_path = state.get_root_path().unwrap();
+pub async fn list_client_files(state: &State<AppState>, client_id: i64) -> Json<Vec<ClientFile>> {
+    let files = state.with_root(move |root_path, repo| {
+        let mut documents = repo.list_client_documents(client_id).expect("Failed to execute query");
+        client_file_names(root_path, client_id).into_iter().map(|file_name| {
+            let document = documents.iter().position(|d| d.file_name == file_name).map(|i| documents.swap_remove(i));
+            ClientFile { file_name, document }
+        }).collect()
+    }).await;
+    Json(files.expect("Root path should be set"))
+}
+
+/// Names of the files in a client's folder under the root path.
+pub(crate) fn client_file_names(root_path: &Path, client_id: i64) -> Vec<String> {
     let client_path = root_path.join(client_id.to_string());
     
     let mut files = Vec::new();
     
     if let Ok(entries) = std::fs::read_dir(&client_path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_file() {
-                        if let Ok(file_name) = entry.file_name().into_string() {
-                            files.push(file_name);
-                        }
+        for entry in entries.flatten() {
+            if let Ok(file_type) = entry.file_type() {
+                if file_type.is_file() {
+                    if let Ok(file_name) = entry.file_name().into_string() {
+                        files.push(file_name);
                     }
                 }
             }
         }
     }
-    Json(files)
-}
-fn map_tax_return(row: &rusqlite::Row) -> rusqlite::Result<TaxReturn> {
-    Ok(TaxReturn {
-        tax_return_id: Some(row.get(0)?),
-        client_id: row.get(1)?,
-        tax_year: row.get(2)?,
-        filing_status: row.get(3)?,
-        income_sources: serde_json::from_str(&row.get::<_, String>(4)?).unwrap_or_default(),
-        deductions: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or_default(),
-        credits: serde_json::from_str(&row.get::<_, String>(6)?).unwrap_or_default(),
-        taxes_paid: row.get(7)?,
-        tax_liability: row.get(8)?,
-        refund_or_amount_due: row.get(9)?,
-        created_at: Some(row.get(10)?),
-        updated_at: Some(row.get(11)?),
-    })
+    files
 }
 
-#[get("/returns?<client_id>")]
-pub async fn list_returns(state: &State<AppState>, client_id: Option<i64>) -> Json<Vec<TaxReturn>> {
-    let db_lock = state.get_db().expect("Database connection should be available");
-    let db = db_lock.as_ref().expect("Database should be initialized");
-    let conn = db.conn.lock().expect("Failed to acquire database connection lock");
-
-    let query = if let Some(_cid) = client_id {
-        "SELECT tax_return_id, client_id, tax_year, filing_status, income_sources, 
-                deductions, credits, taxes_paid, tax_liability, refund_or_amount_due,
-                created_at, updated_at 
-         FROM tax_returns 
-         WHERE client_id = ?"
-    } else {
-        "SELECT tax_return_id, client_id, tax_year, filing_status, income_sources, 
-                deductions, credits, taxes_paid, tax_liability, refund_or_amount_due,
-                created_at, updated_at 
-         FROM tax_returns"
+/// Lists the effective return for each year and jurisdiction; `chain=true`
+/// includes the superseded returns of each amendment chain as well.
+/// `jurisdiction` is `federal` or a state code.
+#[get("/returns?<client_id>&<jurisdiction>&<chain>")]
+pub async fn list_returns(
+    state: &State<AppState>,
+    client_id: Option<i64>,
+    jurisdiction: Option<&str>,
+    chain: Option<bool>,
+) -> Result<Json<Vec<TaxReturn>>, status::Custom<Json<ApiResponse>>> {
+    let jurisdiction = match jurisdiction {
+        Some(value) => Some(Jurisdiction::parse(value).ok_or_else(|| {
+            error(Status::BadRequest, format!("Unknown jurisdiction {:?}; use federal or a state code", value))
+        })?),
+        None => None,
     };
 
-    let mut stmt = conn.prepare(query).expect("Failed to prepare statement");
-
-    let returns_iter = if let Some(cid) = client_id {
-        stmt.query_map([cid], map_tax_return)
-    } else {
-        stmt.query_map([], map_tax_return)
-    }.expect("Failed to execute query");
-
-    let returns: Vec<TaxReturn> = returns_iter
-        .filter_map(Result::ok)
-        .collect();
+    let returns = state
+        .with_repository(move |repo| repo.list_tax_returns(client_id, jurisdiction.as_ref(), chain.unwrap_or(false)))
+        .await
+        .expect("Failed to execute query");
+    Ok(Json(returns))
+}
 
-    Json(returns)
+/// A client's effective returns grouped by year, newest first, each state
+/// return next to the federal return it belongs to.
+#[get("/clients/<client_id>/returns")]
+pub async fn list_client_returns(state: &State<AppState>, client_id: i64) -> Json<Vec<ReturnYear>> {
+    Json(state.with_repository(move |repo| repo.return_years(client_id)).await.expect("Failed to execute query"))
 }
 
 #[get("/returns/<tax_return_id>")]
 pub async fn get_return(state: &State<AppState>, tax_return_id: i64) -> Option<Json<TaxReturn>> {
-    let db_lock = state.get_db().expect("Database connection should be available");
-    let db = db_lock.as_ref().expect("Database should be initialized");
-    let conn = db.conn.lock().expect("Failed to acquire database connection lock");
-
-    let mut stmt = conn.prepare("
-        SELECT tax_return_id, client_id, tax_year, filing_status, income_sources, 
-               deductions, credits, taxes_paid, tax_liability, refund_or_amount_due,
-               created_at, updated_at 
-        FROM tax_returns 
-        WHERE tax_return_id = ?
-    ").expect("Failed to prepare statement");
-
-    let tax_return = stmt.query_row([tax_return_id], |row| {
-        Ok(TaxReturn {
-            tax_return_id: Some(row.get(0)?),
-            client_id: row.get(1)?,
-            tax_year: row.get(2)?,
-            filing_status: row.get(3)?, income_sources: serde_json::from_str(&row.get::<_, String>(4)?).unwrap_or_default(),
-            deductions: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or_default(),
-            credits: serde_json::from_str(&row.get::<_, String>(6)?).unwrap_or_default(),
-            taxes_paid: row.get(7)?,
-            tax_liability: row.get(8)?,
-            refund_or_amount_due: row.get(9)?,
-            created_at: Some(row.get(10)?),
-            updated_at: Some(row.get(11)?),
-        })
-    }).ok();
-
-    tax_return.map(Json)
+    state.with_repository(move |repo| repo.get_tax_return(tax_return_id)).await.ok().flatten().map(Json)
 }
-
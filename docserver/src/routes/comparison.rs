@@ -0,0 +1,98 @@
+use rocket::{get, State};
+use rocket::http::{ContentType, Status};
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::{Jurisdiction, LineItem, OfficeRepository, StoreResult, TaxReturn};
+use crate::tax::{self, ComparedYear, YearComparison};
+use super::error;
+
+/// Picks the client's effective returns to compare, oldest year first:
+/// `from` and `to` together, otherwise the latest `last` years (default 2).
+fn compared_returns(
+    effective: Vec<TaxReturn>,
+    from: Option<i32>,
+    to: Option<i32>,
+    last: Option<usize>,
+) -> Result<Vec<TaxReturn>, status::Custom<Json<ApiResponse>>> {
+    // One effective return per year, newest year first
+    let mut selected: Vec<TaxReturn> = match (from, to) {
+        (Some(from), Some(to)) => {
+            if from == to {
+                return Err(error(Status::UnprocessableEntity, "from and to must be different years".to_string()));
+            }
+            let pair: Vec<TaxReturn> = effective.into_iter()
+                .filter(|r| r.tax_year == from || r.tax_year == to)
+                .collect();
+            if let Some(year) = [from, to].into_iter().find(|year| !pair.iter().any(|r| r.tax_year == *year)) {
+                return Err(error(Status::NotFound, format!("No effective return for {}", year)));
+            }
+            pair
+        }
+        (None, None) => {
+            let last = last.unwrap_or(2);
+            if last < 2 {
+                return Err(error(Status::UnprocessableEntity, "last must be at least 2".to_string()));
+            }
+            if effective.len() < 2 {
+                return Err(error(
+                    Status::UnprocessableEntity,
+                    "The client needs returns for at least two years to compare".to_string(),
+                ));
+            }
+            effective.into_iter().take(last).collect()
+        }
+        _ => return Err(error(Status::BadRequest, "from and to must be given together".to_string())),
+    };
+    selected.reverse();
+    Ok(selected)
+}
+
+fn build_comparison(
+    repo: &dyn OfficeRepository,
+    client_id: i64,
+    from: Option<i32>,
+    to: Option<i32>,
+    last: Option<usize>,
+) -> Result<YearComparison, status::Custom<Json<ApiResponse>>> {
+    let effective = repo.list_tax_returns(Some(client_id), Some(&Jurisdiction::Federal), false).expect("Failed to execute query");
+    if effective.is_empty() {
+        return Err(error(Status::NotFound, format!("Client {} has no tax returns", client_id)));
+    }
+    let selected = compared_returns(effective, from, to, last)?;
+    let items: Vec<Vec<LineItem>> = selected.iter()
+        .map(|r| repo.list_line_items(r.tax_return_id.unwrap_or_default(), None))
+        .collect::<StoreResult<_>>()
+        .expect("Failed to execute query");
+
+    let years: Vec<ComparedYear> = selected.iter().zip(&items)
+        .map(|(tax_return, line_items)| ComparedYear { tax_return, line_items })
+        .collect();
+    Ok(tax::compare_years(&years))
+}
+
+/// Compares a client's returns year over year, e.g. `?from=2022&to=2023` or `?last=3`.
+#[get("/clients/<client_id>/comparison?<from>&<to>&<last>")]
+pub async fn get_year_comparison(
+    state: &State<AppState>,
+    client_id: i64,
+    from: Option<i32>,
+    to: Option<i32>,
+    last: Option<usize>,
+) -> Result<Json<YearComparison>, status::Custom<Json<ApiResponse>>> {
+    state.with_repository(move |repo| build_comparison(repo, client_id, from, to, last)).await.map(Json)
+}
+
+/// The same comparison as a CSV download.
+#[get("/clients/<client_id>/comparison.csv?<from>&<to>&<last>")]
+pub async fn get_year_comparison_csv(
+    state: &State<AppState>,
+    client_id: i64,
+    from: Option<i32>,
+    to: Option<i32>,
+    last: Option<usize>,
+) -> Result<(ContentType, String), status::Custom<Json<ApiResponse>>> {
+    let comparison = state.with_repository(move |repo| build_comparison(repo, client_id, from, to, last)).await?;
+    Ok((ContentType::CSV, comparison.to_csv()))
+}
@@ -1,15 +1,21 @@
 
 use rocket::{get, post};
+use rocket::http::Status;
+use rocket::response::status;
 use rocket::serde::json::Json;
 use rocket::State;
 use serde::Deserialize;
 use std::path::PathBuf;
 
-use crate::config::{AppState, ApiResponse};
+use super::error;
+use crate::config::{AppState, ApiResponse, RelocationMode, RelocationProgress};
 
 #[derive(Deserialize)]
 pub struct SetRootPathRequest {
     path: String,
+    // Whether the old root keeps its files; moved by default
+    #[serde(default)]
+    mode: RelocationMode,
 }
 
 #[get("/path")]
@@ -27,23 +33,52 @@ pub async fn get_root_path(state: &State<AppState>) -> Json<ApiResponse> {
     }
 }
 
+/// Starts relocating the storage root: files, database and keys are copied
+/// to the new directory, checked, and switched to, then saved to the config
+/// file. Answers 202 at once; `GET /config/relocation` follows the copy.
 #[post("/path", format = "json", data = "<request>")]
-pub async fn set_root_path(request: Json<SetRootPathRequest>, state: &State<AppState>) -> Json<ApiResponse> {
+pub async fn set_root_path(request: Json<SetRootPathRequest>, state: &State<AppState>) -> status::Custom<Json<ApiResponse>> {
     let path = PathBuf::from(&request.path);
-    println!("Request path: {:?}", path);
     // Verify the path exists and is a directory
     if !path.exists() || !path.is_dir() {
-        return Json(ApiResponse {
+        return status::Custom(Status::Ok, Json(ApiResponse {
             status: "error".to_string(),
             message: "Invalid path: directory does not exist".to_string(),
-        });
+        }));
     }
 
-    // Store the absolute path
-    let absolute_path = path.canonicalize().unwrap_or(path);
-    state.set_root_path(absolute_path.clone());
-    Json(ApiResponse {
-        status: "success".to_string(),
-        message: format!("Root path set to: {}", absolute_path.to_string_lossy()),
-    })
+    let progress = match state.start_relocation(&path, request.mode) {
+        Ok(progress) => progress,
+        Err(e) => {
+            return status::Custom(Status::Ok, Json(ApiResponse {
+                status: "error".to_string(),
+                message: e.to_string(),
+            }))
+        }
+    };
+
+    let verb = match request.mode {
+        RelocationMode::Move => "Moving",
+        RelocationMode::Copy => "Copying",
+    };
+    let mut message = format!(
+        "{} the root to {}; GET /config/relocation reports progress",
+        verb,
+        progress.to.to_string_lossy(),
+    );
+    if state.settings().root_overridden {
+        message.push_str("; DOCSERVER_ROOT_PATH or --root-path will override it on restart");
+    }
+    status::Custom(Status::Accepted, Json(ApiResponse {
+        status: "accepted".to_string(),
+        message,
+    }))
+}
+
+/// Progress of the running relocation, or the outcome of the last one.
+#[get("/relocation")]
+pub async fn get_relocation(state: &State<AppState>) -> Result<Json<RelocationProgress>, status::Custom<Json<ApiResponse>>> {
+    state.relocation_progress()
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, "No relocation has been started".to_string()))
 }
@@ -0,0 +1,27 @@
+use rocket::{get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::config::{AppState, ApiResponse};
+use crate::documents::consistency::{ConsistencyReport, RepairReport};
+use super::error;
+
+/// Compares the database with the files under the root, changing nothing.
+#[get("/consistency")]
+pub async fn check_consistency(state: &State<AppState>) -> Result<Json<ConsistencyReport>, status::Custom<Json<ApiResponse>>> {
+    let settings = state.settings().clone();
+    state.with_repository(move |repo| repo.check_consistency(&settings)).await
+        .map(Json)
+        .map_err(|e| error(Status::InternalServerError, e.to_string()))
+}
+
+/// Checks, then moves orphan folders and files to the trash and records
+/// missing checksums. Returns what was found before repairing and what changed.
+#[post("/consistency/repair")]
+pub async fn repair_consistency(state: &State<AppState>) -> Result<Json<RepairReport>, status::Custom<Json<ApiResponse>>> {
+    let settings = state.settings().clone();
+    state.with_repository(move |repo| repo.repair_consistency(&settings)).await
+        .map(Json)
+        .map_err(|e| error(Status::InternalServerError, e.to_string()))
+}
@@ -0,0 +1,264 @@
+use chrono::{Days, NaiveDate, Utc};
+use rocket::{delete, get, post, put, State};
+use rocket::http::{ContentType, Status};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::deadlines::ExtensionError;
+use crate::db::{ClientTaxProfile, OfficeRepository, ReturnExtension, ReturnStatus, ReturnType, StoreResult};
+use crate::money::Money;
+use crate::tax::{self, CalendarEntry, Deadline, DeadlineKind};
+use super::error;
+
+// How far ahead `GET /deadlines` looks unless told otherwise
+const UPCOMING_DAYS: u64 = 30;
+
+#[derive(Deserialize)]
+pub struct TaxProfileRequest {
+    fiscal_year_end_month: Option<u32>,
+    home_state: Option<String>,
+    #[serde(default)]
+    return_type: ReturnType,
+}
+
+#[derive(Deserialize)]
+pub struct ExtensionRequest {
+    filed_on: NaiveDate,
+    #[serde(default)]
+    estimated_tax_liability: Money,
+    #[serde(default)]
+    amount_paid: Money,
+    confirmation_number: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReturnDeadlines {
+    tax_return_id: i64,
+    tax_year: i32,
+    status: ReturnStatus,
+    extension: Option<ReturnExtension>,
+    deadlines: Vec<Deadline>,
+    // The deadlines still in force, an extended date replacing the original
+    current: Vec<Deadline>,
+}
+
+/// An unfiled return's deadline, as listed and put on the calendar.
+#[derive(Serialize)]
+pub struct DueReturn {
+    tax_return_id: i64,
+    client_id: i64,
+    client_name: String,
+    tax_year: i32,
+    status: ReturnStatus,
+    jurisdiction: String,
+    kind: DeadlineKind,
+    due_date: NaiveDate,
+}
+
+#[derive(Serialize)]
+pub struct DeadlineReport {
+    as_of: NaiveDate,
+    through: NaiveDate,
+    overdue: Vec<DueReturn>,
+    upcoming: Vec<DueReturn>,
+}
+
+fn extension_error(e: ExtensionError) -> status::Custom<Json<ApiResponse>> {
+    let code = match e {
+        ExtensionError::ReturnNotFound => Status::NotFound,
+        ExtensionError::AlreadyExtended => Status::Conflict,
+        ExtensionError::NotOriginal | ExtensionError::AfterDueDate(_) => Status::UnprocessableEntity,
+        ExtensionError::Database(_) => Status::InternalServerError,
+    };
+    error(code, e.to_string())
+}
+
+/// The current deadline of every effective return not yet filed, soonest first.
+fn due_returns(repo: &dyn OfficeRepository) -> StoreResult<Vec<DueReturn>> {
+    let names: HashMap<i64, String> = repo.list_clients()?
+        .into_iter()
+        .filter_map(|c| Some((c.client_id?, format!("{}, {}", c.last_name, c.first_name))))
+        .collect();
+
+    let mut due = Vec::new();
+    for tax_return in repo.list_tax_returns(None, None, false)? {
+        if tax_return.status.is_filed() {
+            continue;
+        }
+        let deadlines = repo.return_deadlines(&tax_return)?;
+        for deadline in tax::current_deadlines(&deadlines) {
+            due.push(DueReturn {
+                tax_return_id: tax_return.tax_return_id.unwrap_or_default(),
+                client_id: tax_return.client_id,
+                client_name: names.get(&tax_return.client_id).cloned().unwrap_or_default(),
+                tax_year: tax_return.tax_year,
+                status: tax_return.status,
+                jurisdiction: deadline.jurisdiction.clone(),
+                kind: deadline.kind,
+                due_date: deadline.due_date,
+            });
+        }
+    }
+    due.sort_by_key(|d| (d.due_date, d.tax_return_id));
+    Ok(due)
+}
+
+#[get("/clients/<client_id>/tax-profile")]
+pub async fn get_tax_profile(state: &State<AppState>, client_id: i64) -> Json<ClientTaxProfile> {
+    state.with_repository(move |repo| {
+        let profile = repo.get_tax_profile(client_id).expect("Failed to execute query");
+        Json(profile.unwrap_or(ClientTaxProfile { client_id, ..Default::default() }))
+    }).await
+}
+
+/// Sets a client's fiscal year end (leave it out for calendar-year filers),
+/// the kind of federal return they file (default `1040`) and the state they
+/// file in.
+#[put("/clients/<client_id>/tax-profile", format = "json", data = "<request>")]
+pub async fn set_tax_profile(
+    state: &State<AppState>,
+    client_id: i64,
+    request: Json<TaxProfileRequest>,
+) -> Result<Json<ClientTaxProfile>, status::Custom<Json<ApiResponse>>> {
+    let request = request.into_inner();
+    let fiscal_year_end_month = match request.fiscal_year_end_month {
+        Some(month) if !(1..=12).contains(&month) => {
+            return Err(error(Status::UnprocessableEntity, "fiscal_year_end_month must be between 1 and 12".to_string()));
+        }
+        // A year ending in December is a calendar year
+        Some(12) | None => None,
+        month => month,
+    };
+    let home_state = request.home_state.map(|s| s.trim().to_uppercase()).filter(|s| !s.is_empty());
+    if home_state.as_ref().is_some_and(|s| s.len() != 2 || !s.chars().all(|c| c.is_ascii_alphabetic())) {
+        return Err(error(Status::UnprocessableEntity, "home_state must be a two-letter state code".to_string()));
+    }
+
+    state.with_repository(move |repo| {
+        if repo.get_client(client_id).ok().flatten().is_none() {
+            return Err(error(Status::NotFound, format!("Client {} not found", client_id)));
+        }
+        let profile = ClientTaxProfile {
+            client_id,
+            fiscal_year_end_month,
+            home_state,
+            return_type: request.return_type,
+            updated_at: None,
+        };
+        repo.set_tax_profile(&profile)
+            .map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+        repo.get_tax_profile(client_id)
+            .ok()
+            .flatten()
+            .map(Json)
+            .ok_or_else(|| error(Status::InternalServerError, "Tax profile was not saved".to_string()))
+    }).await
+}
+
+#[get("/returns/<tax_return_id>/deadlines")]
+pub async fn get_return_deadlines(
+    state: &State<AppState>,
+    tax_return_id: i64,
+) -> Result<Json<ReturnDeadlines>, status::Custom<Json<ApiResponse>>> {
+    state.with_repository(move |repo| {
+        let tax_return = repo.get_tax_return(tax_return_id).ok().flatten()
+            .ok_or_else(|| error(Status::NotFound, format!("Tax return {} not found", tax_return_id)))?;
+        let deadlines = repo.return_deadlines(&tax_return).expect("Failed to execute query");
+        let current = tax::current_deadlines(&deadlines).into_iter().cloned().collect();
+        Ok(Json(ReturnDeadlines {
+            tax_return_id,
+            tax_year: tax_return.tax_year,
+            status: tax_return.status,
+            extension: repo.get_extension(tax_return_id).expect("Failed to execute query"),
+            deadlines,
+            current,
+        }))
+    }).await
+}
+
+/// Records a Form 4868 extension for a return.
+#[post("/returns/<tax_return_id>/extension", format = "json", data = "<request>")]
+pub async fn add_return_extension(
+    state: &State<AppState>,
+    tax_return_id: i64,
+    request: Json<ExtensionRequest>,
+) -> Result<Json<ReturnExtension>, status::Custom<Json<ApiResponse>>> {
+    let request = request.into_inner();
+    let extension = ReturnExtension {
+        extension_id: None,
+        tax_return_id,
+        filed_on: request.filed_on,
+        estimated_tax_liability: request.estimated_tax_liability,
+        amount_paid: request.amount_paid,
+        confirmation_number: request.confirmation_number.map(|c| c.trim().to_string()).filter(|c| !c.is_empty()),
+        created_at: None,
+    };
+
+    state.with_repository(move |repo| repo.add_extension(&extension)).await.map(Json).map_err(extension_error)
+}
+
+#[delete("/returns/<tax_return_id>/extension")]
+pub async fn remove_return_extension(state: &State<AppState>, tax_return_id: i64) -> Json<ApiResponse> {
+    state.with_repository(move |repo| {
+        match repo.remove_extension(tax_return_id) {
+            Ok(true) => Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Extension removed".to_string(),
+            }),
+            Ok(false) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: "The return has no extension on file".to_string(),
+            }),
+            Err(e) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: format!("Database error: {}", e),
+            }),
+        }
+    }).await
+}
+
+/// Unfiled returns past their deadline, and those due within `days` days
+/// (default 30) of `as_of` (`YYYY-MM-DD`, default today).
+#[get("/deadlines?<as_of>&<days>")]
+pub async fn list_deadlines(
+    state: &State<AppState>,
+    as_of: Option<&str>,
+    days: Option<u64>,
+) -> Result<Json<DeadlineReport>, status::Custom<Json<ApiResponse>>> {
+    let as_of = match as_of {
+        Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| error(Status::BadRequest, format!("as_of must be YYYY-MM-DD, got {:?}", value)))?,
+        None => Utc::now().date_naive(),
+    };
+    let through = as_of.checked_add_days(Days::new(days.unwrap_or(UPCOMING_DAYS)))
+        .ok_or_else(|| error(Status::BadRequest, "days is too large".to_string()))?;
+
+    state.with_repository(move |repo| {
+        let (overdue, rest): (Vec<DueReturn>, Vec<DueReturn>) = due_returns(repo)
+            .expect("Failed to execute query")
+            .into_iter()
+            .partition(|d| d.due_date < as_of);
+        let upcoming = rest.into_iter().filter(|d| d.due_date <= through).collect();
+        Ok(Json(DeadlineReport { as_of, through, overdue, upcoming }))
+    }).await
+}
+
+/// Every unfiled return's deadline as an iCalendar feed to subscribe to.
+#[get("/deadlines.ics")]
+pub async fn deadline_calendar(state: &State<AppState>) -> (ContentType, String) {
+    let due = state.with_repository(|repo| due_returns(repo)).await.expect("Failed to execute query");
+
+    let entries: Vec<CalendarEntry> = due.iter()
+        .map(|d| CalendarEntry {
+            uid: format!("return-{}-{}-{}@docserver", d.tax_return_id, d.jurisdiction.to_lowercase(), d.kind.as_str()),
+            date: d.due_date,
+            summary: format!("{}: {} {} {}", d.client_name, d.tax_year, d.jurisdiction, d.kind.label()),
+            description: format!("Tax return {} is {}", d.tax_return_id, d.status.as_str()),
+        })
+        .collect();
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    (ContentType::Calendar, tax::to_icalendar("Filing deadlines", &entries, &stamp))
+}
@@ -0,0 +1,41 @@
+use rocket::{get, put, State};
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+use crate::config::AppState;
+use crate::db::{Document, DocumentType};
+
+#[derive(Deserialize)]
+pub struct OverrideTypeRequest {
+    document_type: DocumentType,
+    tax_year: Option<i32>,
+}
+
+#[get("/clients/<client_id>/documents")]
+pub async fn list_client_documents(state: &State<AppState>, client_id: i64) -> Json<Vec<Document>> {
+    state.with_repository(move |repo| {
+        let documents = repo.list_client_documents(client_id)
+            .expect("Failed to execute query");
+        Json(documents)
+    }).await
+}
+
+#[get("/documents/<document_id>")]
+pub async fn get_document(state: &State<AppState>, document_id: i64) -> Option<Json<Document>> {
+    state.with_repository(move |repo| {
+        repo.get_document(document_id).ok().flatten().map(Json)
+    }).await
+}
+
+#[put("/documents/<document_id>/type", format = "json", data = "<request>")]
+pub async fn override_document_type(
+    state: &State<AppState>,
+    document_id: i64,
+    request: Json<OverrideTypeRequest>,
+) -> Option<Json<Document>> {
+    state.with_root(move |root_path, repo| {
+        repo.override_document(root_path, document_id, request.document_type, request.tax_year)
+            .expect("Failed to update document")
+            .map(Json)
+    }).await.flatten()
+}
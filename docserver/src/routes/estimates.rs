@@ -0,0 +1,221 @@
+use chrono::{NaiveDate, Utc};
+use rocket::{delete, get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::{EstimatedPayment, Jurisdiction, OfficeRepository, PaymentMethod};
+use crate::money::Money;
+use crate::tax::{self, EstimatedTaxRules, FilingStatus, SafeHarbor};
+use super::error;
+
+#[derive(Deserialize)]
+pub struct NewEstimatedPayment {
+    tax_year: i32,
+    quarter: i32,
+    amount: Money,
+    paid_on: NaiveDate,
+    method: PaymentMethod,
+    note: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BehindClient {
+    client_id: i64,
+    first_name: String,
+    last_name: String,
+    cumulative_required: Money,
+    cumulative_paid: Money,
+    shortfall: Money,
+}
+
+/// Clients short of the safe harbor on the next installment due.
+#[derive(Serialize)]
+pub struct BehindReport {
+    as_of: NaiveDate,
+    tax_year: i32,
+    quarter: i32,
+    due_date: NaiveDate,
+    clients: Vec<BehindClient>,
+}
+
+fn estimated_rules(state: &State<AppState>, tax_year: i32) -> Result<EstimatedTaxRules, status::Custom<Json<ApiResponse>>> {
+    let rule_set = state.get_tax_rules();
+    let rules = rule_set.get(tax_year)
+        .ok_or_else(|| error(Status::UnprocessableEntity, rule_set.missing_year_message(tax_year)))?;
+    rules.estimated_payments.clone().ok_or_else(|| error(
+        Status::UnprocessableEntity,
+        format!("The {} tax rules have no [estimated_payments] section", tax_year),
+    ))
+}
+
+/// The safe harbor for a client's year, from the effective returns for it
+/// and the year before.
+fn client_safe_harbor(
+    repo: &dyn OfficeRepository,
+    rules: &EstimatedTaxRules,
+    client_id: i64,
+    tax_year: i32,
+) -> Result<Option<SafeHarbor>, status::Custom<Json<ApiResponse>>> {
+    let effective = repo.list_tax_returns(Some(client_id), Some(&Jurisdiction::Federal), false).expect("Failed to execute query");
+    let current = effective.iter().find(|r| r.tax_year == tax_year);
+    let prior = effective.iter().find(|r| r.tax_year == tax_year - 1);
+    let Some(filing_status) = current.or(prior).map(|r| r.filing_status.as_str()) else {
+        return Ok(None);
+    };
+    let filing_status = FilingStatus::parse(filing_status).ok_or_else(|| {
+        error(Status::UnprocessableEntity, format!("Unknown filing status: {:?}", filing_status))
+    })?;
+
+    let payments = repo.list_estimated_payments(client_id, Some(tax_year)).expect("Failed to execute query");
+    Ok(tax::safe_harbor(rules, tax_year, filing_status, prior, current, &payments))
+}
+
+/// Records a 1040-ES payment; it is added to the year's `taxes_paid`.
+#[post("/clients/<client_id>/estimated-payments", format = "json", data = "<request>")]
+pub async fn add_estimated_payment(
+    state: &State<AppState>,
+    client_id: i64,
+    request: Json<NewEstimatedPayment>,
+) -> Result<Json<EstimatedPayment>, status::Custom<Json<ApiResponse>>> {
+    let request = request.into_inner();
+    if !(1..=4).contains(&request.quarter) {
+        return Err(error(Status::UnprocessableEntity, "quarter must be between 1 and 4".to_string()));
+    }
+    if request.amount <= Money::ZERO {
+        return Err(error(Status::UnprocessableEntity, "amount must be positive".to_string()));
+    }
+
+    state.with_repository(move |repo| {
+        if repo.get_client(client_id).ok().flatten().is_none() {
+            return Err(error(Status::NotFound, format!("Client {} not found", client_id)));
+        }
+
+        let payment = EstimatedPayment {
+            payment_id: None,
+            client_id,
+            tax_year: request.tax_year,
+            quarter: request.quarter,
+            amount: request.amount,
+            paid_on: request.paid_on,
+            method: request.method,
+            note: request.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+            created_at: None,
+        };
+        let payment_id = repo.add_estimated_payment(&payment)
+            .map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+        repo.get_estimated_payment(payment_id)
+            .ok()
+            .flatten()
+            .map(Json)
+            .ok_or_else(|| error(Status::InternalServerError, "Estimated payment was not saved".to_string()))
+    }).await
+}
+
+#[get("/clients/<client_id>/estimated-payments?<tax_year>")]
+pub async fn list_estimated_payments(
+    state: &State<AppState>,
+    client_id: i64,
+    tax_year: Option<i32>,
+) -> Json<Vec<EstimatedPayment>> {
+    state.with_repository(move |repo| Json(repo.list_estimated_payments(client_id, tax_year).expect("Failed to execute query"))).await
+}
+
+#[delete("/estimated-payments/<payment_id>")]
+pub async fn delete_estimated_payment(state: &State<AppState>, payment_id: i64) -> Json<ApiResponse> {
+    state.with_repository(move |repo| {
+        match repo.remove_estimated_payment(payment_id) {
+            Ok(true) => Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Estimated payment removed".to_string(),
+            }),
+            Ok(false) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: "Estimated payment not found".to_string(),
+            }),
+            Err(e) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: format!("Database error: {}", e),
+            }),
+        }
+    }).await
+}
+
+/// Safe-harbor targets for a year and how far the client's payments cover
+/// each installment.
+#[get("/clients/<client_id>/safe-harbor/<tax_year>")]
+pub async fn get_safe_harbor(
+    state: &State<AppState>,
+    client_id: i64,
+    tax_year: i32,
+) -> Result<Json<SafeHarbor>, status::Custom<Json<ApiResponse>>> {
+    let rules = estimated_rules(state, tax_year)?;
+
+    state.with_repository(move |repo| {
+        client_safe_harbor(repo, &rules, client_id, tax_year)?.map(Json).ok_or_else(|| error(
+            Status::NotFound,
+            format!("Client {} has no return for {} or {}", client_id, tax_year - 1, tax_year),
+        ))
+    }).await
+}
+
+/// Clients who pay estimated tax and have not covered the next installment
+/// due on or after `as_of` (`YYYY-MM-DD`, default today).
+#[get("/estimated-payments/behind?<as_of>")]
+pub async fn list_clients_behind(
+    state: &State<AppState>,
+    as_of: Option<&str>,
+) -> Result<Json<BehindReport>, status::Custom<Json<ApiResponse>>> {
+    let as_of = match as_of {
+        Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| error(Status::BadRequest, format!("as_of must be YYYY-MM-DD, got {:?}", value)))?,
+        None => Utc::now().date_naive(),
+    };
+
+    // The fourth installment falls in January, so the next one due may
+    // belong to the previous tax year
+    let upcoming = {
+        let rule_set = state.get_tax_rules();
+        rule_set.years.values()
+            .filter_map(|loaded| {
+                let rules = loaded.rules.estimated_payments.as_ref()?;
+                let index = rules.due_dates.iter().position(|due| *due >= as_of)?;
+                Some((rules.due_dates[index], loaded.rules.tax_year, index as i32 + 1, rules.clone()))
+            })
+            .min_by_key(|(due_date, ..)| *due_date)
+    };
+    let Some((due_date, tax_year, quarter, rules)) = upcoming else {
+        return Err(error(
+            Status::UnprocessableEntity,
+            format!("No tax rules list an estimated payment due on or after {}", as_of),
+        ));
+    };
+
+    state.with_repository(move |repo| {
+        let mut behind = Vec::new();
+        for client_id in repo.paying_clients(tax_year).expect("Failed to execute query") {
+            let Some(harbor) = client_safe_harbor(repo, &rules, client_id, tax_year)? else {
+                continue;
+            };
+            let installment = &harbor.installments[quarter as usize - 1];
+            if installment.shortfall.is_zero() {
+                continue;
+            }
+            let Some(client) = repo.get_client(client_id).ok().flatten() else {
+                continue;
+            };
+            behind.push(BehindClient {
+                client_id,
+                first_name: client.first_name,
+                last_name: client.last_name,
+                cumulative_required: installment.cumulative_required,
+                cumulative_paid: installment.cumulative_paid,
+                shortfall: installment.shortfall,
+            });
+        }
+
+        Ok(Json(BehindReport { as_of, tax_year, quarter, due_date, clients: behind }))
+    }).await
+}
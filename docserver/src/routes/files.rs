@@ -2,16 +2,20 @@ use rocket::serde::json::Json;
 use rocket::State;
 use rocket::fs::NamedFile;
 use rocket::{get, post};
-use rocket::http::ContentType;
+use rocket::http::{ContentType, Status};
+use rocket::response::status;
 use rocket::Data;
 use rocket_multipart_form_data::{
     MultipartFormData, MultipartFormDataField, MultipartFormDataOptions, Repetition
 };
 use serde::Serialize;
-use std::path::PathBuf;
-use std::fs;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 use uuid::Uuid;
 use crate::config::{AppState, ApiResponse};
+use crate::db::Document;
+use super::error;
 
 #[derive(Serialize)]
 pub struct FileList {
@@ -33,52 +37,136 @@ pub async fn get_file(path: PathBuf, state: &State<AppState>) -> Option<NamedFil
 }
 
 #[post("/files/upload/<client_id>", data = "<data>")]
-pub async fn upload_files(content_type: &ContentType, data: Data<'_>, client_id: String, state: &State<AppState>) -> Json<FileList> {
+pub async fn upload_files(
+    content_type: &ContentType,
+    data: Data<'_>,
+    client_id: String,
+    state: &State<AppState>,
+) -> Result<Json<FileList>, status::Custom<Json<ApiResponse>>> {
     println!("Request path: /files/upload/{}", client_id);
     println!("Content type: {:?}", content_type);
-    let root_path = match state.get_root_path() {
-        Some(path) => path,
-        None => return Json(FileList { files: vec![] }),
-    };
+    refuse_during_relocation(state)?;
+    let stored = store_uploads(state, content_type, data, &client_id).await;
+    Ok(Json(FileList { files: stored.into_iter().map(|upload| upload.file_name).collect() }))
+}
 
-    // Create client directory if it doesn't exist
-    let client_dir = root_path.join(&client_id);
-    if !client_dir.exists() {
-        if let Err(_) = fs::create_dir_all(&client_dir) {
-            return Json(FileList { files: vec![] });
-        }
+/// Uploads are turned away while the root is being relocated rather than
+/// held until the copy finishes.
+pub(crate) fn refuse_during_relocation(state: &AppState) -> Result<(), status::Custom<Json<ApiResponse>>> {
+    if state.relocation_running() {
+        return Err(error(
+            Status::ServiceUnavailable,
+            "The storage root is being relocated; try again once GET /config/relocation reports it finished".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) struct StoredUpload {
+    pub file_name: String,
+    pub document: Option<Document>,
+}
+
+/// Saves the `files` parts of a multipart upload under `<root>/<client_id>/`
+/// and classifies them. This is the one upload pipeline; staff uploads and
+/// public upload links both go through it.
+pub(crate) async fn store_uploads(
+    state: &AppState,
+    content_type: &ContentType,
+    data: Data<'_>,
+    client_id: &str,
+) -> Vec<StoredUpload> {
+    if state.get_root_path().is_none() || safe_file_name(client_id).as_deref() != Some(client_id) {
+        return vec![];
     }
 
     // Configure multipart form options
     let options = MultipartFormDataOptions::with_multipart_form_data_fields(vec![
         MultipartFormDataField::file("files")
-            .size_limit(10 * 1024 * 1024) // 10MB size limit
+            .size_limit(state.settings().upload_limit_bytes())
             .repetition(Repetition::infinite()), // Allow multiple files
     ]);
 
     // Process the multipart form data
     let multipart_form_data = match MultipartFormData::parse(content_type, data, options).await {
         Ok(multipart) => multipart,
-        Err(_) => return Json(FileList { files: vec![] }),
+        Err(_) => return vec![],
     };
     
-    let files = multipart_form_data.files.get("files");
-    let mut saved_files = Vec::new();
+    let numeric_id = client_id.parse::<i64>().ok();
+    let client_id = client_id.to_string();
+    // Files are written under whichever root is current once the database is
+    // free, so one that raced a relocation is not left in the old root
+    let stored = state.with_root(move |root_path, repo| {
+        // Create client directory if it doesn't exist
+        let client_dir = root_path.join(&client_id);
+        if !client_dir.exists() && fs::create_dir_all(&client_dir).is_err() {
+            return vec![];
+        }
 
-    if let Some(files) = files {
-        for file in files {
+        let mut saved_files = Vec::new();
+        for file in multipart_form_data.files.get("files").into_iter().flatten() {
             let file_name = match &file.file_name {
-                Some(name) => name.clone(),
+                Some(name) => match safe_file_name(name) {
+                    Some(name) => name,
+                    None => continue,
+                },
                 None => Uuid::new_v4().to_string(),
             };
-            
-            let file_path = client_dir.join(&file_name);
-            if let Err(_) = fs::copy(&file.path, &file_path) {
-                continue;
+
+            match save_new(&file.path, &client_dir, &file_name) {
+                Ok(file_name) => saved_files.push(file_name),
+                Err(e) => eprintln!("Failed to store upload {}: {}", file_name, e),
             }
-            saved_files.push(file_name);
         }
+
+        // Classify uploads for known clients; other folders are stored as-is
+        saved_files.into_iter().map(|file_name| {
+            let document = numeric_id.and_then(|client_id| {
+                repo.ingest_upload(root_path, client_id, &file_name)
+                    .map_err(|e| eprintln!("Failed to record document {}: {}", file_name, e))
+                    .ok()
+            });
+            StoredUpload { file_name, document }
+        }).collect()
+    }).await;
+    stored.unwrap_or_default()
+}
+
+/// The last component of a client-supplied file name, so an upload can only
+/// land in its client's folder. Empty, `.`, `..` and absolute names are refused.
+fn safe_file_name(name: &str) -> Option<String> {
+    let path = Path::new(name);
+    if path.is_absolute() {
+        return None;
     }
+    match path.components().next_back()? {
+        Component::Normal(last) => Some(last.to_str()?.to_string()),
+        _ => None,
+    }
+}
 
-    Json(FileList { files: saved_files })
+/// Copies `source` into `dir` as `file_name` without replacing anything
+/// already there; a name that is taken gets a unique suffix. Returns the
+/// name the file was stored under.
+fn save_new(source: &Path, dir: &Path, file_name: &str) -> io::Result<String> {
+    let mut name = file_name.to_string();
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(dir.join(&name)) {
+            Ok(mut destination) => {
+                io::copy(&mut fs::File::open(source)?, &mut destination)?;
+                return Ok(name);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let original = Path::new(file_name);
+                let stem = original.file_stem().and_then(|s| s.to_str()).unwrap_or(file_name);
+                name = match original.extension().and_then(|e| e.to_str()) {
+                    Some(extension) => format!("{}-{}.{}", stem, Uuid::new_v4(), extension),
+                    None => format!("{}-{}", stem, Uuid::new_v4()),
+                };
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
+
@@ -0,0 +1,176 @@
+use chrono::NaiveDate;
+use rocket::{delete, get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::household::HouseholdError;
+use crate::db::{HouseholdMember, MemberRole};
+use crate::tax::{self, FilingStatus, HouseholdSummary};
+use super::error;
+use super::tax::covered_household;
+
+#[derive(Deserialize)]
+pub struct NewHouseholdMember {
+    role: MemberRole,
+    first_name: String,
+    last_name: String,
+    // Defaults to "spouse" for a spouse; required for dependents
+    relationship: Option<String>,
+    birth_date: NaiveDate,
+    social_security_number: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReturnMembersRequest {
+    member_ids: Vec<i64>,
+}
+
+/// The household a return covers, with what it means for the return.
+/// `problems` lists conflicts with the return's filing status.
+#[derive(Serialize)]
+pub struct ReturnHousehold {
+    tax_return_id: i64,
+    filing_status: String,
+    members: Vec<HouseholdMember>,
+    summary: Option<HouseholdSummary>,
+    problems: Vec<String>,
+}
+
+/// Normalizes an SSN to `123-45-6789`, or `None` if it is not nine digits.
+fn normalize_ssn(value: &str) -> Option<String> {
+    let digits: String = value.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    if digits.len() != 9 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!("{}-{}-{}", &digits[..3], &digits[3..5], &digits[5..]))
+}
+
+fn household_error(e: HouseholdError) -> status::Custom<Json<ApiResponse>> {
+    let code = match e {
+        HouseholdError::NotFound => Status::NotFound,
+        HouseholdError::SpouseExists => Status::Conflict,
+        HouseholdError::NotInHousehold(_) => Status::UnprocessableEntity,
+        HouseholdError::Database(_) => Status::InternalServerError,
+    };
+    error(code, e.to_string())
+}
+
+#[post("/clients/<client_id>/household", format = "json", data = "<request>")]
+pub async fn add_household_member(
+    state: &State<AppState>,
+    client_id: i64,
+    request: Json<NewHouseholdMember>,
+) -> Result<Json<HouseholdMember>, status::Custom<Json<ApiResponse>>> {
+    let request = request.into_inner();
+    let social_security_number = normalize_ssn(&request.social_security_number).ok_or_else(|| {
+        error(Status::UnprocessableEntity, "social_security_number must be nine digits".to_string())
+    })?;
+    let relationship = match (request.role, request.relationship) {
+        (_, Some(relationship)) if !relationship.trim().is_empty() => relationship.trim().to_lowercase(),
+        (MemberRole::Spouse, _) => "spouse".to_string(),
+        (MemberRole::Dependent, _) => {
+            return Err(error(Status::UnprocessableEntity, "A dependent needs a relationship".to_string()));
+        }
+    };
+    if request.first_name.trim().is_empty() || request.last_name.trim().is_empty() {
+        return Err(error(Status::UnprocessableEntity, "first_name and last_name are required".to_string()));
+    }
+
+    state.with_repository(move |repo| {
+        if repo.get_client(client_id).ok().flatten().is_none() {
+            return Err(error(Status::NotFound, format!("Client {} not found", client_id)));
+        }
+
+        let member = HouseholdMember {
+            member_id: None,
+            client_id,
+            role: request.role,
+            first_name: request.first_name.trim().to_string(),
+            last_name: request.last_name.trim().to_string(),
+            relationship,
+            birth_date: request.birth_date,
+            social_security_number,
+            created_at: None,
+            updated_at: None,
+        };
+        let member_id = repo.add_household_member(&member).map_err(household_error)?;
+        repo.get_household_member(member_id)
+            .ok()
+            .flatten()
+            .map(Json)
+            .ok_or_else(|| error(Status::InternalServerError, "Household member was not saved".to_string()))
+    }).await
+}
+
+#[get("/clients/<client_id>/household")]
+pub async fn list_household(state: &State<AppState>, client_id: i64) -> Json<Vec<HouseholdMember>> {
+    state.with_repository(move |repo| Json(repo.list_household(client_id).expect("Failed to execute query"))).await
+}
+
+#[delete("/household-members/<member_id>")]
+pub async fn remove_household_member(state: &State<AppState>, member_id: i64) -> Json<ApiResponse> {
+    state.with_repository(move |repo| {
+        match repo.remove_household_member(member_id) {
+            Ok(true) => Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Household member removed".to_string(),
+            }),
+            Ok(false) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: "Household member not found".to_string(),
+            }),
+            Err(e) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: format!("Database error: {}", e),
+            }),
+        }
+    }).await
+}
+
+/// Sets which household members a return covers.
+#[post("/returns/<tax_return_id>/household", format = "json", data = "<request>")]
+pub async fn set_return_household(
+    state: &State<AppState>,
+    tax_return_id: i64,
+    request: Json<ReturnMembersRequest>,
+) -> Result<Json<ReturnHousehold>, status::Custom<Json<ApiResponse>>> {
+    let member_ids = request.into_inner().member_ids;
+    state.with_repository(move |repo| repo.set_return_household(tax_return_id, &member_ids)).await
+        .map_err(household_error)?;
+    get_return_household(state, tax_return_id).await
+}
+
+#[get("/returns/<tax_return_id>/household")]
+pub async fn get_return_household(
+    state: &State<AppState>,
+    tax_return_id: i64,
+) -> Result<Json<ReturnHousehold>, status::Custom<Json<ApiResponse>>> {
+    let (tax_return, members) = state.with_repository(move |repo| {
+        let tax_return = repo.get_tax_return(tax_return_id).ok().flatten()
+            .ok_or_else(|| error(Status::NotFound, format!("Tax return {} not found", tax_return_id)))?;
+        let members = covered_household(repo, &tax_return)
+            .map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+        Ok((tax_return, members))
+    }).await?;
+
+    let rule_set = state.get_tax_rules();
+    let child_age_limit = rule_set.get(tax_return.tax_year)
+        .and_then(|rules| rules.dependent_credits.as_ref())
+        .map(|credits| credits.child_age_limit);
+    let members = members.unwrap_or_default();
+    let problems = match FilingStatus::parse(&tax_return.filing_status) {
+        Some(status) => tax::filing_status_problems(status, &members, tax_return.tax_year),
+        None => vec![format!("Unknown filing status: {:?}", tax_return.filing_status)],
+    };
+
+    Ok(Json(ReturnHousehold {
+        tax_return_id,
+        summary: child_age_limit.map(|limit| tax::summarize(&members, tax_return.tax_year, limit)),
+        filing_status: tax_return.filing_status,
+        members,
+        problems,
+    }))
+}
@@ -0,0 +1,133 @@
+use rocket::{delete, get, post, put, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::line_items::LineItemError;
+use crate::db::{LineItem, LineItemSection};
+use crate::money::Money;
+use crate::tax::{Category, CATEGORIES};
+use super::error;
+
+#[derive(Deserialize)]
+pub struct LineItemRequest {
+    section: LineItemSection,
+    category: String,
+    payer: Option<String>,
+    amount: Money,
+    document_id: Option<i64>,
+}
+
+impl LineItemRequest {
+    fn into_line_item(self, tax_return_id: i64) -> LineItem {
+        LineItem {
+            line_item_id: None,
+            tax_return_id,
+            section: self.section,
+            category: self.category.trim().to_string(),
+            payer: self.payer.map(|p| p.trim().to_string()).filter(|p| !p.is_empty()),
+            amount: self.amount,
+            document_id: self.document_id,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+fn parse_section(section: Option<&str>) -> Result<Option<LineItemSection>, status::Custom<Json<ApiResponse>>> {
+    match section {
+        Some(value) => LineItemSection::parse(value)
+            .map(Some)
+            .ok_or_else(|| error(Status::BadRequest, format!("Unknown line item section: {}", value))),
+        None => Ok(None),
+    }
+}
+
+fn line_item_error(e: LineItemError) -> status::Custom<Json<ApiResponse>> {
+    let code = match e {
+        LineItemError::NotFound | LineItemError::ReturnNotFound => Status::NotFound,
+        LineItemError::Superseded => Status::Conflict,
+        LineItemError::UnknownCategory { .. } | LineItemError::DocumentNotFound(_) => Status::UnprocessableEntity,
+        LineItemError::Database(_) => Status::InternalServerError,
+    };
+    error(code, e.to_string())
+}
+
+/// The categories line items can be filed under.
+#[get("/line-item-categories?<section>")]
+pub async fn list_line_item_categories(
+    section: Option<&str>,
+) -> Result<Json<Vec<Category>>, status::Custom<Json<ApiResponse>>> {
+    let section = parse_section(section)?;
+    Ok(Json(CATEGORIES.iter().filter(|c| section.is_none_or(|s| c.section == s)).copied().collect()))
+}
+
+#[get("/returns/<tax_return_id>/line-items?<section>")]
+pub async fn list_return_line_items(
+    state: &State<AppState>,
+    tax_return_id: i64,
+    section: Option<&str>,
+) -> Result<Json<Vec<LineItem>>, status::Custom<Json<ApiResponse>>> {
+    let section = parse_section(section)?;
+
+    state.with_repository(move |repo| Ok(Json(repo.list_line_items(tax_return_id, section).expect("Failed to execute query")))).await
+}
+
+/// Adds a line to a return; its map total for the category follows.
+#[post("/returns/<tax_return_id>/line-items", format = "json", data = "<request>")]
+pub async fn add_line_item(
+    state: &State<AppState>,
+    tax_return_id: i64,
+    request: Json<LineItemRequest>,
+) -> Result<Json<LineItem>, status::Custom<Json<ApiResponse>>> {
+    let item = request.into_inner().into_line_item(tax_return_id);
+
+    state.with_repository(move |repo| repo.add_line_item(&item).map(Json).map_err(line_item_error)).await
+}
+
+#[put("/line-items/<line_item_id>", format = "json", data = "<request>")]
+pub async fn update_line_item(
+    state: &State<AppState>,
+    line_item_id: i64,
+    request: Json<LineItemRequest>,
+) -> Result<Json<LineItem>, status::Custom<Json<ApiResponse>>> {
+    // The line keeps its return; the id here is replaced when it is loaded
+    let item = request.into_inner().into_line_item(0);
+
+    state.with_repository(move |repo| repo.update_line_item(line_item_id, &item).map(Json).map_err(line_item_error)).await
+}
+
+#[delete("/line-items/<line_item_id>")]
+pub async fn delete_line_item(
+    state: &State<AppState>,
+    line_item_id: i64,
+) -> Result<Json<ApiResponse>, status::Custom<Json<ApiResponse>>> {
+    state.with_repository(move |repo| {
+        repo.remove_line_item(line_item_id).map_err(line_item_error)?;
+        Ok(Json(ApiResponse {
+            status: "success".to_string(),
+            message: "Line item removed".to_string(),
+        }))
+    }).await
+}
+
+/// Line items across effective returns, e.g. `?category=wages&tax_year=2023`.
+#[get("/line-items?<section>&<category>&<client_id>&<tax_year>")]
+pub async fn search_line_items(
+    state: &State<AppState>,
+    section: Option<&str>,
+    category: Option<&str>,
+    client_id: Option<i64>,
+    tax_year: Option<i32>,
+) -> Result<Json<Vec<LineItem>>, status::Custom<Json<ApiResponse>>> {
+    let section = parse_section(section)?;
+
+    let category = category.map(str::to_string);
+    let items = state
+        .with_repository(move |repo| repo.search_line_items(section, category.as_deref(), client_id, tax_year))
+        .await
+        .expect("Failed to execute query");
+    Ok(Json(items))
+}
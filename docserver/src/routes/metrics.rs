@@ -0,0 +1,13 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::config::AppState;
+use crate::db::PoolMetrics;
+
+/// Connection pool usage: connections open and in use, checkouts, and how
+/// long callers waited for a connection.
+#[get("/metrics/database")]
+pub async fn get_database_metrics(state: &State<AppState>) -> Option<Json<PoolMetrics>> {
+    state.database_metrics().await.map(Json)
+}
@@ -1,7 +1,55 @@
+mod backups;
 mod config;
+mod consistency;
 mod files;
 mod clients;
+mod comparison;
+mod deadlines;
+mod documents;
+mod estimates;
+mod proposals;
+mod tax;
+mod amendments;
+mod workflow;
+mod checklists;
+mod upload_links;
+mod share_links;
+mod state_returns;
+mod portal;
+mod household;
+mod line_items;
+mod metrics;
 
+pub use backups::*;
 pub use config::*;
+pub use consistency::*;
 pub use files::*;
 pub use clients::*;
+pub use comparison::*;
+pub use deadlines::*;
+pub use documents::*;
+pub use estimates::*;
+pub use proposals::*;
+pub use tax::*;
+pub use amendments::*;
+pub use workflow::*;
+pub use checklists::*;
+pub use upload_links::*;
+pub use share_links::*;
+pub use state_returns::*;
+pub use portal::*;
+pub use household::*;
+pub use line_items::*;
+pub use metrics::*;
+
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use crate::config::ApiResponse;
+
+fn error(code: Status, message: String) -> status::Custom<Json<ApiResponse>> {
+    status::Custom(code, Json(ApiResponse {
+        status: "error".to_string(),
+        message,
+    }))
+}
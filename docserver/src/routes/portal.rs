@@ -0,0 +1,319 @@
+use chrono::{Duration, Utc};
+use rocket::{get, post, State};
+use rocket::fs::NamedFile;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::{Component, Path};
+use std::time::Duration as StdDuration;
+
+use crate::config::{
+    unblock, AppState, ApiResponse, PORTAL_LOGINS_PER_EMAIL, PORTAL_PASSWORD_MIN_LEN, PORTAL_SESSION_HOURS, PUBLIC_RATE_WINDOW_SECS,
+    PUBLIC_REQUESTS_PER_ADDRESS,
+};
+use crate::db::portal::ReviewError;
+use crate::db::{ChangeRequestStatus, Client, ContactChangeRequest, PortalUser, TaxReturn};
+use crate::links;
+use super::clients::client_file_names;
+use super::error;
+
+#[derive(Deserialize)]
+pub struct PortalCredentials {
+    email: String,
+    password: String,
+}
+
+/// A newly started session. `token` is sent back as `Authorization: Bearer`.
+#[derive(Serialize)]
+pub struct PortalLogin {
+    token: String,
+    expires_at: chrono::DateTime<Utc>,
+    user: PortalUser,
+}
+
+#[derive(Deserialize)]
+pub struct ContactChange {
+    address: Option<String>,
+    phone_number: Option<String>,
+    email: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ReviewRequest {
+    note: Option<String>,
+}
+
+/// The signed-in portal user. Every portal route takes this guard and reads
+/// only the data of `user.client_id`; requests without a live session get 401.
+pub struct PortalSession {
+    user: PortalUser,
+    token_digest: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PortalSession {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let token = request.headers().get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let (Some(token), Some(state)) = (token, request.rocket().state::<AppState>()) else {
+            return request::Outcome::Error((Status::Unauthorized, ()));
+        };
+
+        let token_digest = links::token_digest(token);
+        let digest = token_digest.clone();
+        let user = state.with_repository(move |repo| repo.session_user(&digest, Utc::now())).await.ok().flatten();
+
+        match user {
+            Some(user) => request::Outcome::Success(PortalSession { user, token_digest }),
+            None => request::Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Keeps only the last four digits of a social security number.
+fn mask_ssn(ssn: &str) -> String {
+    let digits: Vec<char> = ssn.chars().filter(|c| c.is_ascii_digit()).collect();
+    let last_four: String = digits[digits.len().saturating_sub(4)..].iter().collect();
+    format!("***-**-{}", last_four)
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+#[post("/login", format = "json", data = "<credentials>")]
+pub async fn portal_login(
+    state: &State<AppState>,
+    credentials: Json<PortalCredentials>,
+    remote: Option<IpAddr>,
+) -> Result<Json<PortalLogin>, status::Custom<Json<ApiResponse>>> {
+    let window = StdDuration::from_secs(PUBLIC_RATE_WINDOW_SECS);
+    let address = remote.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    let email = credentials.email.trim().to_lowercase();
+    if !state.rate_limiter().check(&format!("address:{}", address), PUBLIC_REQUESTS_PER_ADDRESS, window)
+        || !state.rate_limiter().check(&format!("portal_login:{}", email), PORTAL_LOGINS_PER_EMAIL, window)
+    {
+        return Err(error(Status::TooManyRequests, "Too many requests; try again later".to_string()));
+    }
+
+    let password = credentials.into_inner().password;
+    let user = state.with_repository(move |repo| {
+        let user = repo.find_portal_user(&email).ok().flatten();
+
+        // Unknown, disabled and wrong-password sign-ins all get the same answer,
+        // and take the same time: a password is always checked
+        let hash = match &user {
+            Some(user) => user.password_hash.as_str(),
+            None => links::dummy_password_hash(),
+        };
+        let verified = links::verify_password(&password, hash);
+        user.filter(|user| verified && !user.disabled)
+    }).await;
+    let user = user.ok_or_else(|| error(Status::Unauthorized, "Incorrect email or password".to_string()))?;
+    let user_id = user.user_id.expect("Stored users have an id");
+
+    let token = links::new_session_token();
+    let expires_at = links::expiry_after(Utc::now(), Duration::hours(PORTAL_SESSION_HOURS));
+    let token_digest = links::token_digest(&token);
+    state.with_repository(move |repo| repo.create_portal_session(user_id, &token_digest, expires_at)).await
+        .map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+
+    Ok(Json(PortalLogin { token, expires_at, user }))
+}
+
+#[post("/logout")]
+pub async fn portal_logout(state: &State<AppState>, session: PortalSession) -> Json<ApiResponse> {
+    let token_digest = session.token_digest;
+    match state.with_repository(move |repo| repo.revoke_portal_session(&token_digest)).await {
+        Ok(_) => Json(ApiResponse {
+            status: "success".to_string(),
+            message: "Signed out".to_string(),
+        }),
+        Err(e) => Json(ApiResponse {
+            status: "error".to_string(),
+            message: format!("Database error: {}", e),
+        }),
+    }
+}
+
+/// The signed-in client's own record, with the SSN masked.
+#[get("/me")]
+pub async fn portal_me(state: &State<AppState>, session: PortalSession) -> Option<Json<Client>> {
+    state.with_repository(move |repo| {
+        let mut client = repo.get_client(session.user.client_id).ok().flatten()?;
+        client.social_security_number = mask_ssn(&client.social_security_number);
+        Some(Json(client))
+    }).await
+}
+
+/// Every return on file for the signed-in client, amended ones included.
+#[get("/returns")]
+pub async fn portal_returns(state: &State<AppState>, session: PortalSession) -> Json<Vec<TaxReturn>> {
+    state.with_repository(move |repo| Json(repo.get_client_tax_returns(session.user.client_id).expect("Failed to execute query"))).await
+}
+
+#[get("/files")]
+pub async fn portal_files(state: &State<AppState>, session: PortalSession) -> Json<Vec<String>> {
+    let Some(root_path) = state.get_root_path() else {
+        return Json(Vec::new());
+    };
+    let client_id = session.user.client_id;
+    Json(unblock(move || client_file_names(&root_path, client_id)).await)
+}
+
+/// One file from the signed-in client's own folder.
+#[get("/files/<file_name>")]
+pub async fn portal_file(state: &State<AppState>, session: PortalSession, file_name: &str) -> Option<NamedFile> {
+    let mut components = Path::new(file_name).components();
+    if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+        return None;
+    }
+    let root_path = state.get_root_path()?;
+    NamedFile::open(root_path.join(session.user.client_id.to_string()).join(file_name)).await.ok()
+}
+
+/// Asks staff to change the client's contact details. Nothing changes on
+/// the client record until the request is approved.
+#[post("/contact-changes", format = "json", data = "<change>")]
+pub async fn portal_request_contact_change(
+    state: &State<AppState>,
+    session: PortalSession,
+    change: Json<ContactChange>,
+) -> Result<Json<ContactChangeRequest>, status::Custom<Json<ApiResponse>>> {
+    let mut request = ContactChangeRequest {
+        request_id: None,
+        client_id: session.user.client_id,
+        user_id: session.user.user_id.expect("Stored users have an id"),
+        address: non_empty(&change.address),
+        phone_number: non_empty(&change.phone_number),
+        email: non_empty(&change.email),
+        status: ChangeRequestStatus::Pending,
+        review_note: None,
+        created_at: None,
+        reviewed_at: None,
+    };
+    if request.address.is_none() && request.phone_number.is_none() && request.email.is_none() {
+        return Err(error(
+            Status::UnprocessableEntity,
+            "Provide at least one of address, phone_number or email".to_string(),
+        ));
+    }
+
+    state.with_repository(move |repo| {
+        let request_id = repo.create_change_request(&request)
+            .map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+        request = repo.get_change_request(request_id).ok().flatten().unwrap_or(request);
+        Ok(Json(request))
+    }).await
+}
+
+#[get("/contact-changes")]
+pub async fn portal_contact_changes(state: &State<AppState>, session: PortalSession) -> Json<Vec<ContactChangeRequest>> {
+    state.with_repository(move |repo| {
+        let requests = repo.list_change_requests(Some(session.user.client_id), None)
+            .expect("Failed to execute query");
+        Json(requests)
+    }).await
+}
+
+/// Gives a client a portal sign-in.
+#[post("/clients/<client_id>/portal-users", format = "json", data = "<credentials>")]
+pub async fn create_portal_user(
+    state: &State<AppState>,
+    client_id: i64,
+    credentials: Json<PortalCredentials>,
+) -> Result<Json<PortalUser>, status::Custom<Json<ApiResponse>>> {
+    if credentials.email.trim().is_empty() || credentials.password.len() < PORTAL_PASSWORD_MIN_LEN {
+        return Err(error(
+            Status::UnprocessableEntity,
+            format!("An email and a password of at least {} characters are required", PORTAL_PASSWORD_MIN_LEN),
+        ));
+    }
+
+    state.with_repository(move |repo| {
+        if repo.get_client(client_id).ok().flatten().is_none() {
+            return Err(error(Status::NotFound, format!("Client {} not found", client_id)));
+        }
+        if repo.find_portal_user(&credentials.email).ok().flatten().is_some() {
+            return Err(error(Status::Conflict, "That email already has a portal sign-in".to_string()));
+        }
+
+        repo.create_portal_user(client_id, &credentials.email, &links::hash_password(&credentials.password))
+            .map_err(|e| match e.is_unique_violation() {
+                // Another request took the email since the check above
+                true => error(Status::Conflict, "That email already has a portal sign-in".to_string()),
+                false => error(Status::InternalServerError, format!("Database error: {}", e)),
+            })?;
+        repo.find_portal_user(&credentials.email).ok().flatten()
+            .map(Json)
+            .ok_or_else(|| error(Status::InternalServerError, "Portal user was not saved".to_string()))
+    }).await
+}
+
+#[get("/clients/<client_id>/portal-users")]
+pub async fn list_portal_users(state: &State<AppState>, client_id: i64) -> Json<Vec<PortalUser>> {
+    state.with_repository(move |repo| Json(repo.list_portal_users(client_id).expect("Failed to execute query"))).await
+}
+
+/// Contact change requests for staff review, oldest first.
+#[get("/contact-change-requests?<client_id>&<status>")]
+pub async fn list_contact_change_requests(
+    state: &State<AppState>,
+    client_id: Option<i64>,
+    status: Option<&str>,
+) -> Result<Json<Vec<ContactChangeRequest>>, status::Custom<Json<ApiResponse>>> {
+    let status = match status {
+        Some(value) => Some(ChangeRequestStatus::parse(value).ok_or_else(|| {
+            error(Status::BadRequest, format!("Unknown change request status: {}", value))
+        })?),
+        None => None,
+    };
+
+    let requests = state.with_repository(move |repo| repo.list_change_requests(client_id, status)).await
+        .expect("Failed to execute query");
+    Ok(Json(requests))
+}
+
+#[post("/contact-change-requests/<request_id>/approve", data = "<review>")]
+pub async fn approve_contact_change(
+    state: &State<AppState>,
+    request_id: i64,
+    review: Option<Json<ReviewRequest>>,
+) -> Result<Json<ContactChangeRequest>, status::Custom<Json<ApiResponse>>> {
+    review_contact_change(state, request_id, true, review).await
+}
+
+#[post("/contact-change-requests/<request_id>/reject", data = "<review>")]
+pub async fn reject_contact_change(
+    state: &State<AppState>,
+    request_id: i64,
+    review: Option<Json<ReviewRequest>>,
+) -> Result<Json<ContactChangeRequest>, status::Custom<Json<ApiResponse>>> {
+    review_contact_change(state, request_id, false, review).await
+}
+
+async fn review_contact_change(
+    state: &AppState,
+    request_id: i64,
+    approve: bool,
+    review: Option<Json<ReviewRequest>>,
+) -> Result<Json<ContactChangeRequest>, status::Custom<Json<ApiResponse>>> {
+    let review = review.map(Json::into_inner).unwrap_or_default();
+
+    state.with_repository(move |repo| repo.review_change_request(request_id, approve, review.note.as_deref())).await
+        .map(Json)
+        .map_err(|e| {
+            let code = match e {
+                ReviewError::NotFound => Status::NotFound,
+                ReviewError::AlreadyReviewed(_) => Status::Conflict,
+                ReviewError::Database(_) => Status::InternalServerError,
+            };
+            error(code, e.to_string())
+        })
+}
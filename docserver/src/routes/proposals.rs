@@ -0,0 +1,90 @@
+use rocket::{get, post, State};
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::{IncomeProposal, IncomeProvenance, ProposalStatus};
+
+#[derive(Deserialize, Default)]
+pub struct AcceptProposalRequest {
+    tax_return_id: Option<i64>,
+}
+
+#[get("/documents/<document_id>/proposal")]
+pub async fn get_document_proposal(state: &State<AppState>, document_id: i64) -> Option<Json<IncomeProposal>> {
+    state.with_repository(move |repo| repo.latest_document_proposal(document_id).ok().flatten().map(Json)).await
+}
+
+#[get("/proposals?<client_id>&<status>")]
+pub async fn list_proposals(
+    state: &State<AppState>,
+    client_id: Option<i64>,
+    status: Option<&str>,
+) -> Json<Vec<IncomeProposal>> {
+    let status = status.and_then(|s| match s {
+        "pending" => Some(ProposalStatus::Pending),
+        "accepted" => Some(ProposalStatus::Accepted),
+        "rejected" => Some(ProposalStatus::Rejected),
+        _ => None,
+    });
+
+    state.with_repository(move |repo| {
+        let proposals = repo.list_proposals(client_id, status)
+            .expect("Failed to execute query");
+        Json(proposals)
+    }).await
+}
+
+#[post("/proposals/<proposal_id>/accept", data = "<request>")]
+pub async fn accept_proposal(
+    state: &State<AppState>,
+    proposal_id: i64,
+    request: Option<Json<AcceptProposalRequest>>,
+) -> Json<ApiResponse> {
+    let tax_return_id = request.and_then(|r| r.tax_return_id);
+
+    state.with_repository(move |repo| {
+        match repo.accept_proposal(proposal_id, tax_return_id) {
+            Ok(proposal) => Json(ApiResponse {
+                status: "success".to_string(),
+                message: format!(
+                    "Proposal accepted into tax return {}",
+                    proposal.tax_return_id.unwrap_or_default()
+                ),
+            }),
+            Err(e) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }).await
+}
+
+#[post("/proposals/<proposal_id>/reject")]
+pub async fn reject_proposal(state: &State<AppState>, proposal_id: i64) -> Json<ApiResponse> {
+    state.with_repository(move |repo| {
+        match repo.reject_proposal(proposal_id) {
+            Ok(true) => Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Proposal rejected".to_string(),
+            }),
+            Ok(false) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: "Proposal not found or already reviewed".to_string(),
+            }),
+            Err(e) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: format!("Database error: {}", e),
+            }),
+        }
+    }).await
+}
+
+#[get("/returns/<tax_return_id>/provenance")]
+pub async fn get_return_provenance(state: &State<AppState>, tax_return_id: i64) -> Json<Vec<IncomeProvenance>> {
+    state.with_repository(move |repo| {
+        let provenance = repo.list_return_provenance(tax_return_id)
+            .expect("Failed to execute query");
+        Json(provenance)
+    }).await
+}
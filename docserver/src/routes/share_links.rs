@@ -0,0 +1,276 @@
+use chrono::{Duration, Utc};
+use rocket::{get, post, State};
+use rocket::form::{Form, FromForm};
+use rocket::fs::NamedFile;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::{Component, Path};
+use std::time::Duration as StdDuration;
+
+use crate::config::{
+    unblock, AppState, ApiResponse, DEFAULT_SHARE_LINK_HOURS, PUBLIC_RATE_WINDOW_SECS,
+    PUBLIC_REQUESTS_PER_ADDRESS, PUBLIC_REQUESTS_PER_LINK,
+};
+use crate::db::{AccessOutcome, LinkStatus, ShareLink, ShareLinkAccess, StoreError};
+use crate::links::{self, LinkPurpose, LinkToken};
+use super::error;
+
+#[derive(Deserialize)]
+pub struct CreateShareLinkRequest {
+    path: String,
+    expires_in_hours: Option<i64>,
+    password: Option<String>,
+    max_downloads: Option<i64>,
+    recipient: Option<String>,
+}
+
+/// A newly created link. `token` is not stored and cannot be shown again.
+#[derive(Serialize)]
+pub struct IssuedShareLink {
+    link: ShareLink,
+    token: String,
+    path: String,
+}
+
+#[derive(FromForm)]
+pub struct SharePassword {
+    password: String,
+}
+
+/// Who is calling, for the access log.
+pub struct RemoteClient {
+    address: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RemoteClient {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(RemoteClient {
+            address: request.client_ip().map(|ip| ip.to_string()),
+            user_agent: request.headers().get_one("User-Agent").map(str::to_string),
+        })
+    }
+}
+
+/// Turns a path as served by `/files/<path>` into its normalized form,
+/// refusing anything that would leave the root directory.
+fn shareable_path(root_path: &Path, path: &str) -> Option<String> {
+    let relative = Path::new(path);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let full_path = root_path.join(relative);
+    if !full_path.is_file() {
+        return None;
+    }
+    let parts: Vec<String> = relative.components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some(parts.join("/"))
+}
+
+/// Mints a download link for one file under the root path.
+#[post("/share-links", format = "json", data = "<request>")]
+pub async fn create_share_link(
+    state: &State<AppState>,
+    request: Json<CreateShareLinkRequest>,
+) -> Result<Json<IssuedShareLink>, status::Custom<Json<ApiResponse>>> {
+    let root_path = state.get_root_path()
+        .ok_or_else(|| error(Status::ServiceUnavailable, "Root path has not been set".to_string()))?;
+    let file_path = shareable_path(&root_path, &request.path)
+        .ok_or_else(|| error(Status::NotFound, format!("No file at {}", request.path)))?;
+
+    let hours = request.expires_in_hours.unwrap_or(DEFAULT_SHARE_LINK_HOURS);
+    if hours <= 0 || request.max_downloads.is_some_and(|max| max <= 0) {
+        return Err(error(
+            Status::UnprocessableEntity,
+            "expires_in_hours and max_downloads must be positive".to_string(),
+        ));
+    }
+    if request.password.as_deref().is_some_and(str::is_empty) {
+        return Err(error(Status::UnprocessableEntity, "password must not be empty".to_string()));
+    }
+
+    let now = Utc::now();
+    let mut link = ShareLink {
+        link_id: None,
+        // Files live in per-client folders named by client id
+        client_id: file_path.split('/').next().and_then(|folder| folder.parse().ok()),
+        file_path,
+        recipient: request.recipient.clone(),
+        nonce: links::new_nonce(),
+        // Hashed below, off the async workers
+        password_hash: None,
+        password_protected: request.password.is_some(),
+        max_downloads: request.max_downloads,
+        download_count: 0,
+        expires_at: links::expiry_after(now, Duration::hours(hours)),
+        revoked_at: None,
+        created_at: None,
+        status: LinkStatus::Open,
+    };
+
+    let password = request.into_inner().password;
+    let link = state.with_repository(move |repo| {
+        link.password_hash = password.as_deref().map(links::hash_password);
+        let link_id = repo.create_share_link(&link)?;
+        link.link_id = Some(link_id);
+        Ok::<_, StoreError>(repo.get_share_link(link_id, now).ok().flatten().unwrap_or(link))
+    }).await;
+    let link = link.map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+
+    let link_id = link.link_id.expect("Stored links have an id");
+    let token = LinkToken { link_id, nonce: link.nonce.clone(), expires_at: link.expires_at }
+        .encode(state.signing_key(), LinkPurpose::Download);
+
+    Ok(Json(IssuedShareLink {
+        path: format!("/public/share/{}", token),
+        link,
+        token,
+    }))
+}
+
+#[get("/share-links?<client_id>&<status>")]
+pub async fn list_share_links(
+    state: &State<AppState>,
+    client_id: Option<i64>,
+    status: Option<&str>,
+) -> Result<Json<Vec<ShareLink>>, status::Custom<Json<ApiResponse>>> {
+    let status = match status {
+        Some(value) => Some(LinkStatus::parse(value).ok_or_else(|| {
+            error(Status::BadRequest, format!("Unknown link status: {}", value))
+        })?),
+        None => None,
+    };
+
+    let links = state.with_repository(move |repo| repo.list_share_links(client_id, status, Utc::now())).await
+        .expect("Failed to execute query");
+    Ok(Json(links))
+}
+
+#[get("/share-links/<link_id>/accesses")]
+pub async fn list_share_link_accesses(state: &State<AppState>, link_id: i64) -> Json<Vec<ShareLinkAccess>> {
+    state.with_repository(move |repo| Json(repo.list_share_accesses(link_id).expect("Failed to execute query"))).await
+}
+
+#[post("/share-links/<link_id>/revoke")]
+pub async fn revoke_share_link(state: &State<AppState>, link_id: i64) -> Json<ApiResponse> {
+    state.with_repository(move |repo| {
+        match repo.revoke_share_link(link_id) {
+            Ok(true) => Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Share link revoked".to_string(),
+            }),
+            Ok(false) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: "Share link not found or already revoked".to_string(),
+            }),
+            Err(e) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: format!("Database error: {}", e),
+            }),
+        }
+    }).await
+}
+
+/// Public download through a share link without a password.
+#[get("/public/share/<token>")]
+pub async fn public_download(
+    state: &State<AppState>,
+    token: &str,
+    remote: RemoteClient,
+) -> Result<NamedFile, status::Custom<Json<ApiResponse>>> {
+    serve_share_link(state, token, None, &remote).await
+}
+
+/// Public download through a password-protected share link; the password is
+/// posted as a form field so it stays out of URLs and logs.
+#[post("/public/share/<token>", data = "<form>")]
+pub async fn public_download_with_password(
+    state: &State<AppState>,
+    token: &str,
+    form: Form<SharePassword>,
+    remote: RemoteClient,
+) -> Result<NamedFile, status::Custom<Json<ApiResponse>>> {
+    serve_share_link(state, token, Some(&form.password), &remote).await
+}
+
+async fn serve_share_link(
+    state: &AppState,
+    token: &str,
+    password: Option<&str>,
+    remote: &RemoteClient,
+) -> Result<NamedFile, status::Custom<Json<ApiResponse>>> {
+    let window = StdDuration::from_secs(PUBLIC_RATE_WINDOW_SECS);
+    let address = remote.address.as_deref().unwrap_or("unknown");
+    if !state.rate_limiter().check(&format!("address:{}", address), PUBLIC_REQUESTS_PER_ADDRESS, window) {
+        return Err(error(Status::TooManyRequests, "Too many requests; try again later".to_string()));
+    }
+
+    let now = Utc::now();
+    let unavailable = || error(Status::NotFound, "This link is invalid or has expired".to_string());
+    let token = LinkToken::decode(token, state.signing_key(), LinkPurpose::Download, now).ok_or_else(unavailable)?;
+    // Also bounds password guessing against a single link
+    if !state.rate_limiter().check(&format!("share_link:{}", token.link_id), PUBLIC_REQUESTS_PER_LINK, window) {
+        return Err(error(Status::TooManyRequests, "Too many requests; try again later".to_string()));
+    }
+
+    let root_path = state.get_root_path().ok_or_else(unavailable)?;
+    let link_id = token.link_id;
+    let record = |outcome| record_access(state, link_id, outcome, remote);
+
+    let link = state.with_repository(move |repo| repo.get_share_link(link_id, now)).await.ok().flatten();
+    let link = link.filter(|link| link.nonce == token.nonce).ok_or_else(unavailable)?;
+    if link.status != LinkStatus::Open {
+        record(AccessOutcome::Unavailable).await;
+        return Err(unavailable());
+    }
+
+    // The password is checked without holding a database connection
+    if let Some(hash) = link.password_hash.clone() {
+        let Some(password) = password.map(str::to_string) else {
+            record(AccessOutcome::PasswordRequired).await;
+            return Err(error(Status::Unauthorized, "This link requires a password".to_string()));
+        };
+        if !unblock(move || links::verify_password(&password, &hash)).await {
+            record(AccessOutcome::WrongPassword).await;
+            return Err(error(Status::Forbidden, "Incorrect password".to_string()));
+        }
+    }
+
+    let nonce = token.nonce;
+    let claimed = state.with_repository(move |repo| repo.claim_download(link_id, &nonce, now)).await.unwrap_or(false);
+    if !claimed {
+        record(AccessOutcome::Unavailable).await;
+        return Err(unavailable());
+    }
+    record(AccessOutcome::Downloaded).await;
+    let file_path = link.file_path;
+
+    match NamedFile::open(root_path.join(&file_path)).await {
+        Ok(file) => Ok(file),
+        Err(_) => {
+            if let Err(e) = state.with_repository(move |repo| repo.release_download(link_id)).await {
+                eprintln!("Failed to release share link {}: {}", link_id, e);
+            }
+            Err(unavailable())
+        }
+    }
+}
+
+async fn record_access(state: &AppState, link_id: i64, outcome: AccessOutcome, remote: &RemoteClient) {
+    let (address, user_agent) = (remote.address.clone(), remote.user_agent.clone());
+    let recorded = state.with_repository(move |repo| {
+        repo.record_share_access(link_id, outcome, address.as_deref(), user_agent.as_deref())
+    }).await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to record access to share link {}: {}", link_id, e);
+    }
+}
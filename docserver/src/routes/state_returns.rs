@@ -0,0 +1,48 @@
+use rocket::{get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::returns::{StateReturn, StateReturnError};
+use crate::db::{Jurisdiction, TaxReturn};
+use crate::tax;
+use super::error;
+
+/// Files a state return with its own figures alongside a federal return.
+#[post("/returns/<federal_return_id>/state-returns", format = "json", data = "<state_return>")]
+pub async fn add_state_return(
+    state: &State<AppState>,
+    federal_return_id: i64,
+    state_return: Json<StateReturn>,
+) -> Result<Json<TaxReturn>, status::Custom<Json<ApiResponse>>> {
+    let state_return = state_return.into_inner();
+    if let Jurisdiction::State(code) = &state_return.state {
+        if tax::state_deadline(code).is_none() {
+            return Err(error(
+                Status::UnprocessableEntity,
+                format!("{} has no individual income tax return", code),
+            ));
+        }
+    }
+
+    state.with_repository(move |repo| repo.add_state_return(federal_return_id, state_return)).await
+        .map(Json)
+        .map_err(|e| {
+            let code = match e {
+                StateReturnError::FederalNotFound => Status::NotFound,
+                StateReturnError::Superseded(_) | StateReturnError::Exists(_) => Status::Conflict,
+                StateReturnError::NotFederal | StateReturnError::NotAState | StateReturnError::UnknownCategory { .. } => {
+                    Status::UnprocessableEntity
+                }
+                StateReturnError::Database(_) => Status::InternalServerError,
+            };
+            error(code, e.to_string())
+        })
+}
+
+#[get("/returns/<federal_return_id>/state-returns")]
+pub async fn list_state_returns(state: &State<AppState>, federal_return_id: i64) -> Json<Vec<TaxReturn>> {
+    let returns = state.with_repository(move |repo| repo.state_returns(federal_return_id)).await;
+    Json(returns.expect("Failed to execute query"))
+}
@@ -0,0 +1,106 @@
+use rocket::{get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::Serialize;
+
+use crate::config::{AppState, ApiResponse};
+use crate::tax::{self, ComputationReport, RuleFileError, TaxYearRules};
+use crate::db::{HouseholdMember, OfficeRepository, StoreResult, TaxReturn};
+use super::error;
+
+#[derive(Serialize)]
+pub struct TaxYearSummary {
+    tax_year: i32,
+    version: String,
+    file: String,
+}
+
+#[derive(Serialize)]
+pub struct TaxRulesStatus {
+    directory: Option<String>,
+    years: Vec<TaxYearSummary>,
+    errors: Vec<RuleFileError>,
+}
+
+fn rules_status(rule_set: &tax::RuleSet) -> TaxRulesStatus {
+    TaxRulesStatus {
+        directory: rule_set.directory.as_ref().map(|d| d.to_string_lossy().to_string()),
+        years: rule_set.years.values().map(|loaded| TaxYearSummary {
+            tax_year: loaded.rules.tax_year,
+            version: loaded.rules.version.clone(),
+            file: loaded.file.clone(),
+        }).collect(),
+        errors: rule_set.errors.clone(),
+    }
+}
+
+/// The household members a return covers. A return with none linked, even
+/// one whose client has a household on file, is left to its own entered
+/// credits.
+pub(crate) fn covered_household(
+    repo: &dyn OfficeRepository,
+    tax_return: &TaxReturn,
+) -> StoreResult<Option<Vec<HouseholdMember>>> {
+    let tax_return_id = tax_return.tax_return_id.expect("Stored returns have an id");
+    let members = repo.return_household(tax_return_id)?;
+    Ok(Some(members).filter(|members| !members.is_empty()))
+}
+
+/// Recomputes a return from its income, deductions and credits, and flags
+/// where the stored liability or refund disagrees. Only federal returns can
+/// be computed; the rule tables carry no state brackets.
+#[get("/returns/<tax_return_id>/computation")]
+pub async fn get_return_computation(
+    state: &State<AppState>,
+    tax_return_id: i64,
+) -> Result<Json<ComputationReport>, status::Custom<Json<ApiResponse>>> {
+    let (tax_return, household) = state.with_repository(move |repo| {
+        let tax_return = repo.get_tax_return(tax_return_id).ok().flatten()
+            .ok_or_else(|| error(Status::NotFound, format!("Tax return {} not found", tax_return_id)))?;
+        if !tax_return.jurisdiction.is_federal() {
+            return Err(error(
+                Status::UnprocessableEntity,
+                format!("Tax return {} is a {} return; only federal returns can be computed", tax_return_id, tax_return.jurisdiction),
+            ));
+        }
+        let household = covered_household(repo, &tax_return)
+            .map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+        Ok((tax_return, household))
+    }).await?;
+
+    let rule_set = state.get_tax_rules();
+    let rules = rule_set.get(tax_return.tax_year).ok_or_else(|| error(
+        Status::UnprocessableEntity,
+        rule_set.missing_year_message(tax_return.tax_year),
+    ))?;
+
+    tax::check(&tax_return, household.as_deref(), rules)
+        .map(Json)
+        .map_err(|e| error(Status::UnprocessableEntity, e.to_string()))
+}
+
+#[get("/tax-rules")]
+pub async fn list_tax_rules(state: &State<AppState>) -> Json<TaxRulesStatus> {
+    Json(rules_status(&state.get_tax_rules()))
+}
+
+#[get("/tax-rules/<tax_year>")]
+pub async fn get_tax_rules(
+    state: &State<AppState>,
+    tax_year: i32,
+) -> Result<Json<TaxYearRules>, status::Custom<Json<ApiResponse>>> {
+    let rule_set = state.get_tax_rules();
+    rule_set.get(tax_year)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, rule_set.missing_year_message(tax_year)))
+}
+
+/// Picks up new or edited parameter files without a restart.
+#[post("/tax-rules/reload")]
+pub async fn reload_tax_rules(state: &State<AppState>) -> Result<Json<TaxRulesStatus>, status::Custom<Json<ApiResponse>>> {
+    state.reload_tax_rules_in_background().await
+        .map(|rule_set| Json(rules_status(&rule_set)))
+        .ok_or_else(|| error(Status::Conflict, "Root path has not been set".to_string()))
+}
@@ -0,0 +1,192 @@
+use chrono::{Duration, Utc};
+use rocket::{get, post, State};
+use rocket::data::Data;
+use rocket::http::{ContentType, Status};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::time::Duration as StdDuration;
+
+use crate::config::{
+    AppState, ApiResponse, DEFAULT_UPLOAD_LINK_HOURS, PUBLIC_RATE_WINDOW_SECS,
+    PUBLIC_REQUESTS_PER_ADDRESS, PUBLIC_REQUESTS_PER_LINK,
+};
+use crate::db::{LinkStatus, UploadLink};
+use crate::links::{self, LinkPurpose, LinkToken};
+use super::error;
+use super::files::{refuse_during_relocation, store_uploads};
+
+#[derive(Deserialize, Default)]
+pub struct CreateUploadLinkRequest {
+    expires_in_hours: Option<i64>,
+    checklist_item_id: Option<i64>,
+    max_uploads: Option<i64>,
+    note: Option<String>,
+}
+
+/// A newly created link. `token` is not stored and cannot be shown again.
+#[derive(Serialize)]
+pub struct IssuedUploadLink {
+    link: UploadLink,
+    token: String,
+    path: String,
+}
+
+#[post("/clients/<client_id>/upload-links", data = "<request>")]
+pub async fn create_upload_link(
+    state: &State<AppState>,
+    client_id: i64,
+    request: Option<Json<CreateUploadLinkRequest>>,
+) -> Result<Json<IssuedUploadLink>, status::Custom<Json<ApiResponse>>> {
+    let request = request.map(Json::into_inner).unwrap_or_default();
+    let hours = request.expires_in_hours.unwrap_or(DEFAULT_UPLOAD_LINK_HOURS);
+    let max_uploads = request.max_uploads.unwrap_or(1);
+    if hours <= 0 || max_uploads <= 0 {
+        return Err(error(
+            Status::UnprocessableEntity,
+            "expires_in_hours and max_uploads must be positive".to_string(),
+        ));
+    }
+
+    let now = Utc::now();
+    let nonce = links::new_nonce();
+    let expires_at = links::expiry_after(now, Duration::hours(hours));
+    let stored_nonce = nonce.clone();
+    let link = state.with_repository(move |repo| {
+        if let Some(item_id) = request.checklist_item_id {
+            let item = repo.get_checklist_item(item_id).expect("Failed to execute query");
+            if item.is_none_or(|item| item.client_id != client_id) {
+                return Err(error(
+                    Status::UnprocessableEntity,
+                    format!("Checklist item {} does not belong to client {}", item_id, client_id),
+                ));
+            }
+        }
+
+        let link_id = repo.create_upload_link(
+            client_id,
+            request.checklist_item_id,
+            request.note.as_deref(),
+            max_uploads,
+            &stored_nonce,
+            expires_at,
+        ).map_err(|e| error(Status::InternalServerError, format!("Database error: {}", e)))?;
+        repo.get_upload_link(link_id, now)
+            .ok()
+            .flatten()
+            .ok_or_else(|| error(Status::InternalServerError, "Upload link was not saved".to_string()))
+    }).await?;
+
+    let link_id = link.link_id.expect("Stored links have an id");
+    let token = LinkToken { link_id, nonce, expires_at }.encode(state.signing_key(), LinkPurpose::Upload);
+
+    Ok(Json(IssuedUploadLink {
+        path: format!("/public/upload/{}", token),
+        link,
+        token,
+    }))
+}
+
+/// Upload links with their current status, for seeing which are still open,
+/// used or expired.
+#[get("/upload-links?<client_id>&<status>")]
+pub async fn list_upload_links(
+    state: &State<AppState>,
+    client_id: Option<i64>,
+    status: Option<&str>,
+) -> Result<Json<Vec<UploadLink>>, status::Custom<Json<ApiResponse>>> {
+    let status = match status {
+        Some(value) => Some(LinkStatus::parse(value).ok_or_else(|| {
+            error(Status::BadRequest, format!("Unknown link status: {}", value))
+        })?),
+        None => None,
+    };
+
+    let links = state.with_repository(move |repo| repo.list_upload_links(client_id, status, Utc::now())).await
+        .expect("Failed to execute query");
+    Ok(Json(links))
+}
+
+#[post("/upload-links/<link_id>/revoke")]
+pub async fn revoke_upload_link(state: &State<AppState>, link_id: i64) -> Json<ApiResponse> {
+    state.with_repository(move |repo| {
+        match repo.revoke_upload_link(link_id) {
+            Ok(true) => Json(ApiResponse {
+                status: "success".to_string(),
+                message: "Upload link revoked".to_string(),
+            }),
+            Ok(false) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: "Upload link not found or already revoked".to_string(),
+            }),
+            Err(e) => Json(ApiResponse {
+                status: "error".to_string(),
+                message: format!("Database error: {}", e),
+            }),
+        }
+    }).await
+}
+
+/// Public, unauthenticated upload through a link. Feeds the same pipeline as
+/// `POST /files/upload/<client_id>` but answers with nothing beyond whether
+/// the files were received.
+#[post("/public/upload/<token>", data = "<data>")]
+pub async fn public_upload(
+    state: &State<AppState>,
+    token: &str,
+    content_type: &ContentType,
+    data: Data<'_>,
+    remote: Option<IpAddr>,
+) -> status::Custom<Json<ApiResponse>> {
+    let window = StdDuration::from_secs(PUBLIC_RATE_WINDOW_SECS);
+    let address = remote.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    if !state.rate_limiter().check(&format!("address:{}", address), PUBLIC_REQUESTS_PER_ADDRESS, window) {
+        return error(Status::TooManyRequests, "Too many requests; try again later".to_string());
+    }
+
+    if let Err(refused) = refuse_during_relocation(state) {
+        return refused;
+    }
+
+    // Forged, tampered and expired tokens all look the same from outside
+    let now = Utc::now();
+    let invalid = || error(Status::NotFound, "This upload link is invalid or has expired".to_string());
+    let Some(token) = LinkToken::decode(token, state.signing_key(), LinkPurpose::Upload, now) else {
+        return invalid();
+    };
+    if !state.rate_limiter().check(&format!("upload_link:{}", token.link_id), PUBLIC_REQUESTS_PER_LINK, window) {
+        return error(Status::TooManyRequests, "Too many requests; try again later".to_string());
+    }
+
+    let link_id = token.link_id;
+    let link = state.with_repository(move |repo| repo.claim_upload(link_id, &token.nonce, now)).await.ok().flatten();
+    let Some(link) = link else {
+        return invalid();
+    };
+
+    let stored = store_uploads(state, content_type, data, &link.client_id.to_string()).await;
+
+    if stored.is_empty() {
+        if let Err(e) = state.with_repository(move |repo| repo.release_upload(link_id)).await {
+            eprintln!("Failed to release upload link {}: {}", link_id, e);
+        }
+        return error(Status::BadRequest, "No files were received".to_string());
+    }
+
+    // A link requested for a specific checklist item is answered by its first file
+    if let Some(item_id) = link.checklist_item_id {
+        let document_id = stored.iter().find_map(|upload| upload.document.as_ref()?.document_id);
+        if let Some(document_id) = document_id {
+            let marked = state.with_repository(move |repo| repo.mark_checklist_item_received(item_id, document_id)).await;
+            if let Err(e) = marked {
+                eprintln!("Failed to update checklist item {}: {}", item_id, e);
+            }
+        }
+    }
+
+    status::Custom(Status::Ok, Json(ApiResponse {
+        status: "success".to_string(),
+        message: format!("Received {} file(s)", stored.len()),
+    }))
+}
@@ -0,0 +1,87 @@
+use rocket::{get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::config::{AppState, ApiResponse};
+use crate::db::workflow::TransitionError;
+use crate::db::{ReturnStatus, StatusChange, TaxReturn};
+use super::error;
+
+#[derive(Deserialize)]
+pub struct TransitionRequest {
+    status: ReturnStatus,
+    /// Absent keeps the current assignee; `null` clears it.
+    #[serde(default, deserialize_with = "present")]
+    assignee: Option<Option<String>>,
+    note: Option<String>,
+}
+
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize)]
+pub struct BoardColumn {
+    status: ReturnStatus,
+    returns: Vec<TaxReturn>,
+}
+
+#[post("/returns/<tax_return_id>/transition", format = "json", data = "<request>")]
+pub async fn transition_return(
+    state: &State<AppState>,
+    tax_return_id: i64,
+    request: Json<TransitionRequest>,
+) -> Result<Json<TaxReturn>, status::Custom<Json<ApiResponse>>> {
+    state.with_repository(move |repo| {
+        repo.transition_return(
+            tax_return_id,
+            request.status,
+            request.assignee.as_ref().map(Option::as_deref),
+            request.note.as_deref(),
+        )
+        .map(Json)
+        .map_err(|e| {
+            let code = match e {
+                TransitionError::NotFound => Status::NotFound,
+                TransitionError::NotAllowed { .. } => Status::Conflict,
+                TransitionError::Database(_) => Status::InternalServerError,
+            };
+            error(code, e.to_string())
+        })
+    }).await
+}
+
+#[get("/returns/<tax_return_id>/history")]
+pub async fn get_return_history(state: &State<AppState>, tax_return_id: i64) -> Json<Vec<StatusChange>> {
+    state.with_repository(move |repo| Json(repo.status_history(tax_return_id).expect("Failed to execute query"))).await
+}
+
+/// Effective returns grouped by workflow stage, in workflow order, for the
+/// board view. `status` narrows it to one column and `assignee` to one person.
+#[get("/workflow/board?<status>&<assignee>")]
+pub async fn get_workflow_board(
+    state: &State<AppState>,
+    status: Option<&str>,
+    assignee: Option<&str>,
+) -> Result<Json<Vec<BoardColumn>>, status::Custom<Json<ApiResponse>>> {
+    let statuses = match status {
+        Some(value) => vec![ReturnStatus::parse(value).ok_or_else(|| {
+            error(Status::BadRequest, format!("Unknown return status: {}", value))
+        })?],
+        None => ReturnStatus::ALL.to_vec(),
+    };
+
+    let assignee = assignee.map(str::to_string);
+    let mut returns = state.with_repository(move |repo| repo.list_by_status(None, assignee.as_deref())).await
+        .expect("Failed to execute query");
+    let columns = statuses.into_iter().map(|status| {
+        let (in_column, rest): (Vec<TaxReturn>, Vec<TaxReturn>) =
+            returns.drain(..).partition(|r| r.status == status);
+        returns = rest;
+        BoardColumn { status, returns: in_column }
+    }).collect();
+
+    Ok(Json(columns))
+}
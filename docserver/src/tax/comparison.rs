@@ -0,0 +1,338 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::db::{LineItem, LineItemSection, TaxReturn};
+use crate::money::Money;
+
+/// Marks a line that only one side of a year-to-year comparison has, such
+/// as a W-2 employer that did not issue one the next year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeFlag {
+    New,
+    Disappeared,
+}
+
+impl ChangeFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeFlag::New => "new",
+            ChangeFlag::Disappeared => "disappeared",
+        }
+    }
+}
+
+/// The move of one line from a year to the next compared year.
+/// `percent_change` is relative to the earlier amount and is left out when
+/// that amount is missing or zero.
+#[derive(Debug, Serialize)]
+pub struct YearChange {
+    pub from_year: i32,
+    pub to_year: i32,
+    pub change: Money,
+    pub percent_change: Option<f64>,
+    pub flag: Option<ChangeFlag>,
+}
+
+/// One line across the compared years: a category and payer within a
+/// section, a category's subtotal, or one of the return totals (section
+/// `totals`).
+#[derive(Debug, Serialize)]
+pub struct ComparisonLine {
+    pub section: String,
+    pub category: String,
+    pub payer: Option<String>,
+    // The whole category, following its payer lines
+    pub subtotal: bool,
+    // One entry per compared year, `None` where that year has no such line
+    pub amounts: Vec<Option<Money>>,
+    pub changes: Vec<YearChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct YearComparison {
+    pub client_id: i64,
+    pub years: Vec<i32>,
+    pub tax_return_ids: Vec<Option<i64>>,
+    pub filing_statuses: Vec<String>,
+    pub lines: Vec<ComparisonLine>,
+}
+
+/// A return to compare, with its line items.
+pub struct ComparedYear<'a> {
+    pub tax_return: &'a TaxReturn,
+    pub line_items: &'a [LineItem],
+}
+
+/// One category's lines in each compared year: amounts by payer, and the
+/// amount entered without one.
+struct CategoryAmounts<'a> {
+    by_payer: BTreeMap<&'a str, Vec<Option<Money>>>,
+    unattributed: Vec<Option<Money>>,
+    total: Vec<Option<Money>>,
+}
+
+fn add(amounts: &mut [Option<Money>], index: usize, amount: Money) {
+    amounts[index] = Some(amounts[index].unwrap_or_default() + amount);
+}
+
+/// Compares returns line by line, oldest year first. Lines with the same
+/// category and payer in one year are added together, and a category with
+/// several lines gets a subtotal. Lines entered without a payer are only
+/// compared through that subtotal: a year that has them may hold any payer,
+/// so payers are not flagged new or disappeared against it.
+pub fn compare_years(years: &[ComparedYear]) -> YearComparison {
+    let year_numbers: Vec<i32> = years.iter().map(|y| y.tax_return.tax_year).collect();
+    let mut lines = Vec::new();
+
+    for section in LineItemSection::ALL {
+        let mut categories: BTreeMap<&str, CategoryAmounts> = BTreeMap::new();
+        for (index, year) in years.iter().enumerate() {
+            for item in year.line_items.iter().filter(|item| item.section == section) {
+                let category = categories.entry(item.category.as_str()).or_insert_with(|| CategoryAmounts {
+                    by_payer: BTreeMap::new(),
+                    unattributed: vec![None; years.len()],
+                    total: vec![None; years.len()],
+                });
+                match item.payer.as_deref() {
+                    Some(payer) => {
+                        let amounts = category.by_payer.entry(payer).or_insert_with(|| vec![None; years.len()]);
+                        add(amounts, index, item.amount);
+                    }
+                    None => add(&mut category.unattributed, index, item.amount),
+                }
+                add(&mut category.total, index, item.amount);
+            }
+        }
+
+        for (category, amounts) in categories {
+            let has_unattributed = amounts.unattributed.iter().any(Option::is_some);
+            if amounts.by_payer.is_empty() {
+                lines.push(line(section.as_str(), category, None, false, amounts.total, &year_numbers));
+                continue;
+            }
+            let subtotal = has_unattributed || amounts.by_payer.len() > 1;
+            for (payer, payer_amounts) in amounts.by_payer {
+                let mut payer_line = line(section.as_str(), category, Some(payer.to_string()), false, payer_amounts, &year_numbers);
+                for (index, change) in payer_line.changes.iter_mut().enumerate() {
+                    if amounts.unattributed[index].is_some() || amounts.unattributed[index + 1].is_some() {
+                        change.flag = None;
+                    }
+                }
+                lines.push(payer_line);
+            }
+            if subtotal {
+                lines.push(line(section.as_str(), category, None, true, amounts.total, &year_numbers));
+            }
+        }
+    }
+
+    let totals = [
+        ("taxes_paid", years.iter().map(|y| Some(y.tax_return.taxes_paid)).collect()),
+        ("tax_liability", years.iter().map(|y| Some(y.tax_return.tax_liability)).collect()),
+        ("refund_or_amount_due", years.iter().map(|y| Some(y.tax_return.refund_or_amount_due)).collect()),
+    ];
+    for (field, amounts) in totals {
+        lines.push(line("totals", field, None, false, amounts, &year_numbers));
+    }
+
+    YearComparison {
+        client_id: years.first().map(|y| y.tax_return.client_id).unwrap_or_default(),
+        years: year_numbers,
+        tax_return_ids: years.iter().map(|y| y.tax_return.tax_return_id).collect(),
+        filing_statuses: years.iter().map(|y| y.tax_return.filing_status.clone()).collect(),
+        lines,
+    }
+}
+
+fn line(
+    section: &str,
+    category: &str,
+    payer: Option<String>,
+    subtotal: bool,
+    amounts: Vec<Option<Money>>,
+    years: &[i32],
+) -> ComparisonLine {
+    let changes = amounts
+        .windows(2)
+        .zip(years.windows(2))
+        .map(|(pair, year_pair)| change(pair[0], pair[1], year_pair[0], year_pair[1]))
+        .collect();
+    ComparisonLine {
+        section: section.to_string(),
+        category: category.to_string(),
+        payer,
+        subtotal,
+        amounts,
+        changes,
+    }
+}
+
+fn change(before: Option<Money>, after: Option<Money>, from_year: i32, to_year: i32) -> YearChange {
+    let change = after.unwrap_or_default() - before.unwrap_or_default();
+    let percent_change = before
+        .filter(|amount| !amount.is_zero())
+        .map(|amount| (change.to_f64() / amount.abs().to_f64() * 1000.0).round() / 10.0);
+    let flag = match (before, after) {
+        (None, Some(_)) => Some(ChangeFlag::New),
+        (Some(_), None) => Some(ChangeFlag::Disappeared),
+        _ => None,
+    };
+    YearChange { from_year, to_year, change, percent_change, flag }
+}
+
+impl YearComparison {
+    /// The comparison as CSV: one row per line, marked when it is a category
+    /// subtotal, an amount column per year,
+    /// then change, percent and flag columns for each pair of years.
+    pub fn to_csv(&self) -> String {
+        let mut header = vec![
+            "section".to_string(),
+            "category".to_string(),
+            "payer".to_string(),
+            "subtotal".to_string(),
+        ];
+        header.extend(self.years.iter().map(|year| year.to_string()));
+        for pair in self.years.windows(2) {
+            let span = format!("{}_{}", pair[0], pair[1]);
+            header.push(format!("change_{}", span));
+            header.push(format!("percent_{}", span));
+            header.push(format!("flag_{}", span));
+        }
+
+        let mut csv = csv_row(&header);
+        for line in &self.lines {
+            let mut fields = vec![
+                line.section.clone(),
+                line.category.clone(),
+                line.payer.clone().unwrap_or_default(),
+                if line.subtotal { "subtotal".to_string() } else { String::new() },
+            ];
+            fields.extend(line.amounts.iter().map(|a| a.map(|a| a.to_string()).unwrap_or_default()));
+            for change in &line.changes {
+                fields.push(change.change.to_string());
+                fields.push(change.percent_change.map(|p| format!("{:.1}", p)).unwrap_or_default());
+                fields.push(change.flag.map(|f| f.as_str().to_string()).unwrap_or_default());
+            }
+            csv.push_str(&csv_row(&fields));
+        }
+        csv
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    format!("{}\r\n", quoted.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn tax_return(tax_year: i32, tax_liability: i64) -> TaxReturn {
+        TaxReturn {
+            tax_return_id: Some(tax_year as i64),
+            client_id: 1,
+            tax_year,
+            filing_status: "Single".to_string(),
+            income_sources: HashMap::new(),
+            deductions: HashMap::new(),
+            credits: HashMap::new(),
+            taxes_paid: Money::ZERO,
+            tax_liability: Money::from_dollars(tax_liability),
+            refund_or_amount_due: Money::ZERO,
+            return_kind: Default::default(),
+            parent_return_id: None,
+            amendment_reason: None,
+            status: Default::default(),
+            assignee: None,
+            jurisdiction: Default::default(),
+            federal_return_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn wages(payer: &str, dollars: i64) -> LineItem {
+        LineItem {
+            line_item_id: None,
+            tax_return_id: 0,
+            section: LineItemSection::Income,
+            category: "wages".to_string(),
+            payer: Some(payer.to_string()),
+            amount: Money::from_dollars(dollars),
+            document_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_compare_flags_new_and_missing_payers() {
+        let (earlier, later) = (tax_return(2022, 4_000), tax_return(2023, 5_000));
+        let earlier_items = [wages("Acme", 40_000), wages("Globex", 10_000)];
+        let later_items = [wages("Acme", 50_000), wages("Initech", 5_000)];
+
+        let comparison = compare_years(&[
+            ComparedYear { tax_return: &earlier, line_items: &earlier_items },
+            ComparedYear { tax_return: &later, line_items: &later_items },
+        ]);
+        assert_eq!(comparison.years, vec![2022, 2023]);
+
+        let find = |category: &str, payer: Option<&str>| {
+            comparison.lines.iter()
+                .find(|l| l.category == category && l.payer.as_deref() == payer)
+                .unwrap()
+        };
+        let acme = &find("wages", Some("Acme")).changes[0];
+        assert_eq!(acme.change, Money::from_dollars(10_000));
+        assert_eq!(acme.percent_change, Some(25.0));
+        assert_eq!(acme.flag, None);
+
+        let globex = &find("wages", Some("Globex")).changes[0];
+        assert_eq!(globex.flag, Some(ChangeFlag::Disappeared));
+        assert_eq!(globex.percent_change, Some(-100.0));
+        let initech = &find("wages", Some("Initech")).changes[0];
+        assert_eq!(initech.flag, Some(ChangeFlag::New));
+        assert_eq!(initech.percent_change, None);
+
+        assert_eq!(find("tax_liability", None).changes[0].change, Money::from_dollars(1_000));
+
+        let csv = comparison.to_csv();
+        let mut rows = csv.lines();
+        assert_eq!(rows.next(), Some("section,category,payer,subtotal,2022,2023,change_2022_2023,percent_2022_2023,flag_2022_2023"));
+        assert_eq!(rows.next(), Some("income,wages,Acme,,40000.00,50000.00,10000.00,25.0,"));
+        assert!(csv.contains("income,wages,Globex,,10000.00,,-10000.00,-100.0,disappeared\r\n"));
+        assert!(csv.contains("income,wages,,subtotal,50000.00,55000.00,5000.00,10.0,\r\n"));
+    }
+
+    #[test]
+    fn test_lines_without_payer_pair_by_category() {
+        let (earlier, later) = (tax_return(2022, 4_000), tax_return(2023, 5_000));
+        let mut unattributed = wages("", 48_000);
+        unattributed.payer = None;
+        let earlier_items = [unattributed];
+        let later_items = [wages("Acme", 50_000)];
+
+        let comparison = compare_years(&[
+            ComparedYear { tax_return: &earlier, line_items: &earlier_items },
+            ComparedYear { tax_return: &later, line_items: &later_items },
+        ]);
+        let wage_lines: Vec<&ComparisonLine> = comparison.lines.iter().filter(|l| l.category == "wages").collect();
+        assert_eq!(wage_lines.len(), 2);
+        assert_eq!(wage_lines[0].payer.as_deref(), Some("Acme"));
+        assert_eq!(wage_lines[0].changes[0].flag, None);
+        assert!(wage_lines[1].subtotal);
+        assert_eq!(wage_lines[1].changes[0].change, Money::from_dollars(2_000));
+    }
+}
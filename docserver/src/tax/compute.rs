@@ -0,0 +1,422 @@
+use serde::Serialize;
+use std::fmt;
+
+use super::household::{self, HouseholdSummary};
+use super::{Bracket, FilingStatus, TaxYearRules};
+use crate::db::{HouseholdMember, TaxReturn};
+use crate::money::Money;
+
+// `deductions` keys taken above the line, in arriving at AGI
+const ADJUSTMENT_KEYS: &[&str] = &[
+    "student_loan_interest",
+    "ira_contributions",
+    "hsa_contributions",
+    "educator_expenses",
+    "self_employment_tax_deduction",
+    "alimony_paid",
+];
+
+// The standard deduction comes from the year's rules, not from the return
+const STANDARD_DEDUCTION_KEY: &str = "standard_deduction";
+
+// `credits` keys that can take the balance below zero
+const REFUNDABLE_CREDIT_KEYS: &[&str] = &[
+    "earned_income_credit",
+    "additional_child_tax_credit",
+    "american_opportunity_credit_refundable",
+    "premium_tax_credit",
+    "excess_social_security",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComputeError {
+    UnknownFilingStatus(String),
+    NoBracketsForStatus(FilingStatus),
+    HouseholdMismatch(Vec<String>),
+}
+
+impl fmt::Display for ComputeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComputeError::UnknownFilingStatus(status) => write!(f, "Unknown filing status: {:?}", status),
+            ComputeError::NoBracketsForStatus(status) => write!(f, "No tax brackets for filing status {:?}", status),
+            ComputeError::HouseholdMismatch(problems) => {
+                write!(f, "Filing status does not match the household: {}", problems.join("; "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BracketTax {
+    pub rate_bp: i64,
+    pub taxed_amount: Money,
+    pub tax: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AppliedCredit {
+    pub credit: String,
+    pub claimed: Money,
+    pub allowed: Money,
+    pub refundable: bool,
+}
+
+/// The full worksheet behind a computed liability.
+#[derive(Debug, Serialize)]
+pub struct Computation {
+    pub tax_year: i32,
+    pub filing_status: FilingStatus,
+    // Present when the return was computed from the client's household
+    pub household: Option<HouseholdSummary>,
+    pub gross_income: Money,
+    pub adjustments: Money,
+    pub adjusted_gross_income: Money,
+    pub standard_deduction: Money,
+    pub itemized_deductions: Money,
+    pub itemizes: bool,
+    pub deduction_used: Money,
+    pub taxable_income: Money,
+    pub brackets: Vec<BracketTax>,
+    pub tax_before_credits: Money,
+    pub credits: Vec<AppliedCredit>,
+    pub nonrefundable_credits: Money,
+    pub tax_liability: Money,
+    pub refundable_credits: Money,
+    pub taxes_paid: Money,
+    // Positive is a refund, negative an amount due
+    pub refund_or_amount_due: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Discrepancy {
+    pub field: String,
+    pub stored: Money,
+    pub computed: Money,
+    pub difference: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComputationReport {
+    pub tax_return_id: Option<i64>,
+    pub computation: Computation,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+fn adjustments(tax_return: &TaxReturn) -> Money {
+    tax_return.deductions.iter()
+        .filter(|(key, _)| ADJUSTMENT_KEYS.contains(&key.as_str()))
+        .map(|(_, amount)| *amount)
+        .sum()
+}
+
+/// Total income less above-the-line adjustments, as `compute` works it out.
+pub fn adjusted_gross_income(tax_return: &TaxReturn) -> Money {
+    tax_return.income_sources.values().sum::<Money>() - adjustments(tax_return)
+}
+
+/// Computes liability and refund/amount due for a return under the given rules.
+///
+/// `household` is the spouse and dependents the return covers, for clients
+/// with a household on file. It is checked against the filing status, and
+/// the dependent credits come from it instead of the entered `credits`.
+pub fn compute(
+    tax_return: &TaxReturn,
+    household: Option<&[HouseholdMember]>,
+    rules: &TaxYearRules,
+) -> Result<Computation, ComputeError> {
+    let filing_status = FilingStatus::parse(&tax_return.filing_status)
+        .ok_or_else(|| ComputeError::UnknownFilingStatus(tax_return.filing_status.clone()))?;
+    if let Some(members) = household {
+        let problems = household::filing_status_problems(filing_status, members, tax_return.tax_year);
+        if !problems.is_empty() {
+            return Err(ComputeError::HouseholdMismatch(problems));
+        }
+    }
+    let brackets = rules.brackets.get(&filing_status)
+        .ok_or(ComputeError::NoBracketsForStatus(filing_status))?;
+
+    let gross_income: Money = tax_return.income_sources.values().sum();
+    let adjustments = adjustments(tax_return);
+    let adjusted_gross_income = gross_income - adjustments;
+
+    let standard_deduction = rules.standard_deduction.get(&filing_status).copied().unwrap_or_default();
+    let itemized_deductions: Money = tax_return.deductions.iter()
+        .filter(|(key, _)| !ADJUSTMENT_KEYS.contains(&key.as_str()) && key.as_str() != STANDARD_DEDUCTION_KEY)
+        .map(|(_, amount)| *amount)
+        .sum();
+    let itemizes = itemized_deductions > standard_deduction;
+    let deduction_used = if itemizes { itemized_deductions } else { standard_deduction };
+    let taxable_income = (adjusted_gross_income - deduction_used).max(Money::ZERO);
+
+    let bracket_taxes = apply_brackets(brackets, taxable_income);
+    let tax_before_credits: Money = bracket_taxes.iter().map(|b| b.tax).sum();
+
+    let mut claimed_credits = tax_return.credits.clone();
+    let household = match (household, &rules.dependent_credits) {
+        (Some(members), Some(dependent_credits)) => {
+            let summary = household::summarize(members, tax_return.tax_year, dependent_credits.child_age_limit);
+            for (key, amount) in household::dependent_credit_amounts(&summary, dependent_credits) {
+                claimed_credits.remove(key);
+                if amount > Money::ZERO {
+                    claimed_credits.insert(key.to_string(), amount);
+                }
+            }
+            Some(summary)
+        }
+        _ => None,
+    };
+
+    // Nonrefundable credits are applied in key order until the tax is used up
+    let mut credit_keys: Vec<&String> = claimed_credits.keys().collect();
+    credit_keys.sort();
+
+    let mut credits = Vec::new();
+    let mut remaining_tax = tax_before_credits;
+    let mut refundable_credits = Money::ZERO;
+    for key in credit_keys {
+        let claimed = claimed_credits[key];
+        let after_phaseout = phase_out(rules, key, filing_status, adjusted_gross_income, claimed);
+        let refundable = REFUNDABLE_CREDIT_KEYS.contains(&key.as_str());
+
+        let allowed = if refundable {
+            refundable_credits += after_phaseout;
+            after_phaseout
+        } else {
+            let allowed = after_phaseout.min(remaining_tax);
+            remaining_tax -= allowed;
+            allowed
+        };
+        credits.push(AppliedCredit { credit: key.clone(), claimed, allowed, refundable });
+    }
+
+    let tax_liability = remaining_tax;
+    let nonrefundable_credits = tax_before_credits - tax_liability;
+    let refund_or_amount_due = tax_return.taxes_paid + refundable_credits - tax_liability;
+
+    Ok(Computation {
+        tax_year: tax_return.tax_year,
+        filing_status,
+        household,
+        gross_income,
+        adjustments,
+        adjusted_gross_income,
+        standard_deduction,
+        itemized_deductions,
+        itemizes,
+        deduction_used,
+        taxable_income,
+        brackets: bracket_taxes,
+        tax_before_credits,
+        credits,
+        nonrefundable_credits,
+        tax_liability,
+        refundable_credits,
+        taxes_paid: tax_return.taxes_paid,
+        refund_or_amount_due,
+    })
+}
+
+/// Computes the return and compares the result with its stored figures.
+pub fn check(
+    tax_return: &TaxReturn,
+    household: Option<&[HouseholdMember]>,
+    rules: &TaxYearRules,
+) -> Result<ComputationReport, ComputeError> {
+    let computation = compute(tax_return, household, rules)?;
+
+    let mut discrepancies = Vec::new();
+    let mut compare = |field: &str, stored: Money, computed: Money| {
+        if stored != computed {
+            discrepancies.push(Discrepancy {
+                field: field.to_string(),
+                stored,
+                computed,
+                difference: stored - computed,
+            });
+        }
+    };
+    compare("tax_liability", tax_return.tax_liability, computation.tax_liability);
+    compare("refund_or_amount_due", tax_return.refund_or_amount_due, computation.refund_or_amount_due);
+    if let Some(stored) = tax_return.deductions.get(STANDARD_DEDUCTION_KEY) {
+        compare("deductions.standard_deduction", *stored, computation.standard_deduction);
+    }
+
+    Ok(ComputationReport {
+        tax_return_id: tax_return.tax_return_id,
+        computation,
+        discrepancies,
+    })
+}
+
+fn apply_brackets(brackets: &[Bracket], taxable_income: Money) -> Vec<BracketTax> {
+    let mut lower = Money::ZERO;
+    let mut result = Vec::new();
+
+    for bracket in brackets {
+        if taxable_income <= lower {
+            break;
+        }
+        let upper = match bracket.up_to {
+            Some(up_to) => up_to.min(taxable_income),
+            None => taxable_income,
+        };
+        let taxed_amount = upper - lower;
+        result.push(BracketTax {
+            rate_bp: bracket.rate_bp,
+            taxed_amount,
+            tax: apply_rate(taxed_amount, bracket.rate_bp),
+        });
+        lower = upper;
+    }
+
+    result
+}
+
+/// Multiplies by a basis-point rate, rounding half away from zero to the cent.
+pub(crate) fn apply_rate(amount: Money, rate_bp: i64) -> Money {
+    let scaled = amount.cents() as i128 * rate_bp as i128;
+    let rounded = (scaled.abs() + 5_000) / 10_000 * scaled.signum();
+    Money::from_cents(rounded as i64)
+}
+
+fn phase_out(rules: &TaxYearRules, credit: &str, status: FilingStatus, agi: Money, claimed: Money) -> Money {
+    let phaseout = rules.credit_phaseouts.iter().find(|p| p.credit == credit);
+    let threshold = phaseout.and_then(|p| p.thresholds.get(&status).map(|t| (p, *t)));
+
+    match threshold {
+        Some((phaseout, threshold)) if agi > threshold => {
+            let over = (agi - threshold).cents();
+            let thousands = (over + 99_999) / 100_000;
+            let reduction = Money::from_cents(phaseout.reduction_per_thousand.cents() * thousands);
+            (claimed - reduction).max(Money::ZERO)
+        }
+        _ => claimed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemberRole;
+    use crate::tax::bundled_rules;
+    use chrono::NaiveDate;
+    use std::collections::HashMap;
+
+    fn tax_return(filing_status: &str, wages: i64, credits: &[(&str, i64)]) -> TaxReturn {
+        TaxReturn {
+            tax_return_id: Some(1),
+            client_id: 1,
+            tax_year: 2023,
+            filing_status: filing_status.to_string(),
+            income_sources: HashMap::from([("wages".to_string(), Money::from_dollars(wages))]),
+            deductions: HashMap::new(),
+            credits: credits.iter().map(|(k, v)| (k.to_string(), Money::from_dollars(*v))).collect(),
+            taxes_paid: Money::from_dollars(6_000),
+            tax_liability: Money::ZERO,
+            refund_or_amount_due: Money::ZERO,
+            return_kind: Default::default(),
+            parent_return_id: None,
+            amendment_reason: None,
+            status: Default::default(),
+            assignee: None,
+            jurisdiction: Default::default(),
+            federal_return_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_single_2023_brackets() {
+        let rules = bundled_rules(2023).unwrap();
+        let computation = compute(&tax_return("Single", 60_000, &[]), None, &rules).unwrap();
+
+        // 60,000 - 13,850 = 46,150 taxable
+        assert_eq!(computation.taxable_income, Money::from_dollars(46_150));
+        // 10% of 11,000 + 12% of 33,725 + 22% of 1,425
+        assert_eq!(computation.tax_before_credits, Money::from_cents(546_050));
+        assert_eq!(computation.refund_or_amount_due, Money::from_cents(53_950));
+    }
+
+    #[test]
+    fn test_nonrefundable_credit_is_capped_at_tax() {
+        let rules = bundled_rules(2023).unwrap();
+        let computation = compute(&tax_return("MFJ", 30_000, &[("child_tax_credit", 4_000)]), None, &rules).unwrap();
+
+        // 30,000 - 27,700 = 2,300 taxable, 230 tax
+        assert_eq!(computation.tax_before_credits, Money::from_dollars(230));
+        assert_eq!(computation.tax_liability, Money::ZERO);
+        assert_eq!(computation.credits[0].allowed, Money::from_dollars(230));
+    }
+
+    #[test]
+    fn test_child_tax_credit_phaseout() {
+        let rules = bundled_rules(2023).unwrap();
+        let computation = compute(&tax_return("Single", 210_500, &[("child_tax_credit", 2_000)]), None, &rules).unwrap();
+
+        // 10,500 over the threshold is 11 started thousands, 550 off the credit
+        assert_eq!(computation.credits[0].allowed, Money::from_dollars(1_450));
+    }
+
+    #[test]
+    fn test_check_flags_discrepancies() {
+        let rules = bundled_rules(2023).unwrap();
+        let mut stored = tax_return("Single", 60_000, &[]);
+        stored.tax_liability = Money::from_cents(546_050);
+        stored.refund_or_amount_due = Money::from_dollars(500);
+
+        let report = check(&stored, None, &rules).unwrap();
+        assert_eq!(report.discrepancies.len(), 1);
+        assert_eq!(report.discrepancies[0].field, "refund_or_amount_due");
+        assert_eq!(report.discrepancies[0].difference, Money::from_cents(-3_950));
+    }
+
+    fn household_member(role: MemberRole, born: i32) -> HouseholdMember {
+        HouseholdMember {
+            member_id: None,
+            client_id: 1,
+            role,
+            first_name: "Pat".to_string(),
+            last_name: "Doe".to_string(),
+            relationship: if role == MemberRole::Spouse { "spouse" } else { "daughter" }.to_string(),
+            birth_date: NaiveDate::from_ymd_opt(born, 3, 15).unwrap(),
+            social_security_number: "222-33-4444".to_string(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_dependent_credits_come_from_household() {
+        let rules = bundled_rules(2023).unwrap();
+        let members = [
+            household_member(MemberRole::Spouse, 1984),
+            household_member(MemberRole::Dependent, 2012),
+            household_member(MemberRole::Dependent, 2005),
+        ];
+        // The entered child credit is replaced by one child's worth plus one other dependent
+        let computation = compute(
+            &tax_return("MFJ", 100_000, &[("child_tax_credit", 6_000)]),
+            Some(&members),
+            &rules,
+        ).unwrap();
+
+        let credits: Vec<(&str, Money)> = computation.credits.iter().map(|c| (c.credit.as_str(), c.claimed)).collect();
+        assert_eq!(credits, vec![
+            ("child_tax_credit", Money::from_dollars(2_000)),
+            ("credit_for_other_dependents", Money::from_dollars(500)),
+        ]);
+        assert_eq!(computation.household.unwrap().dependents(), 2);
+
+        let result = compute(&tax_return("Single", 100_000, &[]), Some(&members), &rules);
+        assert!(matches!(result, Err(ComputeError::HouseholdMismatch(_))));
+    }
+
+    #[test]
+    fn test_unknown_filing_status() {
+        let rules = bundled_rules(2023).unwrap();
+        let result = compute(&tax_return("Complicated", 1_000, &[]), None, &rules);
+        assert_eq!(result.unwrap_err(), ComputeError::UnknownFilingStatus("Complicated".to_string()));
+    }
+}
@@ -0,0 +1,432 @@
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use serde::Serialize;
+
+use crate::db::{Jurisdiction, ReturnType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadlineKind {
+    // The return's own due date
+    Original,
+    // The due date once an extension (Form 4868) is on file
+    Extended,
+    // Last day to claim a refund by amending, three years after filing
+    AmendedRefundClaim,
+}
+
+impl DeadlineKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeadlineKind::Original => "original",
+            DeadlineKind::Extended => "extended",
+            DeadlineKind::AmendedRefundClaim => "amended_refund_claim",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeadlineKind::Original => "return due",
+            DeadlineKind::Extended => "extended return due",
+            DeadlineKind::AmendedRefundClaim => "last day to amend for a refund",
+        }
+    }
+}
+
+/// One deadline for a return. `statutory_date` is the date the law sets;
+/// `due_date` is that date moved past weekends and legal holidays.
+#[derive(Debug, Clone, Serialize)]
+pub struct Deadline {
+    // "federal" or a two-letter state code
+    pub jurisdiction: String,
+    pub kind: DeadlineKind,
+    pub statutory_date: NaiveDate,
+    pub due_date: NaiveDate,
+}
+
+/// A state's individual income tax due dates, as (months after the end of
+/// the tax year, day of that month).
+#[derive(Debug, Clone, Copy)]
+pub struct StateDeadline {
+    pub state: &'static str,
+    pub due: (u32, u32),
+    pub extended_due: (u32, u32),
+}
+
+const REFUND_CLAIM_YEARS: u32 = 3;
+
+/// The federal due date and extended due date for a kind of return. Living
+/// abroad moves only the first; Form 4868 still extends to October.
+fn federal_due(return_type: ReturnType) -> ((u32, u32), (u32, u32)) {
+    match return_type {
+        ReturnType::Form1040 => ((4, 15), (10, 15)),
+        ReturnType::Form1040NrWithoutWages => ((6, 15), (12, 15)),
+        ReturnType::Form1040Abroad => ((6, 15), (10, 15)),
+    }
+}
+
+const fn state(state: &'static str, due: (u32, u32), extended_due: (u32, u32)) -> StateDeadline {
+    StateDeadline { state, due, extended_due }
+}
+
+/// States with an individual income tax. States missing here (Alaska,
+/// Florida, Nevada, New Hampshire, South Dakota, Tennessee, Texas,
+/// Washington, Wyoming) have no return to file.
+pub const STATE_DEADLINES: &[StateDeadline] = &[
+    state("AL", (4, 15), (10, 15)),
+    state("AR", (4, 15), (10, 15)),
+    state("AZ", (4, 15), (10, 15)),
+    state("CA", (4, 15), (10, 15)),
+    state("CO", (4, 15), (10, 15)),
+    state("CT", (4, 15), (10, 15)),
+    state("DC", (4, 15), (10, 15)),
+    state("DE", (4, 30), (10, 15)),
+    state("GA", (4, 15), (10, 15)),
+    state("HI", (4, 20), (10, 20)),
+    state("IA", (4, 30), (10, 31)),
+    state("ID", (4, 15), (10, 15)),
+    state("IL", (4, 15), (10, 15)),
+    state("IN", (4, 15), (11, 15)),
+    state("KS", (4, 15), (10, 15)),
+    state("KY", (4, 15), (10, 15)),
+    state("LA", (5, 15), (11, 15)),
+    state("MA", (4, 15), (10, 15)),
+    state("MD", (4, 15), (10, 15)),
+    state("ME", (4, 15), (10, 15)),
+    state("MI", (4, 15), (10, 15)),
+    state("MN", (4, 15), (10, 15)),
+    state("MO", (4, 15), (10, 15)),
+    state("MS", (4, 15), (10, 15)),
+    state("MT", (4, 15), (10, 15)),
+    state("NC", (4, 15), (10, 15)),
+    state("ND", (4, 15), (10, 15)),
+    state("NE", (4, 15), (10, 15)),
+    state("NJ", (4, 15), (10, 15)),
+    state("NM", (4, 15), (10, 15)),
+    state("NY", (4, 15), (10, 15)),
+    state("OH", (4, 15), (10, 15)),
+    state("OK", (4, 15), (10, 15)),
+    state("OR", (4, 15), (10, 15)),
+    state("PA", (4, 15), (10, 15)),
+    state("RI", (4, 15), (10, 15)),
+    state("SC", (4, 15), (10, 15)),
+    state("UT", (4, 15), (10, 15)),
+    state("VA", (5, 1), (11, 1)),
+    state("VT", (4, 15), (10, 15)),
+    state("WI", (4, 15), (10, 15)),
+    state("WV", (4, 15), (10, 15)),
+];
+
+pub fn state_deadline(state: &str) -> Option<&'static StateDeadline> {
+    STATE_DEADLINES.iter().find(|s| s.state.eq_ignore_ascii_case(state))
+}
+
+/// The last day of the tax year: December 31, or for a fiscal-year filer the
+/// end of the twelve months beginning in `tax_year`.
+pub fn year_end(tax_year: i32, fiscal_year_end_month: Option<u32>) -> NaiveDate {
+    let (year, month) = match fiscal_year_end_month {
+        Some(month) if (1..12).contains(&month) => (tax_year + 1, month),
+        _ => (tax_year, 12),
+    };
+    last_day_of_month(year, month)
+}
+
+fn last_day_of_month(year: i32, month: u32) -> NaiveDate {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).expect("month is 1-12");
+    first + Months::new(1) - Days::new(1)
+}
+
+/// `day` of the month `months` after the one `year_end` falls in, kept
+/// within that month.
+fn months_after(year_end: NaiveDate, (months, day): (u32, u32)) -> NaiveDate {
+    let month_start = year_end.with_day(1).expect("every month has a first day") + Months::new(months);
+    let last = last_day_of_month(month_start.year(), month_start.month());
+    month_start.with_day(day.min(last.day())).unwrap_or(last)
+}
+
+fn deadline(jurisdiction: &str, kind: DeadlineKind, statutory_date: NaiveDate) -> Deadline {
+    Deadline {
+        jurisdiction: jurisdiction.to_string(),
+        kind,
+        statutory_date,
+        due_date: roll_forward(statutory_date),
+    }
+}
+
+/// What decides a return's deadlines.
+#[derive(Debug, Clone, Copy)]
+pub struct DeadlineInputs<'a> {
+    pub jurisdiction: &'a Jurisdiction,
+    pub tax_year: i32,
+    pub fiscal_year_end_month: Option<u32>,
+    // Only moves the federal dates
+    pub return_type: ReturnType,
+    // Only used for a federal return, standing in for a state return not on file
+    pub home_state: Option<&'a str>,
+    pub extended: bool,
+    pub amendment: bool,
+    // When the original return was filed, if it has been
+    pub original_filed_on: Option<NaiveDate>,
+}
+
+/// Due dates for a return in its jurisdiction, plus the home state's for a
+/// federal return given one. An extension moves state deadlines too, since
+/// states with a return to file accept the federal extension. An amendment
+/// gets the refund claim deadline instead.
+pub fn return_deadlines(inputs: DeadlineInputs) -> Vec<Deadline> {
+    let year_end = year_end(inputs.tax_year, inputs.fiscal_year_end_month);
+    let (jurisdiction, due, extended_due) = match inputs.jurisdiction {
+        Jurisdiction::Federal => {
+            let (due, extended_due) = federal_due(inputs.return_type);
+            ("federal", due, extended_due)
+        }
+        Jurisdiction::State(code) => match state_deadline(code) {
+            Some(state) => (state.state, state.due, state.extended_due),
+            None => return Vec::new(),
+        },
+    };
+    let original_due = months_after(year_end, due);
+
+    if inputs.amendment {
+        // A return filed early counts as filed on its due date
+        let filed = inputs.original_filed_on.unwrap_or(original_due).max(original_due);
+        let claim = filed + Months::new(12 * REFUND_CLAIM_YEARS);
+        return vec![deadline(jurisdiction, DeadlineKind::AmendedRefundClaim, claim)];
+    }
+
+    let mut deadlines = vec![deadline(jurisdiction, DeadlineKind::Original, original_due)];
+    if inputs.extended {
+        deadlines.push(deadline(jurisdiction, DeadlineKind::Extended, months_after(year_end, extended_due)));
+    }
+    let home_state = inputs.home_state.filter(|_| inputs.jurisdiction.is_federal()).and_then(state_deadline);
+    if let Some(state) = home_state {
+        deadlines.push(deadline(state.state, DeadlineKind::Original, months_after(year_end, state.due)));
+        if inputs.extended {
+            deadlines.push(deadline(state.state, DeadlineKind::Extended, months_after(year_end, state.extended_due)));
+        }
+    }
+    deadlines
+}
+
+/// The deadlines that still apply: the extended date replaces the original
+/// one for each jurisdiction that has both.
+pub fn current_deadlines(deadlines: &[Deadline]) -> Vec<&Deadline> {
+    deadlines.iter()
+        .filter(|d| {
+            d.kind != DeadlineKind::Original
+                || !deadlines.iter().any(|other| other.jurisdiction == d.jurisdiction && other.kind == DeadlineKind::Extended)
+        })
+        .collect()
+}
+
+/// Moves a deadline that falls on a weekend or a legal holiday in the
+/// District of Columbia to the next business day, as the IRS does.
+pub fn roll_forward(date: NaiveDate) -> NaiveDate {
+    let mut date = date;
+    while matches!(date.weekday(), Weekday::Sat | Weekday::Sun) || is_legal_holiday(date) {
+        date = date.succ_opt().expect("dates stay within range");
+    }
+    date
+}
+
+pub fn is_legal_holiday(date: NaiveDate) -> bool {
+    // An observed New Year's Day can fall in the previous December
+    [date.year(), date.year() + 1].iter().any(|year| legal_holidays(*year).contains(&date))
+}
+
+/// Legal holidays in the District of Columbia, on the days they are observed.
+/// Inauguration Day is left out; it never falls near a filing deadline.
+pub fn legal_holidays(year: i32) -> Vec<NaiveDate> {
+    let fixed = [(1, 1), (4, 16), (6, 19), (7, 4), (11, 11), (12, 25)];
+    let mut holidays: Vec<NaiveDate> = fixed.iter()
+        .filter_map(|(month, day)| NaiveDate::from_ymd_opt(year, *month, *day))
+        .map(observed)
+        .collect();
+
+    let floating = [
+        (1, Weekday::Mon, 3),  // Martin Luther King Jr. Day
+        (2, Weekday::Mon, 3),  // Washington's Birthday
+        (9, Weekday::Mon, 1),  // Labor Day
+        (10, Weekday::Mon, 2), // Columbus Day
+        (11, Weekday::Thu, 4), // Thanksgiving
+    ];
+    holidays.extend(floating.iter().filter_map(|(month, weekday, n)| {
+        NaiveDate::from_weekday_of_month_opt(year, *month, *weekday, *n)
+    }));
+
+    // Memorial Day is the last Monday in May
+    let mut memorial_day = last_day_of_month(year, 5);
+    while memorial_day.weekday() != Weekday::Mon {
+        memorial_day = memorial_day.pred_opt().expect("dates stay within range");
+    }
+    holidays.push(memorial_day);
+
+    holidays.sort();
+    holidays
+}
+
+/// A Saturday holiday is observed on the Friday before, a Sunday one on
+/// the Monday after.
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date.pred_opt().expect("dates stay within range"),
+        Weekday::Sun => date.succ_opt().expect("dates stay within range"),
+        _ => date,
+    }
+}
+
+/// One deadline on the staff calendar.
+#[derive(Debug, Clone, Serialize)]
+pub struct CalendarEntry {
+    pub uid: String,
+    pub date: NaiveDate,
+    pub summary: String,
+    pub description: String,
+}
+
+/// Renders entries as an iCalendar (RFC 5545) feed of all-day events.
+/// `stamp` is when the feed was generated, as `YYYYMMDDTHHMMSSZ`.
+pub fn to_icalendar(name: &str, entries: &[CalendarEntry], stamp: &str) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//docserver//filing deadlines//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+    ];
+    for entry in entries {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", entry.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", entry.date.format("%Y%m%d")));
+        let next_day = entry.date.succ_opt().unwrap_or(entry.date);
+        lines.push(format!("DTEND;VALUE=DATE:{}", next_day.format("%Y%m%d")));
+        lines.push(format!("SUMMARY:{}", escape_text(&entry.summary)));
+        lines.push(format!("DESCRIPTION:{}", escape_text(&entry.description)));
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Ends a content line with CRLF, folding it so no line exceeds 75 octets.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn inputs(tax_year: i32) -> DeadlineInputs<'static> {
+        DeadlineInputs {
+            jurisdiction: &Jurisdiction::Federal,
+            tax_year,
+            fiscal_year_end_month: None,
+            return_type: ReturnType::Form1040,
+            home_state: None,
+            extended: false,
+            amendment: false,
+            original_filed_on: None,
+        }
+    }
+
+    #[test]
+    fn test_deadlines_roll_past_weekends_and_holidays() {
+        // April 15, 2023 was a Saturday and Emancipation Day was observed Monday the 17th
+        let deadlines = return_deadlines(inputs(2022));
+        assert_eq!(deadlines[0].statutory_date, date(2023, 4, 15));
+        assert_eq!(deadlines[0].due_date, date(2023, 4, 18));
+        // April 15, 2022 was itself the observed Emancipation Day
+        assert_eq!(return_deadlines(inputs(2021))[0].due_date, date(2022, 4, 18));
+        assert_eq!(return_deadlines(inputs(2023))[0].due_date, date(2024, 4, 15));
+
+        let extended = return_deadlines(DeadlineInputs { extended: true, home_state: Some("va"), ..inputs(2023) });
+        let current: Vec<(&str, NaiveDate)> = current_deadlines(&extended).iter()
+            .map(|d| (d.jurisdiction.as_str(), d.due_date))
+            .collect();
+        assert_eq!(current, vec![("federal", date(2024, 10, 15)), ("VA", date(2024, 11, 1))]);
+
+        // No state return where there is no income tax
+        assert_eq!(return_deadlines(DeadlineInputs { home_state: Some("TX"), ..inputs(2023) }).len(), 1);
+
+        // A state return has only its own state's dates
+        let louisiana = Jurisdiction::State("LA".to_string());
+        let state = return_deadlines(DeadlineInputs { jurisdiction: &louisiana, home_state: Some("VA"), ..inputs(2023) });
+        assert_eq!(state.len(), 1);
+        assert_eq!((state[0].jurisdiction.as_str(), state[0].due_date), ("LA", date(2024, 5, 15)));
+    }
+
+    #[test]
+    fn test_fiscal_year_and_amendment_deadlines() {
+        // A year ending June 30, 2024 is due October 15, 2024
+        let fiscal = return_deadlines(DeadlineInputs { fiscal_year_end_month: Some(6), ..inputs(2023) });
+        assert_eq!(fiscal[0].statutory_date, date(2024, 10, 15));
+
+        // Filed early counts as filed on the due date; filed late starts the clock then
+        let early = return_deadlines(DeadlineInputs { amendment: true, original_filed_on: Some(date(2024, 2, 1)), ..inputs(2023) });
+        assert_eq!(early[0].kind, DeadlineKind::AmendedRefundClaim);
+        assert_eq!(early[0].due_date, date(2027, 4, 15));
+        let late = return_deadlines(DeadlineInputs { amendment: true, original_filed_on: Some(date(2024, 6, 3)), ..inputs(2023) });
+        assert_eq!(late[0].statutory_date, date(2027, 6, 3));
+    }
+
+    #[test]
+    fn test_return_type_deadlines() {
+        let dates = |return_type| {
+            return_deadlines(DeadlineInputs { return_type, extended: true, ..inputs(2023) }).iter()
+                .map(|d| d.due_date)
+                .collect::<Vec<_>>()
+        };
+        // June 15, 2024 was a Saturday
+        assert_eq!(dates(ReturnType::Form1040NrWithoutWages), vec![date(2024, 6, 17), date(2024, 12, 16)]);
+        assert_eq!(dates(ReturnType::Form1040Abroad), vec![date(2024, 6, 17), date(2024, 10, 15)]);
+
+        // The home state keeps its own dates
+        let nonresident = return_deadlines(DeadlineInputs {
+            return_type: ReturnType::Form1040NrWithoutWages,
+            home_state: Some("CA"),
+            ..inputs(2023)
+        });
+        assert_eq!((nonresident[1].jurisdiction.as_str(), nonresident[1].due_date), ("CA", date(2024, 4, 15)));
+    }
+
+    #[test]
+    fn test_icalendar_feed() {
+        let entries = [CalendarEntry {
+            uid: "return-7-federal-original@docserver".to_string(),
+            date: date(2024, 4, 15),
+            summary: "Doe, Jane: 2023 return due".to_string(),
+            description: "Status: in_review".to_string(),
+        }];
+        let feed = to_icalendar("Filing deadlines", &entries, "20240101T000000Z");
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(feed.contains("DTSTART;VALUE=DATE:20240415\r\nDTEND;VALUE=DATE:20240416\r\n"));
+        assert!(feed.contains("SUMMARY:Doe\\, Jane: 2023 return due\r\n"));
+        assert!(feed.lines().all(|line| line.len() <= 75));
+    }
+}
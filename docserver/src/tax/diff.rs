@@ -0,0 +1,139 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::db::TaxReturn;
+use crate::money::Money;
+
+/// One figure compared across two returns. A figure missing from one side
+/// (an income source only the amendment reports, say) has no value there.
+#[derive(Debug, Serialize)]
+pub struct FigureChange {
+    pub field: String,
+    pub before: Option<Money>,
+    pub after: Option<Money>,
+    pub change: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReturnDiff {
+    pub before_return_id: Option<i64>,
+    pub after_return_id: Option<i64>,
+    pub filing_status_before: String,
+    pub filing_status_after: String,
+    // Every figure on either return, changed or not
+    pub figures: Vec<FigureChange>,
+}
+
+impl ReturnDiff {
+    pub fn changed(&self) -> impl Iterator<Item = &FigureChange> {
+        self.figures.iter().filter(|figure| figure.before != figure.after)
+    }
+}
+
+/// Compares every figure of two returns, map entries keyed as
+/// `income_sources.wages` and so on.
+pub fn diff_returns(before: &TaxReturn, after: &TaxReturn) -> ReturnDiff {
+    let mut figures = Vec::new();
+
+    let maps = [
+        ("income_sources", &before.income_sources, &after.income_sources),
+        ("deductions", &before.deductions, &after.deductions),
+        ("credits", &before.credits, &after.credits),
+    ];
+    for (name, before_map, after_map) in maps {
+        diff_map(name, before_map, after_map, &mut figures);
+    }
+
+    let totals = [
+        ("taxes_paid", before.taxes_paid, after.taxes_paid),
+        ("tax_liability", before.tax_liability, after.tax_liability),
+        ("refund_or_amount_due", before.refund_or_amount_due, after.refund_or_amount_due),
+    ];
+    for (field, before_amount, after_amount) in totals {
+        figures.push(figure(field.to_string(), Some(before_amount), Some(after_amount)));
+    }
+
+    ReturnDiff {
+        before_return_id: before.tax_return_id,
+        after_return_id: after.tax_return_id,
+        filing_status_before: before.filing_status.clone(),
+        filing_status_after: after.filing_status.clone(),
+        figures,
+    }
+}
+
+fn diff_map(
+    name: &str,
+    before: &HashMap<String, Money>,
+    after: &HashMap<String, Money>,
+    figures: &mut Vec<FigureChange>,
+) {
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for key in keys {
+        figures.push(figure(
+            format!("{}.{}", name, key),
+            before.get(key).copied(),
+            after.get(key).copied(),
+        ));
+    }
+}
+
+fn figure(field: String, before: Option<Money>, after: Option<Money>) -> FigureChange {
+    FigureChange {
+        change: after.unwrap_or_default() - before.unwrap_or_default(),
+        field,
+        before,
+        after,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tax_return(income: &[(&str, i64)], taxes_paid: i64) -> TaxReturn {
+        TaxReturn {
+            tax_return_id: None,
+            client_id: 1,
+            tax_year: 2023,
+            filing_status: "Single".to_string(),
+            income_sources: income.iter().map(|(k, v)| (k.to_string(), Money::from_dollars(*v))).collect(),
+            deductions: HashMap::new(),
+            credits: HashMap::new(),
+            taxes_paid: Money::from_dollars(taxes_paid),
+            tax_liability: Money::ZERO,
+            refund_or_amount_due: Money::ZERO,
+            return_kind: Default::default(),
+            parent_return_id: None,
+            amendment_reason: None,
+            status: Default::default(),
+            assignee: None,
+            jurisdiction: Default::default(),
+            federal_return_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_covers_every_figure() {
+        let original = tax_return(&[("wages", 50_000)], 5_000);
+        let amended = tax_return(&[("wages", 50_000), ("interest", 200)], 5_100);
+
+        let diff = diff_returns(&original, &amended);
+        let fields: Vec<&str> = diff.figures.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, vec![
+            "income_sources.interest",
+            "income_sources.wages",
+            "taxes_paid",
+            "tax_liability",
+            "refund_or_amount_due",
+        ]);
+
+        let changed: Vec<&FigureChange> = diff.changed().collect();
+        assert_eq!(changed.len(), 2);
+        assert_eq!(changed[0].before, None);
+        assert_eq!(changed[0].change, Money::from_dollars(200));
+        assert_eq!(changed[1].change, Money::from_dollars(100));
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::db::{EstimatedPayment, TaxReturn};
+use crate::money::Money;
+use super::compute::{adjusted_gross_income, apply_rate};
+use super::{EstimatedTaxRules, FilingStatus};
+
+/// Where a client stands on one 1040-ES installment. Amounts are cumulative
+/// through the quarter, so an early overpayment counts toward later ones.
+#[derive(Debug, Serialize)]
+pub struct InstallmentStatus {
+    pub quarter: i32,
+    pub due_date: NaiveDate,
+    pub cumulative_required: Money,
+    pub cumulative_paid: Money,
+    // Zero once the installment is covered
+    pub shortfall: Money,
+}
+
+/// The payments that keep a client clear of the underpayment penalty for a
+/// tax year. A target is only worked out from a return that exists.
+#[derive(Debug, Serialize)]
+pub struct SafeHarbor {
+    pub tax_year: i32,
+    pub filing_status: FilingStatus,
+    pub prior_year_tax_liability: Option<Money>,
+    pub prior_year_agi: Option<Money>,
+    pub prior_year_percent: i64,
+    pub prior_year_target: Option<Money>,
+    pub current_year_tax_liability: Option<Money>,
+    pub current_year_target: Option<Money>,
+    // The smaller of the targets, paid in four equal installments
+    pub required_annual_payment: Money,
+    pub total_paid: Money,
+    pub installments: Vec<InstallmentStatus>,
+}
+
+impl SafeHarbor {
+    /// The first installment due on or after `date`.
+    pub fn upcoming(&self, date: NaiveDate) -> Option<&InstallmentStatus> {
+        self.installments.iter().find(|installment| installment.due_date >= date)
+    }
+}
+
+/// Works out the safe-harbor targets for `tax_year` from the prior year's
+/// return and, once there is one, this year's. Returns `None` when neither
+/// return exists.
+pub fn safe_harbor(
+    rules: &EstimatedTaxRules,
+    tax_year: i32,
+    filing_status: FilingStatus,
+    prior: Option<&TaxReturn>,
+    current: Option<&TaxReturn>,
+    payments: &[EstimatedPayment],
+) -> Option<SafeHarbor> {
+    let prior_year_agi = prior.map(adjusted_gross_income);
+    let high_income = match (prior_year_agi, rules.high_income_thresholds.get(&filing_status)) {
+        (Some(agi), Some(threshold)) => agi > *threshold,
+        _ => false,
+    };
+    let prior_year_percent = if high_income {
+        rules.high_income_prior_year_percent
+    } else {
+        rules.prior_year_percent
+    };
+
+    let prior_year_tax_liability = prior.map(|r| r.tax_liability);
+    let prior_year_target = prior_year_tax_liability.map(|liability| apply_rate(liability, prior_year_percent * 100));
+    let current_year_tax_liability = current.map(|r| r.tax_liability);
+    let current_year_target = current_year_tax_liability
+        .map(|liability| apply_rate(liability, rules.current_year_percent * 100));
+    let required_annual_payment = match (prior_year_target, current_year_target) {
+        (Some(prior), Some(current)) => prior.min(current),
+        (Some(target), None) | (None, Some(target)) => target,
+        (None, None) => return None,
+    }
+    .max(Money::ZERO);
+
+    let installments = rules.due_dates.iter().enumerate()
+        .map(|(index, due_date)| {
+            let quarter = index as i32 + 1;
+            let cumulative_required = Money::from_cents(required_annual_payment.cents() * quarter as i64 / 4);
+            let cumulative_paid: Money = payments.iter()
+                .filter(|p| p.tax_year == tax_year && p.quarter <= quarter)
+                .map(|p| p.amount)
+                .sum();
+            InstallmentStatus {
+                quarter,
+                due_date: *due_date,
+                cumulative_required,
+                cumulative_paid,
+                shortfall: (cumulative_required - cumulative_paid).max(Money::ZERO),
+            }
+        })
+        .collect();
+
+    Some(SafeHarbor {
+        tax_year,
+        filing_status,
+        prior_year_tax_liability,
+        prior_year_agi,
+        prior_year_percent,
+        prior_year_target,
+        current_year_tax_liability,
+        current_year_target,
+        required_annual_payment,
+        total_paid: payments.iter().filter(|p| p.tax_year == tax_year).map(|p| p.amount).sum(),
+        installments,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::PaymentMethod;
+    use crate::tax::bundled_rules;
+    use std::collections::HashMap;
+
+    fn tax_return(tax_year: i32, wages: i64, tax_liability: i64) -> TaxReturn {
+        TaxReturn {
+            tax_return_id: None,
+            client_id: 1,
+            tax_year,
+            filing_status: "Single".to_string(),
+            income_sources: HashMap::from([("wages".to_string(), Money::from_dollars(wages))]),
+            deductions: HashMap::new(),
+            credits: HashMap::new(),
+            taxes_paid: Money::ZERO,
+            tax_liability: Money::from_dollars(tax_liability),
+            refund_or_amount_due: Money::ZERO,
+            return_kind: Default::default(),
+            parent_return_id: None,
+            amendment_reason: None,
+            status: Default::default(),
+            assignee: None,
+            jurisdiction: Default::default(),
+            federal_return_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn payment(quarter: i32, dollars: i64) -> EstimatedPayment {
+        EstimatedPayment {
+            payment_id: None,
+            client_id: 1,
+            tax_year: 2024,
+            quarter,
+            amount: Money::from_dollars(dollars),
+            paid_on: NaiveDate::from_ymd_opt(2024, 4, 15).unwrap(),
+            method: PaymentMethod::Eftps,
+            note: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_safe_harbor_targets() {
+        let rules = bundled_rules(2024).unwrap().estimated_payments.unwrap();
+        let prior = tax_return(2023, 100_000, 12_000);
+        let payments = [payment(1, 3_000), payment(2, 2_000)];
+
+        let harbor = safe_harbor(&rules, 2024, FilingStatus::Single, Some(&prior), None, &payments).unwrap();
+        assert_eq!(harbor.prior_year_percent, 100);
+        assert_eq!(harbor.required_annual_payment, Money::from_dollars(12_000));
+        assert_eq!(harbor.installments[0].shortfall, Money::ZERO);
+        assert_eq!(harbor.installments[1].shortfall, Money::from_dollars(1_000));
+        let june = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert_eq!(harbor.upcoming(june).unwrap().quarter, 2);
+
+        // A lower current-year tax sets the target; high prior AGI raises the prior one
+        let prior = tax_return(2023, 200_000, 40_000);
+        let current = tax_return(2024, 120_000, 20_000);
+        let harbor = safe_harbor(&rules, 2024, FilingStatus::Single, Some(&prior), Some(&current), &[]).unwrap();
+        assert_eq!(harbor.prior_year_target, Some(Money::from_dollars(44_000)));
+        assert_eq!(harbor.required_annual_payment, Money::from_dollars(18_000));
+
+        assert!(safe_harbor(&rules, 2024, FilingStatus::Single, None, None, &[]).is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::Datelike;
+use serde::Serialize;
+
+use super::{DependentCredits, FilingStatus};
+use crate::db::{HouseholdMember, MemberRole};
+use crate::money::Money;
+
+// `credits` keys replaced by the household figures when a household is on file
+pub const CHILD_TAX_CREDIT_KEY: &str = "child_tax_credit";
+pub const OTHER_DEPENDENTS_CREDIT_KEY: &str = "credit_for_other_dependents";
+
+/// Who a return covers, as far as filing status and credits are concerned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HouseholdSummary {
+    pub spouse: bool,
+    pub qualifying_children: usize,
+    pub other_dependents: usize,
+}
+
+impl HouseholdSummary {
+    pub fn dependents(&self) -> usize {
+        self.qualifying_children + self.other_dependents
+    }
+}
+
+/// Age on the last day of the tax year.
+fn age_at_year_end(member: &HouseholdMember, tax_year: i32) -> i32 {
+    tax_year - member.birth_date.year()
+}
+
+pub fn summarize(members: &[HouseholdMember], tax_year: i32, child_age_limit: i32) -> HouseholdSummary {
+    let dependents: Vec<&HouseholdMember> = members.iter()
+        .filter(|m| m.role == MemberRole::Dependent && age_at_year_end(m, tax_year) >= 0)
+        .collect();
+    let qualifying_children = dependents.iter()
+        .filter(|m| age_at_year_end(m, tax_year) < child_age_limit)
+        .count();
+
+    HouseholdSummary {
+        spouse: members.iter().any(|m| m.role == MemberRole::Spouse),
+        qualifying_children,
+        other_dependents: dependents.len() - qualifying_children,
+    }
+}
+
+/// Checks a filing status against the household members a return covers,
+/// returning every conflict found.
+pub fn filing_status_problems(status: FilingStatus, members: &[HouseholdMember], tax_year: i32) -> Vec<String> {
+    let mut problems = Vec::new();
+    let spouse = members.iter().any(|m| m.role == MemberRole::Spouse);
+    let dependents = members.iter().filter(|m| m.role == MemberRole::Dependent).count();
+
+    for member in members.iter().filter(|m| age_at_year_end(m, tax_year) < 0) {
+        problems.push(format!(
+            "{} {} was born after {} and cannot be claimed for it",
+            member.first_name, member.last_name, tax_year
+        ));
+    }
+
+    match status {
+        FilingStatus::MarriedFilingJointly if !spouse => {
+            problems.push("A joint return must cover the client's spouse".to_string());
+        }
+        FilingStatus::Single | FilingStatus::HeadOfHousehold | FilingStatus::QualifyingSurvivingSpouse if spouse => {
+            problems.push(format!("A {:?} return cannot cover a spouse", status));
+        }
+        _ => {}
+    }
+    if matches!(status, FilingStatus::HeadOfHousehold | FilingStatus::QualifyingSurvivingSpouse) && dependents == 0 {
+        problems.push(format!("A {:?} return must cover at least one dependent", status));
+    }
+
+    problems
+}
+
+/// The child tax credit and credit for other dependents the household allows,
+/// before any phase-out.
+pub fn dependent_credit_amounts(summary: &HouseholdSummary, credits: &DependentCredits) -> [(&'static str, Money); 2] {
+    [
+        (CHILD_TAX_CREDIT_KEY, Money::from_cents(credits.per_qualifying_child.cents() * summary.qualifying_children as i64)),
+        (OTHER_DEPENDENTS_CREDIT_KEY, Money::from_cents(credits.per_other_dependent.cents() * summary.other_dependents as i64)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn member(role: MemberRole, born: i32) -> HouseholdMember {
+        HouseholdMember {
+            member_id: None,
+            client_id: 1,
+            role,
+            first_name: "Pat".to_string(),
+            last_name: "Doe".to_string(),
+            relationship: if role == MemberRole::Spouse { "spouse" } else { "child" }.to_string(),
+            birth_date: NaiveDate::from_ymd_opt(born, 6, 1).unwrap(),
+            social_security_number: "222-33-4444".to_string(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_children_are_counted_by_age_at_year_end() {
+        let members = [member(MemberRole::Spouse, 1985), member(MemberRole::Dependent, 2007), member(MemberRole::Dependent, 2006)];
+        // Born 2007 is 16 at the end of 2023; born 2006 has turned 17
+        let summary = summarize(&members, 2023, 17);
+        assert_eq!(summary, HouseholdSummary { spouse: true, qualifying_children: 1, other_dependents: 1 });
+    }
+
+    #[test]
+    fn test_filing_status_must_match_household() {
+        let couple = [member(MemberRole::Spouse, 1985)];
+        assert!(filing_status_problems(FilingStatus::MarriedFilingJointly, &couple, 2023).is_empty());
+        assert_eq!(filing_status_problems(FilingStatus::Single, &couple, 2023).len(), 1);
+        assert_eq!(filing_status_problems(FilingStatus::MarriedFilingJointly, &[], 2023).len(), 1);
+        assert_eq!(filing_status_problems(FilingStatus::HeadOfHousehold, &[], 2023).len(), 1);
+        assert!(filing_status_problems(FilingStatus::HeadOfHousehold, &[member(MemberRole::Dependent, 2015)], 2023).is_empty());
+        assert_eq!(filing_status_problems(FilingStatus::Single, &[member(MemberRole::Dependent, 2024)], 2023).len(), 1);
+    }
+}
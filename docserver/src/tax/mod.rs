@@ -0,0 +1,63 @@
+mod comparison;
+mod compute;
+mod deadlines;
+mod diff;
+mod estimates;
+mod household;
+mod rules;
+mod tables;
+mod taxonomy;
+
+pub use comparison::*;
+pub use compute::*;
+pub use deadlines::*;
+pub use diff::*;
+pub use estimates::*;
+pub use household::*;
+pub use rules::*;
+pub use tables::*;
+pub use taxonomy::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Filing status as used by the bracket and standard deduction tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilingStatus {
+    Single,
+    MarriedFilingJointly,
+    MarriedFilingSeparately,
+    HeadOfHousehold,
+    QualifyingSurvivingSpouse,
+}
+
+impl FilingStatus {
+    pub const ALL: [FilingStatus; 5] = [
+        FilingStatus::Single,
+        FilingStatus::MarriedFilingJointly,
+        FilingStatus::MarriedFilingSeparately,
+        FilingStatus::HeadOfHousehold,
+        FilingStatus::QualifyingSurvivingSpouse,
+    ];
+
+    /// Interprets the free-text `TaxReturn.filing_status`, accepting the usual
+    /// spellings and abbreviations ("Married Filing Jointly", "MFJ", "head_of_household").
+    pub fn parse(value: &str) -> Option<FilingStatus> {
+        let key: String = value
+            .chars()
+            .filter(|c| c.is_ascii_alphabetic())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match key.as_str() {
+            "single" | "s" => Some(FilingStatus::Single),
+            "marriedfilingjointly" | "marriedjoint" | "joint" | "mfj" => Some(FilingStatus::MarriedFilingJointly),
+            "marriedfilingseparately" | "marriedseparate" | "separate" | "mfs" => Some(FilingStatus::MarriedFilingSeparately),
+            "headofhousehold" | "hoh" => Some(FilingStatus::HeadOfHousehold),
+            "qualifyingsurvivingspouse" | "qualifyingwidower" | "qualifyingwidow" | "qss" | "qw" => {
+                Some(FilingStatus::QualifyingSurvivingSpouse)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::TaxYearRules;
+
+// Parameter files shipped with the server, copied into an empty rules
+// directory so operators have a template for the next year.
+const BUNDLED_RULES: &[(&str, &str)] = &[
+    ("2022.toml", include_str!("../../tax_rules/2022.toml")),
+    ("2023.toml", include_str!("../../tax_rules/2023.toml")),
+    ("2024.toml", include_str!("../../tax_rules/2024.toml")),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleFileError {
+    pub file: String,
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadedRules {
+    pub file: String,
+    pub rules: TaxYearRules,
+}
+
+/// Every tax year's rules found in the rules directory, plus the files that
+/// failed validation and were left out.
+#[derive(Debug, Default, Serialize)]
+pub struct RuleSet {
+    pub directory: Option<PathBuf>,
+    pub years: BTreeMap<i32, LoadedRules>,
+    pub errors: Vec<RuleFileError>,
+}
+
+impl RuleSet {
+    /// Loads every `.toml` and `.json` file in `directory`, seeding it with the
+    /// bundled years first if it does not exist yet.
+    pub fn load(directory: &Path) -> RuleSet {
+        let mut rule_set = RuleSet {
+            directory: Some(directory.to_path_buf()),
+            ..RuleSet::default()
+        };
+
+        if !directory.exists() {
+            if let Err(e) = seed_directory(directory) {
+                rule_set.errors.push(RuleFileError {
+                    file: directory.to_string_lossy().to_string(),
+                    problems: vec![format!("Failed to create rules directory: {}", e)],
+                });
+                return rule_set;
+            }
+        }
+
+        let mut paths: Vec<PathBuf> = match fs::read_dir(directory) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| matches!(extension(path).as_deref(), Some("toml") | Some("json")))
+                .collect(),
+            Err(e) => {
+                rule_set.errors.push(RuleFileError {
+                    file: directory.to_string_lossy().to_string(),
+                    problems: vec![format!("Failed to read rules directory: {}", e)],
+                });
+                return rule_set;
+            }
+        };
+        paths.sort();
+
+        for path in paths {
+            let file = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            match load_file(&path) {
+                Ok(rules) => rule_set.insert(file, rules),
+                Err(problems) => rule_set.errors.push(RuleFileError { file, problems }),
+            }
+        }
+
+        rule_set
+    }
+
+    fn insert(&mut self, file: String, rules: TaxYearRules) {
+        if let Some(existing) = self.years.get(&rules.tax_year) {
+            self.errors.push(RuleFileError {
+                problems: vec![format!(
+                    "tax_year {} is already defined by {}",
+                    rules.tax_year, existing.file
+                )],
+                file,
+            });
+            return;
+        }
+        self.years.insert(rules.tax_year, LoadedRules { file, rules });
+    }
+
+    pub fn get(&self, tax_year: i32) -> Option<&TaxYearRules> {
+        self.years.get(&tax_year).map(|loaded| &loaded.rules)
+    }
+
+    /// A message naming the missing year and where its file should go.
+    pub fn missing_year_message(&self, tax_year: i32) -> String {
+        let loaded: Vec<String> = self.years.keys().map(|year| year.to_string()).collect();
+        let directory = self.directory.as_ref()
+            .map(|d| d.to_string_lossy().to_string())
+            .unwrap_or_else(|| "the tax rules directory".to_string());
+        format!(
+            "No tax rules loaded for tax year {}; add {}.toml to {} (loaded years: {})",
+            tax_year,
+            tax_year,
+            directory,
+            if loaded.is_empty() { "none".to_string() } else { loaded.join(", ") },
+        )
+    }
+}
+
+/// Parses and validates a single rules file.
+pub fn load_file(path: &Path) -> Result<TaxYearRules, Vec<String>> {
+    let contents = fs::read_to_string(path).map_err(|e| vec![format!("Failed to read file: {}", e)])?;
+    let rules = parse_rules(&contents, extension(path).as_deref() == Some("json"))?;
+
+    // `2024.toml` must describe 2024, so a copied file cannot silently shadow another year
+    if let Some(stem_year) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<i32>().ok()) {
+        if stem_year != rules.tax_year {
+            return Err(vec![format!(
+                "file is named for {} but declares tax_year {}",
+                stem_year, rules.tax_year
+            )]);
+        }
+    }
+
+    Ok(rules)
+}
+
+pub fn parse_rules(contents: &str, is_json: bool) -> Result<TaxYearRules, Vec<String>> {
+    let rules: TaxYearRules = if is_json {
+        serde_json::from_str(contents).map_err(|e| vec![e.to_string()])?
+    } else {
+        toml::from_str(contents).map_err(|e| vec![e.to_string()])?
+    };
+
+    let problems = rules.validate();
+    if problems.is_empty() {
+        Ok(rules)
+    } else {
+        Err(problems)
+    }
+}
+
+/// The parameter files compiled into the server.
+pub fn bundled_rules(tax_year: i32) -> Option<TaxYearRules> {
+    BUNDLED_RULES
+        .iter()
+        .find(|(file, _)| file.trim_end_matches(".toml") == tax_year.to_string())
+        .and_then(|(_, contents)| parse_rules(contents, false).ok())
+}
+
+fn seed_directory(directory: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(directory)?;
+    for (file, contents) in BUNDLED_RULES {
+        fs::write(directory.join(file), contents)?;
+    }
+    Ok(())
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_bundled_rules_are_valid() {
+        for (file, contents) in BUNDLED_RULES {
+            assert!(parse_rules(contents, false).is_ok(), "{} failed validation", file);
+        }
+    }
+
+    #[test]
+    fn test_load_seeds_missing_directory() {
+        let temp = tempdir().unwrap();
+        let directory = temp.path().join("tax_rules");
+
+        let rule_set = RuleSet::load(&directory);
+        assert!(rule_set.errors.is_empty());
+        assert!(rule_set.get(2023).is_some());
+        assert!(directory.join("2024.toml").exists());
+        assert!(rule_set.missing_year_message(2031).contains("2031.toml"));
+    }
+
+    #[test]
+    fn test_invalid_files_are_reported() {
+        let temp = tempdir().unwrap();
+        let mut broken = BUNDLED_RULES[1].1.replace("rate_bp = 3700 }", "up_to = 900_000, rate_bp = 3700 }");
+        broken = broken.replace("tax_year = 2023", "tax_year = 2025");
+        fs::write(temp.path().join("2025.toml"), broken).unwrap();
+        fs::write(temp.path().join("2026.json"), "{\"tax_year\": 2026}").unwrap();
+        fs::write(temp.path().join("2027.toml"), BUNDLED_RULES[2].1).unwrap();
+
+        let rule_set = RuleSet::load(temp.path());
+        assert!(rule_set.years.is_empty());
+        assert_eq!(rule_set.errors.len(), 3);
+        assert!(rule_set.errors[0].problems[0].contains("last bracket must not have up_to"));
+        assert!(rule_set.errors[2].problems[0].contains("declares tax_year 2024"));
+    }
+}
@@ -0,0 +1,191 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::FilingStatus;
+use crate::money::Money;
+
+/// One marginal bracket. `up_to` is the top of the bracket's taxable income
+/// range; the last bracket has none.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bracket {
+    pub up_to: Option<Money>,
+    // Rate in basis points (2200 = 22%) so the arithmetic stays exact
+    pub rate_bp: i64,
+}
+
+/// Reduces a credit as AGI rises above a threshold, by a fixed amount for each
+/// $1,000 (or part of $1,000) over it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreditPhaseout {
+    pub credit: String,
+    pub thresholds: HashMap<FilingStatus, Money>,
+    pub reduction_per_thousand: Money,
+}
+
+/// Per-person credits worked out from the dependents a return covers: the
+/// child tax credit for children younger than `child_age_limit` at the end of
+/// the year, and the credit for other dependents for everyone else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DependentCredits {
+    pub child_age_limit: i32,
+    pub per_qualifying_child: Money,
+    pub per_other_dependent: Money,
+}
+
+/// Form 1040-ES parameters: the four installment due dates and the
+/// safe-harbor percentages. Paying `current_year_percent` of this year's tax,
+/// or `prior_year_percent` of last year's (`high_income_prior_year_percent`
+/// when last year's AGI was over the threshold), avoids the underpayment
+/// penalty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EstimatedTaxRules {
+    pub due_dates: Vec<NaiveDate>,
+    pub current_year_percent: i64,
+    pub prior_year_percent: i64,
+    pub high_income_prior_year_percent: i64,
+    pub high_income_thresholds: HashMap<FilingStatus, Money>,
+}
+
+/// Parameters for one tax year, loaded from a `<tax_year>.toml` or `.json` file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaxYearRules {
+    pub schema_version: u32,
+    pub tax_year: i32,
+    // Revision of the parameters, bumped whenever a file is corrected
+    pub version: String,
+    pub brackets: HashMap<FilingStatus, Vec<Bracket>>,
+    pub standard_deduction: HashMap<FilingStatus, Money>,
+    pub credit_phaseouts: Vec<CreditPhaseout>,
+    // Optional so files written before household data still load
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dependent_credits: Option<DependentCredits>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_payments: Option<EstimatedTaxRules>,
+}
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+impl TaxYearRules {
+    /// Checks the constraints the file format cannot express, returning every
+    /// problem found rather than stopping at the first.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.schema_version != SCHEMA_VERSION {
+            problems.push(format!(
+                "schema_version {} is not supported (expected {})",
+                self.schema_version, SCHEMA_VERSION
+            ));
+        }
+        if self.version.trim().is_empty() {
+            problems.push("version must not be empty".to_string());
+        }
+
+        for status in FilingStatus::ALL {
+            match self.standard_deduction.get(&status) {
+                Some(amount) if amount.is_negative() => {
+                    problems.push(format!("standard_deduction.{} must not be negative", status_key(status)));
+                }
+                Some(_) => {}
+                None => problems.push(format!("standard_deduction.{} is missing", status_key(status))),
+            }
+
+            match self.brackets.get(&status) {
+                Some(brackets) => validate_brackets(status, brackets, &mut problems),
+                None => problems.push(format!("brackets.{} is missing", status_key(status))),
+            }
+        }
+
+        for phaseout in &self.credit_phaseouts {
+            if phaseout.reduction_per_thousand.is_negative() {
+                problems.push(format!("credit_phaseouts.{} reduction must not be negative", phaseout.credit));
+            }
+            for status in FilingStatus::ALL {
+                if !phaseout.thresholds.contains_key(&status) {
+                    problems.push(format!(
+                        "credit_phaseouts.{} has no threshold for {}",
+                        phaseout.credit, status_key(status)
+                    ));
+                }
+            }
+        }
+
+        if let Some(dependent_credits) = &self.dependent_credits {
+            if dependent_credits.child_age_limit <= 0 {
+                problems.push("dependent_credits.child_age_limit must be positive".to_string());
+            }
+            if dependent_credits.per_qualifying_child.is_negative() || dependent_credits.per_other_dependent.is_negative() {
+                problems.push("dependent_credits amounts must not be negative".to_string());
+            }
+        }
+
+        if let Some(estimated) = &self.estimated_payments {
+            if estimated.due_dates.len() != 4 {
+                problems.push("estimated_payments.due_dates must list the four installments".to_string());
+            } else if estimated.due_dates.windows(2).any(|pair| pair[0] >= pair[1]) {
+                problems.push("estimated_payments.due_dates must be in order".to_string());
+            }
+            let percents = [
+                estimated.current_year_percent,
+                estimated.prior_year_percent,
+                estimated.high_income_prior_year_percent,
+            ];
+            if percents.iter().any(|percent| *percent <= 0) {
+                problems.push("estimated_payments percentages must be positive".to_string());
+            }
+            for status in FilingStatus::ALL {
+                if !estimated.high_income_thresholds.contains_key(&status) {
+                    problems.push(format!(
+                        "estimated_payments.high_income_thresholds has no threshold for {}",
+                        status_key(status)
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+fn validate_brackets(status: FilingStatus, brackets: &[Bracket], problems: &mut Vec<String>) {
+    let key = status_key(status);
+    if brackets.is_empty() {
+        problems.push(format!("brackets.{} must not be empty", key));
+        return;
+    }
+
+    let mut previous = Money::ZERO;
+    for (i, bracket) in brackets.iter().enumerate() {
+        if !(0..=10_000).contains(&bracket.rate_bp) {
+            problems.push(format!("brackets.{}[{}] rate_bp must be between 0 and 10000", key, i));
+        }
+        let is_last = i == brackets.len() - 1;
+        match (bracket.up_to, is_last) {
+            (None, true) => {}
+            (None, false) => problems.push(format!("brackets.{}[{}] needs up_to; only the last bracket is open-ended", key, i)),
+            (Some(_), true) => problems.push(format!("brackets.{} last bracket must not have up_to", key)),
+            (Some(up_to), false) => {
+                if up_to <= previous {
+                    problems.push(format!("brackets.{}[{}] up_to must be greater than the previous bracket", key, i));
+                }
+                previous = up_to;
+            }
+        }
+    }
+}
+
+fn status_key(status: FilingStatus) -> &'static str {
+    match status {
+        FilingStatus::Single => "single",
+        FilingStatus::MarriedFilingJointly => "married_filing_jointly",
+        FilingStatus::MarriedFilingSeparately => "married_filing_separately",
+        FilingStatus::HeadOfHousehold => "head_of_household",
+        FilingStatus::QualifyingSurvivingSpouse => "qualifying_surviving_spouse",
+    }
+}
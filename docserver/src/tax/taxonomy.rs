@@ -0,0 +1,91 @@
+use serde::Serialize;
+
+use crate::db::LineItemSection;
+
+/// A category line items can be filed under.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Category {
+    pub section: LineItemSection,
+    pub key: &'static str,
+    pub label: &'static str,
+}
+
+const fn category(section: LineItemSection, key: &'static str, label: &'static str) -> Category {
+    Category { section, key, label }
+}
+
+use LineItemSection::{Credit, Deduction, Income};
+
+/// The controlled vocabulary for line items. Keys match the ones the
+/// computation, form extraction and checklists already use.
+pub const CATEGORIES: &[Category] = &[
+    category(Income, "wages", "Wages, salaries, tips (W-2)"),
+    category(Income, "interest", "Taxable interest (1099-INT)"),
+    category(Income, "dividends", "Ordinary dividends (1099-DIV)"),
+    category(Income, "capital_gain_distributions", "Capital gain distributions (1099-DIV)"),
+    category(Income, "capital_gains", "Capital gains and losses (1099-B)"),
+    category(Income, "self_employment", "Nonemployee compensation (1099-NEC)"),
+    category(Income, "business_income", "Business income (Schedule C)"),
+    category(Income, "rents", "Rents (1099-MISC)"),
+    category(Income, "royalties", "Royalties (1099-MISC)"),
+    category(Income, "retirement_distributions", "Pensions, annuities, IRA distributions (1099-R)"),
+    category(Income, "partnership_income", "Partnership and S corporation income (K-1)"),
+    category(Income, "social_security", "Taxable social security benefits (SSA-1099)"),
+    category(Income, "unemployment", "Unemployment compensation (1099-G)"),
+    category(Income, "alimony_received", "Alimony received"),
+    category(Income, "other_income", "Other income"),
+    category(Deduction, "standard_deduction", "Standard deduction"),
+    category(Deduction, "student_loan_interest", "Student loan interest"),
+    category(Deduction, "ira_contributions", "IRA contributions"),
+    category(Deduction, "hsa_contributions", "HSA contributions"),
+    category(Deduction, "educator_expenses", "Educator expenses"),
+    category(Deduction, "self_employment_tax_deduction", "Deductible part of self-employment tax"),
+    category(Deduction, "alimony_paid", "Alimony paid"),
+    category(Deduction, "mortgage_interest", "Home mortgage interest (1098)"),
+    category(Deduction, "state_and_local_taxes", "State and local taxes"),
+    category(Deduction, "charitable_contributions", "Charitable contributions"),
+    category(Deduction, "medical_expenses", "Medical and dental expenses"),
+    category(Deduction, "tuition_and_fees", "Tuition and fees (1098-T)"),
+    category(Deduction, "other_itemized", "Other itemized deductions"),
+    category(Credit, "child_tax_credit", "Child tax credit"),
+    category(Credit, "credit_for_other_dependents", "Credit for other dependents"),
+    category(Credit, "additional_child_tax_credit", "Additional child tax credit"),
+    category(Credit, "earned_income_credit", "Earned income credit"),
+    category(Credit, "child_and_dependent_care_credit", "Child and dependent care credit"),
+    category(Credit, "american_opportunity_credit", "American opportunity credit"),
+    category(Credit, "american_opportunity_credit_refundable", "American opportunity credit (refundable part)"),
+    category(Credit, "lifetime_learning_credit", "Lifetime learning credit"),
+    category(Credit, "saver_credit", "Retirement savings contributions credit"),
+    category(Credit, "residential_energy_credit", "Residential energy credit"),
+    category(Credit, "foreign_tax_credit", "Foreign tax credit"),
+    category(Credit, "premium_tax_credit", "Premium tax credit"),
+    category(Credit, "excess_social_security", "Excess social security withheld"),
+    category(Credit, "other_credits", "Other credits"),
+];
+
+pub fn is_known_category(section: LineItemSection, key: &str) -> bool {
+    CATEGORIES.iter().any(|c| c.section == section && c.key == key)
+}
+
+/// The catch-all category of a section, for amounts no other key fits.
+pub fn other_category(section: LineItemSection) -> &'static str {
+    match section {
+        Income => "other_income",
+        Deduction => "other_itemized",
+        Credit => "other_credits",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_keys_are_unique_within_a_section() {
+        let keys: HashSet<(LineItemSection, &str)> = CATEGORIES.iter().map(|c| (c.section, c.key)).collect();
+        assert_eq!(keys.len(), CATEGORIES.len());
+        assert!(is_known_category(Income, "wages"));
+        assert!(!is_known_category(Credit, "wages"));
+    }
+}
@@ -1,10 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use super::super::rocket;
+    use super::super::server;
+    use docserver::config::{AppState, Settings};
     use rocket::local::blocking::Client;
     use rocket::http::Status;
     use rocket::http::ContentType;
     use std::fs;
+    use std::time::Duration;
     use tempfile::TempDir;
 
     fn setup_client() -> (Client, TempDir) {
@@ -15,10 +17,40 @@ mod tests {
         let test_file_path = temp_dir.path().join("test.txt");
         fs::write(&test_file_path, "test content").expect("Failed to write test file");
         
-        let client = Client::tracked(rocket()).expect("Failed to create client");
+        // Each test gets its own config file, root and database
+        let config_file = temp_dir.path().join("docserver.toml");
+        fs::write(&config_file, "root_path = \"data\"\n").expect("Failed to write config file");
+        let args = vec!["--config".to_string(), config_file.to_string_lossy().to_string()];
+        let settings = Settings::resolve(&args, &|_| None).expect("Test settings should be valid");
+        let state = AppState::new(settings).expect("Test state should open");
+
+        let client = Client::tracked(server(state)).expect("Failed to create client");
         (client, temp_dir)
     }
 
+    /// Relocates the root to `path` and waits for the relocation to finish.
+    fn set_root(client: &Client, path: &str) -> serde_json::Value {
+        let response = client.post("/config/path")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "path": path }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        wait_for_relocation(client)
+    }
+
+    /// Polls `/config/relocation` until the running relocation has finished.
+    fn wait_for_relocation(client: &Client) -> serde_json::Value {
+        for _ in 0..500 {
+            let response = client.get("/config/relocation").dispatch();
+            let progress: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            if !progress["finished_at"].is_null() {
+                return progress;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("The relocation did not finish");
+    }
+
     #[test]
     fn test_index() {
         let (client, _temp_dir) = setup_client();
@@ -63,14 +95,17 @@ mod tests {
             }))
             .dispatch();
         
-        assert_eq!(response.status(), Status::Ok);
+        // The relocation runs in the background and is followed separately
+        assert_eq!(response.status(), Status::Accepted);
         
         let response_json: serde_json::Value = serde_json::from_str(
             &response.into_string().unwrap()
         ).unwrap();
         
-        assert_eq!(response_json["status"], "success");
+        assert_eq!(response_json["status"], "accepted");
         assert!(response_json["message"].as_str().unwrap().contains(&test_path));
+        assert!(response_json["message"].as_str().unwrap().contains("/config/relocation"));
+        let progress = wait_for_relocation(&client);
         
         // Verify the path was actually set by making a GET request
         let get_response = client.get("/config/path").dispatch();
@@ -82,6 +117,21 @@ mod tests {
         
         assert_eq!(get_json["status"], "success");
         assert!(get_json["message"].as_str().unwrap().contains(&test_path));
+
+        // The database moved with the root, and both are saved for the next start
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        assert!(canonical.join("docstore.db").exists());
+        assert!(!temp_dir.path().join("data").join("docstore.db").exists());
+        let saved = fs::read_to_string(temp_dir.path().join("docserver.toml")).unwrap();
+        let saved: toml::Table = saved.parse().unwrap();
+        assert_eq!(saved["root_path"].as_str(), canonical.to_str());
+        assert_eq!(saved["db_path"].as_str(), canonical.join("docstore.db").to_str());
+
+        assert_eq!(progress["stage"], "done");
+        assert_eq!(progress["files_done"], progress["files_total"]);
+
+        // Requests keep working against the relocated database
+        assert_eq!(client.get("/clients/1").dispatch().status(), Status::Ok);
     }
 
     #[test]
@@ -110,12 +160,7 @@ mod tests {
         
         // First set the root path
         let test_path = temp_dir.path().to_string_lossy().to_string();
-        client.post("/config/path")
-            .header(ContentType::JSON)
-            .json(&serde_json::json!({
-                "path": test_path
-            }))
-            .dispatch();
+        set_root(&client, &test_path);
         
         // Create a test file
         let test_file = "test.txt";
@@ -134,12 +179,7 @@ mod tests {
         
         // First set the root path
         let test_path = temp_dir.path().to_string_lossy().to_string();
-        client.post("/config/path")
-            .header(ContentType::JSON)
-            .json(&serde_json::json!({
-                "path": test_path
-            }))
-            .dispatch();
+        set_root(&client, &test_path);
         
         // Try to get a non-existent file
         let response = client.get("/files/nonexistent.txt").dispatch();
@@ -152,12 +192,7 @@ mod tests {
         
         // First set the root path
         let test_path = temp_dir.path().to_string_lossy().to_string();
-        client.post("/config/path")
-            .header(ContentType::JSON)
-            .json(&serde_json::json!({
-                "path": test_path
-            }))
-            .dispatch();
+        set_root(&client, &test_path);
         
         // Create a test file
         let test_file = "test.txt";
@@ -254,4 +289,919 @@ mod tests {
         let files = response_json["files"].as_array().unwrap();
         assert_eq!(files.len(), 0);
     }
+
+    #[test]
+    fn test_upload_classifies_document() {
+        let (client, temp_dir) = setup_client();
+
+        let test_path = temp_dir.path().to_string_lossy().to_string();
+        set_root(&client, &test_path);
+
+        let boundary = "------------------------14737809831466499882746641449";
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"w2.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             2023 Form W-2 Wage and Tax Statement\n\
+             1 Wages, tips, other compensation 52000.00\n\
+             2 Federal income tax withheld 6100.00\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"files\"; filename=\"notes.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             Bring last year's return\r\n--{b}--\r\n",
+            b = boundary
+        );
+        let response = client.post("/files/upload/424242")
+            .header(ContentType::parse_flexible(&format!("multipart/form-data; boundary={}", boundary)).unwrap())
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get("/clients/424242/documents").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let documents: serde_json::Value = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        let document = documents.as_array().unwrap().iter()
+            .find(|d| d["file_name"] == "w2.txt")
+            .expect("Uploaded file should have a document record");
+        assert_eq!(document["document_type"], "W-2");
+        assert_eq!(document["tax_year"], 2023);
+
+        // The file listing links each file to its record
+        let response = client.get("/clients/424242/files").dispatch();
+        let files: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let listed = files.as_array().unwrap().iter().find(|f| f["file_name"] == "w2.txt").unwrap();
+        assert_eq!(listed["document"]["document_id"], document["document_id"]);
+        assert_eq!(listed["document"]["document_type"], "W-2");
+
+        // Override the classification
+        let document_id = document["document_id"].as_i64().unwrap();
+        let response = client.put(format!("/documents/{}/type", document_id))
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({
+                "document_type": "1099-MISC"
+            }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let updated: serde_json::Value = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        assert_eq!(updated["document_type"], "1099-MISC");
+        assert_eq!(updated["type_overridden"], true);
+        assert!(temp_dir.path().join("classifier_rules.json").exists());
+    }
+
+    #[test]
+    fn test_return_computation_flags_discrepancies() {
+        let (client, _temp_dir) = setup_client();
+
+        // The seeded 2023 return stores a liability the computation disagrees with
+        let response = client.get("/returns/1/computation").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let report: serde_json::Value = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        assert_eq!(report["computation"]["taxable_income"], "37150.00");
+        assert_eq!(report["computation"]["tax_liability"], "2238.00");
+        let fields: Vec<&str> = report["discrepancies"].as_array().unwrap().iter()
+            .map(|d| d["field"].as_str().unwrap())
+            .collect();
+        assert!(fields.contains(&"tax_liability"));
+
+        let response = client.get("/returns/999999/computation").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_amend_return_and_diff() {
+        let (client, _temp_dir) = setup_client();
+
+        // Client 1's seeded 2023 return
+        let parent_id = 1;
+
+        let response = client.post(format!("/returns/{}/amend", parent_id))
+            .header(ContentType::JSON)
+            .body(r#"{"amendment_reason": "Corrected withholding", "taxes_paid": "9999.99"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let amended: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(amended["return_kind"], "amended");
+        assert_eq!(amended["parent_return_id"], parent_id);
+        let amended_id = amended["tax_return_id"].as_i64().unwrap();
+
+        let response = client.get(format!("/returns/{}/amendment-diff", amended_id)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let diff: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let taxes_paid = diff["figures"].as_array().unwrap().iter()
+            .find(|f| f["field"] == "taxes_paid")
+            .unwrap();
+        assert_eq!(taxes_paid["after"], "9999.99");
+
+        let response = client.post(format!("/returns/{}/amend", parent_id))
+            .header(ContentType::JSON)
+            .body(r#"{"amendment_reason": "Too late"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+
+        let response = client.get("/returns?client_id=1&chain=true").dispatch();
+        let all: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(all.as_array().unwrap().iter().any(|r| r["tax_return_id"] == parent_id));
+
+        let response = client.get("/returns/1/amendment-diff").dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn test_workflow_board_and_transitions() {
+        let (client, _temp_dir) = setup_client();
+
+        let response = client.get("/workflow/board").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let board: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let columns = board.as_array().unwrap();
+        assert_eq!(columns.len(), 9);
+        assert_eq!(columns[0]["status"], "documents_requested");
+
+        let response = client.get("/workflow/board?status=documents_requested").dispatch();
+        let board: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(board.as_array().unwrap().len(), 1);
+        assert_eq!(board[0]["returns"][0]["tax_return_id"], 1);
+
+        let response = client.get("/workflow/board?status=filed-ish").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client.post("/returns/1/transition")
+            .header(ContentType::JSON)
+            .body(r#"{"status": "complete", "assignee": "sam"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+
+        let response = client.post("/returns/1/transition")
+            .header(ContentType::JSON)
+            .body(r#"{"status": "documents_received", "assignee": "sam"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // Leaving out the assignee keeps it; null clears it
+        let response = client.post("/returns/1/transition")
+            .header(ContentType::JSON)
+            .body(r#"{"status": "in_preparation"}"#)
+            .dispatch();
+        let updated: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(updated["assignee"], "sam");
+
+        let response = client.post("/returns/1/transition")
+            .header(ContentType::JSON)
+            .body(r#"{"status": "documents_requested", "assignee": null}"#)
+            .dispatch();
+        let updated: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(updated["assignee"].is_null());
+
+        let response = client.post("/returns/999999/transition")
+            .header(ContentType::JSON)
+            .body(r#"{"status": "documents_received"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get("/returns/1/history").dispatch();
+        let history: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(history[0]["to_status"], "documents_requested");
+        assert_eq!(history[2]["assignee"], "sam");
+        assert_eq!(history.as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_checklist_from_prior_year_return() {
+        let (client, _temp_dir) = setup_client();
+
+        // Client 1's seeded 2023 return reports wages and interest
+        let response = client.post("/clients/1/checklists/2024/generate").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let items: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let types: Vec<&str> = items.as_array().unwrap().iter()
+            .map(|i| i["document_type"].as_str().unwrap())
+            .collect();
+        assert!(types.contains(&"W-2"));
+        assert!(types.contains(&"1099-INT"));
+
+        let response = client.get("/clients/1/outstanding-documents?tax_year=2024").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let outstanding: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(outstanding.as_array().unwrap().iter().all(|i| i["status"] == "pending"));
+    }
+
+    #[test]
+    fn test_public_upload_link() {
+        let (client, temp_dir) = setup_client();
+        set_root(&client, &temp_dir.path().to_string_lossy());
+
+        let response = client.post("/clients/1/upload-links")
+            .header(ContentType::JSON)
+            .body(r#"{"note": "Missing 1099-INT", "expires_in_hours": 24}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let issued: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(issued["link"]["status"], "open");
+        let link_id = issued["link"]["link_id"].as_i64().unwrap();
+        let path = issued["path"].as_str().unwrap().to_string();
+
+        let boundary = "------------------------9051914041544843365972754266";
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"bank_1099.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             2023 Form 1099-INT Interest Income\n1 Interest income 215.42\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"files\"; filename=\"cover.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             Here you go\r\n--{b}--\r\n",
+            b = boundary
+        );
+        let multipart = ContentType::parse_flexible(&format!("multipart/form-data; boundary={}", boundary)).unwrap();
+
+        let tampered = path.replacen(&format!("/{}.", link_id), &format!("/{}.", link_id + 1000), 1);
+        let response = client.post(tampered).header(multipart.clone()).body(body.clone()).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.post(path.clone()).header(multipart.clone()).body(body.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let reply = response.into_string().unwrap();
+        assert!(!reply.contains("bank_1099"), "public replies should not echo stored files");
+        assert!(temp_dir.path().join("1").join("bank_1099.txt").exists());
+
+        // Single use by default
+        let response = client.post(path).header(multipart).body(body).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get("/upload-links?client_id=1&status=used").dispatch();
+        let used: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(used.as_array().unwrap().iter().any(|l| l["link_id"] == link_id));
+
+        let response = client.post(format!("/upload-links/{}/revoke", link_id)).dispatch();
+        let revoked: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(revoked["status"], "success");
+    }
+
+    #[test]
+    fn test_public_upload_stays_in_client_folder() {
+        let (client, temp_dir) = setup_client();
+        fs::create_dir_all(temp_dir.path().join("1")).unwrap();
+        fs::write(temp_dir.path().join("1").join("x"), "already here").unwrap();
+        set_root(&client, &temp_dir.path().to_string_lossy());
+
+        let response = client.post("/clients/1/upload-links")
+            .header(ContentType::JSON)
+            .body(r#"{"expires_in_hours": 24}"#)
+            .dispatch();
+        let issued: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let path = issued["path"].as_str().unwrap().to_string();
+
+        let boundary = "------------------------9051914041544843365972754266";
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"../x\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             escaped\r\n--{b}\r\n\
+             Content-Disposition: form-data; name=\"files\"; filename=\"/etc/passwd\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             absolute\r\n--{b}--\r\n",
+            b = boundary
+        );
+        let multipart = ContentType::parse_flexible(&format!("multipart/form-data; boundary={}", boundary)).unwrap();
+        let response = client.post(path).header(multipart).body(body).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // `../x` is kept as `x` in the client's folder, beside the file it would have replaced
+        assert!(!temp_dir.path().join("x").exists());
+        assert_eq!(fs::read_to_string(temp_dir.path().join("1").join("x")).unwrap(), "already here");
+        let stored: Vec<String> = fs::read_dir(temp_dir.path().join("1")).unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.contains(&"escaped".to_string()));
+    }
+
+    #[test]
+    fn test_share_link_download() {
+        let (client, temp_dir) = setup_client();
+        fs::create_dir_all(temp_dir.path().join("1")).unwrap();
+        fs::write(temp_dir.path().join("1").join("return_2023.pdf"), "%PDF-1.4 return").unwrap();
+        set_root(&client, &temp_dir.path().to_string_lossy());
+
+        let response = client.post("/share-links")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "path": "../etc/passwd" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.post("/share-links")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({
+                "path": "1/return_2023.pdf",
+                "password": "lender-2023",
+                "max_downloads": 1,
+                "recipient": "First Bank"
+            }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let issued: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(issued["link"]["client_id"], 1);
+        assert_eq!(issued["link"]["password_protected"], true);
+        assert!(issued["link"].get("password_hash").is_none());
+        let link_id = issued["link"]["link_id"].as_i64().unwrap();
+        let path = issued["path"].as_str().unwrap().to_string();
+
+        assert_eq!(client.get(path.clone()).dispatch().status(), Status::Unauthorized);
+        let response = client.post(path.clone())
+            .header(ContentType::Form)
+            .body("password=guess")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.post(path.clone())
+            .header(ContentType::Form)
+            .body("password=lender-2023")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), "%PDF-1.4 return");
+
+        // The download limit has been reached
+        let response = client.post(path)
+            .header(ContentType::Form)
+            .body("password=lender-2023")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get(format!("/share-links/{}/accesses", link_id)).dispatch();
+        let accesses: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let outcomes: Vec<&str> = accesses.as_array().unwrap().iter()
+            .map(|a| a["outcome"].as_str().unwrap())
+            .collect();
+        assert_eq!(outcomes, vec!["password_required", "wrong_password", "downloaded", "unavailable"]);
+    }
+
+    #[test]
+    fn test_portal_is_scoped_to_own_client() {
+        use rocket::http::Header;
+
+        let (client, temp_dir) = setup_client();
+        fs::create_dir_all(temp_dir.path().join("1")).unwrap();
+        fs::write(temp_dir.path().join("1").join("w2_2023.pdf"), "%PDF-1.4 w2").unwrap();
+        fs::create_dir_all(temp_dir.path().join("2")).unwrap();
+        fs::write(temp_dir.path().join("2").join("other.pdf"), "%PDF-1.4 other").unwrap();
+        set_root(&client, &temp_dir.path().to_string_lossy());
+
+        let email = "pat@example.com";
+        let response = client.post("/clients/1/portal-users")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "email": email, "password": "correct horse" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.into_string().unwrap().find("password_hash").is_none());
+
+        assert_eq!(client.get("/portal/me").dispatch().status(), Status::Unauthorized);
+        let response = client.post("/portal/login")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "email": email, "password": "wrong" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client.post("/portal/login")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "email": email, "password": "correct horse" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let login: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let auth = Header::new("Authorization", format!("Bearer {}", login["token"].as_str().unwrap()));
+
+        let response = client.get("/portal/me").header(auth.clone()).dispatch();
+        let me: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(me["client_id"], 1);
+        assert!(me["social_security_number"].as_str().unwrap().starts_with("***-**-"));
+
+        let response = client.get("/portal/returns").header(auth.clone()).dispatch();
+        let returns: Vec<serde_json::Value> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(!returns.is_empty());
+        assert!(returns.iter().all(|r| r["client_id"] == 1));
+
+        let response = client.get("/portal/files").header(auth.clone()).dispatch();
+        let files: Vec<String> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(files, vec!["w2_2023.pdf".to_string()]);
+        assert_eq!(client.get("/portal/files/w2_2023.pdf").header(auth.clone()).dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/portal/files/other.pdf").header(auth.clone()).dispatch().status(), Status::NotFound);
+        assert_eq!(client.get("/portal/files/..%2F2%2Fother.pdf").header(auth.clone()).dispatch().status(), Status::NotFound);
+
+        let response = client.post("/portal/contact-changes")
+            .header(auth.clone())
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "phone_number": "555-0199" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let request: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(request["status"], "pending");
+        let request_id = request["request_id"].as_i64().unwrap();
+
+        let response = client.post(format!("/contact-change-requests/{}/approve", request_id)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/portal/me").header(auth.clone()).dispatch();
+        let me: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(me["phone_number"], "555-0199");
+        assert_eq!(
+            client.post(format!("/contact-change-requests/{}/reject", request_id)).dispatch().status(),
+            Status::Conflict
+        );
+
+        assert_eq!(client.post("/portal/logout").header(auth.clone()).dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/portal/me").header(auth).dispatch().status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn test_household_validation() {
+        let (client, _temp_dir) = setup_client();
+
+        let member = |ssn: &str| serde_json::json!({
+            "role": "dependent",
+            "first_name": "Sam",
+            "last_name": "Doe",
+            "relationship": "son",
+            "birth_date": "2015-09-30",
+            "social_security_number": ssn
+        });
+        let response = client.post("/clients/1/household")
+            .header(ContentType::JSON)
+            .json(&member("12-345"))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client.post("/clients/999999/household")
+            .header(ContentType::JSON)
+            .json(&member("222-33-4444"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.post("/clients/1/household")
+            .header(ContentType::JSON)
+            .json(&member("222-33-4444"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let child: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+        // Until the child is linked, return 1 is computed from its own credits
+        let response = client.get("/returns/1/household").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let household: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(household["filing_status"], "Single");
+        assert_eq!(household["problems"], serde_json::json!([]));
+        let response = client.get("/returns/1/computation").dispatch();
+        let report: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(report["computation"]["household"].is_null());
+
+        let response = client.post("/returns/1/household")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "member_ids": [child["member_id"]] }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/returns/1/computation").dispatch();
+        let report: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(report["computation"]["household"]["qualifying_children"], 1);
+
+        let response = client.post("/returns/1/household")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "member_ids": [999999] }))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn test_line_item_endpoints() {
+        let (client, _temp_dir) = setup_client();
+
+        let response = client.get("/line-item-categories?section=credit").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let categories: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(categories.as_array().unwrap().iter().all(|c| c["section"] == "credit"));
+        assert_eq!(client.get("/line-item-categories?section=bogus").dispatch().status(), Status::BadRequest);
+
+        let response = client.get("/returns/1/line-items?section=income").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let items: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(items.as_array().unwrap().iter().any(|i| i["category"] == "wages" && i["amount"] == "50000.00"));
+
+        let response = client.post("/returns/1/line-items")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "section": "income", "category": "lottery", "amount": "10.00" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client.post("/returns/1/amend")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "amendment_reason": "Won", "income_sources": { "lottery": "10.00" } }))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client.post("/returns/999999/line-items")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "section": "income", "category": "wages", "amount": "10.00" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get("/line-items?category=wages&client_id=1&tax_year=2023").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let found: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(!found.as_array().unwrap().is_empty());
+        assert_eq!(client.delete("/line-items/999999").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_year_comparison_parameters() {
+        let (client, _temp_dir) = setup_client();
+
+        assert_eq!(client.get("/clients/999999/comparison").dispatch().status(), Status::NotFound);
+        assert_eq!(client.get("/clients/1/comparison?from=2022").dispatch().status(), Status::BadRequest);
+        assert_eq!(client.get("/clients/1/comparison?from=2023&to=2023").dispatch().status(), Status::UnprocessableEntity);
+        assert_eq!(client.get("/clients/1/comparison?last=1").dispatch().status(), Status::UnprocessableEntity);
+        assert_eq!(client.get("/clients/1/comparison.csv?from=1990&to=2023").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_year_comparison() {
+        use docserver::db::TaxReturn;
+
+        let (client, _temp_dir) = setup_client();
+
+        // A 2022 return entered as totals, without payers
+        let response = client.get("/returns/1").dispatch();
+        let mut earlier: TaxReturn = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        earlier.tax_return_id = None;
+        earlier.tax_year = 2022;
+        earlier.income_sources = serde_json::from_value(serde_json::json!({ "wages": "48000.00", "interest": "900.00" })).unwrap();
+        let state = client.rocket().state::<AppState>().unwrap();
+        let earlier_id = state.repository().create_tax_return(&earlier).unwrap();
+
+        let response = client.post("/returns/1/line-items")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "section": "income", "category": "wages", "payer": "Acme", "amount": "2000.00" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get("/clients/1/comparison?from=2022&to=2023").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let comparison: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(comparison["years"], serde_json::json!([2022, 2023]));
+        assert_eq!(comparison["tax_return_ids"], serde_json::json!([earlier_id, 1]));
+        let lines = comparison["lines"].as_array().unwrap();
+        let income: Vec<&serde_json::Value> = lines.iter().filter(|l| l["section"] == "income").collect();
+        assert_eq!(income.len(), 3);
+
+        // 2022 has wages without a payer, so Acme is not flagged new
+        assert_eq!(income[0]["category"], "interest");
+        assert_eq!(income[0]["subtotal"], false);
+        assert_eq!(income[1]["payer"], "Acme");
+        assert_eq!(income[1]["amounts"], serde_json::json!([null, "2000.00"]));
+        assert!(income[1]["changes"][0]["flag"].is_null());
+        assert_eq!(income[2]["subtotal"], true);
+        assert_eq!(income[2]["amounts"], serde_json::json!(["48000.00", "52000.00"]));
+        assert_eq!(income[2]["changes"][0], serde_json::json!({
+            "from_year": 2022, "to_year": 2023, "change": "4000.00", "percent_change": 8.3, "flag": null
+        }));
+        assert!(lines.iter().any(|l| l["section"] == "totals" && l["category"] == "tax_liability"));
+
+        let response = client.get("/clients/1/comparison.csv?last=2").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::CSV));
+        assert!(response.into_string().unwrap().contains("income,wages,,subtotal,48000.00,52000.00,4000.00,8.3,\r\n"));
+    }
+
+    #[test]
+    fn test_estimated_payment_endpoints() {
+        let (client, _temp_dir) = setup_client();
+
+        let payment = |quarter: i32| serde_json::json!({
+            "tax_year": 2024,
+            "quarter": quarter,
+            "amount": "1250.00",
+            "paid_on": "2024-04-15",
+            "method": "direct_pay"
+        });
+        let response = client.post("/clients/1/estimated-payments")
+            .header(ContentType::JSON)
+            .json(&payment(5))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client.post("/clients/999999/estimated-payments")
+            .header(ContentType::JSON)
+            .json(&payment(1))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.post("/clients/1/estimated-payments")
+            .header(ContentType::JSON)
+            .json(&payment(1))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let created: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(created["amount"], "1250.00");
+        let payment_id = created["payment_id"].as_i64().unwrap();
+
+        let response = client.get("/clients/1/estimated-payments?tax_year=2024").dispatch();
+        let listed: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["payment_id"], payment_id);
+
+        // Client 1's 2023 return owed 8000.00, so a quarter of that is due by April 15
+        let response = client.get("/clients/1/safe-harbor/2024").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let safe_harbor: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(safe_harbor["required_annual_payment"], "8000.00");
+        assert_eq!(safe_harbor["total_paid"], "1250.00");
+        assert_eq!(safe_harbor["installments"][0]["shortfall"], "750.00");
+
+        let response = client.delete(format!("/estimated-payments/{}", payment_id)).dispatch();
+        let removed: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(removed["status"], "success");
+
+        assert_eq!(client.get("/clients/1/safe-harbor/2030").dispatch().status(), Status::UnprocessableEntity);
+        assert_eq!(client.get("/estimated-payments/behind?as_of=soon").dispatch().status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_deadline_endpoints() {
+        use docserver::db::{Client, TaxReturn};
+
+        let (client, _temp_dir) = setup_client();
+
+        let response = client.get("/returns/1/deadlines").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let deadlines: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(deadlines["deadlines"][0]["jurisdiction"], "federal");
+        assert_eq!(deadlines["deadlines"][0]["statutory_date"], "2024-04-15");
+
+        // Rejected before anything is stored
+        let response = client.post("/returns/1/extension")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "filed_on": "2024-05-01" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client.post("/returns/999999/extension")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "filed_on": "2024-04-01" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.put("/clients/1/tax-profile")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "home_state": "Virginia" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        assert_eq!(client.get("/deadlines?as_of=tomorrow").dispatch().status(), Status::BadRequest);
+
+        // A nonresident without wages files two months later
+        let response = client.put("/clients/1/tax-profile")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "return_type": "1040-nr-without-wages" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/returns/1/deadlines").dispatch();
+        let deadlines: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(deadlines["current"][0]["statutory_date"], "2024-06-15");
+        assert_eq!(deadlines["current"][0]["due_date"], "2024-06-17");
+
+        // A 2022 return for a second client, due on Saturday April 15, 2023
+        let state = client.rocket().state::<AppState>().unwrap();
+        let repo = state.repository();
+        let client_id = repo.create_client(&Client {
+            client_id: None,
+            first_name: "Maximiliana".to_string(),
+            last_name: "Featherstonehaugh-Cholmondeley".to_string(),
+            social_security_number: "123-45-6789".to_string(),
+            address: "1 Main St".to_string(),
+            phone_number: "(555) 010-0000".to_string(),
+            email: "max@example.com".to_string(),
+            created_at: None,
+            updated_at: None,
+        }).unwrap();
+        let mut earlier: TaxReturn = serde_json::from_str(&client.get("/returns/1").dispatch().into_string().unwrap()).unwrap();
+        earlier.tax_return_id = None;
+        earlier.client_id = client_id;
+        earlier.tax_year = 2022;
+        let earlier_id = repo.create_tax_return(&earlier).unwrap();
+        drop(repo);
+
+        // Monday the 17th is Emancipation Day in DC, so it is due the 18th
+        let response = client.get("/deadlines?as_of=2023-04-01&days=30").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let report: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(report["through"], "2023-05-01");
+        assert_eq!(report["overdue"], serde_json::json!([]));
+        assert_eq!(report["upcoming"], serde_json::json!([{
+            "tax_return_id": earlier_id,
+            "client_id": client_id,
+            "client_name": "Featherstonehaugh-Cholmondeley, Maximiliana",
+            "tax_year": 2022,
+            "status": "documents_requested",
+            "jurisdiction": "federal",
+            "kind": "original",
+            "due_date": "2023-04-18"
+        }]));
+        let response = client.get("/deadlines?as_of=2024-06-01&days=30").dispatch();
+        let report: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(report["overdue"][0]["tax_return_id"], earlier_id);
+        assert_eq!(report["upcoming"][0]["tax_return_id"], 1);
+
+        let response = client.get("/deadlines.ics").dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::Calendar));
+        let feed = response.into_string().unwrap();
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(feed.split("\r\n").all(|line| line.len() <= 75));
+        // The long summary is folded onto a continuation line
+        assert!(feed.contains("\r\n "));
+        let unfolded = feed.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!(
+            "UID:return-{}-federal-original@docserver\r\nDTSTAMP:", earlier_id
+        )));
+        assert!(unfolded.contains("DTSTART;VALUE=DATE:20230418\r\nDTEND;VALUE=DATE:20230419\r\n"));
+        assert!(unfolded.contains("SUMMARY:Featherstonehaugh-Cholmondeley\\, Maximiliana: 2022 federal return due\r\n"));
+        assert!(unfolded.contains("DTSTART;VALUE=DATE:20240617\r\n"));
+    }
+
+    #[test]
+    fn test_state_return_endpoints() {
+        let (client, _temp_dir) = setup_client();
+
+        // Rejected before anything is stored
+        let response = client.post("/returns/1/state-returns")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "state": "TX" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client.post("/returns/999999/state-returns")
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "state": "VA" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        assert_eq!(client.get("/returns?jurisdiction=bogus").dispatch().status(), Status::BadRequest);
+        let response = client.get("/returns?client_id=1&jurisdiction=federal").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let federal: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(federal.as_array().unwrap().iter().all(|r| r["jurisdiction"] == "federal"));
+
+        let response = client.get("/clients/1/returns").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let years: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(years.as_array().unwrap().iter().any(|y| y["tax_year"] == 2023 && y["federal"]["jurisdiction"] == "federal"));
+    }
+
+    #[test]
+    fn test_postgres_backend() {
+        let Ok(url) = std::env::var("DOCSERVER_TEST_POSTGRES_URL") else {
+            assert!(std::env::var_os("CI").is_none(), "CI must set DOCSERVER_TEST_POSTGRES_URL");
+            eprintln!("Skipping: set DOCSERVER_TEST_POSTGRES_URL to run the PostgreSQL tests");
+            return;
+        };
+        let server_config: postgres::Config = url.parse().expect("DOCSERVER_TEST_POSTGRES_URL is not a connection URL");
+        let name = format!("docserver_test_{}", uuid::Uuid::new_v4().simple());
+        let mut admin = server_config.connect(docserver::db::postgres::tls_connector().unwrap()).unwrap();
+        admin.batch_execute(&format!("CREATE DATABASE {}", name)).unwrap();
+        let (server_url, _) = url.split_once('?').unwrap_or((&url, ""));
+        let database = format!("{}/{}", server_url.rsplit_once('/').unwrap().0, name);
+
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let result = std::panic::catch_unwind(|| {
+            let config_file = temp_dir.path().join("docserver.toml");
+            let config = format!("root_path = \"data\"\ndb_backend = \"postgres\"\ndb_url = \"{}\"\n", database);
+            fs::write(&config_file, config).expect("Failed to write config file");
+            let args = vec!["--config".to_string(), config_file.to_string_lossy().to_string()];
+            let settings = Settings::resolve(&args, &|_| None).expect("Test settings should be valid");
+            let state = AppState::new(settings).expect("Test state should open");
+            assert!(!temp_dir.path().join("data").join("docstore.db").exists());
+
+            let client_id = state.repository().create_client(&docserver::Client {
+                client_id: None,
+                first_name: "Pat".to_string(),
+                last_name: "Rivera".to_string(),
+                social_security_number: "123-45-6789".to_string(),
+                address: "1 Main St".to_string(),
+                phone_number: "(555) 010-0000".to_string(),
+                email: "pat@example.com".to_string(),
+                created_at: None,
+                updated_at: None,
+            }).unwrap();
+
+            let client = Client::tracked(server(state)).expect("Failed to create client");
+            assert_eq!(client.get(format!("/clients/{}", client_id)).dispatch().status(), Status::Ok);
+            // Office records are on PostgreSQL too
+            let response = client.post(format!("/clients/{}/checklists/2024/generate", client_id)).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let response = client.get("/metrics/database").dispatch();
+            let metrics: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            assert!(metrics["checkouts"].as_u64().unwrap() >= 2);
+
+            // The server only backs up SQLite databases
+            assert_eq!(client.post("/admin/backups").dispatch().status(), Status::Conflict);
+        });
+        admin.batch_execute(&format!("DROP DATABASE {} WITH (FORCE)", name)).unwrap();
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    #[test]
+    fn test_database_metrics() {
+        let (client, _temp_dir) = setup_client();
+        assert_eq!(client.get("/clients").dispatch().status(), Status::Ok);
+
+        let response = client.get("/metrics/database").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let metrics: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(metrics["max_size"], 8);
+        assert_eq!(metrics["in_use"], 0);
+        assert!(metrics["checkouts"].as_u64().unwrap() >= 1);
+        assert_eq!(metrics["checkout_timeouts"], 0);
+    }
+
+    #[test]
+    fn test_tax_rules_endpoints() {
+        let (client, _temp_dir) = setup_client();
+
+        let response = client.get("/tax-rules").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let status: serde_json::Value = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        assert!(status["years"].as_array().unwrap().iter().any(|y| y["tax_year"] == 2023));
+
+        let response = client.get("/tax-rules/2023").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let rules: serde_json::Value = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        assert_eq!(rules["standard_deduction"]["single"], "13850.00");
+
+        let response = client.get("/tax-rules/1999").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let error: serde_json::Value = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        assert!(error["message"].as_str().unwrap().contains("No tax rules loaded for tax year 1999"));
+    }
+
+    #[test]
+    fn test_backup_endpoints() {
+        let (client, temp_dir) = setup_client();
+        assert_eq!(client.get("/admin/backups").dispatch().into_string().unwrap(), "[]");
+
+        let response = client.post("/admin/backups").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let summary: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let name = summary["name"].as_str().unwrap().to_string();
+        let response = client.get("/admin/backups").dispatch();
+        let backups: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(backups[0]["name"], name.as_str());
+
+        let response = client.post(format!("/admin/backups/{}/verify", name)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let manifest: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(manifest["entries"].as_array().unwrap().len(), summary["files"].as_u64().unwrap() as usize);
+        assert_eq!(client.post("/admin/backups/missing.tar.gz/verify").dispatch().status(), Status::NotFound);
+
+        let target = temp_dir.path().join("restored");
+        let restore = |target: &std::path::Path| client.post(format!("/admin/backups/{}/restore", name))
+            .header(ContentType::JSON)
+            .json(&serde_json::json!({ "target": target }))
+            .dispatch()
+            .status();
+        assert_eq!(restore(&target), Status::Ok);
+        assert!(target.join("docstore.db").exists());
+        assert_eq!(restore(&target), Status::Conflict);
+    }
+
+    #[test]
+    fn test_consistency_endpoints() {
+        let (client, temp_dir) = setup_client();
+        let root = temp_dir.path().join("data");
+        let stray = root.join("1").join("stray.txt");
+        fs::create_dir_all(stray.parent().unwrap()).unwrap();
+        fs::write(&stray, "not uploaded").unwrap();
+        fs::create_dir_all(root.join("9999")).unwrap();
+        fs::create_dir_all(root.join("not-a-client")).unwrap();
+        // Older than the five minutes an upload may take to be recorded
+        let long_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        for path in [&stray, &root.join("9999")] {
+            fs::File::open(path).unwrap().set_modified(long_ago).unwrap();
+        }
+        let arriving = root.join("1").join("arriving.txt");
+        fs::write(&arriving, "still being recorded").unwrap();
+
+        let response = client.get("/admin/consistency").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let report: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(report["orphan_files"], serde_json::json!(["1/stray.txt"]));
+        assert_eq!(report["orphan_directories"].as_array().unwrap().len(), 1);
+        assert_eq!(report["orphan_directories"][0]["path"], "9999");
+
+        let response = client.post("/admin/consistency/repair").dispatch();
+        let repaired: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(repaired["repair"]["trashed"].as_array().unwrap().len(), 2);
+        assert!(!stray.exists());
+        assert!(arriving.exists() && root.join("not-a-client").is_dir());
+        let response = client.get("/admin/consistency").dispatch();
+        let report: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(report["orphan_files"].as_array().unwrap().is_empty());
+    }
 }
@@ -0,0 +1,95 @@
+//! Runs `docserver-admin` against a scratch root, as an operator would.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, SystemTime};
+use tempfile::{tempdir, TempDir};
+
+fn scratch() -> TempDir {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("docserver.toml"), "").unwrap();
+    dir
+}
+
+fn admin(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let config = dir.join("docserver.toml");
+    let root = dir.join("root");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_docserver-admin"))
+        .args(args)
+        .args(["--config", config.to_str().unwrap(), "--root-path", root.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_clients_users_and_key_rotation() {
+    let dir = scratch();
+    assert!(stdout(&admin(dir.path(), &["init"], "")).contains("schema version"));
+
+    let clients = r#"[{"first_name": "Pat", "last_name": "Rivera", "social_security_number": "900-12-3456",
+        "address": "1 Main St", "phone_number": "(555) 010-0000", "email": "pat@example.com"}]"#;
+    let imported = stdout(&admin(dir.path(), &["import-clients", "-"], clients));
+    let client_id = imported.split_whitespace().nth(5).unwrap().to_string();
+
+    assert!(stdout(&admin(dir.path(), &["create-user", &client_id, "pat@example.com"], "correct horse\n")).contains("Created"));
+    let duplicate = admin(dir.path(), &["create-user", &client_id, "pat@example.com"], "correct horse\n");
+    assert_eq!(duplicate.status.code(), Some(1));
+    assert_eq!(admin(dir.path(), &["create-user", "x", "pat@example.com"], "").status.code(), Some(2));
+
+    // A running server holds the key shared; rotating under it is refused
+    let key = fs::File::open(dir.path().join("root").join("field.key")).unwrap();
+    key.lock_shared().unwrap();
+    let refused = admin(dir.path(), &["rotate-field-key"], "");
+    assert_eq!(refused.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("stop the server"));
+    drop(key);
+
+    assert!(stdout(&admin(dir.path(), &["rotate-field-key"], "")).contains("Rotated"));
+    // The new key reads the rotated values; the old one waits in the trash
+    assert!(stdout(&admin(dir.path(), &["export-clients"], "")).contains("900-12-3456"));
+    let export = dir.path().join("clients.json");
+    admin(dir.path(), &["export-clients", export.to_str().unwrap()], "");
+    assert!(fs::read_to_string(&export).unwrap().contains("900-12-3456"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&export).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    assert!(dir.path().join("root").join(".trash").read_dir().unwrap().next().is_some());
+    assert!(stdout(&admin(dir.path(), &["purge-trash"], "")).contains("Purged 0"));
+}
+
+#[test]
+fn test_verify_and_reindex() {
+    let dir = scratch();
+    stdout(&admin(dir.path(), &["migrate"], ""));
+    assert!(stdout(&admin(dir.path(), &["verify"], "")).contains("agree"));
+
+    let client_dir = dir.path().join("root").join("1");
+    fs::create_dir_all(&client_dir).unwrap();
+    fs::write(client_dir.join("w2.txt"), "Form W-2 Wage and Tax Statement 2023").unwrap();
+    // Files this new may be uploads not yet recorded
+    assert!(stdout(&admin(dir.path(), &["verify"], "")).contains("agree"));
+    let long_ago = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::open(client_dir.join("w2.txt")).unwrap().set_modified(long_ago).unwrap();
+    let unrecorded = admin(dir.path(), &["verify"], "");
+    assert_eq!(unrecorded.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&unrecorded.stdout).contains("orphan-file\t1/w2.txt"));
+    assert!(stdout(&admin(dir.path(), &["reindex"], "")).contains("Reindexed 0 documents"));
+
+    let repaired = stdout(&admin(dir.path(), &["verify", "--repair"], ""));
+    assert!(repaired.contains("Moved 1 orphans") && !client_dir.join("w2.txt").exists());
+    assert!(stdout(&admin(dir.path(), &["verify"], "")).contains("agree"));
+}
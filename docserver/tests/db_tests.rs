@@ -1,4 +1,5 @@
-use docserver::{Database, Client, TaxReturn};
+use docserver::{Database, Client, Money, TaxReturn};
+use docserver::db::{FieldKey, Repository, ReturnKind};
 use std::collections::HashMap;
 use tempfile::tempdir;
 
@@ -7,7 +8,7 @@ fn test_database_workflow() {
     // Create a temporary database for testing
     let temp_dir = tempdir().unwrap();
     let db_path = temp_dir.path().join("integration_test.db");
-    let db = Database::new(db_path.to_str().unwrap()).unwrap();
+    let db = Database::new(db_path.to_str().unwrap(), FieldKey::generate()).unwrap();
     db.init().unwrap();
 
     // Test creating a client
@@ -27,12 +28,12 @@ fn test_database_workflow() {
 
     // Test creating a tax return for the client
     let mut income_sources = HashMap::new();
-    income_sources.insert("wages".to_string(), 75000.0);
-    income_sources.insert("interest".to_string(), 1000.0);
+    income_sources.insert("wages".to_string(), Money::from_dollars(75000));
+    income_sources.insert("interest".to_string(), Money::from_dollars(1000));
 
     let mut deductions = HashMap::new();
-    deductions.insert("standard_deduction".to_string(), 12950.0);
-    deductions.insert("student_loan_interest".to_string(), 2500.0);
+    deductions.insert("standard_deduction".to_string(), Money::from_dollars(12950));
+    deductions.insert("student_loan_interest".to_string(), Money::from_dollars(2500));
 
     let tax_return = TaxReturn {
         tax_return_id: None,
@@ -42,9 +43,16 @@ fn test_database_workflow() {
         income_sources,
         deductions,
         credits: HashMap::new(),
-        taxes_paid: 15000.0,
-        tax_liability: 14000.0,
-        refund_or_amount_due: 1000.0,
+        taxes_paid: Money::from_dollars(15000),
+        tax_liability: Money::from_dollars(14000),
+        refund_or_amount_due: Money::from_dollars(1000),
+        return_kind: ReturnKind::Original,
+        parent_return_id: None,
+        amendment_reason: None,
+        status: Default::default(),
+        assignee: None,
+        jurisdiction: Default::default(),
+        federal_return_id: None,
         created_at: None,
         updated_at: None,
     };
@@ -59,7 +67,7 @@ fn test_database_workflow() {
     assert_eq!(retrieved_return.tax_return_id, Some(tax_return_id));
     assert_eq!(retrieved_return.client_id, client_id);
     assert_eq!(retrieved_return.tax_year, 2023);
-    assert_eq!(retrieved_return.income_sources.get("wages"), Some(&75000.0));
-    assert_eq!(retrieved_return.income_sources.get("interest"), Some(&1000.0));
-    assert_eq!(retrieved_return.deductions.get("standard_deduction"), Some(&12950.0));
+    assert_eq!(retrieved_return.income_sources.get("wages"), Some(&Money::from_dollars(75000)));
+    assert_eq!(retrieved_return.income_sources.get("interest"), Some(&Money::from_dollars(1000)));
+    assert_eq!(retrieved_return.deductions.get("standard_deduction"), Some(&Money::from_dollars(12950)));
 }
@@ -0,0 +1,455 @@
+//! Runs the same checks against every storage backend. Each check is a
+//! test on SQLite and a test on PostgreSQL, which needs
+//! `DOCSERVER_TEST_POSTGRES_URL` to name a server the tests may create
+//! databases on, such as `postgres://postgres@127.0.0.1:5432/postgres`. CI
+//! provisions one; elsewhere the PostgreSQL tests pass without running when
+//! it is unset. Each PostgreSQL run gets a database of its own, dropped
+//! afterwards.
+
+use chrono::NaiveDate;
+use docserver::{Client, Database, Money, TaxReturn};
+use docserver::db::{
+    ChecklistStatus, Document, DocumentType, EstimatedPayment, FieldKey, HouseholdMember, Jurisdiction, LineItem,
+    LineItemSection, MemberRole, OfficeRepository, PaymentMethod, PoolOptions, PostgresStore, ReturnKind, ReturnStatus,
+};
+use docserver::db::household::HouseholdError;
+use docserver::db::line_items::LineItemError;
+use docserver::db::postgres::tls_connector;
+use docserver::db::returns::{AmendError, Amendment, StateReturn, StateReturnError};
+use docserver::db::workflow::TransitionError;
+use std::collections::HashMap;
+use tempfile::tempdir;
+use uuid::Uuid;
+
+fn on_sqlite(check: fn(&dyn OfficeRepository)) {
+    let dir = tempdir().unwrap();
+    let db = Database::new(dir.path().join("repository.db").to_str().unwrap(), FieldKey::generate()).unwrap();
+    db.init().unwrap();
+    check(&db);
+}
+
+fn on_postgres(check: fn(&dyn OfficeRepository)) {
+    let Ok(url) = std::env::var("DOCSERVER_TEST_POSTGRES_URL") else {
+        // CI always has a server, so a missing URL there is a broken job
+        assert!(std::env::var_os("CI").is_none(), "CI must set DOCSERVER_TEST_POSTGRES_URL");
+        eprintln!("Skipping: set DOCSERVER_TEST_POSTGRES_URL to run the PostgreSQL tests");
+        return;
+    };
+    let server: postgres::Config = url.parse().expect("DOCSERVER_TEST_POSTGRES_URL is not a connection URL");
+    let name = format!("docserver_test_{}", Uuid::new_v4().simple());
+    let mut admin = server.connect(tls_connector().unwrap()).unwrap();
+    admin.batch_execute(&format!("CREATE DATABASE {}", name)).unwrap();
+
+    let mut config = server.clone();
+    config.dbname(&name);
+    let store = PostgresStore::connect(config, FieldKey::generate(), PoolOptions::default());
+    // Dropped before the database, so no connection keeps it open
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| check(&store.unwrap())));
+    admin.batch_execute(&format!("DROP DATABASE {} WITH (FORCE)", name)).unwrap();
+    if let Err(panic) = result {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+fn client(last_name: &str) -> Client {
+    Client {
+        client_id: None,
+        first_name: "Pat".to_string(),
+        last_name: last_name.to_string(),
+        social_security_number: "123-45-6789".to_string(),
+        address: "1 Main St".to_string(),
+        phone_number: "(555) 010-0000".to_string(),
+        email: "pat@example.com".to_string(),
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+fn tax_return(client_id: i64, tax_year: i32) -> TaxReturn {
+    TaxReturn {
+        tax_return_id: None,
+        client_id,
+        tax_year,
+        filing_status: "Single".to_string(),
+        income_sources: HashMap::from([
+            ("wages".to_string(), Money::from_dollars(60000)),
+            ("interest".to_string(), Money::from_dollars(250)),
+        ]),
+        deductions: HashMap::from([("standard_deduction".to_string(), Money::from_dollars(13850))]),
+        credits: HashMap::new(),
+        taxes_paid: Money::from_dollars(7000),
+        tax_liability: Money::from_dollars(6500),
+        refund_or_amount_due: Money::from_dollars(500),
+        return_kind: ReturnKind::Original,
+        parent_return_id: None,
+        amendment_reason: None,
+        status: Default::default(),
+        assignee: None,
+        jurisdiction: Jurisdiction::Federal,
+        federal_return_id: None,
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+fn amendment(reason: &str) -> Amendment {
+    Amendment {
+        amendment_reason: reason.to_string(),
+        filing_status: None,
+        income_sources: Some(HashMap::from([("wages".to_string(), Money::from_dollars(62000))])),
+        deductions: None,
+        credits: None,
+        taxes_paid: None,
+        tax_liability: Some(Money::from_dollars(6900)),
+        refund_or_amount_due: None,
+    }
+}
+
+fn state_return(state: &str) -> StateReturn {
+    StateReturn {
+        state: Jurisdiction::parse(state).unwrap(),
+        filing_status: None,
+        income_sources: HashMap::from([("wages".to_string(), Money::from_dollars(60000))]),
+        deductions: HashMap::new(),
+        credits: HashMap::new(),
+        taxes_paid: Money::from_dollars(2000),
+        tax_liability: Money::from_dollars(1800),
+        refund_or_amount_due: Money::from_dollars(200),
+    }
+}
+
+fn document(client_id: i64, file_name: &str) -> Document {
+    Document {
+        document_id: None,
+        client_id,
+        file_name: file_name.to_string(),
+        document_type: Some(DocumentType::W2),
+        tax_year: Some(2023),
+        confidence: 0.6,
+        type_overridden: false,
+        extracted_text: Some("Wages, tips, other compensation".to_string()),
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+fn check_clients(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let stored = repo.get_client(client_id).unwrap().unwrap();
+    assert_eq!(stored.last_name, "Rivera");
+    assert_eq!(stored.social_security_number, "123-45-6789");
+    assert!(stored.created_at.is_some());
+
+    let other_id = repo.create_client(&client("Okafor")).unwrap();
+    let ids: Vec<i64> = repo.list_clients().unwrap().iter().filter_map(|c| c.client_id).collect();
+    assert!(ids.contains(&client_id) && ids.contains(&other_id));
+    assert!(repo.get_client(other_id + 1000).unwrap().is_none());
+}
+
+fn check_tax_returns(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let older_id = repo.create_tax_return(&tax_return(client_id, 2022)).unwrap();
+    let newer_id = repo.create_tax_return(&tax_return(client_id, 2023)).unwrap();
+
+    let stored = repo.get_tax_return(newer_id).unwrap().unwrap();
+    assert_eq!(stored.income_sources.get("wages"), Some(&Money::from_dollars(60000)));
+    assert_eq!(stored.deductions.get("standard_deduction"), Some(&Money::from_dollars(13850)));
+    assert_eq!(stored.taxes_paid, Money::from_dollars(7000));
+    assert_eq!(stored.jurisdiction, Jurisdiction::Federal);
+
+    // Newest year first
+    let ids: Vec<Option<i64>> = repo.get_client_tax_returns(client_id).unwrap().iter().map(|r| r.tax_return_id).collect();
+    assert_eq!(ids, vec![Some(newer_id), Some(older_id)]);
+    assert!(repo.get_tax_return(newer_id + 1000).unwrap().is_none());
+}
+
+fn check_amendment_chain(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let original_id = repo.create_tax_return(&tax_return(client_id, 2023)).unwrap();
+    assert!(matches!(repo.amend_return(original_id, amendment(" ")), Err(AmendError::MissingReason)));
+
+    let amended = repo.amend_return(original_id, amendment("Corrected W-2")).unwrap();
+    let amended_id = amended.tax_return_id.unwrap();
+    assert_eq!(amended.return_kind, ReturnKind::Amended);
+    assert_eq!(amended.parent_return_id, Some(original_id));
+    assert_eq!(amended.income_sources.get("wages"), Some(&Money::from_dollars(62000)));
+    // Sections the amendment leaves alone carry over
+    assert_eq!(amended.deductions.get("standard_deduction"), Some(&Money::from_dollars(13850)));
+    assert_eq!(amended.tax_liability, Money::from_dollars(6900));
+
+    let chain: Vec<Option<i64>> = repo.return_chain(original_id).unwrap().iter().map(|r| r.tax_return_id).collect();
+    assert_eq!(chain, vec![Some(original_id), Some(amended_id)]);
+    assert_eq!(repo.get_tax_return(original_id).unwrap().unwrap().return_kind, ReturnKind::Superseded);
+    assert!(matches!(
+        repo.amend_return(original_id, amendment("Again")),
+        Err(AmendError::Superseded(Some(id))) if id == amended_id
+    ));
+
+    assert_eq!(repo.list_tax_returns(Some(client_id), None, false).unwrap().len(), 1);
+    assert_eq!(repo.list_tax_returns(Some(client_id), None, true).unwrap().len(), 2);
+}
+
+fn check_state_returns(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let federal_id = repo.create_tax_return(&tax_return(client_id, 2023)).unwrap();
+
+    let state = repo.add_state_return(federal_id, state_return("CA")).unwrap();
+    let state_id = state.tax_return_id.unwrap();
+    assert_eq!(state.jurisdiction, Jurisdiction::State("CA".to_string()));
+    assert_eq!((state.federal_return_id, state.tax_year), (Some(federal_id), 2023));
+    assert!(matches!(
+        repo.add_state_return(federal_id, state_return("CA")),
+        Err(StateReturnError::Exists(id)) if id == state_id
+    ));
+    assert!(matches!(repo.add_state_return(state_id, state_return("NY")), Err(StateReturnError::NotFederal)));
+    assert!(matches!(repo.add_state_return(federal_id, state_return("us")), Err(StateReturnError::NotAState)));
+
+    let by_state = repo.list_tax_returns(None, Some(&Jurisdiction::State("CA".to_string())), false).unwrap();
+    assert!(by_state.iter().any(|r| r.tax_return_id == Some(state_id)));
+
+    // Amending the federal return brings its state returns along
+    let amended_id = repo.amend_return(federal_id, amendment("Corrected W-2")).unwrap().tax_return_id.unwrap();
+    assert!(repo.state_returns(federal_id).unwrap().is_empty());
+    let states: Vec<Option<i64>> = repo.state_returns(amended_id).unwrap().iter().map(|r| r.tax_return_id).collect();
+    assert_eq!(states, vec![Some(state_id)]);
+
+    let years = repo.return_years(client_id).unwrap();
+    assert_eq!(years.len(), 1);
+    assert_eq!(years[0].federal.as_ref().and_then(|r| r.tax_return_id), Some(amended_id));
+    assert_eq!(years[0].states.len(), 1);
+}
+
+fn check_documents(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let w2_id = repo.save_document(&document(client_id, "w2.pdf")).unwrap();
+    repo.save_document(&document(client_id, "bank.pdf")).unwrap();
+
+    // Saving the same file again updates it in place
+    let rescanned = Document { confidence: 0.9, ..document(client_id, "w2.pdf") };
+    assert_eq!(repo.save_document(&rescanned).unwrap(), w2_id);
+    let names: Vec<String> = repo.list_client_documents(client_id).unwrap().into_iter().map(|d| d.file_name).collect();
+    assert_eq!(names, vec!["bank.pdf", "w2.pdf"]);
+
+    assert!(repo.override_document_type(w2_id, DocumentType::Form1099Int, None).unwrap());
+    let stored = repo.get_document(w2_id).unwrap().unwrap();
+    assert_eq!(stored.document_type, Some(DocumentType::Form1099Int));
+    assert_eq!((stored.tax_year, stored.confidence, stored.type_overridden), (Some(2023), 1.0, true));
+    assert!(!repo.override_document_type(w2_id + 1000, DocumentType::W2, None).unwrap());
+}
+
+fn check_checklist(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let last_year_id = repo.save_document(&document(client_id, "w2.pdf")).unwrap();
+
+    // Last year's W-2 is chased again this year
+    let items = repo.generate_checklist(client_id, 2024).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].item_key, format!("document:{}", last_year_id));
+    assert_eq!((items[0].document_type, items[0].status), (DocumentType::W2, ChecklistStatus::Pending));
+    // Generating again adds nothing
+    assert_eq!(repo.generate_checklist(client_id, 2024).unwrap().len(), 1);
+
+    let item_id = items[0].item_id.unwrap();
+    let this_year_id = repo.save_document(&Document { tax_year: Some(2024), ..document(client_id, "w2-2024.pdf") }).unwrap();
+    assert!(repo.mark_checklist_item_received(item_id, this_year_id).unwrap());
+    let item = repo.get_checklist_item(item_id).unwrap().unwrap();
+    assert_eq!((item.status, item.received_document_id), (ChecklistStatus::Received, Some(this_year_id)));
+    assert!(repo.list_checklist_items(client_id, Some(2024), Some(ChecklistStatus::Pending)).unwrap().is_empty());
+}
+
+fn check_line_items_and_payments(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let return_id = repo.create_tax_return(&tax_return(client_id, 2023)).unwrap();
+    let second_job = LineItem {
+        line_item_id: None,
+        tax_return_id: return_id,
+        section: LineItemSection::Income,
+        category: "wages".to_string(),
+        payer: Some("Acme Corp".to_string()),
+        amount: Money::from_dollars(12500),
+        document_id: None,
+        created_at: None,
+        updated_at: None,
+    };
+
+    // The return's maps are the totals of its line items
+    let added = repo.add_line_item(&second_job).unwrap();
+    let stored = repo.get_tax_return(return_id).unwrap().unwrap();
+    assert_eq!(stored.income_sources.get("wages"), Some(&Money::from_dollars(72500)));
+    assert_eq!(repo.list_line_items(return_id, Some(LineItemSection::Income)).unwrap().len(), 3);
+    repo.remove_line_item(added.line_item_id.unwrap()).unwrap();
+    let stored = repo.get_tax_return(return_id).unwrap().unwrap();
+    assert_eq!(stored.income_sources.get("wages"), Some(&Money::from_dollars(60000)));
+    let unknown = LineItem { category: "lottery_winnings_maybe".to_string(), ..second_job };
+    assert!(matches!(repo.add_line_item(&unknown), Err(LineItemError::UnknownCategory { .. })));
+
+    // Estimated payments count toward the year's taxes paid
+    let payment_id = repo.add_estimated_payment(&EstimatedPayment {
+        payment_id: None,
+        client_id,
+        tax_year: 2023,
+        quarter: 1,
+        amount: Money::from_dollars(1500),
+        paid_on: NaiveDate::from_ymd_opt(2023, 4, 18).unwrap(),
+        method: PaymentMethod::DirectPay,
+        note: None,
+        created_at: None,
+    }).unwrap();
+    assert_eq!(repo.get_tax_return(return_id).unwrap().unwrap().taxes_paid, Money::from_dollars(8500));
+    assert_eq!(repo.paying_clients(2023).unwrap(), vec![client_id]);
+    assert!(repo.remove_estimated_payment(payment_id).unwrap());
+    assert_eq!(repo.get_tax_return(return_id).unwrap().unwrap().taxes_paid, Money::from_dollars(7000));
+}
+
+fn check_household(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let other_id = repo.create_client(&client("Okafor")).unwrap();
+    let return_id = repo.create_tax_return(&tax_return(client_id, 2023)).unwrap();
+    let spouse = HouseholdMember {
+        member_id: None,
+        client_id,
+        role: MemberRole::Spouse,
+        first_name: "Sam".to_string(),
+        last_name: "Rivera".to_string(),
+        relationship: "spouse".to_string(),
+        birth_date: NaiveDate::from_ymd_opt(1985, 6, 1).unwrap(),
+        social_security_number: "222-33-4444".to_string(),
+        created_at: None,
+        updated_at: None,
+    };
+
+    let spouse_id = repo.add_household_member(&spouse).unwrap();
+    assert!(matches!(repo.add_household_member(&spouse), Err(HouseholdError::SpouseExists)));
+    let stored = repo.get_household_member(spouse_id).unwrap().unwrap();
+    assert_eq!(stored.social_security_number, "222-33-4444");
+
+    let stranger_id = repo.add_household_member(&HouseholdMember { client_id: other_id, ..spouse }).unwrap();
+    assert!(matches!(
+        repo.set_return_household(return_id, &[spouse_id, stranger_id]),
+        Err(HouseholdError::NotInHousehold(id)) if id == stranger_id
+    ));
+    repo.set_return_household(return_id, &[spouse_id]).unwrap();
+    let covered: Vec<Option<i64>> = repo.return_household(return_id).unwrap().iter().map(|m| m.member_id).collect();
+    assert_eq!(covered, vec![Some(spouse_id)]);
+}
+
+fn check_workflow(repo: &dyn OfficeRepository) {
+    let client_id = repo.create_client(&client("Rivera")).unwrap();
+    let return_id = repo.create_tax_return(&tax_return(client_id, 2023)).unwrap();
+
+    let moved = repo.transition_return(return_id, ReturnStatus::DocumentsReceived, Some(Some("alex")), Some("All in")).unwrap();
+    assert_eq!((moved.status, moved.assignee.as_deref()), (ReturnStatus::DocumentsReceived, Some("alex")));
+    assert!(matches!(
+        repo.transition_return(return_id, ReturnStatus::Complete, None, None),
+        Err(TransitionError::NotAllowed { .. })
+    ));
+    assert!(matches!(
+        repo.transition_return(return_id + 1000, ReturnStatus::DocumentsReceived, None, None),
+        Err(TransitionError::NotFound)
+    ));
+
+    let history = repo.status_history(return_id).unwrap();
+    let last = history.last().unwrap();
+    assert_eq!((last.to_status, last.note.as_deref()), (ReturnStatus::DocumentsReceived, Some("All in")));
+    let assigned: Vec<Option<i64>> = repo.list_by_status(Some(ReturnStatus::DocumentsReceived), Some("alex")).unwrap()
+        .iter().map(|r| r.tax_return_id).collect();
+    assert_eq!(assigned, vec![Some(return_id)]);
+}
+
+mod sqlite {
+    use super::*;
+
+    #[test]
+    fn test_clients() {
+        on_sqlite(check_clients);
+    }
+
+    #[test]
+    fn test_tax_returns() {
+        on_sqlite(check_tax_returns);
+    }
+
+    #[test]
+    fn test_amendment_chain() {
+        on_sqlite(check_amendment_chain);
+    }
+
+    #[test]
+    fn test_state_returns() {
+        on_sqlite(check_state_returns);
+    }
+
+    #[test]
+    fn test_documents() {
+        on_sqlite(check_documents);
+    }
+
+    #[test]
+    fn test_checklist() {
+        on_sqlite(check_checklist);
+    }
+
+    #[test]
+    fn test_line_items_and_payments() {
+        on_sqlite(check_line_items_and_payments);
+    }
+
+    #[test]
+    fn test_household() {
+        on_sqlite(check_household);
+    }
+
+    #[test]
+    fn test_workflow() {
+        on_sqlite(check_workflow);
+    }
+}
+
+mod postgresql {
+    use super::*;
+
+    #[test]
+    fn test_clients() {
+        on_postgres(check_clients);
+    }
+
+    #[test]
+    fn test_tax_returns() {
+        on_postgres(check_tax_returns);
+    }
+
+    #[test]
+    fn test_amendment_chain() {
+        on_postgres(check_amendment_chain);
+    }
+
+    #[test]
+    fn test_state_returns() {
+        on_postgres(check_state_returns);
+    }
+
+    #[test]
+    fn test_documents() {
+        on_postgres(check_documents);
+    }
+
+    #[test]
+    fn test_checklist() {
+        on_postgres(check_checklist);
+    }
+
+    #[test]
+    fn test_line_items_and_payments() {
+        on_postgres(check_line_items_and_payments);
+    }
+
+    #[test]
+    fn test_household() {
+        on_postgres(check_household);
+    }
+
+    #[test]
+    fn test_workflow() {
+        on_postgres(check_workflow);
+    }
+}
@@ -7,27 +7,39 @@ import { ApiError } from './types';
  * @property {number} client_id
  * @property {number} tax_year
  * @property {string} filing_status
- * @property {Object[]} income_sources
- * @property {Object[]} deductions
- * @property {Object[]} credits
- * @property {number} taxes_paid
- * @property {number} tax_liability
- * @property {number} refund_or_amount_due
+ * @property {Object<string, string>} income_sources - Amounts as decimal strings
+ * @property {Object<string, string>} deductions - Amounts as decimal strings
+ * @property {Object<string, string>} credits - Amounts as decimal strings
+ * @property {string} taxes_paid - Decimal string, e.g. "1234.56"
+ * @property {string} tax_liability - Decimal string, e.g. "1234.56"
+ * @property {string} refund_or_amount_due - Decimal string, e.g. "1234.56"
+ * @property {'original'|'amended'|'superseded'} return_kind
+ * @property {number|null} parent_return_id - The return this one amends
+ * @property {string|null} amendment_reason
+ * @property {string} status - Workflow stage, e.g. "in_review"
+ * @property {string|null} assignee - Owner of the current stage
+ * @property {string} jurisdiction - "federal" or a state code, e.g. "VA"
+ * @property {number|null} federal_return_id - The federal return a state return is filed with
  * @property {string} [created_at]
  * @property {string} [updated_at]
  */
 
 /**
- * Fetches all tax returns, optionally filtered by client ID
+ * Fetches all tax returns, optionally filtered by client ID and jurisdiction
  * @async
  * @param {number} [clientId] - Optional client ID to filter returns
+ * @param {string} [jurisdiction] - Optional "federal" or state code
  * @returns {Promise<TaxReturn[]>} A promise that resolves to an array of tax returns
  * @throws {ApiError} If the server returns an error response
  */
-export async function listReturns(clientId) {
+export async function listReturns(clientId, jurisdiction) {
     try {
-        const url = clientId 
-            ? `${createUrl('/returns')}?client_id=${clientId}`
+        const params = new URLSearchParams();
+        if (clientId) params.set('client_id', String(clientId));
+        if (jurisdiction) params.set('jurisdiction', jurisdiction);
+        const query = params.toString();
+        const url = query
+            ? `${createUrl('/returns')}?${query}`
             : `${createUrl('/returns')}`;
         const response = await fetch(url);
         if (!response.ok) {